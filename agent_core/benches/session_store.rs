@@ -0,0 +1,82 @@
+//! One append to a long session, with the append-only layout of [`PersistentSessionStore`] and
+//! with the former layout rewriting the whole session record and its index on every append.
+//! Run with `cargo bench -p agent_core --bench session_store`.
+
+use std::collections::HashMap;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use redb::{Database, TableDefinition};
+
+use agent_core::session::persistent_store::PersistentSessionRecord;
+use agent_core::session::{PersistentSessionStore, SessionStoreApi};
+use agent_models::response_item::{ContentPart, ResponseItem, Role};
+
+/// Items already in the session when the measured appends start
+const HISTORY_ITEMS: usize = 1000;
+
+const LEGACY_SESSIONS_TABLE: TableDefinition<&str, Vec<u8>> = TableDefinition::new("sessions");
+const LEGACY_INDEX_TABLE: TableDefinition<&str, &str> = TableDefinition::new("response_index");
+
+fn message(i: usize) -> ResponseItem {
+    ResponseItem::Message {
+        id: format!("msg_{i}"),
+        role: Role::User,
+        content: vec![ContentPart::Text { text: format!("message number {i} with some text") }],
+    }
+}
+
+fn bench_append(c: &mut Criterion) {
+    let temp_dir = std::env::temp_dir().join(format!("swarm_bench_redb_{}", uuid::Uuid::new_v4()));
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group(format!("append_to_{HISTORY_ITEMS}_item_session"));
+
+    let store = PersistentSessionStore::new(temp_dir.join("append_only.redb").to_str().unwrap()).unwrap();
+    runtime.block_on(async {
+        for i in 0..HISTORY_ITEMS {
+            store.append_items("bench", &[message(i)]).await.unwrap();
+        }
+    });
+    let mut next = HISTORY_ITEMS;
+    group.bench_function("append_only", |b| {
+        b.iter(|| {
+            runtime.block_on(store.append_items("bench", &[message(next)])).unwrap();
+            next += 1;
+        })
+    });
+    drop(store);
+
+    let db = Database::create(temp_dir.join("record_rewrite.redb")).unwrap();
+    let mut record = PersistentSessionRecord {
+        id: "bench".to_string(),
+        parent_response_id: None,
+        items: (0..HISTORY_ITEMS).map(message).collect(),
+        metadata: HashMap::new(),
+        usage: None,
+        last_accessed: None,
+    };
+    group.bench_function("record_rewrite", |b| {
+        b.iter(|| {
+            // The session keeps its length so every append costs the same
+            record.items.remove(0);
+            record.items.push(message(next));
+            next += 1;
+            let write_txn = db.begin_write().unwrap();
+            {
+                let mut sessions = write_txn.open_table(LEGACY_SESSIONS_TABLE).unwrap();
+                sessions.insert("bench", serde_json::to_vec(&record).unwrap()).unwrap();
+                let mut index = write_txn.open_table(LEGACY_INDEX_TABLE).unwrap();
+                for item in &record.items {
+                    index.insert(item.id(), "bench").unwrap();
+                }
+            }
+            write_txn.commit().unwrap();
+        })
+    });
+    group.finish();
+    drop(db);
+
+    let _ = std::fs::remove_dir_all(temp_dir);
+}
+
+criterion_group!(benches, bench_append);
+criterion_main!(benches);
@@ -3,6 +3,8 @@ use agent_models::execution::execution_result::{ExecutionResult};
 
 use agent_models::agent_request::AgentRequest;
 use configuration::AgentConfig;
+use llm_api::client::LlmClient;
+use llm_api::retry::{http_client, RetryPolicy};
 
 use std::sync::Arc;
 use crate::business_logic::services::MemoryService;
@@ -11,6 +13,7 @@ use crate::business_logic::services::DiscoveryService;
 use crate::business_logic::services::WorkflowServiceApi;
 
 use crate::business_logic::mcp_runtime::McpRuntimeDetails;
+use crate::server::model_provider::ProviderSpec;
 
 #[async_trait]
 pub trait Agent: Send + Sync  + Clone + 'static {
@@ -25,3 +28,40 @@ pub trait Agent: Send + Sync  + Clone + 'static {
     ) -> anyhow::Result<Self>;
     async fn handle_request(&self, request: AgentRequest) -> anyhow::Result<ExecutionResult>;
 }
+
+/// LLM client for the model and endpoint an agent is configured with, retrying and timing out
+/// as `agent_llm_retry` and `agent_llm_http` say. Agents run their turns through it, like the
+/// gateway providers, whatever protocol the endpoint speaks.
+pub fn agent_llm_client(agent_config: &AgentConfig, agent_api_key: &str) -> Box<dyn LlmClient> {
+    let mut spec = ProviderSpec::for_endpoint(agent_config.agent_name(), agent_config.agent_llm_url());
+    spec.api_key = Some(agent_api_key.to_string());
+    spec.retry_policy = agent_config.agent_llm_retry.as_ref().map(RetryPolicy::from).unwrap_or_default();
+    let client = http_client(&agent_config.agent_llm_http().unwrap_or_default());
+    spec.llm_client(&agent_config.agent_model_id(), &client)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_agent_llm_client_uses_the_configured_model() {
+        let mut builder = AgentConfig::builder();
+        builder.agent_id = Some("agent".to_string());
+        builder.agent_name = Some("agent".to_string());
+        builder.agent_http_endpoint = Some("http://localhost:8080".to_string());
+        builder.agent_ws_endpoint = Some("ws://localhost:8081".to_string());
+        builder.agent_version = Some("1.0".to_string());
+        builder.agent_description = Some(String::new());
+        builder.agent_skill_id = Some(String::new());
+        builder.agent_skill_name = Some(String::new());
+        builder.agent_skill_description = Some(String::new());
+        let config = builder
+            .agent_model_id("gemini-2.5-flash".to_string())
+            .agent_llm_url("https://generativelanguage.googleapis.com/v1beta/models".to_string())
+            .agent_llm_retry(toml::from_str("max_attempts = 1").unwrap())
+            .build()
+            .unwrap();
+        assert_eq!(agent_llm_client(&config, "key").model_id(), "gemini-2.5-flash");
+    }
+}
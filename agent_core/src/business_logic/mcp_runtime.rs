@@ -1,7 +1,63 @@
 use configuration::McpRuntimeConfig;
+use llm_api::client::LlmClient;
+use llm_api::retry::{http_client, RetryPolicy};
+
+use crate::server::model_provider::ProviderSpec;
 
 #[derive(Debug, Clone)]
 pub struct McpRuntimeDetails {
     pub config: McpRuntimeConfig,
     pub api_key: String,
 }
+
+impl McpRuntimeDetails {
+    /// Retry policy of the MCP LLM, the client defaults overlaid with `agent_mcp_llm_retry`
+    pub fn llm_retry_policy(&self) -> RetryPolicy {
+        self.config.agent_mcp_llm_retry.as_ref().map(RetryPolicy::from).unwrap_or_default()
+    }
+
+    /// Client for the LLM driving the MCP tool loop, with the configured retries and timeouts
+    pub fn llm_client(&self) -> Box<dyn LlmClient> {
+        let mut spec = ProviderSpec::for_endpoint("mcp", self.config.agent_mcp_llm_url.clone());
+        spec.api_key = Some(self.api_key.clone());
+        spec.retry_policy = self.llm_retry_policy();
+        let client = http_client(&self.config.agent_mcp_llm_http.clone().unwrap_or_default());
+        spec.llm_client(&self.config.agent_mcp_model_id, &client)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_llm_retry_policy_keeps_unset_defaults() {
+        let config: McpRuntimeConfig = toml::from_str(
+            r#"
+            agent_mcp_role_tool = "tool"
+            agent_mcp_role_assistant = "assistant"
+            agent_mcp_tool_choice_auto = "auto"
+            agent_mcp_finish_reason_tool_calls = "tool_calls"
+            agent_mcp_finish_reason_stop = "stop"
+            agent_mcp_max_loops = 5
+            agent_mcp_model_id = "model"
+            agent_mcp_llm_url = "http://localhost/v1/chat/completions"
+            agent_mcp_system_prompt = ""
+            agent_mcp_evaluation_prompt = ""
+            agent_mcp_correction_prompt = ""
+            agent_mcp_llm_retry = { max_attempts = 5, request_timeout_ms = 15000 }
+            agent_mcp_llm_http = { read_timeout_ms = 120000 }
+            "#,
+        )
+        .unwrap();
+        let details = McpRuntimeDetails { config, api_key: String::new() };
+        let policy = details.llm_retry_policy();
+        assert_eq!(policy.max_attempts, 5);
+        assert_eq!(policy.request_timeout, Some(Duration::from_secs(15)));
+        assert_eq!(policy.base_delay, RetryPolicy::default().base_delay);
+        assert_eq!(policy.retryable_statuses, RetryPolicy::default().retryable_statuses);
+        assert_eq!(details.llm_client().model_id(), "model");
+        assert_eq!(details.config.agent_mcp_llm_http.unwrap().read_timeout_ms, Some(120_000));
+    }
+}
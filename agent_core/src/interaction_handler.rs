@@ -1,4 +1,4 @@
-use crate::session::SessionStore;
+use crate::session::{HistoryPolicy, SessionStore};
 use anyhow::Result;
 use agent_models::agent_request::AgentRequest;
 use agent_models::response_item::{ContentPart, ResponseItem, Role};
@@ -7,11 +7,21 @@ use uuid::Uuid;
 
 pub struct InteractionHandler {
     session_store: Arc<SessionStore>,
+    history_policy: HistoryPolicy,
 }
 
 impl InteractionHandler {
     pub fn new(session_store: Arc<SessionStore>) -> Self {
-        Self { session_store }
+        Self {
+            session_store,
+            history_policy: HistoryPolicy::default(),
+        }
+    }
+
+    /// Limit the history put in requests with `policy`; the full history is used by default
+    pub fn with_history_policy(mut self, policy: HistoryPolicy) -> Self {
+        self.history_policy = policy;
+        self
     }
 
     pub async fn process_request(
@@ -29,8 +39,10 @@ impl InteractionHandler {
         // 2. Append the new item to the session history
         self.session_store.append_items(session_id, &[user_item]).await;
 
-        // 3. Get the full, updated history
+        // 3. Get the updated history, within the policy's limits
         let history = self.session_store.get_history(session_id).await;
+        let token_budget = self.history_policy.token_budget(None, None);
+        let history = self.history_policy.apply(history, token_budget).await;
 
         // 4. Return provider-agnostic AgentRequest
         Ok(AgentRequest {
@@ -79,6 +91,16 @@ mod tests {
 
             assert_eq!(request2.items.len(), 3);
             assert_eq!(request2.user_query(), "How are you?");
+
+            // Only the latest items are sent once the history exceeds the policy
+            let handler = InteractionHandler::new(session_store)
+                .with_history_policy(HistoryPolicy::default().with_max_items(2));
+            let request3 = handler
+                .process_request(session_id, "Still there?".to_string())
+                .await
+                .unwrap();
+            assert_eq!(request3.items.len(), 2);
+            assert_eq!(request3.user_query(), "Still there?");
         });
     }
 }
@@ -5,4 +5,7 @@ pub mod session;
 pub mod interaction_handler;
 
 pub use session::*;
-pub use server::gateway_server::{GatewayBackend, GatewayServer, GatewayState, MultiModelGatewayBackend, SimpleGatewayBackend};
+pub use server::gateway_server::{GatewayBackend, GatewayError, GatewayServer, GatewayState, MultiModelGatewayBackend, SimpleGatewayBackend};
+pub use server::model_catalog::{ModelCapabilities, ModelCatalog, ModelObject};
+pub use server::response_stream::{ResponseStreamBuilder, StreamDelta};
+pub use server::model_provider::{ModelProvider, ProviderProtocol, ProviderRegistry, ProviderSpec, ResolvedRoute, RouteRule};
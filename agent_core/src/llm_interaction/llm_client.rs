@@ -1,8 +0,0 @@
-
-
-/* 
-#[async_trait]
-pub trait LlmClient: Send + Sync + Clone + 'static {
-    async fn call_llm(&self, messages: Vec<LlmMessageRequest>) -> anyhow::Result<LlmMessageResponse>;
-}
-*/
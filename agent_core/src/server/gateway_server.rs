@@ -1,28 +1,48 @@
+use std::collections::{BTreeMap, HashMap};
 use std::convert::Infallible;
 use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use axum::{
-    extract::State,
+    extract::{DefaultBodyLimit, Path, State},
     http::StatusCode,
     response::{
         sse::{Event, Sse},
         IntoResponse, Response,
     },
-    routing::post,
+    routing::{get, post},
     Json, Router,
 };
 use serde::Serialize;
 use uuid::Uuid;
 
+use configuration::{HttpClientConfig, RetryConfig};
+
 use agent_models::response_item::{
-    ContentPart, CreateResponseRequest, ResponseItem, ResponseObject, ResponseUsage, ResponsesInput, Role,
+    ContentPart, CreateResponseRequest, ResponseItem, ResponseObject, ResponseStreamEvent, ResponseUsage, ResponsesInput,
+    Role,
 };
 use llm_api::chat::{
-    ChatCompletionRequest, ChatCompletionResponse, Choice, ResponseMessage, Usage,
+    ChatCompletionChunk, ChatCompletionRequest, ChatCompletionResponse, Choice, ResponseMessage, Usage,
+};
+use llm_api::client::{LlmError, LlmTurn};
+use llm_api::generation::{GenerationParams, ReasoningOptions};
+use llm_api::google_interactions::SafetySetting;
+use llm_api::retry::http_client;
+use llm_api::tools::Tool;
+
+use crate::server::response_stream::{item_deltas, ChatChunkNormalizer, ResponseStreamBuilder, StreamDelta};
+use crate::server::media::{MediaError, MediaLimits};
+use crate::server::session_api;
+use crate::server::model_catalog::{ModelCatalog, ModelList, ModelObject};
+use crate::server::model_provider::{
+    builtin_provider_specs, ModelProvider, ProviderProtocol, ProviderRegistry, ProviderSpec, ResolvedRoute,
+};
+use crate::session::history::{render_transcript, SUMMARY_MAX_TOKENS};
+use crate::session::{
+    spawn_session_reaper, HistoryPolicy, HistorySummarizer, PersistentSessionStore, SessionError, SessionLimits,
+    SessionStore, SessionStoreApi, StoredResponse, WriteMode, DEFAULT_CACHE_IDLE,
 };
-
-use crate::session::SessionStoreApi;
 
 /// Usage data returned from a backend turn
 #[derive(Debug, Clone, Default, Serialize, serde::Deserialize, PartialEq)]
@@ -37,6 +57,146 @@ pub struct BackendUsage {
 pub struct BackendTurnResult {
     pub items: Vec<ResponseItem>,
     pub usage: Option<BackendUsage>,
+    /// Model that actually served the turn, when it differs from or refines the requested one
+    pub model: Option<String>,
+}
+
+/// Outcome of a streamed backend turn; its items were sent as deltas
+#[derive(Debug, Clone, Default)]
+pub struct BackendStreamResult {
+    pub usage: Option<BackendUsage>,
+    /// Model that actually served the turn, when it differs from or refines the requested one
+    pub model: Option<String>,
+}
+
+impl From<ResponseUsage> for BackendUsage {
+    fn from(usage: ResponseUsage) -> Self {
+        Self {
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+            total_tokens: usage.total_tokens,
+        }
+    }
+}
+
+impl From<LlmTurn> for BackendTurnResult {
+    fn from(turn: LlmTurn) -> Self {
+        Self {
+            items: turn.items,
+            usage: turn.usage.map(Into::into),
+            model: turn.model,
+        }
+    }
+}
+
+/// Failure of a gateway turn, answered to clients as an OpenAI-style error
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum GatewayError {
+    /// No provider of the fallback chain can serve the requested model
+    #[error("{0}")]
+    ModelUnavailable(String),
+    /// An upstream call failed with an error another model would not fix
+    #[error(transparent)]
+    Upstream(#[from] LlmError),
+    /// Every model of the fallback chain failed or returned nothing; `last` is the last upstream error
+    #[error("All providers failed for model '{model}': {}", failures.join("; "))]
+    ChainExhausted {
+        model: String,
+        failures: Vec<String>,
+        last: Option<LlmError>,
+    },
+}
+
+impl GatewayError {
+    /// The upstream error the client should be told about, if any
+    fn llm_error(&self) -> Option<&LlmError> {
+        match self {
+            GatewayError::Upstream(err) => Some(err),
+            GatewayError::ChainExhausted { last, .. } => last.as_ref(),
+            GatewayError::ModelUnavailable(_) => None,
+        }
+    }
+
+    /// HTTP status answered for this error. Upstream client errors are passed through, upstream
+    /// server errors, rejected gateway credentials and undecodable answers become 502.
+    pub fn status_code(&self) -> StatusCode {
+        let Some(err) = self.llm_error() else {
+            return match self {
+                GatewayError::ModelUnavailable(_) => StatusCode::NOT_FOUND,
+                _ => StatusCode::BAD_GATEWAY,
+            };
+        };
+        match err {
+            LlmError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            LlmError::ContextLengthExceeded(_) | LlmError::ContentFiltered(_) | LlmError::InvalidRequest(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            LlmError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            LlmError::UpstreamStatus { code: 503 | 529, .. } => StatusCode::SERVICE_UNAVAILABLE,
+            LlmError::UpstreamStatus { code, .. } if (400..500).contains(code) => {
+                StatusCode::from_u16(*code).unwrap_or(StatusCode::BAD_REQUEST)
+            }
+            LlmError::Auth(_) | LlmError::Connection(_) | LlmError::UpstreamStatus { .. } | LlmError::Decode(_) => {
+                StatusCode::BAD_GATEWAY
+            }
+        }
+    }
+
+    /// Machine-readable `error.code`
+    pub fn code(&self) -> &'static str {
+        match (self, self.llm_error()) {
+            (GatewayError::ModelUnavailable(_), _) => "model_not_found",
+            (_, Some(err)) => err.code(),
+            (_, None) => "upstream_error",
+        }
+    }
+
+    /// `{"error": {"message", "type", "param", "code"}}` as returned by OpenAI
+    pub fn error_body(&self) -> serde_json::Value {
+        let status = self.status_code();
+        let r#type = if status == StatusCode::TOO_MANY_REQUESTS {
+            "rate_limit_error"
+        } else if status.is_client_error() {
+            "invalid_request_error"
+        } else {
+            "server_error"
+        };
+        serde_json::json!({
+            "error": {
+                "message": self.to_string(),
+                "type": r#type,
+                "param": null,
+                "code": self.code(),
+            }
+        })
+    }
+}
+
+impl IntoResponse for GatewayError {
+    fn into_response(self) -> Response {
+        let mut response = (self.status_code(), Json(self.error_body())).into_response();
+        if let Some(LlmError::RateLimited { retry_after: Some(delay), .. }) = self.llm_error()
+            && let Ok(value) = axum::http::HeaderValue::from_str(&delay.as_secs().max(1).to_string())
+        {
+            response.headers_mut().insert(axum::http::header::RETRY_AFTER, value);
+        }
+        response
+    }
+}
+
+/// Session storage failures are reported as 503 so clients retry instead of losing the turn silently
+impl IntoResponse for SessionError {
+    fn into_response(self) -> Response {
+        let body = serde_json::json!({
+            "error": {
+                "message": self.to_string(),
+                "type": "server_error",
+                "param": null,
+                "code": "session_store_unavailable",
+            }
+        });
+        (StatusCode::SERVICE_UNAVAILABLE, Json(body)).into_response()
+    }
 }
 
 /// Trait for handling the gateway generation backend (e.g. LLM call, agent orchestration loop)
@@ -47,24 +207,51 @@ pub trait GatewayBackend: Send + Sync {
         session_id: &str,
         history: &[ResponseItem],
         model: Option<&str>,
-    ) -> Result<BackendTurnResult, String>;
+        tools: &[Tool],
+        params: &GenerationParams,
+    ) -> Result<BackendTurnResult, GatewayError>;
 
-    /// Stream response chunks. Default implementation calls process_turn and sends items as chunks.
+    /// Stream the turn as deltas. Default implementation calls process_turn and replays the items.
     async fn process_turn_stream(
         &self,
         session_id: &str,
         history: &[ResponseItem],
         model: Option<&str>,
-        tx: tokio::sync::mpsc::Sender<String>,
-    ) -> Result<Option<BackendUsage>, String> {
-        let result = self.process_turn(session_id, history, model).await?;
-        for item in &result.items {
-            let json_str = serde_json::to_string(item).unwrap_or_default();
-            let _ = tx.send(json_str).await;
+        tools: &[Tool],
+        params: &GenerationParams,
+        tx: tokio::sync::mpsc::Sender<StreamDelta>,
+    ) -> Result<BackendStreamResult, GatewayError> {
+        let result = self.process_turn(session_id, history, model, tools, params).await?;
+        for delta in item_deltas(&result.items) {
+            if tx.send(delta).await.is_err() {
+                break;
+            }
         }
-        let _ = tx.send("[DONE]".to_string()).await;
-        Ok(result.usage)
+        Ok(BackendStreamResult { usage: result.usage, model: result.model })
+    }
+
+    /// Models served by this backend, listed on `GET /v1/models`
+    fn list_models(&self) -> Vec<ModelObject> {
+        Vec::new()
+    }
+
+    /// Describe one model for `GET /v1/models/{id}`
+    fn describe_model(&self, id: &str) -> Option<ModelObject> {
+        self.list_models().into_iter().find(|m| m.id.eq_ignore_ascii_case(id))
+    }
+
+    /// Context window the history sent for `model` must fit, when known
+    fn context_window(&self, model: &str) -> Option<u32> {
+        self.describe_model(model).and_then(|m| m.context_window)
+    }
+
+    /// Model serving requests that name none, when the backend has one
+    fn default_model(&self) -> Option<&str> {
+        None
     }
+
+    /// Drop the per-session state of the backend once the session is removed or evicted
+    fn forget_session(&self, _session_id: &str) {}
 }
 
 /// A default Echo/Mock backend or forwarding backend for the gateway
@@ -77,7 +264,9 @@ impl GatewayBackend for SimpleGatewayBackend {
         _session_id: &str,
         history: &[ResponseItem],
         _model: Option<&str>,
-    ) -> Result<BackendTurnResult, String> {
+        _tools: &[Tool],
+        _params: &GenerationParams,
+    ) -> Result<BackendTurnResult, GatewayError> {
         let last_user_text = history
             .iter()
             .rev()
@@ -107,6 +296,7 @@ impl GatewayBackend for SimpleGatewayBackend {
                 output_tokens: 10,
                 total_tokens: last_user_text.split_whitespace().count() as u32 + 10,
             }),
+            model: None,
         })
     }
 }
@@ -117,6 +307,7 @@ pub struct GatewayConfigFile {
     pub session: Option<GatewaySessionSection>,
     pub models: Option<GatewayModelsSection>,
     pub providers: Option<GatewayProvidersSection>,
+    pub media: Option<GatewayMediaSection>,
 }
 
 #[derive(Debug, Clone, serde::Deserialize, Default)]
@@ -125,55 +316,251 @@ pub struct GatewayServerSection {
     pub host: Option<String>,
     pub port: Option<u16>,
     pub log_level: Option<String>,
+    /// Connect and read timeouts of the client calling the providers, e.g.
+    /// `{ connect_timeout_ms = 5000, read_timeout_ms = 120000 }`
+    pub upstream_http: Option<HttpClientConfig>,
+    /// Enables the session management endpoints, which then require this key as bearer token.
+    /// They are not served when unset.
+    pub admin_api_key: Option<String>,
 }
 
 #[derive(Debug, Clone, serde::Deserialize, Default)]
 pub struct GatewaySessionSection {
+    /// Most items stored per session; the oldest exchanges are dropped, system messages are kept
     pub max_history_items: Option<usize>,
+    /// Most history items sent to the backend per turn; system messages do not count
+    pub max_prompt_items: Option<usize>,
+    /// Token budget of the history sent to any model, on top of the model's context window
+    pub max_history_tokens: Option<usize>,
+    /// Context tokens left for the answer when the request sets no max_output_tokens, 4096 by default
+    pub output_reserve_tokens: Option<usize>,
+    /// Always send system messages, true by default
+    pub pin_system_messages: Option<bool>,
+    /// Replace turns dropped from the history by a summary written by the backend
+    pub summarize_history: Option<bool>,
+    /// Model writing the summaries, the default model when unset
+    pub summary_model: Option<String>,
+    /// Idle time after which a session is evicted
     pub session_timeout_seconds: Option<u64>,
+    /// Most sessions kept; the least recently used ones are evicted beyond it
+    pub max_sessions: Option<usize>,
+    /// How often expired sessions are evicted, 60 seconds by default
+    pub reap_interval_seconds: Option<u64>,
     pub persistence_enabled: Option<bool>,
     pub db_path: Option<String>,
+    /// Idle time after which a persisted session is dropped from memory, staying on disk; 600 seconds by default
+    pub cache_idle_seconds: Option<u64>,
+    /// Most persisted sessions kept in memory; the least recently used ones are dropped from it beyond it
+    pub max_cached_sessions: Option<usize>,
+    /// When persisted updates are written: "sync" (default), "write_behind" or "best_effort"
+    pub write_mode: Option<String>,
+    /// Flush interval of the write-behind mode, 1000 ms by default
+    pub flush_interval_ms: Option<u64>,
+}
+
+impl GatewaySessionSection {
+    pub fn limits(&self) -> SessionLimits {
+        SessionLimits {
+            ttl: self.session_timeout_seconds.map(Duration::from_secs),
+            max_sessions: self.max_sessions,
+            max_history_items: self.max_history_items,
+        }
+    }
+
+    /// Bounds of the in-memory cache of the persistent store
+    pub fn cache_limits(&self) -> SessionLimits {
+        SessionLimits {
+            ttl: Some(self.cache_idle_seconds.map_or(DEFAULT_CACHE_IDLE, Duration::from_secs)),
+            max_sessions: self.max_cached_sessions,
+            max_history_items: None,
+        }
+    }
+
+    /// History policy of the gateway; summaries are written by `backend` when enabled
+    pub fn history_policy(&self, backend: &Arc<dyn GatewayBackend>) -> HistoryPolicy {
+        let mut policy = HistoryPolicy::default();
+        policy.max_items = self.max_prompt_items;
+        policy.max_tokens = self.max_history_tokens;
+        if let Some(reserve) = self.output_reserve_tokens {
+            policy.output_reserve_tokens = reserve;
+        }
+        if let Some(pin) = self.pin_system_messages {
+            policy.pin_system = pin;
+        }
+        if self.summarize_history.unwrap_or(false) {
+            policy.with_summarizer(Arc::new(BackendSummarizer::new(backend.clone(), self.summary_model.clone())))
+        } else {
+            policy
+        }
+    }
+
+    pub fn reap_interval(&self) -> Duration {
+        Duration::from_secs(self.reap_interval_seconds.unwrap_or(60).max(1))
+    }
+
+    pub fn write_mode(&self) -> anyhow::Result<WriteMode> {
+        match self.write_mode.as_deref().unwrap_or("sync") {
+            "sync" => Ok(WriteMode::Sync),
+            "write_behind" => Ok(WriteMode::WriteBehind {
+                flush_interval: Duration::from_millis(self.flush_interval_ms.unwrap_or(1000).max(1)),
+            }),
+            "best_effort" => Ok(WriteMode::BestEffort),
+            other => anyhow::bail!("unknown session write_mode '{}', expected sync, write_behind or best_effort", other),
+        }
+    }
+
+    /// Open the configured session store: redb-backed when persistence is enabled, in memory otherwise.
+    /// Starts the reaper evicting its expired sessions, which `backend` then forgets, and in
+    /// write-behind mode the flusher; both need a Tokio runtime and stop with the store.
+    pub fn open_store(&self, backend: &Arc<dyn GatewayBackend>) -> anyhow::Result<Arc<dyn SessionStoreApi>> {
+        let store: Arc<dyn SessionStoreApi> = if self.persistence_enabled.unwrap_or(false) {
+            let db_path = self.db_path.as_deref().unwrap_or("data/sessions.redb");
+            let store = PersistentSessionStore::new(db_path)?
+                .with_limits(self.limits())
+                .with_cache_limits(self.cache_limits())
+                .with_write_mode(self.write_mode()?);
+            let store = Arc::new(store);
+            PersistentSessionStore::spawn_flusher(&store);
+            store
+        } else {
+            Arc::new(SessionStore::new().with_limits(self.limits()))
+        };
+        let backend = Arc::downgrade(backend);
+        spawn_session_reaper(&store, self.reap_interval(), move |evicted| {
+            if let Some(backend) = backend.upgrade() {
+                evicted.iter().for_each(|session_id| backend.forget_session(session_id));
+            }
+        });
+        Ok(store)
+    }
+}
+
+/// Limits on images, audio and files in requests; unset values keep the [`MediaLimits`] defaults
+#[derive(Debug, Clone, serde::Deserialize, Default)]
+pub struct GatewayMediaSection {
+    pub max_part_bytes: Option<usize>,
+    pub max_request_bytes: Option<usize>,
+    pub image_types: Option<Vec<String>>,
+    pub audio_types: Option<Vec<String>>,
+    pub file_types: Option<Vec<String>>,
 }
 
 #[derive(Debug, Clone, serde::Deserialize, Default)]
 pub struct GatewayModelsSection {
     pub default_model: Option<String>,
+    /// Ordered fallback chain per requested model, e.g. `"gpt-4o" = ["groq/llama-3.3-70b-versatile", "ollama/llama3.2"]`.
+    /// The next model is tried on 5xx, 429, timeouts, connection errors or an empty response.
+    pub fallbacks: Option<HashMap<String, Vec<String>>>,
+    /// Answer with the SimpleGatewayBackend echo when every model of the chain failed or returned nothing.
+    /// Disabled by default so outages surface as errors.
+    pub mock_fallback: Option<bool>,
+    /// Generation defaults and limits per model id, e.g. `[models.generation."gpt-4o"]`.
+    /// The `"*"` entry applies to models without an entry of their own.
+    pub generation: Option<HashMap<String, GatewayGenerationEntry>>,
+    /// Model metadata shown on `/v1/models`, overriding the built-in values, e.g. `[models.catalog."mistral-large"]`
+    pub catalog: Option<HashMap<String, GatewayModelEntry>>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, Default)]
+pub struct GatewayModelEntry {
+    pub owned_by: Option<String>,
+    pub context_window: Option<u32>,
+    pub tools: Option<bool>,
+    pub vision: Option<bool>,
+    pub streaming: Option<bool>,
+}
+
+/// Defaults fill parameters the client left unset; limits cap whatever the client sent
+#[derive(Debug, Clone, serde::Deserialize, Default)]
+pub struct GatewayGenerationEntry {
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_output_tokens: Option<u32>,
+    pub stop: Option<Vec<String>>,
+    /// Highest temperature forwarded upstream
+    pub max_temperature: Option<f32>,
+    /// Highest max_output_tokens forwarded upstream, also sent when the client set none
+    pub max_output_tokens_limit: Option<u32>,
+    /// Reasoning handling when the client did not choose
+    pub reasoning: Option<ReasoningOptions>,
+}
+
+impl GatewayGenerationEntry {
+    /// Apply this entry's defaults and limits to the client's parameters
+    pub fn apply(&self, params: &GenerationParams) -> GenerationParams {
+        let defaults = GenerationParams {
+            temperature: self.temperature,
+            top_p: self.top_p,
+            max_output_tokens: self.max_output_tokens,
+            stop: self.stop.clone(),
+            tool_choice: None,
+            response_format: None,
+            reasoning: self.reasoning,
+        };
+        let mut resolved = params.with_defaults(&defaults);
+        if let Some(max_temperature) = self.max_temperature {
+            resolved.temperature = resolved.temperature.map(|t| t.min(max_temperature));
+        }
+        if let Some(limit) = self.max_output_tokens_limit {
+            resolved.max_output_tokens = Some(resolved.max_output_tokens.map_or(limit, |n| n.min(limit)));
+        }
+        resolved
+    }
 }
 
+/// Upstream providers. The built-in providers (groq, google, anthropic, openai, custom) can be
+/// tuned by name; any other table (e.g. `[providers.mistral]`) declares an additional provider,
+/// routed after the built-ins.
 #[derive(Debug, Clone, serde::Deserialize, Default)]
 pub struct GatewayProvidersSection {
     pub groq: Option<GatewayProviderEntry>,
     pub google: Option<GatewayProviderEntry>,
+    pub anthropic: Option<GatewayProviderEntry>,
     pub openai: Option<GatewayProviderEntry>,
     pub custom: Option<GatewayProviderEntry>,
+    #[serde(flatten)]
+    pub additional: BTreeMap<String, GatewayProviderEntry>,
 }
 
 #[derive(Debug, Clone, serde::Deserialize, Default)]
 pub struct GatewayProviderEntry {
     pub api_url: Option<String>,
     pub api_key: Option<String>,
+    /// Name of the environment variable holding the API key
+    pub api_key_env: Option<String>,
     pub default_model: Option<String>,
     pub recommended_models: Option<Vec<String>>,
+    /// Wire protocol: "openai_chat" (default), "openai_responses", "gemini" or "anthropic"
+    pub protocol: Option<ProviderProtocol>,
+    /// Model id patterns routed to this provider, `*` is a wildcard (e.g. "mistral-*")
+    pub model_patterns: Option<Vec<String>>,
+    /// Prefixes removed from the model id before calling upstream (e.g. "mistral/")
+    pub strip_prefixes: Option<Vec<String>>,
+    pub requires_api_key: Option<bool>,
+    /// Serve models that no other provider matched
+    pub catch_all: Option<bool>,
+    /// Gemini content filter thresholds, as `{ category, threshold }` tables
+    pub safety_settings: Option<Vec<SafetySetting>>,
+    /// Retries and timeouts of upstream calls, e.g. `{ max_attempts = 3, request_timeout_ms = 20000 }`
+    pub retry: Option<RetryConfig>,
 }
 
-/// Multi-model gateway backend capable of routing to Google Gemini, Groq, OpenAI, or local models
+/// Multi-model gateway backend routing each turn to a provider from its `ProviderRegistry`
 pub struct MultiModelGatewayBackend {
     pub client: reqwest::Client,
-    pub gemini_api_key: Option<String>,
-    pub groq_api_key: Option<String>,
-    pub openai_api_key: Option<String>,
-    pub custom_endpoint: Option<String>,
-    pub groq_url: String,
-    pub gemini_url: String,
-    pub openai_url: String,
     pub default_model: Option<String>,
-    pub groq_models: Vec<String>,
-    pub google_models: Vec<String>,
-    pub openai_models: Vec<String>,
-    pub custom_models: Vec<String>,
+    pub registry: ProviderRegistry,
+    /// Fallback chains keyed by requested model id
+    pub fallbacks: HashMap<String, Vec<String>>,
+    /// Whether to answer with the mock echo backend when the whole chain failed
+    pub mock_fallback: bool,
+    /// Generation defaults and limits keyed by model id (`"*"` for any model)
+    pub generation: HashMap<String, GatewayGenerationEntry>,
+    /// Metadata for the models listed on `/v1/models`
+    pub catalog: ModelCatalog,
 }
 
-fn get_env_var(key: &str) -> Option<String> {
+pub(crate) fn get_env_var(key: &str) -> Option<String> {
     if let Ok(v) = std::env::var(key) {
         let trimmed = v.trim();
         if !trimmed.is_empty() && !trimmed.starts_with("your_") && !trimmed.starts_with('<') {
@@ -193,12 +580,12 @@ fn get_env_var(key: &str) -> Option<String> {
                 if trimmed.is_empty() || trimmed.starts_with('#') {
                     continue;
                 }
-                if let Some((k, v)) = trimmed.split_once('=') {
-                    if k.trim() == key {
-                        let val = v.trim().trim_matches('"').trim_matches('\'');
-                        if !val.is_empty() && !val.starts_with("your_") && !val.starts_with('<') {
-                            return Some(val.to_string());
-                        }
+                if let Some((k, v)) = trimmed.split_once('=')
+                    && k.trim() == key
+                {
+                    let val = v.trim().trim_matches('"').trim_matches('\'');
+                    if !val.is_empty() && !val.starts_with("your_") && !val.starts_with('<') {
+                        return Some(val.to_string());
                     }
                 }
             }
@@ -215,115 +602,62 @@ impl Default for MultiModelGatewayBackend {
 
 impl MultiModelGatewayBackend {
     pub fn from_env() -> Self {
-        let gemini_api_key = get_env_var("GEMINI_API_KEY")
-            .or_else(|| get_env_var("LLM_GEMINI_API_KEY"));
-        let groq_api_key = get_env_var("GROQ_API_KEY")
-            .or_else(|| get_env_var("LLM_GROQ_API_KEY"))
-            .or_else(|| get_env_var("LLM_API_KEY"));
-        let openai_api_key = get_env_var("OPENAI_API_KEY");
-        let custom_endpoint = get_env_var("SWARM_LLM_URL");
+        let client = http_client(&HttpClientConfig::default());
+        let registry = ProviderRegistry::from_specs(builtin_provider_specs(), &client);
 
         Self {
-            client: reqwest::Client::builder()
-                .timeout(std::time::Duration::from_secs(45))
-                .build()
-                .unwrap_or_else(|_| reqwest::Client::new()),
-            gemini_api_key,
-            groq_api_key,
-            openai_api_key,
-            custom_endpoint,
-            groq_url: "https://api.groq.com/openai/v1/chat/completions".to_string(),
-            gemini_url: "https://generativelanguage.googleapis.com/v1beta/models".to_string(),
-            openai_url: "https://api.openai.com/v1/chat/completions".to_string(),
+            client,
             default_model: None,
-            groq_models: vec![
-                "groq/llama-3.3-70b-versatile".to_string(),
-                "openai/gpt-oss-20b".to_string(),
-                "qwen/qwen3-32b".to_string(),
-                "llama-3.3-70b-versatile".to_string(),
-                "llama-3.1-8b-instant".to_string(),
-            ],
-            google_models: vec![
-                "gemini-2.0-flash".to_string(),
-                "gemini-1.5-pro".to_string(),
-                "gemini-1.5-flash".to_string(),
-                "google/gemini-2.0-flash".to_string(),
-            ],
-            openai_models: vec![
-                "gpt-4o".to_string(),
-                "gpt-4o-mini".to_string(),
-                "gpt-4-turbo".to_string(),
-                "gpt-3.5-turbo".to_string(),
-            ],
-            custom_models: vec![
-                "llama3.2:latest".to_string(),
-                "mistral:latest".to_string(),
-                "deepseek-r1:8b".to_string(),
-                "qwen2.5:latest".to_string(),
-            ],
+            registry,
+            fallbacks: HashMap::new(),
+            mock_fallback: false,
+            generation: HashMap::new(),
+            catalog: ModelCatalog::default(),
         }
     }
 
     pub fn from_config(config: &GatewayConfigFile) -> Self {
-        let mut backend = Self::from_env();
-
-        if let Some(models) = &config.models {
-            if let Some(dm) = &models.default_model {
-                backend.default_model = Some(dm.clone());
-            }
-        }
+        let upstream_http = config.server.as_ref().and_then(|server| server.upstream_http.clone());
+        let client = http_client(&upstream_http.unwrap_or_default());
+        let mut specs = builtin_provider_specs();
 
         if let Some(providers) = &config.providers {
-            if let Some(groq) = &providers.groq {
-                if let Some(url) = &groq.api_url {
-                    backend.groq_url = url.clone();
-                }
-                if let Some(key) = &groq.api_key {
-                    if !key.is_empty() && !key.starts_with('<') {
-                        backend.groq_api_key = Some(key.clone());
-                    }
-                }
-                if let Some(models) = &groq.recommended_models {
-                    backend.groq_models = models.clone();
-                }
-            }
-            if let Some(google) = &providers.google {
-                if let Some(url) = &google.api_url {
-                    backend.gemini_url = url.clone();
-                }
-                if let Some(key) = &google.api_key {
-                    if !key.is_empty() && !key.starts_with('<') {
-                        backend.gemini_api_key = Some(key.clone());
-                    }
-                }
-                if let Some(models) = &google.recommended_models {
-                    backend.google_models = models.clone();
-                }
-            }
-            if let Some(openai) = &providers.openai {
-                if let Some(url) = &openai.api_url {
-                    backend.openai_url = url.clone();
-                }
-                if let Some(key) = &openai.api_key {
-                    if !key.is_empty() && !key.starts_with('<') {
-                        backend.openai_api_key = Some(key.clone());
+            let builtin_entries = [
+                ("groq", &providers.groq),
+                ("google", &providers.google),
+                ("anthropic", &providers.anthropic),
+                ("openai", &providers.openai),
+                ("custom", &providers.custom),
+            ];
+            let entries = builtin_entries
+                .into_iter()
+                .filter_map(|(name, entry)| entry.as_ref().map(|e| (name, e)))
+                .chain(providers.additional.iter().map(|(name, e)| (name.as_str(), e)));
+
+            for (name, entry) in entries {
+                match specs.iter_mut().find(|s| s.name == name) {
+                    Some(spec) => {
+                        spec.apply_entry(entry);
+                        // An explicitly configured local endpoint also serves unrouted models
+                        if name == "custom" && entry.api_url.is_some() && entry.catch_all.is_none() {
+                            spec.catch_all = true;
+                        }
                     }
-                }
-                if let Some(models) = &openai.recommended_models {
-                    backend.openai_models = models.clone();
-                }
-            }
-            if let Some(custom) = &providers.custom {
-                if let Some(url) = &custom.api_url {
-                    backend.custom_endpoint = Some(url.clone());
-                }
-                if let Some(models) = &custom.recommended_models {
-                    backend.custom_models = models.clone();
+                    None => specs.push(ProviderSpec::from_entry(name, entry)),
                 }
             }
         }
 
-        backend
+        let models = config.models.clone().unwrap_or_default();
+        Self {
+            registry: ProviderRegistry::from_specs(specs, &client),
+            client,
+            default_model: models.default_model,
+            fallbacks: models.fallbacks.unwrap_or_default(),
+            mock_fallback: models.mock_fallback.unwrap_or(false),
+            generation: models.generation.unwrap_or_default(),
+            catalog: ModelCatalog::new(models.catalog.unwrap_or_default()),
+        }
     }
 
     pub fn from_config_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
@@ -331,6 +665,67 @@ impl MultiModelGatewayBackend {
         let config: GatewayConfigFile = toml::from_str(&content)?;
         Ok(Self::from_config(&config))
     }
+
+    /// Register a provider implemented in code, replacing any provider with the same name
+    pub fn register_provider(&mut self, provider: Arc<dyn ModelProvider>) {
+        self.registry.register(provider);
+    }
+
+    fn requested_model<'a>(&'a self, model: Option<&'a str>) -> &'a str {
+        model
+            .or(self.default_model.as_deref())
+            .unwrap_or("groq/llama-3.3-70b-versatile")
+    }
+
+    /// Resolve the provider for a requested model (or the configured default model)
+    pub fn resolve(&self, model: Option<&str>) -> Result<ResolvedRoute, String> {
+        let route = self.registry.resolve(self.requested_model(model))?;
+        tracing::debug!(
+            "Gateway routed model '{}' to provider '{}' via {} (upstream model '{}')",
+            route.requested_model,
+            route.provider_name(),
+            route.rule,
+            route.upstream_model
+        );
+        Ok(route)
+    }
+
+    /// The requested model followed by its configured fallbacks, without duplicates
+    pub fn fallback_chain(&self, model: Option<&str>) -> Vec<String> {
+        let requested = self.requested_model(model).to_string();
+        let mut chain = vec![requested.clone()];
+        for fallback in self.fallbacks.get(&requested).into_iter().flatten() {
+            if !chain.contains(fallback) {
+                chain.push(fallback.clone());
+            }
+        }
+        chain
+    }
+
+    /// Parameters sent upstream for `model`: its configured defaults and limits applied,
+    /// then clamped to the ranges every provider accepts
+    pub fn generation_params(&self, model: &str, params: &GenerationParams) -> GenerationParams {
+        let mut resolved = match self.generation.get(model).or_else(|| self.generation.get("*")) {
+            Some(entry) => entry.apply(params),
+            None => params.clone(),
+        };
+        resolved.temperature = resolved.temperature.map(|t| t.clamp(0.0, 2.0));
+        resolved.top_p = resolved.top_p.map(|p| p.clamp(0.0, 1.0));
+        resolved
+    }
+
+    /// Error for a chain where no model produced output. Without any routable model the
+    /// model is reported unavailable, otherwise the last upstream error decides the status.
+    fn chain_exhausted(&self, requested: &str, failures: Vec<String>, last: Option<LlmError>, routed: bool) -> GatewayError {
+        if !routed {
+            return GatewayError::ModelUnavailable(failures.join("; "));
+        }
+        GatewayError::ChainExhausted {
+            model: requested.to_string(),
+            failures,
+            last,
+        }
+    }
 }
 
 #[async_trait::async_trait]
@@ -340,431 +735,209 @@ impl GatewayBackend for MultiModelGatewayBackend {
         session_id: &str,
         history: &[ResponseItem],
         model: Option<&str>,
-    ) -> Result<BackendTurnResult, String> {
-        let model_str = model
-            .or_else(|| self.default_model.as_deref())
-            .unwrap_or("groq/llama-3.3-70b-versatile");
-
-        // 1. Google Gemini routing via GoogleInteractionsAdapter
-        let is_gemini = self.google_models.iter().any(|m| m.eq_ignore_ascii_case(model_str))
-            || model_str.contains("gemini")
-            || model_str.starts_with("google/");
-
-        if is_gemini {
-            if let Some(key) = &self.gemini_api_key {
-                let gemini_req = llm_api::google_interactions::GoogleInteractionsAdapter::to_gemini_request(
-                    history,
-                    Some(session_id.to_string()),
-                ).map_err(|e| format!("Failed to build Gemini interaction request: {}", e))?;
-
-                let target_model = model_str.strip_prefix("google/").unwrap_or(model_str);
-                let base_url = self.gemini_url.trim_end_matches('/');
-                let url = format!("{}/{}:generateContent?key={}", base_url, target_model, key);
-
-                let res = self.client.post(&url)
-                    .json(&gemini_req)
-                    .send()
-                    .await
-                    .map_err(|e| format!("Gemini API request failed: {}", e))?;
-
-                if !res.status().is_success() {
-                    let err_text = res.text().await.unwrap_or_default();
-                    return Err(format!("Gemini API error: {}", err_text));
-                }
-
-                #[derive(serde::Deserialize)]
-                #[serde(rename_all = "camelCase")]
-                struct GeminiUsageMetadata {
-                    prompt_token_count: Option<u32>,
-                    candidates_token_count: Option<u32>,
-                    total_token_count: Option<u32>,
-                }
-
-                #[derive(serde::Deserialize)]
-                #[serde(rename_all = "camelCase")]
-                struct GeminiResponse {
-                    candidates: Option<Vec<GeminiCandidate>>,
-                    usage_metadata: Option<GeminiUsageMetadata>,
-                }
-                #[derive(serde::Deserialize)]
-                struct GeminiCandidate {
-                    content: Option<GeminiContent>,
-                }
-                #[derive(serde::Deserialize)]
-                struct GeminiContent {
-                    parts: Option<Vec<GeminiPartResponse>>,
-                }
-                #[derive(serde::Deserialize)]
-                #[serde(untagged)]
-                enum GeminiPartResponse {
-                    Text { text: String },
-                    FunctionCall { function_call: serde_json::Value },
+        tools: &[Tool],
+        params: &GenerationParams,
+    ) -> Result<BackendTurnResult, GatewayError> {
+        let chain = self.fallback_chain(model);
+        let mut failures = Vec::new();
+        let mut last_error = None;
+        let mut routed = false;
+
+        for candidate in &chain {
+            let route = match self.resolve(Some(candidate)) {
+                Ok(route) => route,
+                Err(err) => {
+                    failures.push(format!("{}: {}", candidate, err));
+                    continue;
                 }
+            };
+            routed = true;
 
-                let gemini_data: GeminiResponse = res.json().await
-                    .map_err(|e| format!("Failed to parse Gemini response: {}", e))?;
-
-                let mut output_items = Vec::new();
-                if let Some(candidates) = gemini_data.candidates {
-                    if let Some(first) = candidates.into_iter().next() {
-                        if let Some(content) = first.content {
-                            if let Some(parts) = content.parts {
-                                for part in parts {
-                                    match part {
-                                        GeminiPartResponse::Text { text } => {
-                                            output_items.push(ResponseItem::Message {
-                                                id: format!("resp_msg_{}", Uuid::new_v4()),
-                                                role: Role::Assistant,
-                                                content: vec![ContentPart::Text { text }],
-                                            });
-                                        }
-                                        GeminiPartResponse::FunctionCall { function_call } => {
-                                            let name = function_call.get("name").and_then(|v| v.as_str()).unwrap_or("unknown_tool").to_string();
-                                            let args = function_call.get("args").map(|v| v.to_string()).unwrap_or_default();
-                                            output_items.push(ResponseItem::FunctionCall {
-                                                id: format!("fc_{}", Uuid::new_v4()),
-                                                call_id: format!("call_{}", Uuid::new_v4()),
-                                                name,
-                                                arguments: args,
-                                            });
-                                        }
-                                    }
-                                }
-                            }
-                        }
+            let candidate_params = self.generation_params(candidate, params);
+            match route.provider.process_turn(session_id, history, &route.upstream_model, tools, &candidate_params).await {
+                Ok(mut result) if !result.items.is_empty() => {
+                    if candidate != &chain[0] {
+                        tracing::warn!("Model '{}' served by fallback '{}'", chain[0], candidate);
                     }
+                    result.model = Some(candidate.clone());
+                    return Ok(result);
                 }
-
-                if !output_items.is_empty() {
-                    let usage = gemini_data.usage_metadata.map(|u| BackendUsage {
-                        input_tokens: u.prompt_token_count.unwrap_or(0),
-                        output_tokens: u.candidates_token_count.unwrap_or(0),
-                        total_tokens: u.total_token_count.unwrap_or(0),
-                    });
-                    return Ok(BackendTurnResult {
-                        items: output_items,
-                        usage,
-                    });
+                Ok(_) => failures.push(format!("{}: provider '{}' returned no output", candidate, route.provider_name())),
+                Err(err) if err.is_retryable() => {
+                    tracing::warn!("Provider '{}' failed for model '{}': {}", route.provider_name(), candidate, err);
+                    failures.push(format!("{}: {}", candidate, err));
+                    last_error = Some(err);
                 }
-            } else {
-                return Err(format!("Gemini API key not found. Please set GEMINI_API_KEY to use model '{}'.", model_str));
+                Err(err) => return Err(err.into()),
             }
         }
 
-        // 2. OpenAI / Groq / Custom chat completion routing
-        let is_groq = self.groq_models.iter().any(|m| m.eq_ignore_ascii_case(model_str))
-            || model_str.starts_with("groq/")
-            || model_str == "openai/gpt-oss-20b"
-            || model_str.starts_with("qwen/");
-
-        let is_openai = self.openai_models.iter().any(|m| m.eq_ignore_ascii_case(model_str))
-            || model_str.starts_with("gpt-")
-            || model_str.starts_with("o1")
-            || model_str.starts_with("o3");
-
-        let is_custom = self.custom_models.iter().any(|m| m.eq_ignore_ascii_case(model_str))
-            || model_str.contains(':')
-            || model_str.starts_with("ollama/")
-            || model_str.starts_with("local/");
-
-        let (endpoint, api_key, target_model) = if is_groq && self.groq_api_key.is_some() {
-            (
-                self.groq_url.clone(),
-                self.groq_api_key.clone().unwrap(),
-                model_str.strip_prefix("groq/").unwrap_or(model_str).to_string(),
-            )
-        } else if is_openai && self.openai_api_key.is_some() {
-            (
-                self.openai_url.clone(),
-                self.openai_api_key.clone().unwrap(),
-                model_str.strip_prefix("openai/").unwrap_or(model_str).to_string(),
-            )
-        } else if is_custom {
-            (
-                self.custom_endpoint.clone().unwrap_or_else(|| "http://localhost:11434/v1/chat/completions".to_string()),
-                self.openai_api_key.clone().or_else(|| self.groq_api_key.clone()).unwrap_or_default(),
-                model_str.strip_prefix("ollama/").or_else(|| model_str.strip_prefix("local/")).unwrap_or(model_str).to_string(),
-            )
-        } else if let Some(groq_key) = &self.groq_api_key {
-            // Default to Groq when GROQ_API_KEY is available
-            (
-                self.groq_url.clone(),
-                groq_key.clone(),
-                model_str.strip_prefix("groq/").unwrap_or(model_str).to_string(),
-            )
-        } else if let Some(openai_key) = &self.openai_api_key {
-            // Default to OpenAI when OPENAI_API_KEY is available
-            (
-                self.openai_url.clone(),
-                openai_key.clone(),
-                model_str.strip_prefix("openai/").unwrap_or(model_str).to_string(),
-            )
-        } else if let Some(custom_url) = &self.custom_endpoint {
-            // Fall back to custom / local endpoint
-            (
-                custom_url.clone(),
-                String::new(),
-                model_str.strip_prefix("ollama/").or_else(|| model_str.strip_prefix("local/")).unwrap_or(model_str).to_string(),
-            )
-        } else {
-            return Err(format!("No configured provider or API key found to handle model '{}'. Set GROQ_API_KEY, OPENAI_API_KEY, or SWARM_LLM_URL.", model_str));
-        };
+        let err = self.chain_exhausted(&chain[0], failures, last_error, routed);
+        if self.mock_fallback {
+            tracing::warn!("{}; answering with mock backend", err);
+            return SimpleGatewayBackend.process_turn(session_id, history, model, tools, params).await;
+        }
+        Err(err)
+    }
 
-        if !endpoint.is_empty() {
-            let mut messages = Vec::new();
-            for item in history {
-                match item {
-                    ResponseItem::Message { role, content, .. } => {
-                        let r = match role {
-                            Role::System => "system",
-                            Role::Assistant => "assistant",
-                            Role::Tool => "tool",
-                            Role::User => "user",
-                        };
-                        let text = content.iter().filter_map(|p| match p {
-                            ContentPart::Text { text } => Some(text.clone()),
-                            _ => None,
-                        }).collect::<Vec<_>>().join("\n");
-                        messages.push(llm_api::chat::Message {
-                            role: r.to_string(),
-                            content: Some(text),
-                            tool_call_id: None,
-                            tool_calls: None,
-                        });
-                    }
-                    ResponseItem::FunctionCall { call_id, name, arguments, .. } => {
-                        messages.push(llm_api::chat::Message {
-                            role: "assistant".to_string(),
-                            content: None,
-                            tool_call_id: None,
-                            tool_calls: Some(vec![llm_api::chat::ToolCall {
-                                id: call_id.clone(),
-                                r#type: "function".to_string(),
-                                function: llm_api::chat::FunctionCall {
-                                    name: name.clone(),
-                                    arguments: arguments.clone(),
-                                },
-                            }]),
-                        });
-                    }
-                    ResponseItem::FunctionCallOutput { call_id, output, .. } => {
-                        messages.push(llm_api::chat::Message {
-                            role: "tool".to_string(),
-                            content: Some(output.clone()),
-                            tool_call_id: Some(call_id.clone()),
-                            tool_calls: None,
-                        });
-                    }
-                    ResponseItem::Reasoning { .. } => {}
+    /// Streaming fails over only while nothing has been sent to the client yet.
+    async fn process_turn_stream(
+        &self,
+        session_id: &str,
+        history: &[ResponseItem],
+        model: Option<&str>,
+        tools: &[Tool],
+        params: &GenerationParams,
+        tx: tokio::sync::mpsc::Sender<StreamDelta>,
+    ) -> Result<BackendStreamResult, GatewayError> {
+        let chain = self.fallback_chain(model);
+        let mut failures = Vec::new();
+        let mut last_error = None;
+        let mut routed = false;
+
+        for candidate in &chain {
+            let route = match self.resolve(Some(candidate)) {
+                Ok(route) => route,
+                Err(err) => {
+                    failures.push(format!("{}: {}", candidate, err));
+                    continue;
                 }
-            }
-
-            let llm = llm_api::chat::ChatLlmInteraction::new(endpoint, target_model.clone(), api_key);
-            let chat_req = llm_api::chat::ChatCompletionRequest {
-                model: target_model,
-                messages,
-                temperature: Some(0.7),
-                max_tokens: None,
-                top_p: None,
-                stop: None,
-                stream: None,
-                tools: None,
-                tool_choice: None,
             };
-
-            let res = llm.call_chat_completions_v2(&chat_req).await
-                .map_err(|e| format!("Chat completions call failed: {}", e))?;
-
-            let usage = Some(BackendUsage {
-                input_tokens: res.usage.prompt_tokens,
-                output_tokens: res.usage.completion_tokens,
-                total_tokens: res.usage.total_tokens,
-            });
-
-            if let Some(choice) = res.choices.into_iter().next() {
-                let mut output_items = Vec::new();
-                if let Some(tool_calls) = choice.message.tool_calls {
-                    for tc in tool_calls {
-                        output_items.push(ResponseItem::FunctionCall {
-                            id: format!("fc_{}", Uuid::new_v4()),
-                            call_id: tc.id,
-                            name: tc.function.name,
-                            arguments: tc.function.arguments,
-                        });
+            routed = true;
+
+            // Forward through a per-attempt channel to know whether the client already received data
+            let (attempt_tx, mut attempt_rx) = tokio::sync::mpsc::channel::<StreamDelta>(64);
+            let client_tx = tx.clone();
+            let forwarder = tokio::spawn(async move {
+                let mut forwarded = 0usize;
+                while let Some(delta) = attempt_rx.recv().await {
+                    forwarded += 1;
+                    if client_tx.send(delta).await.is_err() {
+                        break;
                     }
                 }
-                if let Some(content) = choice.message.content {
-                    if !content.is_empty() {
-                        output_items.push(ResponseItem::Message {
-                            id: format!("resp_msg_{}", Uuid::new_v4()),
-                            role: Role::Assistant,
-                            content: vec![ContentPart::Text { text: content }],
-                        });
+                forwarded
+            });
+
+            let candidate_params = self.generation_params(candidate, params);
+            let result = route
+                .provider
+                .process_turn_stream(session_id, history, &route.upstream_model, tools, &candidate_params, attempt_tx)
+                .await;
+            let forwarded = forwarder.await.unwrap_or(0);
+
+            match result {
+                Ok(usage) => {
+                    if candidate != &chain[0] {
+                        tracing::warn!("Streaming model '{}' served by fallback '{}'", chain[0], candidate);
                     }
+                    return Ok(BackendStreamResult { usage, model: Some(candidate.clone()) });
                 }
-                if !output_items.is_empty() {
-                    return Ok(BackendTurnResult {
-                        items: output_items,
-                        usage,
-                    });
+                Err(err) if err.is_retryable() && forwarded == 0 => {
+                    tracing::warn!("Provider '{}' failed for streaming model '{}': {}", route.provider_name(), candidate, err);
+                    failures.push(format!("{}: {}", candidate, err));
+                    last_error = Some(err);
                 }
+                Err(err) => return Err(err.into()),
             }
         }
 
-        // Default mock fallback
-        SimpleGatewayBackend.process_turn(session_id, history, model).await
+        let err = self.chain_exhausted(&chain[0], failures, last_error, routed);
+        if self.mock_fallback {
+            tracing::warn!("{}; streaming mock backend", err);
+            return SimpleGatewayBackend.process_turn_stream(session_id, history, model, tools, params, tx).await;
+        }
+        Err(err)
     }
 
-    async fn process_turn_stream(
-        &self,
-        session_id: &str,
-        history: &[ResponseItem],
-        model: Option<&str>,
-        tx: tokio::sync::mpsc::Sender<String>,
-    ) -> Result<Option<BackendUsage>, String> {
-        let model_str = model
-            .or_else(|| self.default_model.as_deref())
-            .unwrap_or("groq/llama-3.3-70b-versatile");
-
-        let is_gemini = self.google_models.iter().any(|m| m.eq_ignore_ascii_case(model_str))
-            || model_str.contains("gemini")
-            || model_str.starts_with("google/");
-
-        if is_gemini {
-            // Fallback for Gemini to generate items and stream as response.item
-            let result = self.process_turn(session_id, history, model).await?;
-            for item in &result.items {
-                let json_str = serde_json::to_string(item).unwrap_or_default();
-                let _ = tx.send(json_str).await;
-            }
-            let _ = tx.send("[DONE]".to_string()).await;
-            return Ok(result.usage);
-        }
+    fn list_models(&self) -> Vec<ModelObject> {
+        self.catalog.list(&self.registry)
+    }
 
-        let is_groq = self.groq_models.iter().any(|m| m.eq_ignore_ascii_case(model_str))
-            || model_str.starts_with("groq/")
-            || model_str == "openai/gpt-oss-20b"
-            || model_str.starts_with("qwen/");
-
-        let is_openai = self.openai_models.iter().any(|m| m.eq_ignore_ascii_case(model_str))
-            || model_str.starts_with("gpt-")
-            || model_str.starts_with("o1")
-            || model_str.starts_with("o3");
-
-        let is_custom = self.custom_models.iter().any(|m| m.eq_ignore_ascii_case(model_str))
-            || model_str.contains(':')
-            || model_str.starts_with("ollama/")
-            || model_str.starts_with("local/");
-
-        let (endpoint, api_key, target_model) = if is_groq && self.groq_api_key.is_some() {
-            (
-                self.groq_url.clone(),
-                self.groq_api_key.clone().unwrap(),
-                model_str.strip_prefix("groq/").unwrap_or(model_str).to_string(),
-            )
-        } else if is_openai && self.openai_api_key.is_some() {
-            (
-                self.openai_url.clone(),
-                self.openai_api_key.clone().unwrap(),
-                model_str.strip_prefix("openai/").unwrap_or(model_str).to_string(),
-            )
-        } else if is_custom {
-            (
-                self.custom_endpoint.clone().unwrap_or_else(|| "http://localhost:11434/v1/chat/completions".to_string()),
-                self.openai_api_key.clone().or_else(|| self.groq_api_key.clone()).unwrap_or_default(),
-                model_str.strip_prefix("ollama/").or_else(|| model_str.strip_prefix("local/")).unwrap_or(model_str).to_string(),
-            )
-        } else if let Some(groq_key) = &self.groq_api_key {
-            (
-                self.groq_url.clone(),
-                groq_key.clone(),
-                model_str.strip_prefix("groq/").unwrap_or(model_str).to_string(),
-            )
-        } else if let Some(openai_key) = &self.openai_api_key {
-            (
-                self.openai_url.clone(),
-                openai_key.clone(),
-                model_str.strip_prefix("openai/").unwrap_or(model_str).to_string(),
-            )
-        } else if let Some(custom_url) = &self.custom_endpoint {
-            (
-                custom_url.clone(),
-                String::new(),
-                model_str.strip_prefix("ollama/").or_else(|| model_str.strip_prefix("local/")).unwrap_or(model_str).to_string(),
-            )
-        } else {
-            return Err(format!("No configured provider found for streaming model '{}'.", model_str));
-        };
+    fn describe_model(&self, id: &str) -> Option<ModelObject> {
+        self.catalog.describe(&self.registry, id)
+    }
 
-        let mut messages = Vec::new();
-        for item in history {
-            match item {
-                ResponseItem::Message { role, content, .. } => {
-                    let r = match role {
-                        Role::System => "system",
-                        Role::Assistant => "assistant",
-                        Role::Tool => "tool",
-                        Role::User => "user",
-                    };
-                    let text = content.iter().filter_map(|p| match p {
-                        ContentPart::Text { text } => Some(text.clone()),
-                        _ => None,
-                    }).collect::<Vec<_>>().join("\n");
-                    messages.push(llm_api::chat::Message {
-                        role: r.to_string(),
-                        content: Some(text),
-                        tool_call_id: None,
-                        tool_calls: None,
-                    });
-                }
-                ResponseItem::FunctionCall { call_id, name, arguments, .. } => {
-                    messages.push(llm_api::chat::Message {
-                        role: "assistant".to_string(),
-                        content: None,
-                        tool_call_id: None,
-                        tool_calls: Some(vec![llm_api::chat::ToolCall {
-                            id: call_id.clone(),
-                            r#type: "function".to_string(),
-                            function: llm_api::chat::FunctionCall {
-                                name: name.clone(),
-                                arguments: arguments.clone(),
-                            },
-                        }]),
-                    });
-                }
-                ResponseItem::FunctionCallOutput { call_id, output, .. } => {
-                    messages.push(llm_api::chat::Message {
-                        role: "tool".to_string(),
-                        content: Some(output.clone()),
-                        tool_call_id: Some(call_id.clone()),
-                        tool_calls: None,
-                    });
-                }
-                ResponseItem::Reasoning { .. } => {}
-            }
+    /// The smallest window of the fallback chain, as any model of it may serve the turn
+    fn context_window(&self, model: &str) -> Option<u32> {
+        self.fallback_chain(Some(model))
+            .iter()
+            .filter_map(|candidate| self.describe_model(candidate)?.context_window)
+            .min()
+    }
+
+    fn default_model(&self) -> Option<&str> {
+        Some(self.requested_model(None))
+    }
+
+    fn forget_session(&self, session_id: &str) {
+        for provider in self.registry.providers() {
+            provider.forget_session(session_id);
         }
+    }
+}
 
-        let llm = llm_api::chat::ChatLlmInteraction::new(endpoint, target_model.clone(), api_key);
-        let chat_req = llm_api::chat::ChatCompletionRequest {
-            model: target_model,
-            messages,
-            temperature: Some(0.7),
-            max_tokens: None,
-            top_p: None,
-            stop: None,
-            stream: Some(true),
-            tools: None,
-            tool_choice: None,
-        };
+/// Summarizes dropped history turns with a backend turn
+pub struct BackendSummarizer {
+    backend: Arc<dyn GatewayBackend>,
+    model: Option<String>,
+}
 
-        let usage = llm.call_chat_completions_stream(&chat_req, tx).await
-            .map_err(|e| format!("Streaming chat completions failed: {}", e))?;
+impl BackendSummarizer {
+    pub fn new(backend: Arc<dyn GatewayBackend>, model: Option<String>) -> Self {
+        Self { backend, model }
+    }
+}
 
-        Ok(usage.map(|u| BackendUsage {
-            input_tokens: u.prompt_tokens,
-            output_tokens: u.completion_tokens,
-            total_tokens: u.total_tokens,
-        }))
+#[async_trait::async_trait]
+impl HistorySummarizer for BackendSummarizer {
+    async fn summarize(&self, previous: Option<&str>, items: &[ResponseItem]) -> anyhow::Result<String> {
+        let mut transcript = String::new();
+        if let Some(previous) = previous {
+            transcript.push_str(&format!("Summary so far:\n{}\n\nContinuation:\n", previous));
+        }
+        transcript.push_str(&render_transcript(items));
+        let prompt = vec![
+            ResponseItem::Message {
+                id: format!("msg_{}", Uuid::new_v4()),
+                role: Role::System,
+                content: vec![ContentPart::Text {
+                    text: "Summarize the conversation below for the assistant continuing it. Keep facts, \
+                           decisions, tool results and open questions; be concise."
+                        .to_string(),
+                }],
+            },
+            ResponseItem::Message {
+                id: format!("msg_{}", Uuid::new_v4()),
+                role: Role::User,
+                content: vec![ContentPart::Text { text: transcript }],
+            },
+        ];
+        let params = GenerationParams {
+            max_output_tokens: Some(SUMMARY_MAX_TOKENS as u32),
+            ..GenerationParams::default()
+        };
+        let session_id = format!("summary_{}", Uuid::new_v4());
+        let result = self.backend.process_turn(&session_id, &prompt, self.model.as_deref(), &[], &params).await;
+        self.backend.forget_session(&session_id);
+        let result = result?;
+        let summary: String = result
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                ResponseItem::Message { content, .. } => Some(content),
+                _ => None,
+            })
+            .flatten()
+            .filter_map(|part| match part {
+                ContentPart::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        if summary.trim().is_empty() {
+            anyhow::bail!("the backend returned an empty summary");
+        }
+        Ok(summary)
     }
 }
 
@@ -773,10 +946,13 @@ impl GatewayBackend for MultiModelGatewayBackend {
 pub struct GatewayState {
     pub session_store: Arc<dyn SessionStoreApi>,
     pub backend: Arc<dyn GatewayBackend>,
+    pub media_limits: Arc<MediaLimits>,
+    pub history_policy: Arc<HistoryPolicy>,
 }
 
 pub struct GatewayServer {
     state: GatewayState,
+    admin_api_key: Option<Arc<str>>,
 }
 
 impl GatewayServer {
@@ -785,19 +961,51 @@ impl GatewayServer {
             state: GatewayState {
                 session_store,
                 backend,
+                media_limits: Arc::new(MediaLimits::default()),
+                history_policy: Arc::new(HistoryPolicy::default()),
             },
+            admin_api_key: None,
         }
     }
 
+    /// Limit the history sent to the backend with `policy`; the full history is sent by default
+    pub fn with_history_policy(mut self, policy: HistoryPolicy) -> Self {
+        self.state.history_policy = Arc::new(policy);
+        self
+    }
+
+    /// Enforce `limits` on the media parts of requests instead of the defaults
+    pub fn with_media_limits(mut self, limits: MediaLimits) -> Self {
+        self.state.media_limits = Arc::new(limits);
+        self
+    }
+
+    /// Serve the session management endpoints to requests bearing `key`; they are disabled by
+    /// default and stay so for an empty key
+    pub fn with_admin_api_key(mut self, key: impl Into<String>) -> Self {
+        let key = key.into();
+        self.admin_api_key = (!key.is_empty()).then(|| Arc::from(key));
+        self
+    }
+
     pub fn with_default_backend(session_store: Arc<dyn SessionStoreApi>) -> Self {
         Self::new(session_store, Arc::new(SimpleGatewayBackend))
     }
 
     /// Build the Axum router for the gateway
     pub fn router(&self) -> Router {
-        Router::new()
+        let mut router = Router::new()
             .route("/v1/responses", post(handle_responses))
             .route("/v1/chat/completions", post(handle_chat_completions))
+            .route("/v1/models", get(handle_list_models))
+            // Model ids may contain slashes (e.g. "groq/llama-3.3-70b-versatile")
+            .route("/v1/models/{*id}", get(handle_get_model))
+            .route("/health", get(handle_health));
+        if let Some(admin_api_key) = &self.admin_api_key {
+            router = router.merge(session_api::routes(admin_api_key.clone()));
+        }
+        router
+            .layer(DefaultBodyLimit::max(self.state.media_limits.max_request_bytes))
             .with_state(self.state.clone())
     }
 
@@ -819,10 +1027,15 @@ async fn handle_responses(
     Json(payload): Json<CreateResponseRequest>,
 ) -> Response {
     let is_stream = payload.stream.unwrap_or(false);
-    let session = state
+    let params = GenerationParams::from(&payload);
+    let session = match state
         .session_store
         .resolve_session(payload.previous_response_id.as_deref())
-        .await;
+        .await
+    {
+        Ok(session) => session,
+        Err(err) => return err.into_response(),
+    };
 
     // 1. Normalize input into ResponseItem(s)
     let input_items: Vec<ResponseItem> = match payload.input {
@@ -834,109 +1047,158 @@ async fn handle_responses(
         Some(ResponsesInput::Items(items)) => items,
         None => vec![],
     };
+    if let Err(err) = state.media_limits.check_items(&input_items, "input") {
+        return err.into_response();
+    }
 
     // 2. Append input items to session
-    if !input_items.is_empty() {
-        state
-            .session_store
-            .append_items(&session.id, &input_items)
-            .await;
+    if !input_items.is_empty()
+        && let Err(err) = state.session_store.append_items(&session.id, &input_items).await
+    {
+        return err.into_response();
     }
 
-    // 3. Retrieve full history
-    let history = state.session_store.get_history(&session.id).await;
+    let input_item_ids: Vec<String> = input_items.iter().map(|item| item.id().to_string()).collect();
+
+    // 3. Retrieve the history, fitted to the model's context window
+    let model_name = payload
+        .model
+        .as_deref()
+        .or(state.backend.default_model())
+        .unwrap_or("default-swarm-model")
+        .to_string();
+    let history = match state.session_store.get_history(&session.id).await {
+        Ok(history) => history,
+        Err(err) => return err.into_response(),
+    };
+    let context_window = state.backend.context_window(&model_name);
+    let token_budget = state.history_policy.token_budget(context_window, params.max_output_tokens);
+    let history = state.history_policy.apply(history, token_budget).await;
 
     // 4. Process through backend
-    let model_name = payload.model.clone().unwrap_or_else(|| "default-swarm-model".to_string());
+    let tools: Vec<Tool> = payload.tools.iter().flatten().map(Tool::from).collect();
 
     if is_stream {
-        let (tx, mut rx) = tokio::sync::mpsc::channel::<String>(64);
+        let (event_tx, mut event_rx) = tokio::sync::mpsc::channel::<ResponseStreamEvent>(64);
         let backend = state.backend.clone();
-        let session_id_clone = session.id.clone();
-        let history_clone = history.clone();
-        let model_clone = payload.model.clone();
+        let session_store = state.session_store.clone();
+        let session_id = session.id.clone();
+        let model = payload.model.clone();
+        let input_item_ids = input_item_ids.clone();
+
+        // The response id is announced by response.created so clients can chain before the stream ends
+        let mut builder = ResponseStreamBuilder::new(format!("resp_{}", Uuid::new_v4()), model_name);
+        let _ = event_tx.send(builder.created()).await;
 
+        // The turn runs to completion and is stored even if the client disconnects mid-stream
         tokio::spawn(async move {
-            let _ = backend.process_turn_stream(
-                &session_id_clone,
-                &history_clone,
-                model_clone.as_deref(),
-                tx,
-            ).await;
+            let (tx, mut rx) = tokio::sync::mpsc::channel::<StreamDelta>(64);
+            let turn = backend.process_turn_stream(&session_id, &history, model.as_deref(), &tools, &params, tx);
+            let forward = async {
+                while let Some(delta) = rx.recv().await {
+                    for event in builder.push(delta) {
+                        let _ = event_tx.send(event).await;
+                    }
+                }
+            };
+            let (result, _) = tokio::join!(turn, forward);
+
+            let terminal_events = match result {
+                Ok(BackendStreamResult { usage, model }) => {
+                    if let Some(model) = model {
+                        builder.set_model(model);
+                    }
+                    let usage = usage.map(|u| ResponseUsage {
+                        input_tokens: u.input_tokens,
+                        output_tokens: u.output_tokens,
+                        total_tokens: u.total_tokens,
+                    });
+                    let response = StoredResponse {
+                        id: builder.response_id().to_string(),
+                        session_id: session_id.clone(),
+                        created: builder.created_at(),
+                        model: builder.model().to_string(),
+                        input_item_ids,
+                        output_item_ids: builder.output().iter().map(|item| item.id().to_string()).collect(),
+                        usage: usage.clone(),
+                    };
+                    let stored = session_store.save_turn(response, builder.output()).await;
+                    match stored {
+                        Ok(()) => builder.finish(usage),
+                        // A response that cannot be continued must not be reported as completed
+                        Err(err) => vec![builder.fail("session_store_unavailable", err.to_string())],
+                    }
+                }
+                Err(err) => vec![builder.fail(err.code(), err.to_string())],
+            };
+            for event in terminal_events {
+                let _ = event_tx.send(event).await;
+            }
         });
 
         let stream = async_stream::stream! {
-            while let Some(chunk) = rx.recv().await {
-                if chunk == "[DONE]" {
-                    yield Ok::<_, Infallible>(Event::default().data("[DONE]"));
-                    break;
-                }
-                yield Ok::<_, Infallible>(Event::default().event("response.item").data(chunk));
+            while let Some(event) = event_rx.recv().await {
+                yield Ok::<_, Infallible>(response_event(&event));
             }
+            yield Ok(Event::default().data("[DONE]"));
         };
         Sse::new(stream).into_response()
     } else {
         let turn_result = match state
             .backend
-            .process_turn(&session.id, &history, payload.model.as_deref())
+            .process_turn(&session.id, &history, payload.model.as_deref(), &tools, &params)
             .await
         {
             Ok(res) => res,
-            Err(err) => {
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(serde_json::json!({ "error": err })),
-                )
-                    .into_response();
-            }
+            Err(err) => return err.into_response(),
         };
 
         let output_items = turn_result.items;
+        let usage = turn_result.usage.map(|u| ResponseUsage {
+            input_tokens: u.input_tokens,
+            output_tokens: u.output_tokens,
+            total_tokens: u.total_tokens,
+        });
 
         // 5. Append output items to session and track parent response ID
-        let response_id = format!("resp_{}", Uuid::new_v4());
-        if let Some(last_item) = output_items.last() {
-            let last_id = match last_item {
-                ResponseItem::Message { id, .. } => id.clone(),
-                ResponseItem::Reasoning { id, .. } => id.clone(),
-                ResponseItem::FunctionCall { id, .. } => id.clone(),
-                ResponseItem::FunctionCallOutput { id, .. } => id.clone(),
-            };
-            state
-                .session_store
-                .set_parent_response_id(&session.id, last_id)
-                .await;
+        let response = StoredResponse {
+            id: format!("resp_{}", Uuid::new_v4()),
+            session_id: session.id.clone(),
+            created: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            model: turn_result.model.unwrap_or(model_name),
+            input_item_ids,
+            output_item_ids: output_items.iter().map(|item| item.id().to_string()).collect(),
+            usage: usage.clone(),
+        };
+        let (response_id, created, model) = (response.id.clone(), response.created, response.model.clone());
+        if let Err(err) = state.session_store.save_turn(response, &output_items).await {
+            return err.into_response();
         }
-        state
-            .session_store
-            .set_parent_response_id(&session.id, response_id.clone())
-            .await;
-        state
-            .session_store
-            .append_items(&session.id, &output_items)
-            .await;
-
-        let created = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
 
         let response_obj = ResponseObject {
             id: response_id,
             object: "response".to_string(),
             created,
-            model: model_name,
+            model,
             output: output_items,
-            usage: turn_result.usage.map(|u| ResponseUsage {
-                input_tokens: u.input_tokens,
-                output_tokens: u.output_tokens,
-                total_tokens: u.total_tokens,
-            }),
+            usage,
+            status: Some("completed".to_string()),
+            error: None,
         };
         Json(response_obj).into_response()
     }
 }
 
+/// Serialize an Open Responses stream event as a named SSE event
+fn response_event(event: &ResponseStreamEvent) -> Event {
+    Event::default()
+        .event(event.event_type())
+        .data(serde_json::to_string(event).unwrap_or_default())
+}
+
 // -------------------------------------------------------------------------------------------------
 // Route 2: POST /v1/chat/completions (Stateless Chat Completions Normalization)
 // -------------------------------------------------------------------------------------------------
@@ -947,10 +1209,11 @@ async fn handle_chat_completions(
 ) -> Response {
     let session_id = format!("stateless_chat_{}", Uuid::new_v4());
     let is_stream = payload.stream.unwrap_or(false);
+    let params = GenerationParams::from(&payload);
 
     // 1. Normalize OpenAI messages into internal ResponseItems
     let mut normalized_items = Vec::new();
-    for msg in payload.messages {
+    for (index, msg) in payload.messages.into_iter().enumerate() {
         let role = match msg.role.to_lowercase().as_str() {
             "system" => Role::System,
             "assistant" => Role::Assistant,
@@ -971,80 +1234,114 @@ async fn handle_chat_completions(
             normalized_items.push(ResponseItem::FunctionCallOutput {
                 id: format!("fco_{}", Uuid::new_v4()),
                 call_id: msg.tool_call_id.unwrap_or_else(|| "call_default".to_string()),
-                output: msg.content.unwrap_or_default(),
+                output: msg.content.map(|c| c.text()).unwrap_or_default(),
                 is_error: false,
             });
         } else if let Some(content) = msg.content {
+            let param = format!("messages[{}].content", index);
+            let checked = content
+                .to_content_parts()
+                .map_err(|message| MediaError::invalid(&param, message))
+                .and_then(|parts| state.media_limits.check_parts(&parts, &param).map(|_| parts));
+            let content = match checked {
+                Ok(parts) => parts,
+                Err(err) => return err.into_response(),
+            };
             normalized_items.push(ResponseItem::Message {
                 id: format!("msg_{}", Uuid::new_v4()),
                 role,
-                content: vec![ContentPart::Text { text: content }],
+                content,
             });
         }
     }
 
-    // 2. Append to session store
-    state
-        .session_store
-        .append_items(&session_id, &normalized_items)
-        .await;
-
+    // Chat completions are stateless: the session id only scopes the backend state of this call
+    // and the messages are never stored
     if is_stream {
-        let (tx, mut rx) = tokio::sync::mpsc::channel::<String>(64);
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<StreamDelta>(64);
         let backend = state.backend.clone();
         let session_id_clone = session_id.clone();
         let history_clone = normalized_items.clone();
         let model_clone = payload.model.clone();
-
-        tokio::spawn(async move {
-            let _ = backend.process_turn_stream(
+        let tools = payload.tools.clone().unwrap_or_default();
+        let cleanup_backend = state.backend.clone();
+
+        let mut normalizer = ChatChunkNormalizer::new(
+            model_clone.clone(),
+            payload.stream_options.as_ref().is_some_and(|o| o.include_usage),
+        );
+        let turn = tokio::spawn(async move {
+            backend.process_turn_stream(
                 &session_id_clone,
                 &history_clone,
                 Some(&model_clone),
+                &tools,
+                &params,
                 tx,
-            ).await;
+            ).await
         });
 
         let stream = async_stream::stream! {
-            while let Some(chunk) = rx.recv().await {
-                if chunk == "[DONE]" {
-                    yield Ok::<_, Infallible>(Event::default().data("[DONE]"));
-                    break;
+            while let Some(delta) = rx.recv().await {
+                for chunk in normalizer.push(delta) {
+                    yield Ok::<_, Infallible>(chat_chunk_event(&chunk));
+                }
+            }
+            match turn.await {
+                Ok(Ok(BackendStreamResult { usage, .. })) => {
+                    let usage = usage.map(|u| Usage {
+                        prompt_tokens: u.input_tokens,
+                        completion_tokens: u.output_tokens,
+                        total_tokens: u.total_tokens,
+                    });
+                    for chunk in normalizer.finish(usage) {
+                        yield Ok(chat_chunk_event(&chunk));
+                    }
+                }
+                Ok(Err(err)) => yield Ok(Event::default().data(err.error_body().to_string())),
+                Err(join_err) => {
+                    yield Ok(Event::default().data(serde_json::json!({ "error": join_err.to_string() }).to_string()))
                 }
-                yield Ok::<_, Infallible>(Event::default().data(chunk));
             }
+            cleanup_backend.forget_session(&session_id);
+            yield Ok(Event::default().data("[DONE]"));
         };
         Sse::new(stream).into_response()
     } else {
-        // 3. Process with backend
-        let turn_result = match state
+        // 2. Process with backend
+        let turn_result = state
             .backend
-            .process_turn(&session_id, &normalized_items, Some(&payload.model))
-            .await
-        {
+            .process_turn(
+                &session_id,
+                &normalized_items,
+                Some(&payload.model),
+                payload.tools.as_deref().unwrap_or_default(),
+                &params,
+            )
+            .await;
+        state.backend.forget_session(&session_id);
+        let turn_result = match turn_result {
             Ok(res) => res,
-            Err(err) => {
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(serde_json::json!({ "error": err })),
-                )
-                    .into_response();
-            }
+            Err(err) => return err.into_response(),
         };
 
         let output_items = turn_result.items;
 
-        // 4. Normalize ResponseItems back into standard ChatCompletionResponse
+        // 3. Normalize ResponseItems back into standard ChatCompletionResponse
         let created = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap_or_default()
             .as_secs();
 
         let mut response_text = String::new();
+        let mut reasoning_text = String::new();
         let mut tool_calls = Vec::new();
 
         for item in &output_items {
             match item {
+                ResponseItem::Reasoning { thought_process, .. } => {
+                    reasoning_text.push_str(thought_process);
+                }
                 ResponseItem::Message { content, .. } => {
                     for part in content {
                         if let ContentPart::Text { text } = part {
@@ -1088,13 +1385,14 @@ async fn handle_chat_completions(
             id: format!("chatcmpl-{}", Uuid::new_v4()),
             object: "chat.completion".to_string(),
             created,
-            model: payload.model,
+            model: turn_result.model.unwrap_or(payload.model),
             choices: vec![Choice {
                 index: 0,
                 message: ResponseMessage {
                     role: "assistant".to_string(),
                     content: content_opt,
                     tool_calls: tool_calls_opt,
+                    reasoning_content: (!reasoning_text.is_empty()).then_some(reasoning_text),
                 },
                 logprobs: None,
                 finish_reason,
@@ -1114,3 +1412,38 @@ async fn handle_chat_completions(
         Json(chat_response).into_response()
     }
 }
+
+fn chat_chunk_event(chunk: &ChatCompletionChunk) -> Event {
+    Event::default().data(serde_json::to_string(chunk).unwrap_or_default())
+}
+
+// -------------------------------------------------------------------------------------------------
+// Route 3: GET /v1/models (OpenAI-compatible model listing)
+// -------------------------------------------------------------------------------------------------
+
+async fn handle_list_models(State(state): State<GatewayState>) -> Response {
+    Json(ModelList {
+        object: "list".to_string(),
+        data: state.backend.list_models(),
+    })
+    .into_response()
+}
+
+async fn handle_get_model(State(state): State<GatewayState>, Path(id): Path<String>) -> Response {
+    match state.backend.describe_model(&id) {
+        Some(model) => Json(model).into_response(),
+        None => GatewayError::ModelUnavailable(format!("The model '{}' does not exist", id)).into_response(),
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// Route 4: GET /health (liveness and session store write counters for monitoring)
+// -------------------------------------------------------------------------------------------------
+
+async fn handle_health(State(state): State<GatewayState>) -> Response {
+    Json(serde_json::json!({
+        "status": "ok",
+        "session_store": state.session_store.stats(),
+    }))
+    .into_response()
+}
@@ -0,0 +1,210 @@
+//! Limits on media sent to the gateway.
+//!
+//! Images, audio and files are checked when a request enters the gateway, before anything is
+//! stored or forwarded: the MIME type must be allowed for its kind of part, inline data must be
+//! valid base64 within the size limit and URLs must be http(s) or `data:` URLs. Limits are set
+//! in the gateway configuration (`[media]`).
+
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+
+use agent_models::response_item::{ContentPart, ResponseItem};
+
+use crate::server::gateway_server::GatewayMediaSection;
+
+/// Size and type limits of the media parts of a request
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaLimits {
+    /// Largest decoded size of one inline part
+    pub max_part_bytes: usize,
+    /// Largest request body, base64 overhead included
+    pub max_request_bytes: usize,
+    pub image_types: Vec<String>,
+    pub audio_types: Vec<String>,
+    pub file_types: Vec<String>,
+}
+
+impl Default for MediaLimits {
+    fn default() -> Self {
+        let types = |types: &[&str]| types.iter().map(|t| t.to_string()).collect();
+        Self {
+            max_part_bytes: 20 * 1024 * 1024,
+            max_request_bytes: 64 * 1024 * 1024,
+            image_types: types(&["image/png", "image/jpeg", "image/gif", "image/webp"]),
+            audio_types: types(&["audio/wav", "audio/x-wav", "audio/mpeg", "audio/mp3"]),
+            file_types: types(&["application/pdf", "text/plain"]),
+        }
+    }
+}
+
+impl From<&GatewayMediaSection> for MediaLimits {
+    fn from(section: &GatewayMediaSection) -> Self {
+        let defaults = Self::default();
+        Self {
+            max_part_bytes: section.max_part_bytes.unwrap_or(defaults.max_part_bytes),
+            max_request_bytes: section.max_request_bytes.unwrap_or(defaults.max_request_bytes),
+            image_types: section.image_types.clone().unwrap_or(defaults.image_types),
+            audio_types: section.audio_types.clone().unwrap_or(defaults.audio_types),
+            file_types: section.file_types.clone().unwrap_or(defaults.file_types),
+        }
+    }
+}
+
+/// A media part the gateway refuses, answered as an OpenAI-style `invalid_request_error`
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("{message}")]
+pub struct MediaError {
+    pub status: StatusCode,
+    pub code: &'static str,
+    pub message: String,
+    /// Path of the offending part in the request, e.g. `input[0].content[1]`
+    pub param: String,
+}
+
+impl MediaError {
+    fn new(status: StatusCode, code: &'static str, param: &str, message: String) -> Self {
+        Self { status, code, message, param: param.to_string() }
+    }
+
+    pub fn invalid(param: &str, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "invalid_media", param, message.into())
+    }
+}
+
+impl IntoResponse for MediaError {
+    fn into_response(self) -> Response {
+        let body = serde_json::json!({
+            "error": {
+                "message": self.message,
+                "type": "invalid_request_error",
+                "param": self.param,
+                "code": self.code,
+            }
+        });
+        (self.status, Json(body)).into_response()
+    }
+}
+
+impl MediaLimits {
+    /// Check the content of every message of `items`, `param` naming the list in errors
+    pub fn check_items(&self, items: &[ResponseItem], param: &str) -> Result<(), MediaError> {
+        for (index, item) in items.iter().enumerate() {
+            if let ResponseItem::Message { content, .. } = item {
+                self.check_parts(content, &format!("{}[{}].content", param, index))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Check every part of one message's content
+    pub fn check_parts(&self, parts: &[ContentPart], param: &str) -> Result<(), MediaError> {
+        for (index, part) in parts.iter().enumerate() {
+            let param = format!("{}[{}]", param, index);
+            match part {
+                ContentPart::Text { .. } => {}
+                ContentPart::Image { media_type, data_base64 } => {
+                    self.check_inline("image", &self.image_types, media_type, data_base64, &param)?
+                }
+                ContentPart::Audio { media_type, data_base64 } => {
+                    self.check_inline("audio", &self.audio_types, media_type, data_base64, &param)?
+                }
+                ContentPart::ImageUrl { url, .. } => match ContentPart::parse_data_url(url) {
+                    Some((media_type, data)) => self.check_inline("image", &self.image_types, media_type, data, &param)?,
+                    None => check_remote_url(url, &param)?,
+                },
+                ContentPart::File { media_type, data_base64, url, .. } => match (data_base64, url) {
+                    (Some(data), _) => self.check_inline("file", &self.file_types, media_type, data, &param)?,
+                    (None, Some(url)) => {
+                        check_type("file", &self.file_types, media_type, &param)?;
+                        check_remote_url(url, &param)?
+                    }
+                    (None, None) => return Err(MediaError::invalid(&param, "file parts need data_base64 or url")),
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn check_inline(&self, kind: &str, allowed: &[String], media_type: &str, data: &str, param: &str) -> Result<(), MediaError> {
+        check_type(kind, allowed, media_type, param)?;
+        if !is_base64(data) {
+            return Err(MediaError::invalid(param, format!("{} data is not valid base64", kind)));
+        }
+        let size = decoded_len(data);
+        if size > self.max_part_bytes {
+            return Err(MediaError::new(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "media_too_large",
+                param,
+                format!("{} of {} bytes exceeds the limit of {} bytes", kind, size, self.max_part_bytes),
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn check_type(kind: &str, allowed: &[String], media_type: &str, param: &str) -> Result<(), MediaError> {
+    if allowed.iter().any(|t| t.eq_ignore_ascii_case(media_type)) {
+        return Ok(());
+    }
+    Err(MediaError::new(
+        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        "unsupported_media_type",
+        param,
+        format!("{} type '{}' is not supported, expected one of: {}", kind, media_type, allowed.join(", ")),
+    ))
+}
+
+fn check_remote_url(url: &str, param: &str) -> Result<(), MediaError> {
+    if url.starts_with("https://") || url.starts_with("http://") {
+        return Ok(());
+    }
+    Err(MediaError::invalid(param, "media URLs must be http(s) or base64 data: URLs"))
+}
+
+fn is_base64(data: &str) -> bool {
+    let data = data.trim_end_matches('=');
+    data.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'+' || b == b'/' || b == b'-' || b == b'_')
+}
+
+/// Size of the decoded payload of base64 `data`
+fn decoded_len(data: &str) -> usize {
+    let padding = data.bytes().rev().take_while(|&b| b == b'=').count();
+    (data.len() * 3 / 4).saturating_sub(padding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(media_type: &str, data: &str) -> ContentPart {
+        ContentPart::Image { media_type: media_type.to_string(), data_base64: data.to_string() }
+    }
+
+    #[test]
+    fn test_media_limits_check_type_size_and_urls() {
+        let limits = MediaLimits { max_part_bytes: 6, ..MediaLimits::default() };
+        let parts = vec![
+            ContentPart::Text { text: "look".to_string() },
+            image("image/png", "aGVsbG8="),
+            ContentPart::ImageUrl { url: "https://example.com/cat.png".to_string(), detail: None },
+        ];
+        assert_eq!(limits.check_parts(&parts, "input[0].content"), Ok(()));
+
+        let err = limits.check_parts(&[image("image/tiff", "aGVsbG8=")], "input[0].content").unwrap_err();
+        assert_eq!((err.status, err.param.as_str()), (StatusCode::UNSUPPORTED_MEDIA_TYPE, "input[0].content[0]"));
+
+        let err = limits.check_parts(&[image("image/png", "aGVsbG8gd29ybGQ=")], "c").unwrap_err();
+        assert_eq!((err.status, err.code), (StatusCode::PAYLOAD_TOO_LARGE, "media_too_large"));
+
+        let err = limits.check_parts(&[image("image/png", "not base64!")], "c").unwrap_err();
+        assert_eq!(err.code, "invalid_media");
+
+        let file_url = ContentPart::ImageUrl { url: "file:///etc/passwd".to_string(), detail: None };
+        assert!(limits.check_parts(&[file_url], "c").is_err());
+
+        let data_url = ContentPart::ImageUrl { url: ContentPart::data_url("image/svg+xml", "PHN2Zz4="), detail: None };
+        assert_eq!(limits.check_parts(&[data_url], "c").unwrap_err().status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+}
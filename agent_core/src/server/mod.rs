@@ -2,3 +2,8 @@ pub mod agent_server;
 pub mod agent_handler;
 pub mod secure_agent_server;
 pub mod gateway_server;
+pub mod model_provider;
+pub mod model_catalog;
+pub mod response_stream;
+pub mod media;
+pub mod session_api;
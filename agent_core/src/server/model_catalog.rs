@@ -0,0 +1,192 @@
+//! Model catalog served on `GET /v1/models`.
+//!
+//! Entries come from the provider catalogs of the [`ProviderRegistry`]; context windows and
+//! capability flags are looked up in a built-in table of known model families and can be
+//! overridden per model in the gateway configuration (`[models.catalog."<id>"]`).
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::server::gateway_server::GatewayModelEntry;
+use crate::server::model_provider::{ModelPattern, ProviderRegistry, ProviderSpec};
+
+/// What a model can be asked to do through the gateway
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModelCapabilities {
+    pub tools: bool,
+    pub vision: bool,
+    pub streaming: bool,
+}
+
+/// One entry of the OpenAI-compatible model list, extended with gateway routing details
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelObject {
+    pub id: String,
+    pub object: String,
+    pub created: u64,
+    /// Name of the provider serving the model
+    pub owned_by: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_window: Option<u32>,
+    /// Whether the provider's API key is configured (always true for providers needing none)
+    pub credentials_present: bool,
+    pub capabilities: ModelCapabilities,
+}
+
+/// Response body of `GET /v1/models`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelList {
+    pub object: String,
+    pub data: Vec<ModelObject>,
+}
+
+/// Known model families as (pattern, context window, tools, vision), first match wins
+const KNOWN_MODELS: &[(&str, u32, bool, bool)] = &[
+    ("*gemini-1.5-pro*", 2_097_152, true, true),
+    ("*gemini*", 1_048_576, true, true),
+    ("*claude*", 200_000, true, true),
+    ("*gpt-oss*", 131_072, true, false),
+    ("*gpt-4o*", 128_000, true, true),
+    ("*gpt-4-turbo*", 128_000, true, true),
+    ("*gpt-3.5-turbo*", 16_385, true, false),
+    ("o1*", 200_000, true, true),
+    ("o3*", 200_000, true, true),
+    ("*llama-3.3-70b*", 131_072, true, false),
+    ("*llama-3.1-8b*", 131_072, true, false),
+    ("*qwen3-32b*", 131_072, true, false),
+    ("*llama3.2*", 131_072, true, false),
+    ("*qwen2.5*", 32_768, true, false),
+    ("*mistral*", 32_768, true, false),
+    ("*deepseek-r1*", 131_072, false, false),
+];
+
+/// Builds [`ModelObject`]s from provider specs, applying configured overrides
+#[derive(Debug, Clone, Default)]
+pub struct ModelCatalog {
+    overrides: HashMap<String, GatewayModelEntry>,
+}
+
+impl ModelCatalog {
+    pub fn new(overrides: HashMap<String, GatewayModelEntry>) -> Self {
+        Self { overrides }
+    }
+
+    /// Describe `id` as served by `spec`
+    pub fn model_object(&self, spec: &ProviderSpec, id: &str) -> ModelObject {
+        let known = KNOWN_MODELS
+            .iter()
+            .find(|(pattern, ..)| ModelPattern::new(*pattern).matches(id));
+        let entry = self
+            .overrides
+            .iter()
+            .find(|(model, _)| model.eq_ignore_ascii_case(id))
+            .map(|(_, entry)| entry);
+
+        let capabilities = ModelCapabilities {
+            tools: entry.and_then(|e| e.tools).or(known.map(|k| k.2)).unwrap_or(false),
+            vision: entry.and_then(|e| e.vision).or(known.map(|k| k.3)).unwrap_or(false),
+            // Every model can be streamed, natively or by replaying the full turn
+            streaming: entry.and_then(|e| e.streaming).unwrap_or(true),
+        };
+
+        ModelObject {
+            id: id.to_string(),
+            object: "model".to_string(),
+            created: 0,
+            owned_by: entry
+                .and_then(|e| e.owned_by.clone())
+                .unwrap_or_else(|| spec.name.clone()),
+            context_window: entry.and_then(|e| e.context_window).or(known.map(|k| k.1)),
+            credentials_present: spec.has_credentials(),
+            capabilities,
+        }
+    }
+
+    /// Every model listed in a provider catalog, in routing order and without duplicates
+    pub fn list(&self, registry: &ProviderRegistry) -> Vec<ModelObject> {
+        let mut models: Vec<ModelObject> = Vec::new();
+        for provider in registry.providers() {
+            let spec = provider.spec();
+            for id in &spec.models {
+                if !models.iter().any(|m| m.id.eq_ignore_ascii_case(id)) {
+                    models.push(self.model_object(spec, id));
+                }
+            }
+        }
+        models
+    }
+
+    /// Describe a listed model, or one matched by a provider's routing patterns.
+    /// Catch-all providers are not consulted, so unknown ids are reported as missing.
+    pub fn describe(&self, registry: &ProviderRegistry, id: &str) -> Option<ModelObject> {
+        let providers = registry.providers();
+        providers
+            .iter()
+            .find(|p| p.spec().exact_match(id).is_some())
+            .or_else(|| providers.iter().find(|p| p.spec().pattern_match(id).is_some()))
+            .map(|provider| self.model_object(provider.spec(), id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::model_provider::ProviderProtocol;
+
+    fn registry() -> ProviderRegistry {
+        let mut hosted = ProviderSpec::new("hosted", ProviderProtocol::OpenAiChat, "http://hosted");
+        hosted.models = vec!["gpt-4o".to_string(), "in-house-7b".to_string()];
+        hosted.model_patterns = vec![ModelPattern::new("gpt-*")];
+
+        let mut local = ProviderSpec::new("local", ProviderProtocol::OpenAiChat, "http://local");
+        local.requires_api_key = false;
+        local.models = vec!["GPT-4o".to_string(), "deepseek-r1:8b".to_string()];
+        local.catch_all = true;
+
+        ProviderRegistry::from_specs(vec![hosted, local], &reqwest::Client::new())
+    }
+
+    #[test]
+    fn test_list_uses_known_metadata_and_dedups() {
+        let catalog = ModelCatalog::default();
+        let models = catalog.list(&registry());
+        let ids: Vec<&str> = models.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["gpt-4o", "in-house-7b", "deepseek-r1:8b"]);
+
+        assert_eq!(models[0].owned_by, "hosted");
+        assert_eq!(models[0].context_window, Some(128_000));
+        assert!(models[0].capabilities.vision);
+        assert!(!models[0].credentials_present);
+
+        assert_eq!(models[1].context_window, None);
+        assert!(!models[1].capabilities.tools);
+
+        assert!(models[2].credentials_present);
+        assert!(!models[2].capabilities.tools);
+    }
+
+    #[test]
+    fn test_overrides_and_describe() {
+        let overrides: HashMap<String, GatewayModelEntry> = toml::from_str(
+            r#"
+            [in-house-7b]
+            context_window = 8192
+            tools = true
+            owned_by = "research"
+            "#,
+        )
+        .unwrap();
+        let catalog = ModelCatalog::new(overrides);
+        let registry = registry();
+
+        let model = catalog.describe(&registry, "in-house-7b").unwrap();
+        assert_eq!(model.context_window, Some(8192));
+        assert!(model.capabilities.tools);
+        assert_eq!(model.owned_by, "research");
+
+        // Pattern-routed ids are described, catch-all ones are not
+        assert_eq!(catalog.describe(&registry, "gpt-4.1").unwrap().owned_by, "hosted");
+        assert!(catalog.describe(&registry, "unknown-model").is_none());
+    }
+}
@@ -0,0 +1,1088 @@
+//! Pluggable model providers for the multi-model gateway.
+//!
+//! Every upstream endpoint is described by a [`ProviderSpec`] (model catalog, routing patterns,
+//! credentials and wire protocol) and served by a [`ModelProvider`]. The [`ProviderRegistry`]
+//! resolves a requested model id to exactly one provider and is shared by the streaming and
+//! non-streaming gateway paths.
+
+use std::fmt;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use agent_models::response_item::{CreateResponseRequest, ResponseItem, ResponseObject, ResponseStreamEvent};
+use llm_api::anthropic::AnthropicClient;
+use llm_api::chat::ChatLlmInteraction;
+use llm_api::client::{LlmClient, LlmError};
+use llm_api::generation::GenerationParams;
+use llm_api::google_interactions::{GeminiClient, SafetySetting};
+use llm_api::responses::{response_error, ResponsesLlmInteraction};
+use llm_api::retry::RetryPolicy;
+use llm_api::tools::Tool;
+
+use crate::server::gateway_server::{get_env_var, BackendTurnResult, BackendUsage, GatewayProviderEntry};
+use crate::server::response_stream::{item_deltas, StreamDelta};
+
+/// Wire protocol spoken by an upstream provider
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ProviderProtocol {
+    /// OpenAI-compatible `/chat/completions` (OpenAI, Groq, Ollama, vLLM, Mistral, ...)
+    #[default]
+    #[serde(rename = "openai_chat")]
+    OpenAiChat,
+    /// Google Gemini `generateContent`
+    #[serde(rename = "gemini")]
+    Gemini,
+    /// Anthropic Messages `/v1/messages`
+    #[serde(rename = "anthropic")]
+    Anthropic,
+    /// Open Responses `/v1/responses`, history sent as `ResponseItem`s and chained upstream
+    #[serde(rename = "openai_responses")]
+    OpenAiResponses,
+}
+
+impl ProviderProtocol {
+    /// Best guess of the protocol spoken by an endpoint, for configurations that only give a url
+    pub fn from_url(url: &str) -> Self {
+        let url = url.to_ascii_lowercase();
+        if url.contains("generativelanguage.googleapis.com") {
+            ProviderProtocol::Gemini
+        } else if url.contains("api.anthropic.com") || url.trim_end_matches('/').ends_with("/messages") {
+            ProviderProtocol::Anthropic
+        } else if url.trim_end_matches('/').ends_with("/responses") {
+            ProviderProtocol::OpenAiResponses
+        } else {
+            ProviderProtocol::OpenAiChat
+        }
+    }
+}
+
+/// Case-insensitive model id pattern where `*` matches any sequence of characters
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelPattern(String);
+
+impl ModelPattern {
+    pub fn new(pattern: impl Into<String>) -> Self {
+        Self(pattern.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn matches(&self, model: &str) -> bool {
+        wildcard_match(&self.0.to_ascii_lowercase(), &model.to_ascii_lowercase())
+    }
+}
+
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let segments: Vec<&str> = pattern.split('*').collect();
+    if segments.len() == 1 {
+        return pattern == text;
+    }
+
+    let first = segments[0];
+    let last = segments[segments.len() - 1];
+    if !text.starts_with(first) {
+        return false;
+    }
+
+    let mut rest = &text[first.len()..];
+    for segment in &segments[1..segments.len() - 1] {
+        if segment.is_empty() {
+            continue;
+        }
+        match rest.find(segment) {
+            Some(pos) => rest = &rest[pos + segment.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// The rule that selected a provider for a model
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RouteRule {
+    /// The model id is listed in the provider's model catalog
+    ExactModel(String),
+    /// The model id matched one of the provider's patterns
+    Pattern(String),
+    /// No rule matched and the provider accepts unrouted models
+    CatchAll,
+}
+
+impl fmt::Display for RouteRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RouteRule::ExactModel(model) => write!(f, "model '{}'", model),
+            RouteRule::Pattern(pattern) => write!(f, "pattern '{}'", pattern),
+            RouteRule::CatchAll => write!(f, "catch-all"),
+        }
+    }
+}
+
+/// Static description of an upstream provider: where it lives, how to authenticate and which models it serves
+#[derive(Debug, Clone)]
+pub struct ProviderSpec {
+    pub name: String,
+    pub protocol: ProviderProtocol,
+    pub api_url: String,
+    pub api_key: Option<String>,
+    /// Environment variable the API key is read from (used in error hints)
+    pub api_key_env: Option<String>,
+    pub requires_api_key: bool,
+    /// Model ids served by this provider, matched exactly (case-insensitive)
+    pub models: Vec<String>,
+    pub model_patterns: Vec<ModelPattern>,
+    /// Routing prefixes removed from the model id before calling upstream (e.g. "groq/")
+    pub strip_prefixes: Vec<String>,
+    /// Whether this provider serves models that no exact model or pattern matched
+    pub catch_all: bool,
+    /// Content filter thresholds sent with every Gemini request
+    pub safety_settings: Vec<SafetySetting>,
+    /// Retries and timeouts of every call to this provider
+    pub retry_policy: RetryPolicy,
+}
+
+impl ProviderSpec {
+    pub fn new(name: impl Into<String>, protocol: ProviderProtocol, api_url: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            protocol,
+            api_url: api_url.into(),
+            api_key: None,
+            api_key_env: None,
+            requires_api_key: true,
+            models: Vec::new(),
+            model_patterns: Vec::new(),
+            strip_prefixes: Vec::new(),
+            catch_all: false,
+            safety_settings: Vec::new(),
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+
+    /// Provider for a single endpoint known only by its url, as agents are configured.
+    /// The protocol is guessed from the url.
+    pub fn for_endpoint(name: impl Into<String>, api_url: impl Into<String>) -> Self {
+        let api_url = api_url.into();
+        Self::new(name, ProviderProtocol::from_url(&api_url), api_url)
+    }
+
+    /// Build a provider declared only in the gateway configuration file
+    pub fn from_entry(name: &str, entry: &GatewayProviderEntry) -> Self {
+        let mut spec = Self::new(name, entry.protocol.unwrap_or_default(), String::new());
+        spec.apply_entry(entry);
+        spec
+    }
+
+    /// Overlay the values set in a configuration entry on top of this spec
+    pub fn apply_entry(&mut self, entry: &GatewayProviderEntry) {
+        if let Some(protocol) = entry.protocol {
+            self.protocol = protocol;
+        }
+        if let Some(url) = &entry.api_url {
+            self.api_url = url.clone();
+        }
+        if let Some(env_key) = &entry.api_key_env {
+            self.api_key_env = Some(env_key.clone());
+            if let Some(key) = get_env_var(env_key) {
+                self.api_key = Some(key);
+            }
+        }
+        if let Some(key) = &entry.api_key
+            && !key.is_empty()
+            && !key.starts_with('<')
+        {
+            self.api_key = Some(key.clone());
+        }
+        if let Some(requires_api_key) = entry.requires_api_key {
+            self.requires_api_key = requires_api_key;
+        }
+        if let Some(models) = &entry.recommended_models {
+            self.models = models.clone();
+        }
+        if let Some(patterns) = &entry.model_patterns {
+            self.model_patterns = patterns.iter().map(ModelPattern::new).collect();
+        }
+        if let Some(prefixes) = &entry.strip_prefixes {
+            self.strip_prefixes = prefixes.clone();
+        }
+        if let Some(catch_all) = entry.catch_all {
+            self.catch_all = catch_all;
+        }
+        if let Some(safety_settings) = &entry.safety_settings {
+            self.safety_settings = safety_settings.clone();
+        }
+        if let Some(retry) = &entry.retry {
+            self.retry_policy = RetryPolicy::from(retry);
+        }
+    }
+
+    /// True when the provider can be called (credentials present, or none required)
+    pub fn has_credentials(&self) -> bool {
+        !self.requires_api_key || self.api_key.is_some()
+    }
+
+    pub fn exact_match(&self, model: &str) -> Option<RouteRule> {
+        self.models
+            .iter()
+            .find(|m| m.eq_ignore_ascii_case(model))
+            .map(|m| RouteRule::ExactModel(m.clone()))
+    }
+
+    pub fn pattern_match(&self, model: &str) -> Option<RouteRule> {
+        self.model_patterns
+            .iter()
+            .find(|p| p.matches(model))
+            .map(|p| RouteRule::Pattern(p.as_str().to_string()))
+    }
+
+    /// Client speaking this provider's protocol to its endpoint for one upstream model
+    pub fn llm_client(&self, model: &str, client: &reqwest::Client) -> Box<dyn LlmClient> {
+        let url = self.api_url.clone();
+        let key = self.api_key.clone().unwrap_or_default();
+        let retry = self.retry_policy.clone();
+        match self.protocol {
+            ProviderProtocol::OpenAiChat => Box::new(
+                ChatLlmInteraction::new(url, model.to_string(), key)
+                    .with_client(client.clone())
+                    .with_retry_policy(retry),
+            ),
+            ProviderProtocol::Gemini => Box::new(
+                GeminiClient::new(url, model.to_string(), key)
+                    .with_client(client.clone())
+                    .with_safety_settings(self.safety_settings.clone())
+                    .with_retry_policy(retry),
+            ),
+            ProviderProtocol::Anthropic => Box::new(
+                AnthropicClient::new(url, model.to_string(), key)
+                    .with_client(client.clone())
+                    .with_retry_policy(retry),
+            ),
+            ProviderProtocol::OpenAiResponses => Box::new(
+                ResponsesLlmInteraction::new(url, model.to_string(), key)
+                    .with_client(client.clone())
+                    .with_retry_policy(retry),
+            ),
+        }
+    }
+
+    /// Model id as sent upstream, with the first matching routing prefix removed
+    pub fn upstream_model(&self, model: &str) -> String {
+        self.strip_prefixes
+            .iter()
+            .find_map(|prefix| model.strip_prefix(prefix.as_str()))
+            .unwrap_or(model)
+            .to_string()
+    }
+}
+
+/// Built-in providers, in routing priority order, with credentials read from the environment
+pub fn builtin_provider_specs() -> Vec<ProviderSpec> {
+    let gemini_api_key = get_env_var("GEMINI_API_KEY")
+        .or_else(|| get_env_var("LLM_GEMINI_API_KEY"));
+    let groq_api_key = get_env_var("GROQ_API_KEY")
+        .or_else(|| get_env_var("LLM_GROQ_API_KEY"))
+        .or_else(|| get_env_var("LLM_API_KEY"));
+    let openai_api_key = get_env_var("OPENAI_API_KEY");
+    let anthropic_api_key = get_env_var("ANTHROPIC_API_KEY");
+    let custom_endpoint = get_env_var("SWARM_LLM_URL");
+
+    let mut google = ProviderSpec::new(
+        "google",
+        ProviderProtocol::Gemini,
+        "https://generativelanguage.googleapis.com/v1beta/models",
+    );
+    google.api_key = gemini_api_key;
+    google.api_key_env = Some("GEMINI_API_KEY".to_string());
+    google.models = vec![
+        "gemini-2.0-flash".to_string(),
+        "gemini-1.5-pro".to_string(),
+        "gemini-1.5-flash".to_string(),
+        "google/gemini-2.0-flash".to_string(),
+    ];
+    google.model_patterns = vec![ModelPattern::new("*gemini*"), ModelPattern::new("google/*")];
+    google.strip_prefixes = vec!["google/".to_string()];
+
+    let mut anthropic = ProviderSpec::new(
+        "anthropic",
+        ProviderProtocol::Anthropic,
+        "https://api.anthropic.com/v1/messages",
+    );
+    anthropic.api_key = anthropic_api_key;
+    anthropic.api_key_env = Some("ANTHROPIC_API_KEY".to_string());
+    anthropic.models = vec![
+        "claude-sonnet-4-5".to_string(),
+        "claude-opus-4-1".to_string(),
+        "claude-3-5-haiku-latest".to_string(),
+    ];
+    anthropic.model_patterns = vec![ModelPattern::new("claude-*"), ModelPattern::new("anthropic/*")];
+    anthropic.strip_prefixes = vec!["anthropic/".to_string()];
+
+    let mut groq = ProviderSpec::new(
+        "groq",
+        ProviderProtocol::OpenAiChat,
+        "https://api.groq.com/openai/v1/chat/completions",
+    );
+    groq.api_key = groq_api_key.clone();
+    groq.api_key_env = Some("GROQ_API_KEY".to_string());
+    groq.models = vec![
+        "groq/llama-3.3-70b-versatile".to_string(),
+        "openai/gpt-oss-20b".to_string(),
+        "qwen/qwen3-32b".to_string(),
+        "llama-3.3-70b-versatile".to_string(),
+        "llama-3.1-8b-instant".to_string(),
+    ];
+    groq.model_patterns = vec![ModelPattern::new("groq/*"), ModelPattern::new("qwen/*")];
+    groq.strip_prefixes = vec!["groq/".to_string()];
+    groq.catch_all = true;
+
+    let mut openai = ProviderSpec::new(
+        "openai",
+        ProviderProtocol::OpenAiChat,
+        "https://api.openai.com/v1/chat/completions",
+    );
+    openai.api_key = openai_api_key.clone();
+    openai.api_key_env = Some("OPENAI_API_KEY".to_string());
+    openai.models = vec![
+        "gpt-4o".to_string(),
+        "gpt-4o-mini".to_string(),
+        "gpt-4-turbo".to_string(),
+        "gpt-3.5-turbo".to_string(),
+    ];
+    openai.model_patterns = vec![
+        ModelPattern::new("gpt-*"),
+        ModelPattern::new("o1*"),
+        ModelPattern::new("o3*"),
+    ];
+    openai.strip_prefixes = vec!["openai/".to_string()];
+    openai.catch_all = true;
+
+    // Local / self-hosted OpenAI-compatible endpoint (Ollama by default)
+    let mut custom = ProviderSpec::new(
+        "custom",
+        ProviderProtocol::OpenAiChat,
+        custom_endpoint
+            .clone()
+            .unwrap_or_else(|| "http://localhost:11434/v1/chat/completions".to_string()),
+    );
+    custom.api_key = openai_api_key.or(groq_api_key);
+    custom.api_key_env = Some("SWARM_LLM_URL".to_string());
+    custom.requires_api_key = false;
+    custom.models = vec![
+        "llama3.2:latest".to_string(),
+        "mistral:latest".to_string(),
+        "deepseek-r1:8b".to_string(),
+        "qwen2.5:latest".to_string(),
+    ];
+    custom.model_patterns = vec![
+        ModelPattern::new("*:*"),
+        ModelPattern::new("ollama/*"),
+        ModelPattern::new("local/*"),
+    ];
+    custom.strip_prefixes = vec!["ollama/".to_string(), "local/".to_string()];
+    custom.catch_all = custom_endpoint.is_some();
+
+    vec![google, anthropic, groq, openai, custom]
+}
+
+/// An upstream LLM provider reachable through the gateway
+#[async_trait::async_trait]
+pub trait ModelProvider: Send + Sync {
+    fn spec(&self) -> &ProviderSpec;
+
+    /// Run one turn. `model` is the upstream model id (routing prefixes already stripped).
+    async fn process_turn(
+        &self,
+        session_id: &str,
+        history: &[ResponseItem],
+        model: &str,
+        tools: &[Tool],
+        params: &GenerationParams,
+    ) -> Result<BackendTurnResult, LlmError>;
+
+    /// Stream one turn as deltas. Default implementation calls process_turn and replays the items.
+    async fn process_turn_stream(
+        &self,
+        session_id: &str,
+        history: &[ResponseItem],
+        model: &str,
+        tools: &[Tool],
+        params: &GenerationParams,
+        tx: tokio::sync::mpsc::Sender<StreamDelta>,
+    ) -> Result<Option<BackendUsage>, LlmError> {
+        let result = self.process_turn(session_id, history, model, tools, params).await?;
+        for delta in item_deltas(&result.items) {
+            if tx.send(delta).await.is_err() {
+                break;
+            }
+        }
+        Ok(result.usage)
+    }
+
+    /// Drop whatever the provider keeps for a session that was removed or evicted
+    fn forget_session(&self, _session_id: &str) {}
+}
+
+/// Create the provider implementation matching the spec's wire protocol
+pub fn build_provider(spec: ProviderSpec, client: &reqwest::Client) -> Arc<dyn ModelProvider> {
+    match spec.protocol {
+        ProviderProtocol::OpenAiResponses => Arc::new(ResponsesProvider::new(spec, client.clone())),
+        ProviderProtocol::OpenAiChat | ProviderProtocol::Gemini | ProviderProtocol::Anthropic => {
+            Arc::new(LlmClientProvider::new(spec, client.clone()))
+        }
+    }
+}
+
+/// Outcome of routing a model id through the registry
+#[derive(Clone)]
+pub struct ResolvedRoute {
+    pub provider: Arc<dyn ModelProvider>,
+    pub rule: RouteRule,
+    pub requested_model: String,
+    pub upstream_model: String,
+}
+
+impl ResolvedRoute {
+    pub fn provider_name(&self) -> &str {
+        &self.provider.spec().name
+    }
+}
+
+impl fmt::Debug for ResolvedRoute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResolvedRoute")
+            .field("provider", &self.provider_name())
+            .field("rule", &self.rule)
+            .field("requested_model", &self.requested_model)
+            .field("upstream_model", &self.upstream_model)
+            .finish()
+    }
+}
+
+/// Ordered set of providers. Earlier providers win when several match the same model.
+#[derive(Clone, Default)]
+pub struct ProviderRegistry {
+    providers: Vec<Arc<dyn ModelProvider>>,
+}
+
+impl ProviderRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_specs(specs: Vec<ProviderSpec>, client: &reqwest::Client) -> Self {
+        let mut registry = Self::new();
+        for spec in specs {
+            registry.register(build_provider(spec, client));
+        }
+        registry
+    }
+
+    /// Add a provider, replacing any existing provider with the same name in place
+    pub fn register(&mut self, provider: Arc<dyn ModelProvider>) {
+        let name = provider.spec().name.clone();
+        match self.providers.iter_mut().find(|p| p.spec().name == name) {
+            Some(existing) => *existing = provider,
+            None => self.providers.push(provider),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn ModelProvider>> {
+        self.providers.iter().find(|p| p.spec().name == name).cloned()
+    }
+
+    pub fn providers(&self) -> &[Arc<dyn ModelProvider>] {
+        &self.providers
+    }
+
+    /// Resolve a model id to a provider.
+    ///
+    /// Exact catalog matches are tried first across all providers, then patterns, then the
+    /// first catch-all provider with credentials. A rule match on a provider without
+    /// credentials is reported as an error rather than silently routed elsewhere.
+    pub fn resolve(&self, model: &str) -> Result<ResolvedRoute, String> {
+        let mut unavailable: Option<(&Arc<dyn ModelProvider>, RouteRule)> = None;
+
+        let passes: [fn(&ProviderSpec, &str) -> Option<RouteRule>; 2] =
+            [ProviderSpec::exact_match, ProviderSpec::pattern_match];
+        for matcher in passes {
+            for provider in &self.providers {
+                if let Some(rule) = matcher(provider.spec(), model) {
+                    if provider.spec().has_credentials() {
+                        return Ok(self.route(provider, rule, model));
+                    }
+                    unavailable.get_or_insert((provider, rule));
+                }
+            }
+        }
+
+        if let Some((provider, rule)) = unavailable {
+            let spec = provider.spec();
+            let hint = spec
+                .api_key_env
+                .as_ref()
+                .map(|env| format!(" Please set {} to use it.", env))
+                .unwrap_or_default();
+            return Err(format!(
+                "Provider '{}' matched model '{}' ({}) but no API key is configured.{}",
+                spec.name, model, rule, hint
+            ));
+        }
+
+        self.providers
+            .iter()
+            .find(|p| p.spec().catch_all && p.spec().has_credentials())
+            .map(|provider| self.route(provider, RouteRule::CatchAll, model))
+            .ok_or_else(|| {
+                format!(
+                    "No configured provider or API key found to handle model '{}'. Set GROQ_API_KEY, OPENAI_API_KEY, or SWARM_LLM_URL.",
+                    model
+                )
+            })
+    }
+
+    fn route(&self, provider: &Arc<dyn ModelProvider>, rule: RouteRule, model: &str) -> ResolvedRoute {
+        ResolvedRoute {
+            provider: provider.clone(),
+            rule,
+            requested_model: model.to_string(),
+            upstream_model: provider.spec().upstream_model(model),
+        }
+    }
+}
+
+/// Provider calling its upstream through the [`LlmClient`] of its wire protocol. The upstream
+/// keeps no conversation state, so every turn carries the full history.
+pub struct LlmClientProvider {
+    spec: ProviderSpec,
+    client: reqwest::Client,
+}
+
+impl LlmClientProvider {
+    pub fn new(spec: ProviderSpec, client: reqwest::Client) -> Self {
+        Self { spec, client }
+    }
+}
+
+#[async_trait::async_trait]
+impl ModelProvider for LlmClientProvider {
+    fn spec(&self) -> &ProviderSpec {
+        &self.spec
+    }
+
+    async fn process_turn(
+        &self,
+        _session_id: &str,
+        history: &[ResponseItem],
+        model: &str,
+        tools: &[Tool],
+        params: &GenerationParams,
+    ) -> Result<BackendTurnResult, LlmError> {
+        let turn = self.spec.llm_client(model, &self.client).complete(history, params, tools).await?;
+        Ok(turn.into())
+    }
+
+    async fn process_turn_stream(
+        &self,
+        _session_id: &str,
+        history: &[ResponseItem],
+        model: &str,
+        tools: &[Tool],
+        params: &GenerationParams,
+        tx: tokio::sync::mpsc::Sender<StreamDelta>,
+    ) -> Result<Option<BackendUsage>, LlmError> {
+        let usage = self.spec.llm_client(model, &self.client).stream(history, params, tools, tx).await?;
+        Ok(usage.map(Into::into))
+    }
+}
+
+/// Most sessions whose upstream chain a [`ResponsesProvider`] remembers; the least recently
+/// used ones are forgotten beyond it and resend their full history on the next turn
+pub const MAX_UPSTREAM_CHAINS: usize = 10_000;
+
+/// Last upstream response of a session, used to send only new items on the next turn
+#[derive(Debug, Clone)]
+struct UpstreamChain {
+    response_id: String,
+    /// Number of history items sent for the upstream response
+    input_count: usize,
+    /// Hash of the ids of those items, to tell whether a later history still starts with them
+    input_hash: u64,
+    /// Output of the upstream response, stored by the gateway right after its input
+    output: Vec<ResponseItem>,
+    last_used: std::time::Instant,
+}
+
+impl UpstreamChain {
+    /// Whether `history` starts with the items the upstream response covers and adds new ones.
+    /// A history the gateway truncated or summarized since no longer matches.
+    fn continued_by(&self, history: &[ResponseItem]) -> bool {
+        let covered = self.input_count + self.output.len();
+        history.len() > covered
+            && item_ids_hash(&history[..self.input_count]) == self.input_hash
+            && history[self.input_count..covered]
+                .iter()
+                .zip(&self.output)
+                .all(|(stored, upstream)| same_turn_item(stored, upstream))
+    }
+}
+
+fn item_ids_hash(items: &[ResponseItem]) -> u64 {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    for item in items {
+        item.id().hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// Whether a stored history item is the one the upstream produced. Streamed output is stored
+/// under gateway-assigned ids, so items are compared by call id or content.
+fn same_turn_item(stored: &ResponseItem, upstream: &ResponseItem) -> bool {
+    match (stored, upstream) {
+        (ResponseItem::FunctionCall { call_id: a, .. }, ResponseItem::FunctionCall { call_id: b, .. })
+        | (ResponseItem::FunctionCallOutput { call_id: a, .. }, ResponseItem::FunctionCallOutput { call_id: b, .. }) => {
+            a == b
+        }
+        (
+            ResponseItem::Message { role: role_a, content: content_a, .. },
+            ResponseItem::Message { role: role_b, content: content_b, .. },
+        ) => role_a == role_b && content_a == content_b,
+        (a, b) => a == b,
+    }
+}
+
+/// Provider speaking the Open Responses protocol. Turns of a session are chained upstream with
+/// `previous_response_id`, so only the items added since the last turn are sent.
+pub struct ResponsesProvider {
+    spec: ProviderSpec,
+    client: reqwest::Client,
+    chains: dashmap::DashMap<String, UpstreamChain>,
+    max_chains: usize,
+}
+
+impl ResponsesProvider {
+    pub fn new(spec: ProviderSpec, client: reqwest::Client) -> Self {
+        Self { spec, client, chains: dashmap::DashMap::new(), max_chains: MAX_UPSTREAM_CHAINS }
+    }
+
+    /// Remember the upstream chains of at most `max_chains` sessions
+    pub fn with_max_chains(mut self, max_chains: usize) -> Self {
+        self.max_chains = max_chains.max(1);
+        self
+    }
+
+    fn llm(&self, model: &str) -> ResponsesLlmInteraction {
+        ResponsesLlmInteraction::new(
+            self.spec.api_url.clone(),
+            model.to_string(),
+            self.spec.api_key.clone().unwrap_or_default(),
+        )
+        .with_client(self.client.clone())
+        .with_retry_policy(self.spec.retry_policy.clone())
+    }
+
+    /// Request continuing the session's upstream chain when the history still extends it,
+    /// otherwise a request carrying the full history
+    fn turn_request(
+        &self,
+        llm: &ResponsesLlmInteraction,
+        session_id: &str,
+        history: &[ResponseItem],
+        tools: &[Tool],
+        params: &GenerationParams,
+    ) -> (CreateResponseRequest, bool) {
+        let chain = self.chains.get(session_id).map(|c| c.clone());
+        if let Some(chain) = chain
+            && chain.continued_by(history)
+        {
+            let covered = chain.input_count + chain.output.len();
+            let request = llm.build_request(history[covered..].to_vec(), tools, params, Some(chain.response_id));
+            return (request, true);
+        }
+        (llm.build_request(history.to_vec(), tools, params, None), false)
+    }
+
+    /// Record the upstream response along with the output items the gateway stores for it
+    fn remember(&self, session_id: &str, history: &[ResponseItem], response_id: &str, output: &[ResponseItem]) {
+        if history.is_empty() && output.is_empty() {
+            return;
+        }
+        self.chains.insert(
+            session_id.to_string(),
+            UpstreamChain {
+                response_id: response_id.to_string(),
+                input_count: history.len(),
+                input_hash: item_ids_hash(history),
+                output: output.to_vec(),
+                last_used: std::time::Instant::now(),
+            },
+        );
+        if self.chains.len() > self.max_chains {
+            self.forget_least_recently_used();
+        }
+    }
+
+    /// Forget the least recently used chains, a tenth of the capacity at once so that
+    /// the scan does not run on every turn
+    fn forget_least_recently_used(&self) {
+        let mut chains: Vec<(std::time::Instant, String)> =
+            self.chains.iter().map(|chain| (chain.last_used, chain.key().clone())).collect();
+        chains.sort();
+        let keep = self.max_chains - self.max_chains / 10;
+        let excess = chains.len().saturating_sub(keep);
+        for (_, session_id) in chains.into_iter().take(excess) {
+            self.chains.remove(&session_id);
+        }
+    }
+
+    /// The upstream no longer knows the previous response (expired or store disabled)
+    fn chain_rejected(err: &LlmError) -> bool {
+        matches!(err, LlmError::UpstreamStatus { code: 400 | 404, .. })
+    }
+
+    fn backend_usage(response: &ResponseObject) -> Option<BackendUsage> {
+        response.usage.clone().map(Into::into)
+    }
+
+    /// Stream one request, translating upstream events into deltas
+    async fn stream_request(
+        llm: &ResponsesLlmInteraction,
+        request: &CreateResponseRequest,
+        include_reasoning: bool,
+        tx: &tokio::sync::mpsc::Sender<StreamDelta>,
+    ) -> Result<Option<ResponseObject>, LlmError> {
+        let (event_tx, mut event_rx) = tokio::sync::mpsc::channel::<ResponseStreamEvent>(64);
+        let tx = tx.clone();
+        let translate = async move {
+            // Function call index keyed by upstream output index
+            let mut calls = std::collections::HashMap::new();
+            // Output indices whose content already arrived as deltas
+            let mut streamed = std::collections::HashSet::new();
+            while let Some(event) = event_rx.recv().await {
+                let deltas = match event {
+                    ResponseStreamEvent::OutputItemAdded {
+                        output_index,
+                        item: ResponseItem::FunctionCall { call_id, name, .. },
+                        ..
+                    } => {
+                        let index = calls.len();
+                        calls.insert(output_index, index);
+                        vec![StreamDelta::FunctionCallStart { index, call_id, name }]
+                    }
+                    ResponseStreamEvent::OutputTextDelta { output_index, delta, .. } => {
+                        streamed.insert(output_index);
+                        vec![StreamDelta::Text(delta)]
+                    }
+                    ResponseStreamEvent::ReasoningTextDelta { output_index, delta, .. } if include_reasoning => {
+                        streamed.insert(output_index);
+                        vec![StreamDelta::Reasoning { text: delta, signature: None }]
+                    }
+                    ResponseStreamEvent::FunctionCallArgumentsDelta { output_index, delta, .. } => {
+                        streamed.insert(output_index);
+                        calls
+                            .get(&output_index)
+                            .map(|&index| StreamDelta::FunctionCallArguments { index, delta })
+                            .into_iter()
+                            .collect()
+                    }
+                    // Items completed without deltas are replayed whole
+                    ResponseStreamEvent::OutputItemDone { output_index, item, .. } if !streamed.contains(&output_index) => {
+                        match item {
+                            ResponseItem::FunctionCall { call_id, name, arguments, .. } => {
+                                let mut deltas = Vec::new();
+                                let index = match calls.get(&output_index) {
+                                    Some(&index) => index,
+                                    None => {
+                                        let index = calls.len();
+                                        calls.insert(output_index, index);
+                                        deltas.push(StreamDelta::FunctionCallStart { index, call_id, name });
+                                        index
+                                    }
+                                };
+                                deltas.push(StreamDelta::FunctionCallArguments { index, delta: arguments });
+                                deltas
+                            }
+                            item @ ResponseItem::Message { .. } => item_deltas(std::slice::from_ref(&item)),
+                            item @ ResponseItem::Reasoning { .. } if include_reasoning => {
+                                item_deltas(std::slice::from_ref(&item))
+                            }
+                            _ => Vec::new(),
+                        }
+                    }
+                    // The signature only comes with the finished item
+                    ResponseStreamEvent::OutputItemDone { item: ResponseItem::Reasoning { signature: Some(signature), .. }, .. }
+                        if include_reasoning =>
+                    {
+                        vec![StreamDelta::Reasoning { text: String::new(), signature: Some(signature) }]
+                    }
+                    _ => Vec::new(),
+                };
+                for delta in deltas {
+                    if tx.send(delta).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        };
+        let (result, _) = tokio::join!(llm.create_response_stream(request, event_tx), translate);
+        result
+    }
+}
+
+#[async_trait::async_trait]
+impl ModelProvider for ResponsesProvider {
+    fn spec(&self) -> &ProviderSpec {
+        &self.spec
+    }
+
+    async fn process_turn(
+        &self,
+        session_id: &str,
+        history: &[ResponseItem],
+        model: &str,
+        tools: &[Tool],
+        params: &GenerationParams,
+    ) -> Result<BackendTurnResult, LlmError> {
+        let llm = self.llm(model);
+        let (request, chained) = self.turn_request(&llm, session_id, history, tools, params);
+
+        let result = match llm.create_response(&request).await {
+            Err(e) if chained && Self::chain_rejected(&e) => {
+                tracing::warn!("Upstream rejected previous_response_id for session {}, resending full history: {}", session_id, e);
+                self.chains.remove(session_id);
+                llm.create_response(&llm.build_request(history.to_vec(), tools, params, None)).await
+            }
+            result => result,
+        };
+        let response = result?;
+        if let Some(error) = &response.error {
+            return Err(response_error(error));
+        }
+
+        let usage = Self::backend_usage(&response);
+        let items = params.reasoning_options().output(response.output);
+        self.remember(session_id, history, &response.id, &items);
+        Ok(BackendTurnResult {
+            usage,
+            model: Some(response.model).filter(|m| !m.is_empty()).or_else(|| Some(model.to_string())),
+            items,
+        })
+    }
+
+    async fn process_turn_stream(
+        &self,
+        session_id: &str,
+        history: &[ResponseItem],
+        model: &str,
+        tools: &[Tool],
+        params: &GenerationParams,
+        tx: tokio::sync::mpsc::Sender<StreamDelta>,
+    ) -> Result<Option<BackendUsage>, LlmError> {
+        let llm = self.llm(model);
+        let (request, chained) = self.turn_request(&llm, session_id, history, tools, params);
+
+        // HTTP errors surface before any event is read, so the retry never duplicates output
+        let include_reasoning = params.reasoning_options().include;
+        let result = match Self::stream_request(&llm, &request, include_reasoning, &tx).await {
+            Err(e) if chained && Self::chain_rejected(&e) => {
+                tracing::warn!("Upstream rejected previous_response_id for session {}, resending full history: {}", session_id, e);
+                self.chains.remove(session_id);
+                let request = llm.build_request(history.to_vec(), tools, params, None);
+                Self::stream_request(&llm, &request, include_reasoning, &tx).await
+            }
+            result => result,
+        };
+        let response = result?.ok_or_else(|| LlmError::Decode("Responses stream ended without a terminal event".to_string()))?;
+        if let Some(error) = &response.error {
+            return Err(response_error(error));
+        }
+
+        // Streamed turns are stored as rebuilt from the deltas, reasoning included only if requested
+        let streamed = params.reasoning_options().output(response.output.clone());
+        self.remember(session_id, history, &response.id, &streamed);
+        Ok(Self::backend_usage(&response))
+    }
+
+    fn forget_session(&self, session_id: &str) {
+        self.chains.remove(session_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec_with_key(name: &str, protocol: ProviderProtocol, key: Option<&str>) -> ProviderSpec {
+        let mut spec = ProviderSpec::new(name, protocol, format!("http://localhost/{}", name));
+        spec.api_key = key.map(String::from);
+        spec
+    }
+
+    fn test_registry() -> ProviderRegistry {
+        let mut google = spec_with_key("google", ProviderProtocol::Gemini, Some("g-key"));
+        google.model_patterns = vec![ModelPattern::new("*gemini*")];
+        google.strip_prefixes = vec!["google/".to_string()];
+
+        let mut groq = spec_with_key("groq", ProviderProtocol::OpenAiChat, Some("groq-key"));
+        groq.models = vec!["llama-3.3-70b-versatile".to_string()];
+        groq.model_patterns = vec![ModelPattern::new("groq/*")];
+        groq.strip_prefixes = vec!["groq/".to_string()];
+        groq.catch_all = true;
+
+        let mut openai = spec_with_key("openai", ProviderProtocol::OpenAiChat, None);
+        openai.model_patterns = vec![ModelPattern::new("gpt-*")];
+        openai.api_key_env = Some("OPENAI_API_KEY".to_string());
+        openai.catch_all = true;
+
+        ProviderRegistry::from_specs(vec![google, groq, openai], &reqwest::Client::new())
+    }
+
+    #[test]
+    fn test_model_pattern_wildcards() {
+        assert!(ModelPattern::new("gpt-*").matches("GPT-4o"));
+        assert!(ModelPattern::new("*gemini*").matches("google/gemini-2.0-flash"));
+        assert!(ModelPattern::new("*:*").matches("llama3.2:latest"));
+        assert!(ModelPattern::new("o1").matches("o1"));
+        assert!(!ModelPattern::new("o1").matches("o1-mini"));
+        assert!(!ModelPattern::new("gpt-*").matches("chatgpt-4o"));
+        assert!(!ModelPattern::new("*:*").matches("llama3.2"));
+    }
+
+    #[test]
+    fn test_registry_resolution_rules() {
+        let registry = test_registry();
+
+        let route = registry.resolve("llama-3.3-70b-versatile").unwrap();
+        assert_eq!(route.provider_name(), "groq");
+        assert_eq!(route.rule, RouteRule::ExactModel("llama-3.3-70b-versatile".to_string()));
+
+        let route = registry.resolve("google/gemini-2.0-flash").unwrap();
+        assert_eq!(route.provider_name(), "google");
+        assert_eq!(route.rule, RouteRule::Pattern("*gemini*".to_string()));
+        assert_eq!(route.upstream_model, "gemini-2.0-flash");
+
+        let route = registry.resolve("mixtral-8x7b").unwrap();
+        assert_eq!(route.provider_name(), "groq");
+        assert_eq!(route.rule, RouteRule::CatchAll);
+
+        // Pattern matched, but the provider has no credentials
+        let err = registry.resolve("gpt-4o").unwrap_err();
+        assert!(err.contains("openai"));
+        assert!(err.contains("OPENAI_API_KEY"));
+    }
+
+    #[test]
+    fn test_registry_register_replaces_by_name() {
+        let mut registry = test_registry();
+        let mut openai = spec_with_key("openai", ProviderProtocol::OpenAiChat, Some("sk-test"));
+        openai.model_patterns = vec![ModelPattern::new("gpt-*")];
+        registry.register(build_provider(openai, &reqwest::Client::new()));
+
+        assert_eq!(registry.providers().len(), 3);
+        assert_eq!(registry.resolve("gpt-4o").unwrap().provider_name(), "openai");
+    }
+
+    #[test]
+    fn test_config_declared_provider() {
+        let config: crate::server::gateway_server::GatewayConfigFile = toml::from_str(r#"
+            [providers.mistral]
+            api_url = "https://api.mistral.ai/v1/chat/completions"
+            api_key = "mistral-test-key"
+            model_patterns = ["mistral-*", "mistral/*"]
+            strip_prefixes = ["mistral/"]
+
+            [providers.vllm]
+            api_url = "http://vllm.internal:8000/v1/chat/completions"
+            requires_api_key = false
+            recommended_models = ["internal-llm-70b"]
+
+            [providers.anthropic]
+            api_key = "anthropic-test-key"
+        "#).unwrap();
+
+        let backend = crate::server::gateway_server::MultiModelGatewayBackend::from_config(&config);
+
+        let route = backend.resolve(Some("mistral/mistral-large-latest")).unwrap();
+        assert_eq!(route.provider_name(), "mistral");
+        assert_eq!(route.rule, RouteRule::Pattern("mistral/*".to_string()));
+        assert_eq!(route.upstream_model, "mistral-large-latest");
+        assert_eq!(route.provider.spec().protocol, ProviderProtocol::OpenAiChat);
+
+        let route = backend.resolve(Some("internal-llm-70b")).unwrap();
+        assert_eq!(route.provider_name(), "vllm");
+        assert_eq!(route.rule, RouteRule::ExactModel("internal-llm-70b".to_string()));
+
+        // Built-in providers are tuned in place rather than declared again
+        let route = backend.resolve(Some("anthropic/claude-sonnet-4-5")).unwrap();
+        assert_eq!(route.provider_name(), "anthropic");
+        assert_eq!(route.provider.spec().api_key.as_deref(), Some("anthropic-test-key"));
+        assert_eq!(backend.registry.providers().iter().filter(|p| p.spec().name == "anthropic").count(), 1);
+    }
+
+    fn message(id: &str, role: agent_models::response_item::Role, text: &str) -> ResponseItem {
+        ResponseItem::Message {
+            id: id.to_string(),
+            role,
+            content: vec![agent_models::response_item::ContentPart::Text { text: text.to_string() }],
+        }
+    }
+
+    #[test]
+    fn test_responses_provider_chains_only_matching_histories() {
+        use agent_models::response_item::Role;
+
+        let spec = spec_with_key("responses", ProviderProtocol::OpenAiResponses, Some("k"));
+        let provider = ResponsesProvider::new(spec, reqwest::Client::new()).with_max_chains(10);
+        let llm = provider.llm("m");
+        let params = GenerationParams::default();
+        let previous = |history: &[ResponseItem]| {
+            let (request, chained) = provider.turn_request(&llm, "s", history, &[], &params);
+            chained.then(|| request.previous_response_id.unwrap())
+        };
+
+        let turn = vec![message("u1", Role::User, "hi"), message("u2", Role::User, "there")];
+        // The gateway stores the streamed answer under its own id
+        provider.remember("s", &turn, "resp_1", &[message("upstream_a1", Role::Assistant, "hello")]);
+        let mut history = turn.clone();
+        history.push(message("a1", Role::Assistant, "hello"));
+        history.push(message("u3", Role::User, "next"));
+        assert_eq!(previous(&history).as_deref(), Some("resp_1"));
+
+        // A history truncated in front, or whose output changed, is sent whole
+        assert_eq!(previous(&history[1..]), None);
+        let mut edited = history.clone();
+        edited[2] = message("a1", Role::Assistant, "something else");
+        assert_eq!(previous(&edited), None);
+        assert_eq!(previous(&history[..3]), None);
+
+        provider.forget_session("s");
+        assert_eq!(previous(&history), None);
+
+        // Least recently used chains are forgotten beyond the capacity
+        for i in 0..11 {
+            provider.remember(&format!("s{i}"), &turn, "resp", &[]);
+        }
+        assert!(provider.chains.len() <= 10);
+        assert!(!provider.chains.contains_key("s0"));
+        assert!(provider.chains.contains_key("s10"));
+    }
+
+    #[test]
+    fn test_protocol_from_url() {
+        assert_eq!(
+            ProviderProtocol::from_url("https://generativelanguage.googleapis.com/v1beta/models"),
+            ProviderProtocol::Gemini
+        );
+        assert_eq!(ProviderProtocol::from_url("https://api.anthropic.com/v1/messages"), ProviderProtocol::Anthropic);
+        assert_eq!(ProviderProtocol::from_url("http://localhost:8080/v1/responses"), ProviderProtocol::OpenAiResponses);
+        assert_eq!(
+            ProviderProtocol::from_url("https://api.groq.com/openai/v1/chat/completions"),
+            ProviderProtocol::OpenAiChat
+        );
+        let spec = ProviderSpec::for_endpoint("agent", "https://api.anthropic.com/v1/messages");
+        assert_eq!((spec.protocol, spec.api_url.as_str()), (ProviderProtocol::Anthropic, "https://api.anthropic.com/v1/messages"));
+    }
+}
@@ -0,0 +1,546 @@
+//! Typed streaming for the gateway.
+//!
+//! Backends stream a turn as [`StreamDelta`]s, whatever their upstream wire format. The
+//! [`ResponseStreamBuilder`] turns those deltas into Open Responses [`ResponseStreamEvent`]s and
+//! the [`ChatChunkNormalizer`] into `chat.completion.chunk`s, so every backend produces the same
+//! stream on `POST /v1/responses` and `POST /v1/chat/completions`.
+
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use uuid::Uuid;
+
+use agent_models::response_item::{
+    ContentPart, ResponseError, ResponseItem, ResponseObject, ResponseStreamEvent, ResponseUsage, Role,
+};
+use llm_api::chat::{ChatCompletionChunk, ChunkChoice, ChunkDelta, FunctionCallDelta, ToolCallDelta, Usage};
+
+pub use llm_api::client::{item_deltas, StreamDelta};
+
+/// Builds the Open Responses event sequence of one streamed response and accumulates its
+/// final output items, joining text and tool-call argument fragments
+pub struct ResponseStreamBuilder {
+    response_id: String,
+    model: String,
+    created: u64,
+    sequence_number: u64,
+    output: Vec<ResponseItem>,
+    /// Output index of the message currently receiving text
+    open_message: Option<usize>,
+    /// Output index of the reasoning item currently receiving reasoning text
+    open_reasoning: Option<usize>,
+    /// Tool-call stream index -> output index, for calls still receiving argument fragments
+    open_calls: BTreeMap<usize, usize>,
+}
+
+impl ResponseStreamBuilder {
+    pub fn new(response_id: impl Into<String>, model: impl Into<String>) -> Self {
+        Self {
+            response_id: response_id.into(),
+            model: model.into(),
+            created: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            sequence_number: 0,
+            output: Vec::new(),
+            open_message: None,
+            open_reasoning: None,
+            open_calls: BTreeMap::new(),
+        }
+    }
+
+    pub fn response_id(&self) -> &str {
+        &self.response_id
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// Report `model` from now on, e.g. the fallback that served the turn once it is known.
+    /// `response.created` already went out with the requested model.
+    pub fn set_model(&mut self, model: impl Into<String>) {
+        self.model = model.into();
+    }
+
+    /// Unix time in seconds of the response creation
+    pub fn created_at(&self) -> u64 {
+        self.created
+    }
+
+    /// Items produced so far; complete once `finish` was called
+    pub fn output(&self) -> &[ResponseItem] {
+        &self.output
+    }
+
+    /// First event of the stream
+    pub fn created(&mut self) -> ResponseStreamEvent {
+        ResponseStreamEvent::Created {
+            sequence_number: self.next_sequence_number(),
+            response: self.response("in_progress", None, None),
+        }
+    }
+
+    pub fn push(&mut self, delta: StreamDelta) -> Vec<ResponseStreamEvent> {
+        let mut events = Vec::new();
+        if !matches!(delta, StreamDelta::Reasoning { .. })
+            && let Some(reasoning_index) = self.open_reasoning.take()
+        {
+            events.push(self.item_done(reasoning_index));
+        }
+        match delta {
+            StreamDelta::Text(text) => {
+                let output_index = match self.open_message {
+                    Some(index) => index,
+                    None => {
+                        let item = ResponseItem::Message {
+                            id: format!("resp_msg_{}", Uuid::new_v4()),
+                            role: Role::Assistant,
+                            content: Vec::new(),
+                        };
+                        let index = self.add_item(item, &mut events);
+                        self.open_message = Some(index);
+                        index
+                    }
+                };
+                if let ResponseItem::Message { id, content, .. } = &mut self.output[output_index] {
+                    match content.first_mut() {
+                        Some(ContentPart::Text { text: existing }) => existing.push_str(&text),
+                        _ => content.insert(0, ContentPart::Text { text: text.clone() }),
+                    }
+                    let item_id = id.clone();
+                    events.push(ResponseStreamEvent::OutputTextDelta {
+                        sequence_number: self.next_sequence_number(),
+                        item_id,
+                        output_index,
+                        content_index: 0,
+                        delta: text,
+                    });
+                }
+            }
+            StreamDelta::FunctionCallStart { index, call_id, name } => {
+                // Text following a tool call goes into a new message
+                if let Some(message_index) = self.open_message.take() {
+                    events.push(self.item_done(message_index));
+                }
+                let item = ResponseItem::FunctionCall {
+                    id: format!("fc_{}", Uuid::new_v4()),
+                    call_id,
+                    name,
+                    arguments: String::new(),
+                };
+                let output_index = self.add_item(item, &mut events);
+                if let Some(previous) = self.open_calls.insert(index, output_index) {
+                    events.push(self.item_done(previous));
+                }
+            }
+            StreamDelta::FunctionCallArguments { index, delta } => {
+                let Some(&output_index) = self.open_calls.get(&index) else {
+                    tracing::warn!("Dropping arguments for unknown tool call index {}", index);
+                    return events;
+                };
+                if let ResponseItem::FunctionCall { id, arguments, .. } = &mut self.output[output_index] {
+                    arguments.push_str(&delta);
+                    let item_id = id.clone();
+                    events.push(ResponseStreamEvent::FunctionCallArgumentsDelta {
+                        sequence_number: self.next_sequence_number(),
+                        item_id,
+                        output_index,
+                        delta,
+                    });
+                }
+            }
+            StreamDelta::Reasoning { text, signature } => {
+                // Reasoning following text starts a new item, like text following a tool call
+                if let Some(message_index) = self.open_message.take() {
+                    events.push(self.item_done(message_index));
+                }
+                let output_index = match self.open_reasoning {
+                    Some(index) => index,
+                    None => {
+                        let item = ResponseItem::Reasoning {
+                            id: format!("rs_{}", Uuid::new_v4()),
+                            thought_process: String::new(),
+                            signature: None,
+                        };
+                        let index = self.add_item(item, &mut events);
+                        self.open_reasoning = Some(index);
+                        index
+                    }
+                };
+                if let ResponseItem::Reasoning { id, thought_process, signature: item_signature } =
+                    &mut self.output[output_index]
+                {
+                    if signature.is_some() {
+                        *item_signature = signature;
+                    }
+                    if !text.is_empty() {
+                        thought_process.push_str(&text);
+                        let item_id = id.clone();
+                        events.push(ResponseStreamEvent::ReasoningTextDelta {
+                            sequence_number: self.next_sequence_number(),
+                            item_id,
+                            output_index,
+                            content_index: 0,
+                            delta: text,
+                        });
+                    }
+                }
+            }
+        }
+        events
+    }
+
+    /// Close every open item and emit `response.completed`
+    pub fn finish(&mut self, usage: Option<ResponseUsage>) -> Vec<ResponseStreamEvent> {
+        let mut open: Vec<usize> = self.open_message.take().into_iter().chain(self.open_reasoning.take()).collect();
+        open.extend(std::mem::take(&mut self.open_calls).into_values());
+        open.sort_unstable();
+
+        let mut events: Vec<ResponseStreamEvent> = open.into_iter().map(|index| self.item_done(index)).collect();
+        events.push(ResponseStreamEvent::Completed {
+            sequence_number: self.next_sequence_number(),
+            response: self.response("completed", usage, None),
+        });
+        events
+    }
+
+    /// Terminal event when the backend failed
+    pub fn fail(&mut self, code: impl Into<String>, message: impl Into<String>) -> ResponseStreamEvent {
+        let error = ResponseError {
+            code: code.into(),
+            message: message.into(),
+        };
+        ResponseStreamEvent::Failed {
+            sequence_number: self.next_sequence_number(),
+            response: self.response("failed", None, Some(error)),
+        }
+    }
+
+    fn add_item(&mut self, item: ResponseItem, events: &mut Vec<ResponseStreamEvent>) -> usize {
+        let output_index = self.output.len();
+        events.push(ResponseStreamEvent::OutputItemAdded {
+            sequence_number: self.next_sequence_number(),
+            output_index,
+            item: item.clone(),
+        });
+        self.output.push(item);
+        output_index
+    }
+
+    fn item_done(&mut self, output_index: usize) -> ResponseStreamEvent {
+        ResponseStreamEvent::OutputItemDone {
+            sequence_number: self.next_sequence_number(),
+            output_index,
+            item: self.output[output_index].clone(),
+        }
+    }
+
+    fn response(&self, status: &str, usage: Option<ResponseUsage>, error: Option<ResponseError>) -> ResponseObject {
+        ResponseObject {
+            id: self.response_id.clone(),
+            object: "response".to_string(),
+            created: self.created,
+            model: self.model.clone(),
+            output: self.output.clone(),
+            usage,
+            status: Some(status.to_string()),
+            error,
+        }
+    }
+
+    fn next_sequence_number(&mut self) -> u64 {
+        let current = self.sequence_number;
+        self.sequence_number += 1;
+        current
+    }
+}
+
+/// Renders deltas as spec-compliant `chat.completion.chunk`s: a role-only chunk first, then
+/// content and tool-call deltas, a chunk carrying the finish_reason and, when requested with
+/// `stream_options.include_usage`, a final usage chunk without choices
+pub struct ChatChunkNormalizer {
+    id: String,
+    created: u64,
+    model: String,
+    include_usage: bool,
+    role_sent: bool,
+    saw_tool_call: bool,
+}
+
+impl ChatChunkNormalizer {
+    pub fn new(model: impl Into<String>, include_usage: bool) -> Self {
+        Self {
+            id: format!("chatcmpl-{}", Uuid::new_v4()),
+            created: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            model: model.into(),
+            include_usage,
+            role_sent: false,
+            saw_tool_call: false,
+        }
+    }
+
+    pub fn push(&mut self, delta: StreamDelta) -> Vec<ChatCompletionChunk> {
+        let mut chunks = self.role_chunk().into_iter().collect::<Vec<_>>();
+        let delta = match delta {
+            StreamDelta::Text(text) => ChunkDelta {
+                content: Some(text),
+                ..Default::default()
+            },
+            StreamDelta::FunctionCallStart { index, call_id, name } => {
+                self.saw_tool_call = true;
+                ChunkDelta {
+                    tool_calls: Some(vec![ToolCallDelta {
+                        index: index as u32,
+                        id: Some(call_id),
+                        r#type: Some("function".to_string()),
+                        function: Some(FunctionCallDelta {
+                            name: Some(name),
+                            arguments: Some(String::new()),
+                        }),
+                    }]),
+                    ..Default::default()
+                }
+            }
+            StreamDelta::FunctionCallArguments { index, delta } => ChunkDelta {
+                tool_calls: Some(vec![ToolCallDelta {
+                    index: index as u32,
+                    id: None,
+                    r#type: None,
+                    function: Some(FunctionCallDelta {
+                        name: None,
+                        arguments: Some(delta),
+                    }),
+                }]),
+                ..Default::default()
+            },
+            // Signatures have no place in chat completions
+            StreamDelta::Reasoning { text, .. } if text.is_empty() => return chunks,
+            StreamDelta::Reasoning { text, .. } => ChunkDelta {
+                reasoning_content: Some(text),
+                ..Default::default()
+            },
+        };
+        chunks.push(self.chunk(delta, None));
+        chunks
+    }
+
+    /// Closing chunks of a successful stream
+    pub fn finish(&mut self, usage: Option<Usage>) -> Vec<ChatCompletionChunk> {
+        let mut chunks = self.role_chunk().into_iter().collect::<Vec<_>>();
+        let finish_reason = if self.saw_tool_call { "tool_calls" } else { "stop" };
+        chunks.push(self.chunk(ChunkDelta::default(), Some(finish_reason.to_string())));
+        if self.include_usage {
+            chunks.push(ChatCompletionChunk {
+                choices: Vec::new(),
+                usage: Some(usage.unwrap_or(Usage {
+                    prompt_tokens: 0,
+                    completion_tokens: 0,
+                    total_tokens: 0,
+                })),
+                ..self.chunk(ChunkDelta::default(), None)
+            });
+        }
+        chunks
+    }
+
+    fn role_chunk(&mut self) -> Option<ChatCompletionChunk> {
+        if self.role_sent {
+            return None;
+        }
+        self.role_sent = true;
+        Some(self.chunk(
+            ChunkDelta {
+                role: Some("assistant".to_string()),
+                content: Some(String::new()),
+                tool_calls: None,
+                reasoning_content: None,
+            },
+            None,
+        ))
+    }
+
+    fn chunk(&self, delta: ChunkDelta, finish_reason: Option<String>) -> ChatCompletionChunk {
+        ChatCompletionChunk {
+            id: self.id.clone(),
+            object: "chat.completion.chunk".to_string(),
+            created: self.created,
+            model: self.model.clone(),
+            choices: vec![ChunkChoice {
+                index: 0,
+                delta,
+                finish_reason,
+            }],
+            usage: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder_event_sequence() {
+        let mut builder = ResponseStreamBuilder::new("resp_1", "test-model");
+        let mut events = vec![builder.created()];
+        for delta in [
+            StreamDelta::Text("Checking ".to_string()),
+            StreamDelta::Text("the weather".to_string()),
+            StreamDelta::FunctionCallStart {
+                index: 0,
+                call_id: "call_1".to_string(),
+                name: "get_weather".to_string(),
+            },
+            StreamDelta::FunctionCallArguments { index: 0, delta: "{\"city\":".to_string() },
+            StreamDelta::FunctionCallArguments { index: 0, delta: "\"Paris\"}".to_string() },
+        ] {
+            events.extend(builder.push(delta));
+        }
+        events.extend(builder.finish(None));
+
+        let types: Vec<&str> = events.iter().map(|e| e.event_type()).collect();
+        assert_eq!(
+            types,
+            vec![
+                "response.created",
+                "response.output_item.added",
+                "response.output_text.delta",
+                "response.output_text.delta",
+                "response.output_item.done",
+                "response.output_item.added",
+                "response.function_call_arguments.delta",
+                "response.function_call_arguments.delta",
+                "response.output_item.done",
+                "response.completed",
+            ]
+        );
+
+        match events.last().unwrap() {
+            ResponseStreamEvent::Completed { sequence_number, response } => {
+                assert_eq!(*sequence_number, 9);
+                assert_eq!(response.status.as_deref(), Some("completed"));
+                match &response.output[1] {
+                    ResponseItem::FunctionCall { arguments, call_id, .. } => {
+                        assert_eq!(arguments, "{\"city\":\"Paris\"}");
+                        assert_eq!(call_id, "call_1");
+                    }
+                    other => panic!("Expected function call, got {:?}", other),
+                }
+            }
+            other => panic!("Expected response.completed, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_item_deltas_replay() {
+        let items = vec![
+            ResponseItem::Message {
+                id: "msg_1".to_string(),
+                role: Role::Assistant,
+                content: vec![ContentPart::Text { text: "hi".to_string() }],
+            },
+            ResponseItem::FunctionCall {
+                id: "fc_1".to_string(),
+                call_id: "call_1".to_string(),
+                name: "lookup".to_string(),
+                arguments: "{}".to_string(),
+            },
+        ];
+
+        let mut builder = ResponseStreamBuilder::new("resp_2", "m");
+        for delta in item_deltas(&items) {
+            builder.push(delta);
+        }
+        builder.finish(None);
+        assert_eq!(builder.output().len(), 2);
+        assert!(matches!(&builder.output()[1], ResponseItem::FunctionCall { arguments, .. } if arguments == "{}"));
+    }
+
+    #[test]
+    fn test_builder_streams_reasoning_items() {
+        let mut builder = ResponseStreamBuilder::new("resp_3", "m");
+        let mut events = Vec::new();
+        for delta in [
+            StreamDelta::Reasoning { text: "Weather ".to_string(), signature: None },
+            StreamDelta::Reasoning { text: "needed".to_string(), signature: None },
+            StreamDelta::Reasoning { text: String::new(), signature: Some("sig-1".to_string()) },
+            StreamDelta::Text("On it".to_string()),
+        ] {
+            events.extend(builder.push(delta));
+        }
+        events.extend(builder.finish(None));
+
+        let types: Vec<&str> = events.iter().map(|e| e.event_type()).collect();
+        assert_eq!(
+            types,
+            vec![
+                "response.output_item.added",
+                "response.reasoning_text.delta",
+                "response.reasoning_text.delta",
+                "response.output_item.done",
+                "response.output_item.added",
+                "response.output_text.delta",
+                "response.output_item.done",
+                "response.completed",
+            ]
+        );
+        assert!(matches!(
+            &builder.output()[0],
+            ResponseItem::Reasoning { thought_process, signature: Some(signature), .. }
+                if thought_process == "Weather needed" && signature == "sig-1"
+        ));
+
+        let mut normalizer = ChatChunkNormalizer::new("m", false);
+        let chunks = normalizer.push(StreamDelta::Reasoning { text: "hmm".to_string(), signature: None });
+        assert_eq!(chunks[1].choices[0].delta.reasoning_content.as_deref(), Some("hmm"));
+        let chunks = normalizer.push(StreamDelta::Reasoning { text: String::new(), signature: Some("sig".to_string()) });
+        assert!(chunks.is_empty());
+    }
+
+    #[test]
+    fn test_chat_chunk_normalizer_sequence() {
+        let mut normalizer = ChatChunkNormalizer::new("test-model", true);
+        let mut chunks = Vec::new();
+        for delta in [
+            StreamDelta::FunctionCallStart {
+                index: 0,
+                call_id: "call_1".to_string(),
+                name: "lookup".to_string(),
+            },
+            StreamDelta::FunctionCallArguments { index: 0, delta: "{}".to_string() },
+        ] {
+            chunks.extend(normalizer.push(delta));
+        }
+        let usage = Usage { prompt_tokens: 2, completion_tokens: 3, total_tokens: 5 };
+        chunks.extend(normalizer.finish(Some(usage.clone())));
+
+        assert_eq!(chunks.len(), 5);
+        assert!(chunks.iter().all(|c| c.id == chunks[0].id && c.object == "chat.completion.chunk"));
+        assert_eq!(chunks[0].choices[0].delta.role.as_deref(), Some("assistant"));
+
+        let start = &chunks[1].choices[0].delta.tool_calls.as_ref().unwrap()[0];
+        assert_eq!(start.id.as_deref(), Some("call_1"));
+        let fragment = &chunks[2].choices[0].delta.tool_calls.as_ref().unwrap()[0];
+        assert_eq!(fragment.index, 0);
+        assert!(fragment.id.is_none());
+
+        assert_eq!(chunks[3].choices[0].finish_reason.as_deref(), Some("tool_calls"));
+        assert!(chunks[4].choices.is_empty());
+        assert_eq!(chunks[4].usage, Some(usage));
+    }
+
+    #[test]
+    fn test_chat_chunk_normalizer_without_usage() {
+        let mut normalizer = ChatChunkNormalizer::new("test-model", false);
+        let chunks = normalizer.finish(None);
+        // An empty turn still opens with the role and closes with a finish_reason
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[1].choices[0].finish_reason.as_deref(), Some("stop"));
+        assert!(chunks.iter().all(|c| c.usage.is_none()));
+    }
+}
@@ -35,7 +35,7 @@ pub struct SecureAgentServer<T:Agent> {
 impl<T:Agent> SecureAgentServer<T> {
     pub async fn new(agent_config: AgentConfig, agent: T, auth:AuthConfig,discovery_service: Option<Arc<dyn DiscoveryService>>) -> anyhow::Result<Self> {
         //Ok(Self { config:agent_config,agent:agent,auth:AuthConfig::default(),discovery_service:discovery_service })
-        Ok(Self { config:agent_config,agent:agent,auth:auth,discovery_service:discovery_service })
+        Ok(Self { config:agent_config,agent,auth,discovery_service })
     }
 
     /// Create in-memory storage without push notification
@@ -62,7 +62,7 @@ impl<T:Agent> SecureAgentServer<T> {
 
         if let Some(ds) = &self.discovery_service {
             loop {
-                let registration_result = ds.register_agent(&agent_definition).await;
+                let registration_result = ds.register_agent(agent_definition).await;
 
                 match registration_result {
                     Ok(_) => {
@@ -95,8 +95,8 @@ impl<T:Agent> SecureAgentServer<T> {
 
         let message_handler = AgentHandler::<T>::with_storage(self.agent.clone(),storage.clone());
 
-        let agent_http_endpoint= format!("{}", self.config.agent_http_endpoint());
-        let _agent_ws_endpoint= format!("{}", self.config.agent_ws_endpoint());
+        let agent_http_endpoint= self.config.agent_http_endpoint().to_string();
+        let _agent_ws_endpoint= self.config.agent_ws_endpoint().to_string();
 
         // We should remove that part
         let simple_agent_info = SimpleAgentInfo::new(
@@ -386,10 +386,11 @@ impl a2a_rs::port::authenticator::Authenticator for OAuth2JwtAuthenticator {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum AuthConfig {
     /// No authentication (default for development)
+    #[default]
     None,
     /// Bearer token authentication
     BearerToken {
@@ -418,12 +419,6 @@ pub enum AuthConfig {
     },
 }
 
-impl Default for AuthConfig {
-    fn default() -> Self {
-        Self::None
-    }
-}
-
 impl AuthConfig {
     /// Create auth config from environment variables
     pub fn from_env() -> Self {
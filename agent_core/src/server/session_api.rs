@@ -0,0 +1,420 @@
+//! Session management endpoints of the gateway, served only when an admin key is configured
+//! and only to requests bearing it (`Authorization: Bearer <key>`).
+//!
+//! `GET /v1/sessions`, `GET` and `DELETE /v1/sessions/{id}` inspect and remove sessions.
+//! `GET` and `DELETE /v1/responses/{id}` and `GET /v1/responses/{id}/input_items` follow the
+//! Open Responses API: a response is served again with the items it produced, and its input
+//! items are listed with cursor pagination (`limit`, `after`, `before`, `order`).
+//! Sessions are listed the same way, a page at a time from the store.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+use axum::{
+    extract::{Path, Query, Request, State},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+
+use agent_models::response_item::{ResponseItem, ResponseObject, ResponseUsage};
+
+use crate::server::gateway_server::GatewayState;
+use crate::session::{follows, Session, SessionError, SessionStoreApi, SessionSummary, StoredResponse};
+
+const DEFAULT_PAGE_SIZE: usize = 20;
+const MAX_PAGE_SIZE: usize = 100;
+
+/// A session as returned by the session endpoints
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionObject {
+    pub id: String,
+    pub object: String,
+    pub parent_response_id: Option<String>,
+    pub metadata: HashMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<ResponseUsage>,
+    pub item_count: usize,
+    /// Unix time in seconds
+    pub last_accessed: u64,
+    /// History of the session, only returned by `GET /v1/sessions/{id}`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub items: Option<Vec<ResponseItem>>,
+}
+
+impl From<SessionSummary> for SessionObject {
+    fn from(summary: SessionSummary) -> Self {
+        Self {
+            id: summary.id,
+            object: "session".to_string(),
+            parent_response_id: summary.parent_response_id,
+            metadata: summary.metadata,
+            usage: summary.usage,
+            item_count: summary.item_count,
+            last_accessed: summary.last_accessed.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+            items: None,
+        }
+    }
+}
+
+/// One page of a list endpoint
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ListPage<T> {
+    pub object: String,
+    pub data: Vec<T>,
+    pub first_id: Option<String>,
+    pub last_id: Option<String>,
+    pub has_more: bool,
+}
+
+/// Answer of the `DELETE` endpoints
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeletedObject {
+    pub id: String,
+    pub object: String,
+    pub deleted: bool,
+}
+
+/// Pagination parameters of the list endpoints
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ListQuery {
+    /// Entries per page, 1 to 100, 20 by default
+    pub limit: Option<usize>,
+    /// Return the entries following this id
+    pub after: Option<String>,
+    /// Return the entries preceding this id
+    pub before: Option<String>,
+    /// "asc" or "desc"
+    pub order: Option<String>,
+}
+
+/// Failure of a session endpoint, in the OpenAI error format
+#[derive(Debug)]
+pub enum SessionApiError {
+    Unauthorized,
+    NotFound(String),
+    InvalidParam { param: &'static str, message: String },
+    Store(SessionError),
+}
+
+impl From<SessionError> for SessionApiError {
+    fn from(err: SessionError) -> Self {
+        SessionApiError::Store(err)
+    }
+}
+
+impl IntoResponse for SessionApiError {
+    fn into_response(self) -> Response {
+        let (status, message, param, code) = match self {
+            SessionApiError::Store(err) => return err.into_response(),
+            SessionApiError::Unauthorized => (
+                StatusCode::UNAUTHORIZED,
+                "Missing or invalid admin API key".to_string(),
+                None,
+                "invalid_api_key",
+            ),
+            SessionApiError::NotFound(message) => (StatusCode::NOT_FOUND, message, None, "not_found"),
+            SessionApiError::InvalidParam { param, message } => {
+                (StatusCode::BAD_REQUEST, message, Some(param), "invalid_value")
+            }
+        };
+        let body = serde_json::json!({
+            "error": {
+                "message": message,
+                "type": "invalid_request_error",
+                "param": param,
+                "code": code,
+            }
+        });
+        (status, Json(body)).into_response()
+    }
+}
+
+/// Routes of the session endpoints, merged into the gateway router when an admin key is
+/// configured. Requests without `admin_key` as bearer token are refused.
+pub fn routes(admin_key: Arc<str>) -> Router<GatewayState> {
+    Router::new()
+        .route("/v1/sessions", get(handle_list_sessions))
+        .route("/v1/sessions/{id}", get(handle_get_session).delete(handle_delete_session))
+        .route("/v1/responses/{id}", get(handle_get_response).delete(handle_delete_response))
+        .route("/v1/responses/{id}/input_items", get(handle_list_input_items))
+        .route_layer(middleware::from_fn_with_state(admin_key, require_admin_key))
+}
+
+async fn require_admin_key(State(admin_key): State<Arc<str>>, request: Request, next: Next) -> Response {
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match token {
+        Some(token) if keys_match(token, &admin_key) => next.run(request).await,
+        _ => SessionApiError::Unauthorized.into_response(),
+    }
+}
+
+/// Compare keys in a time independent of where they differ
+fn keys_match(token: &str, key: &str) -> bool {
+    token.len() == key.len() && token.bytes().zip(key.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+fn page_limit(query: &ListQuery) -> Result<usize, SessionApiError> {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(SessionApiError::InvalidParam {
+            param: "limit",
+            message: format!("limit must be between 1 and {}", MAX_PAGE_SIZE),
+        });
+    }
+    Ok(limit)
+}
+
+/// Whether the query lists in descending order, `default_order` applying when it sets none
+fn is_descending(query: &ListQuery, default_order: &str) -> Result<bool, SessionApiError> {
+    match query.order.as_deref().unwrap_or(default_order) {
+        "asc" => Ok(false),
+        "desc" => Ok(true),
+        other => Err(SessionApiError::InvalidParam {
+            param: "order",
+            message: format!("order must be 'asc' or 'desc', got '{}'", other),
+        }),
+    }
+}
+
+fn list_page<T>(data: Vec<T>, id: impl Fn(&T) -> &str, has_more: bool) -> ListPage<T> {
+    ListPage {
+        object: "list".to_string(),
+        first_id: data.first().map(|entry| id(entry).to_string()),
+        last_id: data.last().map(|entry| id(entry).to_string()),
+        data,
+        has_more,
+    }
+}
+
+/// Cut the page described by `query` out of `entries`, which are in ascending order.
+/// `default_order` applies when the query sets none.
+pub fn paginate<T>(
+    mut entries: Vec<T>,
+    id: impl Fn(&T) -> &str,
+    query: &ListQuery,
+    default_order: &str,
+) -> Result<ListPage<T>, SessionApiError> {
+    let limit = page_limit(query)?;
+    if is_descending(query, default_order)? {
+        entries.reverse();
+    }
+
+    let position = |param: &'static str, cursor: &str| {
+        entries.iter().position(|entry| id(entry) == cursor).ok_or_else(|| SessionApiError::InvalidParam {
+            param,
+            message: format!("No entry with id '{}'", cursor),
+        })
+    };
+    let end = match &query.before {
+        Some(before) => position("before", before)?,
+        None => entries.len(),
+    };
+    let start = match &query.after {
+        Some(after) => position("after", after)? + 1,
+        None => 0,
+    };
+    let (start, end) = (start.min(end), end);
+    // Entries before a cursor are the ones closest to it
+    let (start, end, has_more) = if query.before.is_some() && query.after.is_none() {
+        let first = end.saturating_sub(limit).max(start);
+        (first, end, first > start)
+    } else {
+        let last = (start + limit).min(end);
+        (start, last, last < end)
+    };
+
+    let data: Vec<T> = entries.drain(start..end).collect();
+    Ok(list_page(data, id, has_more))
+}
+
+// -------------------------------------------------------------------------------------------------
+// Sessions
+// -------------------------------------------------------------------------------------------------
+
+async fn handle_list_sessions(
+    State(state): State<GatewayState>,
+    Query(query): Query<ListQuery>,
+) -> Result<Response, SessionApiError> {
+    let limit = page_limit(&query)?;
+    let descending = is_descending(&query, "asc")?;
+    let store = state.session_store.as_ref();
+    // One session more than the page tells whether another one follows
+    let (sessions, has_more) = match (query.after.as_deref(), query.before.as_deref()) {
+        (None, Some(before)) => {
+            // The sessions closest to the cursor, read backwards from it
+            let mut sessions = store.list_sessions(Some(before), !descending, limit + 1).await?;
+            let has_more = sessions.len() > limit;
+            sessions.truncate(limit);
+            sessions.reverse();
+            (sessions, has_more)
+        }
+        (after, before) => {
+            let mut sessions = store.list_sessions(after, descending, limit + 1).await?;
+            if let Some(before) = before {
+                sessions.retain(|session| follows(before, &session.id, descending));
+            }
+            let has_more = sessions.len() > limit;
+            sessions.truncate(limit);
+            (sessions, has_more)
+        }
+    };
+    let sessions: Vec<SessionObject> = sessions.into_iter().map(SessionObject::from).collect();
+    Ok(Json(list_page(sessions, |s| s.id.as_str(), has_more)).into_response())
+}
+
+async fn handle_get_session(
+    State(state): State<GatewayState>,
+    Path(id): Path<String>,
+) -> Result<Response, SessionApiError> {
+    let session = find_session(state.session_store.as_ref(), &id).await?;
+    let items = session.items.read().await.clone();
+    let mut object = SessionObject::from(SessionSummary {
+        id: session.id,
+        parent_response_id: session.parent_response_id,
+        metadata: session.metadata,
+        usage: session.usage,
+        item_count: items.len(),
+        last_accessed: session.last_accessed,
+    });
+    object.items = Some(items);
+    Ok(Json(object).into_response())
+}
+
+async fn handle_delete_session(
+    State(state): State<GatewayState>,
+    Path(id): Path<String>,
+) -> Result<Response, SessionApiError> {
+    let removed = state.session_store.remove_session(&id).await?;
+    state.backend.forget_session(&id);
+    if !removed {
+        return Err(SessionApiError::NotFound(format!("Session '{}' not found", id)));
+    }
+    Ok(Json(DeletedObject { id, object: "session".to_string(), deleted: true }).into_response())
+}
+
+async fn find_session(store: &dyn SessionStoreApi, id: &str) -> Result<Session, SessionApiError> {
+    store
+        .get_session(id)
+        .await?
+        .ok_or_else(|| SessionApiError::NotFound(format!("Session '{}' not found", id)))
+}
+
+// -------------------------------------------------------------------------------------------------
+// Responses
+// -------------------------------------------------------------------------------------------------
+
+async fn handle_get_response(
+    State(state): State<GatewayState>,
+    Path(id): Path<String>,
+) -> Result<Response, SessionApiError> {
+    let response = find_response(state.session_store.as_ref(), &id).await?;
+    let output = session_items(state.session_store.as_ref(), &response, &response.output_item_ids).await?;
+    Ok(Json(ResponseObject {
+        id: response.id,
+        object: "response".to_string(),
+        created: response.created,
+        model: response.model,
+        output,
+        usage: response.usage,
+        status: Some("completed".to_string()),
+        error: None,
+    })
+    .into_response())
+}
+
+async fn handle_delete_response(
+    State(state): State<GatewayState>,
+    Path(id): Path<String>,
+) -> Result<Response, SessionApiError> {
+    if !state.session_store.delete_response(&id).await? {
+        return Err(SessionApiError::NotFound(format!("Response '{}' not found", id)));
+    }
+    Ok(Json(DeletedObject { id, object: "response".to_string(), deleted: true }).into_response())
+}
+
+async fn handle_list_input_items(
+    State(state): State<GatewayState>,
+    Path(id): Path<String>,
+    Query(query): Query<ListQuery>,
+) -> Result<Response, SessionApiError> {
+    let response = find_response(state.session_store.as_ref(), &id).await?;
+    let items = session_items(state.session_store.as_ref(), &response, &response.input_item_ids).await?;
+    Ok(Json(paginate(items, ResponseItem::id, &query, "desc")?).into_response())
+}
+
+async fn find_response(store: &dyn SessionStoreApi, id: &str) -> Result<StoredResponse, SessionApiError> {
+    store
+        .get_response(id)
+        .await?
+        .ok_or_else(|| SessionApiError::NotFound(format!("Response '{}' not found", id)))
+}
+
+/// Items of `ids` still in the history of the response's session, in the order of `ids`
+async fn session_items(
+    store: &dyn SessionStoreApi,
+    response: &StoredResponse,
+    ids: &[String],
+) -> Result<Vec<ResponseItem>, SessionError> {
+    let history = store.get_history(&response.session_id).await?;
+    let by_id: HashMap<&str, &ResponseItem> = history.iter().map(|item| (item.id(), item)).collect();
+    Ok(ids.iter().filter_map(|id| by_id.get(id.as_str()).map(|item| (*item).clone())).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(limit: Option<usize>, after: Option<&str>, before: Option<&str>, order: Option<&str>) -> ListQuery {
+        ListQuery {
+            limit,
+            after: after.map(str::to_string),
+            before: before.map(str::to_string),
+            order: order.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_paginate_with_cursors_and_order() {
+        let entries = vec!["a", "b", "c", "d", "e"];
+        let page = paginate(entries.clone(), |e| e, &query(Some(2), None, None, None), "asc").unwrap();
+        assert_eq!((page.data.clone(), page.has_more), (vec!["a", "b"], true));
+        assert_eq!((page.first_id.as_deref(), page.last_id.as_deref()), (Some("a"), Some("b")));
+
+        let page = paginate(entries.clone(), |e| e, &query(Some(2), Some("d"), None, None), "asc").unwrap();
+        assert_eq!((page.data.clone(), page.has_more), (vec!["e"], false));
+
+        let page = paginate(entries.clone(), |e| e, &query(Some(2), None, None, None), "desc").unwrap();
+        assert_eq!(page.data, vec!["e", "d"]);
+
+        // The entries closest to the `before` cursor
+        let page = paginate(entries.clone(), |e| e, &query(Some(2), None, Some("d"), Some("asc")), "desc").unwrap();
+        assert_eq!((page.data.clone(), page.has_more), (vec!["b", "c"], true));
+
+        let page = paginate(entries.clone(), |e| e, &query(None, Some("a"), Some("c"), None), "asc").unwrap();
+        assert_eq!((page.data.clone(), page.has_more), (vec!["b"], false));
+
+        for bad in [query(Some(0), None, None, None), query(None, Some("z"), None, None), query(None, None, None, Some("up"))] {
+            assert!(matches!(
+                paginate(entries.clone(), |e| e, &bad, "asc"),
+                Err(SessionApiError::InvalidParam { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_keys_match() {
+        assert!(keys_match("secret", "secret"));
+        assert!(!keys_match("secreT", "secret"));
+        assert!(!keys_match("secret2", "secret"));
+        assert!(!keys_match("", "secret"));
+    }
+}
@@ -0,0 +1,314 @@
+//! Compact binary encoding of stored session items.
+//!
+//! Every value starts with a format version byte. Integers are LEB128 varints, strings are
+//! length-prefixed UTF-8 and enum variants are one tag byte, so an item costs little more than
+//! its text. New variants get new tags; a new layout gets a new version byte.
+
+use agent_models::response_item::{ContentPart, ResponseItem, Role};
+
+pub(crate) const FORMAT_VERSION: u8 = 1;
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum DecodeError {
+    #[error("unsupported format version {0}")]
+    Version(u8),
+    #[error("unexpected end of data")]
+    Truncated,
+    #[error("invalid {0} tag {1}")]
+    Tag(&'static str, u8),
+    #[error("invalid UTF-8 string")]
+    Utf8,
+    #[error("varint overflows 64 bits")]
+    Overflow,
+    #[error("item range {0}..{1} is reversed")]
+    Range(u64, u64),
+}
+
+pub(crate) struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    pub(crate) fn new() -> Self {
+        Self { buf: vec![FORMAT_VERSION] }
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    pub(crate) fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.buf.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        self.buf.push(value as u8);
+    }
+
+    pub(crate) fn str(&mut self, value: &str) {
+        self.varint(value.len() as u64);
+        self.buf.extend_from_slice(value.as_bytes());
+    }
+
+    pub(crate) fn opt_str(&mut self, value: Option<&str>) {
+        match value {
+            Some(value) => {
+                self.u8(1);
+                self.str(value);
+            }
+            None => self.u8(0),
+        }
+    }
+}
+
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Result<Self, DecodeError> {
+        let mut reader = Self { data };
+        match reader.u8()? {
+            FORMAT_VERSION => Ok(reader),
+            version => Err(DecodeError::Version(version)),
+        }
+    }
+
+    /// Whether every byte was read; values may end with fields added after they were written
+    pub(crate) fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, DecodeError> {
+        let (&first, rest) = self.data.split_first().ok_or(DecodeError::Truncated)?;
+        self.data = rest;
+        Ok(first)
+    }
+
+    pub(crate) fn varint(&mut self) -> Result<u64, DecodeError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            // The tenth byte holds the top bit only
+            if shift == 63 && byte > 1 {
+                return Err(DecodeError::Overflow);
+            }
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(DecodeError::Overflow)
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, DecodeError> {
+        u32::try_from(self.varint()?).map_err(|_| DecodeError::Overflow)
+    }
+
+    pub(crate) fn str(&mut self) -> Result<String, DecodeError> {
+        let len = usize::try_from(self.varint()?).map_err(|_| DecodeError::Overflow)?;
+        if len > self.data.len() {
+            return Err(DecodeError::Truncated);
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::Utf8)
+    }
+
+    pub(crate) fn opt_str(&mut self) -> Result<Option<String>, DecodeError> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => self.str().map(Some),
+            tag => Err(DecodeError::Tag("option", tag)),
+        }
+    }
+}
+
+pub(crate) fn encode_item(item: &ResponseItem) -> Vec<u8> {
+    let mut w = Writer::new();
+    match item {
+        ResponseItem::Message { id, role, content } => {
+            w.u8(0);
+            w.str(id);
+            w.u8(match role {
+                Role::System => 0,
+                Role::User => 1,
+                Role::Assistant => 2,
+                Role::Tool => 3,
+            });
+            w.varint(content.len() as u64);
+            for part in content {
+                encode_part(&mut w, part);
+            }
+        }
+        ResponseItem::Reasoning { id, thought_process, signature } => {
+            w.u8(1);
+            w.str(id);
+            w.str(thought_process);
+            w.opt_str(signature.as_deref());
+        }
+        ResponseItem::FunctionCall { id, call_id, name, arguments } => {
+            w.u8(2);
+            w.str(id);
+            w.str(call_id);
+            w.str(name);
+            w.str(arguments);
+        }
+        ResponseItem::FunctionCallOutput { id, call_id, output, is_error } => {
+            w.u8(3);
+            w.str(id);
+            w.str(call_id);
+            w.str(output);
+            w.u8(u8::from(*is_error));
+        }
+    }
+    w.finish()
+}
+
+fn encode_part(w: &mut Writer, part: &ContentPart) {
+    match part {
+        ContentPart::Text { text } => {
+            w.u8(0);
+            w.str(text);
+        }
+        ContentPart::Image { media_type, data_base64 } => {
+            w.u8(1);
+            w.str(media_type);
+            w.str(data_base64);
+        }
+        ContentPart::ImageUrl { url, detail } => {
+            w.u8(2);
+            w.str(url);
+            w.opt_str(detail.as_deref());
+        }
+        ContentPart::File { filename, media_type, data_base64, url } => {
+            w.u8(3);
+            w.opt_str(filename.as_deref());
+            w.str(media_type);
+            w.opt_str(data_base64.as_deref());
+            w.opt_str(url.as_deref());
+        }
+        ContentPart::Audio { media_type, data_base64 } => {
+            w.u8(4);
+            w.str(media_type);
+            w.str(data_base64);
+        }
+    }
+}
+
+pub(crate) fn decode_item(data: &[u8]) -> Result<ResponseItem, DecodeError> {
+    let mut r = Reader::new(data)?;
+    let item = match r.u8()? {
+        0 => {
+            let id = r.str()?;
+            let role = match r.u8()? {
+                0 => Role::System,
+                1 => Role::User,
+                2 => Role::Assistant,
+                3 => Role::Tool,
+                tag => return Err(DecodeError::Tag("role", tag)),
+            };
+            let count = r.varint()?;
+            let mut content = Vec::new();
+            for _ in 0..count {
+                content.push(decode_part(&mut r)?);
+            }
+            ResponseItem::Message { id, role, content }
+        }
+        1 => ResponseItem::Reasoning { id: r.str()?, thought_process: r.str()?, signature: r.opt_str()? },
+        2 => ResponseItem::FunctionCall { id: r.str()?, call_id: r.str()?, name: r.str()?, arguments: r.str()? },
+        3 => ResponseItem::FunctionCallOutput {
+            id: r.str()?,
+            call_id: r.str()?,
+            output: r.str()?,
+            is_error: r.u8()? != 0,
+        },
+        tag => return Err(DecodeError::Tag("item", tag)),
+    };
+    Ok(item)
+}
+
+fn decode_part(r: &mut Reader<'_>) -> Result<ContentPart, DecodeError> {
+    let part = match r.u8()? {
+        0 => ContentPart::Text { text: r.str()? },
+        1 => ContentPart::Image { media_type: r.str()?, data_base64: r.str()? },
+        2 => ContentPart::ImageUrl { url: r.str()?, detail: r.opt_str()? },
+        3 => ContentPart::File {
+            filename: r.opt_str()?,
+            media_type: r.str()?,
+            data_base64: r.opt_str()?,
+            url: r.opt_str()?,
+        },
+        4 => ContentPart::Audio { media_type: r.str()?, data_base64: r.str()? },
+        tag => return Err(DecodeError::Tag("content part", tag)),
+    };
+    Ok(part)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_item_encoding_round_trip_and_size() {
+        let items = vec![
+            ResponseItem::Message {
+                id: "msg_1".to_string(),
+                role: Role::User,
+                content: vec![
+                    ContentPart::Text { text: "Describe this".to_string() },
+                    ContentPart::ImageUrl { url: "https://example.com/a.png".to_string(), detail: Some("low".to_string()) },
+                    ContentPart::File {
+                        filename: None,
+                        media_type: "application/pdf".to_string(),
+                        data_base64: Some("JVBERi0=".to_string()),
+                        url: None,
+                    },
+                    ContentPart::Audio { media_type: "audio/wav".to_string(), data_base64: "UklGRg==".to_string() },
+                    ContentPart::Image { media_type: "image/png".to_string(), data_base64: "iVBORw==".to_string() },
+                ],
+            },
+            ResponseItem::Reasoning { id: "rs_1".to_string(), thought_process: "é".repeat(200), signature: Some("sig".to_string()) },
+            ResponseItem::FunctionCall {
+                id: "fc_1".to_string(),
+                call_id: "call_1".to_string(),
+                name: "lookup".to_string(),
+                arguments: "{}".to_string(),
+            },
+            ResponseItem::FunctionCallOutput {
+                id: "fco_1".to_string(),
+                call_id: "call_1".to_string(),
+                output: "ok".to_string(),
+                is_error: true,
+            },
+        ];
+        for item in &items {
+            let encoded = encode_item(item);
+            assert_eq!(decode_item(&encoded).as_ref(), Ok(item));
+            assert!(encoded.len() < serde_json::to_vec(item).unwrap().len());
+        }
+
+        let encoded = encode_item(&items[2]);
+        assert_eq!(decode_item(&encoded[..encoded.len() - 1]), Err(DecodeError::Truncated));
+        assert_eq!(decode_item(&[9, 0]), Err(DecodeError::Version(9)));
+        assert_eq!(decode_item(&[FORMAT_VERSION, 7]), Err(DecodeError::Tag("item", 7)));
+    }
+
+    #[test]
+    fn test_varint_rejects_values_beyond_u64() {
+        let mut w = Writer::new();
+        w.varint(u64::MAX);
+        assert_eq!(Reader::new(&w.finish()).unwrap().varint(), Ok(u64::MAX));
+
+        for last in [0x02, 0x81] {
+            let mut data = vec![FORMAT_VERSION];
+            data.extend([0xff; 9]);
+            data.push(last);
+            assert_eq!(Reader::new(&data).unwrap().varint(), Err(DecodeError::Overflow));
+        }
+    }
+}
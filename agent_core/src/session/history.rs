@@ -0,0 +1,411 @@
+//! Context-window management for session histories.
+//!
+//! A [`HistoryPolicy`] picks the part of a session history sent to the backend: the most recent
+//! items within an item count and a token budget, plus the system messages, which stay pinned.
+//! Cuts only fall between complete exchanges, so a `FunctionCall` is never separated from its
+//! `FunctionCallOutput` nor from the reasoning item before it. With a [`HistorySummarizer`]
+//! the dropped turns are replaced by one summary message.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use dashmap::DashMap;
+
+use agent_models::response_item::{ContentPart, ResponseItem, Role};
+
+/// Tokens added per item for role and framing
+const ITEM_OVERHEAD_TOKENS: usize = 4;
+/// Flat estimate for an image, audio or file part
+const MEDIA_PART_TOKENS: usize = 1024;
+/// Tokens kept free for the summary message when a summarizer is configured
+pub const SUMMARY_MAX_TOKENS: usize = 512;
+/// Summaries cached before the cache is reset
+const MAX_CACHED_SUMMARIES: usize = 10_000;
+
+/// Writes the summary replacing the turns dropped from a history
+#[async_trait::async_trait]
+pub trait HistorySummarizer: Send + Sync {
+    /// Summarize `items`, which continue the conversation summarized by `previous`
+    async fn summarize(&self, previous: Option<&str>, items: &[ResponseItem]) -> anyhow::Result<String>;
+}
+
+/// Which part of a session history is sent to the backend
+#[derive(Clone)]
+pub struct HistoryPolicy {
+    /// Most items sent, pinned system messages excluded
+    pub max_items: Option<usize>,
+    /// Token budget applied to every model, on top of its context window
+    pub max_tokens: Option<usize>,
+    /// Tokens of the context window left for the answer when the request sets no max_output_tokens
+    pub output_reserve_tokens: usize,
+    /// Always send system messages, wherever they are in the history
+    pub pin_system: bool,
+    summarizer: Option<Arc<dyn HistorySummarizer>>,
+    /// Summary of a history prefix, keyed by the id of the last item it covers
+    summaries: Arc<DashMap<String, String>>,
+}
+
+impl Default for HistoryPolicy {
+    fn default() -> Self {
+        Self {
+            max_items: None,
+            max_tokens: None,
+            output_reserve_tokens: 4096,
+            pin_system: true,
+            summarizer: None,
+            summaries: Arc::new(DashMap::new()),
+        }
+    }
+}
+
+impl std::fmt::Debug for HistoryPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HistoryPolicy")
+            .field("max_items", &self.max_items)
+            .field("max_tokens", &self.max_tokens)
+            .field("output_reserve_tokens", &self.output_reserve_tokens)
+            .field("pin_system", &self.pin_system)
+            .field("summarize", &self.summarizer.is_some())
+            .finish()
+    }
+}
+
+impl HistoryPolicy {
+    /// Send at most `max_items` items besides pinned system messages
+    pub fn with_max_items(mut self, max_items: usize) -> Self {
+        self.max_items = Some(max_items);
+        self
+    }
+
+    /// Replace dropped turns by a summary written by `summarizer`
+    pub fn with_summarizer(mut self, summarizer: Arc<dyn HistorySummarizer>) -> Self {
+        self.summarizer = Some(summarizer);
+        self
+    }
+
+    /// Tokens available for the history of a model with `context_window` tokens,
+    /// leaving `max_output_tokens` (or the configured reserve) for the answer
+    pub fn token_budget(&self, context_window: Option<u32>, max_output_tokens: Option<u32>) -> Option<usize> {
+        let reserve = max_output_tokens.map_or(self.output_reserve_tokens, |t| t as usize);
+        let window = context_window.map(|w| (w as usize).saturating_sub(reserve));
+        let budget = match (window, self.max_tokens) {
+            (Some(window), Some(max)) => Some(window.min(max)),
+            (window, max) => window.or(max),
+        };
+        match &self.summarizer {
+            Some(_) => budget.map(|b| b.saturating_sub(SUMMARY_MAX_TOKENS)),
+            None => budget,
+        }
+    }
+
+    fn is_pinned(&self, item: &ResponseItem) -> bool {
+        self.pin_system && is_system_message(item)
+    }
+
+    /// Split `history` into the items sent and the items dropped.
+    /// The latest exchange is always sent, even when it alone exceeds the limits.
+    pub fn select(&self, history: &[ResponseItem], token_budget: Option<usize>) -> (Vec<ResponseItem>, Vec<ResponseItem>) {
+        if self.max_items.is_none() && token_budget.is_none() {
+            return (history.to_vec(), Vec::new());
+        }
+        let dropped = dropped_indices(history, self.max_items, token_budget, self.pin_system);
+        let (mut kept, mut dropped_items) = (Vec::new(), Vec::new());
+        let mut next_dropped = dropped.iter().peekable();
+        for (index, item) in history.iter().enumerate() {
+            if next_dropped.next_if_eq(&&index).is_some() {
+                dropped_items.push(item.clone());
+            } else {
+                kept.push(item.clone());
+            }
+        }
+        (kept, dropped_items)
+    }
+
+    /// The history to send within `token_budget`, with a summary of the dropped turns when a
+    /// summarizer is configured. A failed summary leaves the history truncated.
+    pub async fn apply(&self, history: Vec<ResponseItem>, token_budget: Option<usize>) -> Vec<ResponseItem> {
+        let (mut kept, dropped) = self.select(&history, token_budget);
+        let (Some(summarizer), Some(last)) = (&self.summarizer, dropped.last()) else {
+            return kept;
+        };
+
+        // Only the turns dropped since the last summary are summarized again
+        let cached = dropped
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(index, item)| self.summaries.get(item.id()).map(|s| (index, item.id(), s.clone())));
+        let (previous, covered) = match &cached {
+            Some((index, _, summary)) => (Some(summary.as_str()), index + 1),
+            None => (None, 0),
+        };
+        let summary = if covered == dropped.len() {
+            previous.map(str::to_string)
+        } else {
+            match summarizer.summarize(previous, &dropped[covered..]).await {
+                Ok(summary) => {
+                    if let Some((_, id, _)) = &cached {
+                        self.summaries.remove(*id);
+                    }
+                    if self.summaries.len() >= MAX_CACHED_SUMMARIES {
+                        self.summaries.clear();
+                    }
+                    self.summaries.insert(last.id().to_string(), summary.clone());
+                    Some(summary)
+                }
+                Err(err) => {
+                    tracing::warn!("History summarization failed, sending the truncated history: {}", err);
+                    previous.map(str::to_string)
+                }
+            }
+        };
+
+        if let Some(summary) = summary {
+            let position = kept.iter().take_while(|item| self.is_pinned(item)).count();
+            kept.insert(
+                position,
+                ResponseItem::Message {
+                    id: format!("summary_{}", last.id()),
+                    role: Role::System,
+                    content: vec![ContentPart::Text {
+                        text: format!("Summary of the earlier conversation:\n{}", summary),
+                    }],
+                },
+            );
+        }
+        kept
+    }
+}
+
+fn is_system_message(item: &ResponseItem) -> bool {
+    matches!(item, ResponseItem::Message { role: Role::System, .. })
+}
+
+/// Indices of the items [`HistoryPolicy::select`] drops from `history`, in order: everything
+/// before the earliest cut point within the limits, except pinned system messages
+fn dropped_indices(
+    history: &[ResponseItem],
+    max_items: Option<usize>,
+    token_budget: Option<usize>,
+    pin_system: bool,
+) -> Vec<usize> {
+    let allowed = cut_points(history);
+    let pinned: Vec<bool> = history.iter().map(|item| pin_system && is_system_message(item)).collect();
+    let mut tokens: usize = history
+        .iter()
+        .zip(&pinned)
+        .filter(|(_, pinned)| **pinned)
+        .map(|(item, _)| estimate_tokens(item))
+        .sum();
+    let mut items = 0;
+
+    let mut cut = history.len();
+    for index in (0..history.len()).rev() {
+        if !pinned[index] {
+            items += 1;
+            tokens += estimate_tokens(&history[index]);
+        }
+        if !allowed[index] {
+            continue;
+        }
+        let fits = max_items.is_none_or(|max| items <= max) && token_budget.is_none_or(|max| tokens <= max);
+        if !fits && cut < history.len() {
+            break;
+        }
+        cut = index;
+    }
+    (0..cut).filter(|&index| !pinned[index]).collect()
+}
+
+/// Indices of the items to remove from a stored history to keep at most `max_items` of them.
+/// Stored histories are trimmed like the history sent to the backend: whole exchanges only,
+/// system messages kept and not counted.
+pub(crate) fn trimmed_indices(history: &[ResponseItem], max_items: usize) -> Vec<usize> {
+    if history.len() <= max_items {
+        return Vec::new();
+    }
+    dropped_indices(history, Some(max_items), None, true)
+}
+
+/// Whether the history may start at each index: not inside a function call exchange
+/// and not between a reasoning item and the item it precedes
+fn cut_points(history: &[ResponseItem]) -> Vec<bool> {
+    // Calls never answered do not hold the exchange open
+    let answered: HashSet<&str> = history
+        .iter()
+        .filter_map(|item| match item {
+            ResponseItem::FunctionCallOutput { call_id, .. } => Some(call_id.as_str()),
+            _ => None,
+        })
+        .collect();
+    let mut open = HashSet::new();
+    let mut allowed = Vec::with_capacity(history.len());
+    let mut after_reasoning = false;
+    for item in history {
+        allowed.push(open.is_empty() && !after_reasoning);
+        match item {
+            ResponseItem::FunctionCall { call_id, .. } if answered.contains(call_id.as_str()) => {
+                open.insert(call_id.as_str());
+            }
+            ResponseItem::FunctionCallOutput { call_id, .. } => {
+                open.remove(call_id.as_str());
+            }
+            _ => {}
+        }
+        after_reasoning = matches!(item, ResponseItem::Reasoning { .. });
+    }
+    allowed
+}
+
+/// Rough token count of an item: four characters per token, a small per-item overhead
+/// and a flat cost per media part
+pub fn estimate_tokens(item: &ResponseItem) -> usize {
+    let (chars, media) = match item {
+        ResponseItem::Message { content, .. } => content.iter().fold((0, 0), |(chars, media), part| match part {
+            ContentPart::Text { text } => (chars + text.len(), media),
+            _ => (chars, media + 1),
+        }),
+        ResponseItem::Reasoning { thought_process, .. } => (thought_process.len(), 0),
+        ResponseItem::FunctionCall { name, arguments, .. } => (name.len() + arguments.len(), 0),
+        ResponseItem::FunctionCallOutput { output, .. } => (output.len(), 0),
+    };
+    chars.div_ceil(4) + media * MEDIA_PART_TOKENS + ITEM_OVERHEAD_TOKENS
+}
+
+/// Plain-text transcript of `items` for a summarization prompt; reasoning is left out
+pub fn render_transcript(items: &[ResponseItem]) -> String {
+    let mut lines = Vec::new();
+    for item in items {
+        match item {
+            ResponseItem::Message { role, content, .. } => {
+                let role = match role {
+                    Role::System => "system",
+                    Role::User => "user",
+                    Role::Assistant => "assistant",
+                    Role::Tool => "tool",
+                };
+                let text: Vec<&str> = content
+                    .iter()
+                    .map(|part| match part {
+                        ContentPart::Text { text } => text.as_str(),
+                        _ => "[attachment]",
+                    })
+                    .collect();
+                lines.push(format!("{}: {}", role, text.join(" ")));
+            }
+            ResponseItem::FunctionCall { name, arguments, .. } => {
+                lines.push(format!("assistant called {}({})", name, arguments));
+            }
+            ResponseItem::FunctionCallOutput { output, .. } => lines.push(format!("tool result: {}", output)),
+            ResponseItem::Reasoning { .. } => {}
+        }
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn message(id: &str, role: Role, text: &str) -> ResponseItem {
+        ResponseItem::Message {
+            id: id.to_string(),
+            role,
+            content: vec![ContentPart::Text { text: text.to_string() }],
+        }
+    }
+
+    fn ids(items: &[ResponseItem]) -> Vec<&str> {
+        items.iter().map(|item| item.id()).collect()
+    }
+
+    fn conversation() -> Vec<ResponseItem> {
+        vec![
+            message("sys", Role::System, "Be brief."),
+            message("u1", Role::User, "What is the weather in Paris?"),
+            ResponseItem::Reasoning { id: "r1".to_string(), thought_process: "Use the tool".to_string(), signature: None },
+            ResponseItem::FunctionCall {
+                id: "fc1".to_string(),
+                call_id: "call_1".to_string(),
+                name: "weather".to_string(),
+                arguments: r#"{"city":"Paris"}"#.to_string(),
+            },
+            ResponseItem::FunctionCallOutput {
+                id: "fco1".to_string(),
+                call_id: "call_1".to_string(),
+                output: "Sunny".to_string(),
+                is_error: false,
+            },
+            message("a1", Role::Assistant, "It is sunny."),
+            message("u2", Role::User, "And tomorrow?"),
+        ]
+    }
+
+    #[test]
+    fn test_history_policy_keeps_exchanges_whole_and_pins_system() {
+        let history = conversation();
+        let policy = HistoryPolicy::default().with_max_items(3);
+        let (kept, dropped) = policy.select(&history, None);
+        // Cutting at "fco1" or "fc1" would split the call from its output
+        assert_eq!(ids(&kept), vec!["sys", "a1", "u2"]);
+        assert_eq!(ids(&dropped), vec!["u1", "r1", "fc1", "fco1"]);
+
+        let policy = HistoryPolicy::default().with_max_items(5);
+        let (kept, _) = policy.select(&history, None);
+        assert_eq!(ids(&kept), vec!["sys", "r1", "fc1", "fco1", "a1", "u2"]);
+
+        let budget = estimate_tokens(&history[0]) + estimate_tokens(&history[6]);
+        let (kept, _) = HistoryPolicy::default().select(&history, Some(budget));
+        assert_eq!(ids(&kept), vec!["sys", "u2"]);
+
+        // The latest exchange is sent even when over budget
+        let (kept, _) = HistoryPolicy::default().select(&history, Some(1));
+        assert_eq!(ids(&kept), vec!["sys", "u2"]);
+        assert_eq!(HistoryPolicy::default().select(&history, None).0, history);
+    }
+
+    #[test]
+    fn test_history_policy_token_budget() {
+        let policy = HistoryPolicy { max_tokens: Some(50_000), ..HistoryPolicy::default() };
+        assert_eq!(policy.token_budget(Some(128_000), None), Some(50_000));
+        assert_eq!(policy.token_budget(Some(8192), Some(1000)), Some(7192));
+        assert_eq!(HistoryPolicy::default().token_budget(None, None), None);
+    }
+
+    struct CountingSummarizer(AtomicUsize);
+
+    #[async_trait::async_trait]
+    impl HistorySummarizer for CountingSummarizer {
+        async fn summarize(&self, previous: Option<&str>, items: &[ResponseItem]) -> anyhow::Result<String> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(format!("{}+{}", previous.unwrap_or("start"), ids(items).join(",")))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_history_policy_summarizes_dropped_turns_incrementally() {
+        let summarizer = Arc::new(CountingSummarizer(AtomicUsize::new(0)));
+        let policy = HistoryPolicy::default().with_max_items(1).with_summarizer(summarizer.clone());
+        let mut history = conversation();
+
+        let sent = policy.apply(history.clone(), None).await;
+        assert_eq!(ids(&sent), vec!["sys", "summary_a1", "u2"]);
+        let ResponseItem::Message { content, .. } = &sent[1] else { panic!("Expected summary message") };
+        assert_eq!(content[0], ContentPart::Text {
+            text: "Summary of the earlier conversation:\nstart+u1,r1,fc1,fco1,a1".to_string()
+        });
+
+        // Same history: the cached summary is reused
+        policy.apply(history.clone(), None).await;
+        assert_eq!(summarizer.0.load(Ordering::SeqCst), 1);
+
+        history.push(message("a2", Role::Assistant, "Rain."));
+        history.push(message("u3", Role::User, "Thanks"));
+        let sent = policy.apply(history, None).await;
+        assert_eq!(ids(&sent), vec!["sys", "summary_a2", "u3"]);
+        assert_eq!(summarizer.0.load(Ordering::SeqCst), 2);
+        assert_eq!(policy.summaries.len(), 1);
+        assert_eq!(policy.summaries.get("a2").unwrap().as_str(), "start+u1,r1,fc1,fco1,a1+u2,a2");
+    }
+}
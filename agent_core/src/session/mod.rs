@@ -1,8 +1,9 @@
-use std::collections::HashMap;
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, SystemTime};
 use dashmap::DashMap;
 use tokio::sync::RwLock;
-use agent_models::response_item::ResponseItem;
+use agent_models::response_item::{ResponseItem, ResponseUsage};
 
 #[derive(Debug, Clone)]
 pub struct Session {
@@ -10,6 +11,48 @@ pub struct Session {
     pub parent_response_id: Option<String>,
     pub items: Arc<RwLock<Vec<ResponseItem>>>,
     pub metadata: HashMap<String, String>,
+    /// Token usage accumulated over every turn of the session
+    pub usage: Option<ResponseUsage>,
+    /// Last time the session was resolved, read or appended to; idle sessions expire from it
+    pub last_accessed: SystemTime,
+}
+
+/// A session as listed by [`SessionStoreApi::list_sessions`], without its items
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionSummary {
+    pub id: String,
+    pub parent_response_id: Option<String>,
+    pub metadata: HashMap<String, String>,
+    pub usage: Option<ResponseUsage>,
+    pub item_count: usize,
+    pub last_accessed: SystemTime,
+}
+
+/// A response produced in a session, kept to be served again by id. Its items are referenced
+/// by id and read from the session history, so items trimmed from the history are gone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredResponse {
+    pub id: String,
+    pub session_id: String,
+    /// Unix time in seconds
+    pub created: u64,
+    pub model: String,
+    /// Items sent as input of the request that produced the response
+    pub input_item_ids: Vec<String>,
+    pub output_item_ids: Vec<String>,
+    pub usage: Option<ResponseUsage>,
+}
+
+/// Expiry and size limits of a session store; `None` disables a limit
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SessionLimits {
+    /// Sessions idle for longer are removed by [`SessionStoreApi::evict_expired`]
+    pub ttl: Option<Duration>,
+    /// Most sessions kept; the least recently used ones beyond it are evicted
+    pub max_sessions: Option<usize>,
+    /// Most items kept per session; the oldest exchanges are dropped on append, system messages
+    /// are kept and not counted
+    pub max_history_items: Option<usize>,
 }
 
 #[derive(Debug, Default)]
@@ -17,6 +60,8 @@ pub struct SessionStore {
     sessions: DashMap<String, Session>,
     // Mapping from response_id / parent_response_id to session_id for fast lookup
     response_to_session: DashMap<String, String>,
+    responses: DashMap<String, StoredResponse>,
+    limits: SessionLimits,
 }
 
 impl SessionStore {
@@ -24,20 +69,35 @@ impl SessionStore {
         Self {
             sessions: DashMap::new(),
             response_to_session: DashMap::new(),
+            responses: DashMap::new(),
+            limits: SessionLimits::default(),
         }
     }
 
+    /// Expire and trim sessions according to `limits`
+    pub fn with_limits(mut self, limits: SessionLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn limits(&self) -> &SessionLimits {
+        &self.limits
+    }
+
     pub fn get_or_create(&self, session_id: &str) -> Session {
-        self.sessions
+        let mut session = self
+            .sessions
             .entry(session_id.to_string())
             .or_insert_with(|| Session {
                 id: session_id.to_string(),
                 parent_response_id: None,
                 items: Arc::new(RwLock::new(Vec::new())),
                 metadata: HashMap::new(),
-            })
-            .value()
-            .clone()
+                usage: None,
+                last_accessed: SystemTime::now(),
+            });
+        session.last_accessed = SystemTime::now();
+        session.value().clone()
     }
 
     /// Resolve or create a session id based on an optional previous_response_id.
@@ -52,8 +112,7 @@ impl SessionStore {
                 return self.get_or_create(prev_id);
             }
             // Create a new session with id matching or referencing previous_response_id
-            let session = self.get_or_create(prev_id);
-            session
+            self.get_or_create(prev_id)
         } else {
             let new_session_id = uuid::Uuid::new_v4().to_string();
             self.get_or_create(&new_session_id)
@@ -61,28 +120,65 @@ impl SessionStore {
     }
 
     pub async fn append_items(&self, session_id: &str, new_items: &[ResponseItem]) -> Vec<ResponseItem> {
+        self.append_items_trimmed(session_id, new_items).await.0
+    }
+
+    /// Append `new_items`, dropping the oldest exchanges beyond `max_history_items` as
+    /// [`history::trimmed_indices`] picks them. Returns the history and the ids of the dropped
+    /// items, which no longer resolve to the session.
+    pub(crate) async fn append_items_trimmed(
+        &self,
+        session_id: &str,
+        new_items: &[ResponseItem],
+    ) -> (Vec<ResponseItem>, Vec<String>) {
         let session = self.get_or_create(session_id);
         let mut items = session.items.write().await;
         for item in new_items {
-            let item_id = match item {
-                ResponseItem::Message { id, .. } => id.clone(),
-                ResponseItem::Reasoning { id, .. } => id.clone(),
-                ResponseItem::FunctionCall { id, .. } => id.clone(),
-                ResponseItem::FunctionCallOutput { id, .. } => id.clone(),
-            };
+            let item_id = item.id().to_string();
             self.response_to_session.insert(item_id, session_id.to_string());
         }
         items.extend(new_items.iter().cloned());
-        items.clone()
+
+        let mut dropped = Vec::new();
+        if let Some(max) = self.limits.max_history_items {
+            let trimmed = history::trimmed_indices(&items, max);
+            for &index in &trimmed {
+                let item_id = items[index].id();
+                self.response_to_session.remove_if(item_id, |_, sid| sid == session_id);
+                dropped.push(item_id.to_string());
+            }
+            remove_indices(&mut items, &trimmed);
+        }
+        (items.clone(), dropped)
     }
 
-    pub async fn get_history(&self, session_id: &str) -> Vec<ResponseItem> {
-        if let Some(session) = self.sessions.get(session_id) {
-            let items = session.items.read().await;
-            items.clone()
-        } else {
-            Vec::new()
+    /// Positions in the history followed by `new_items`, and ids, of the items
+    /// [`Self::append_items`] would drop to append `new_items`, oldest first
+    pub(crate) async fn trimmed_items(&self, session_id: &str, new_items: &[ResponseItem]) -> Vec<(usize, String)> {
+        let Some(max) = self.limits.max_history_items else {
+            return Vec::new();
+        };
+        let items = self.sessions.get(session_id).map(|session| session.items.clone()).unwrap_or_default();
+        let items = items.read().await;
+        if items.len() + new_items.len() <= max {
+            return Vec::new();
         }
+        let appended: Vec<ResponseItem> = items.iter().chain(new_items).cloned().collect();
+        history::trimmed_indices(&appended, max)
+            .into_iter()
+            .map(|index| (index, appended[index].id().to_string()))
+            .collect()
+    }
+
+    pub async fn get_history(&self, session_id: &str) -> Vec<ResponseItem> {
+        let items = match self.sessions.get_mut(session_id) {
+            Some(mut session) => {
+                session.last_accessed = SystemTime::now();
+                session.items.clone()
+            }
+            None => return Vec::new(),
+        };
+        items.read().await.clone()
     }
 
     pub async fn set_parent_response_id(&self, session_id: &str, parent_response_id: String) {
@@ -97,40 +193,338 @@ impl SessionStore {
             .get(session_id)
             .and_then(|session| session.parent_response_id.clone())
     }
+
+    /// Add the usage of one turn to the session totals
+    pub async fn record_usage(&self, session_id: &str, usage: &ResponseUsage) {
+        if let Some(mut session) = self.sessions.get_mut(session_id) {
+            let total = session.usage.get_or_insert(ResponseUsage {
+                input_tokens: 0,
+                output_tokens: 0,
+                total_tokens: 0,
+            });
+            total.input_tokens += usage.input_tokens;
+            total.output_tokens += usage.output_tokens;
+            total.total_tokens += usage.total_tokens;
+        }
+    }
+
+    pub async fn get_usage(&self, session_id: &str) -> Option<ResponseUsage> {
+        self.sessions
+            .get(session_id)
+            .and_then(|session| session.usage.clone())
+    }
+
+    /// The session without refreshing its last access
+    pub fn get_session(&self, session_id: &str) -> Option<Session> {
+        self.sessions.get(session_id).map(|session| session.value().clone())
+    }
+
+    /// Up to `limit` sessions in id order, ascending or `descending`, following the id `after`.
+    /// Only the sessions of the page are read.
+    pub async fn list_sessions(&self, after: Option<&str>, descending: bool, limit: usize) -> Vec<SessionSummary> {
+        let mut ids: Vec<String> = self
+            .sessions
+            .iter()
+            .filter(|session| after.is_none_or(|cursor| follows(session.key(), cursor, descending)))
+            .map(|session| session.key().clone())
+            .collect();
+        ids.sort_unstable();
+        if descending {
+            ids.reverse();
+        }
+        ids.truncate(limit);
+
+        let mut summaries = Vec::with_capacity(ids.len());
+        for id in ids {
+            let Some(session) = self.get_session(&id) else { continue };
+            let item_count = session.items.read().await.len();
+            summaries.push(SessionSummary {
+                id: session.id,
+                parent_response_id: session.parent_response_id,
+                metadata: session.metadata,
+                usage: session.usage,
+                item_count,
+                last_accessed: session.last_accessed,
+            });
+        }
+        summaries
+    }
+
+    pub fn save_response(&self, response: StoredResponse) {
+        self.response_to_session.insert(response.id.clone(), response.session_id.clone());
+        self.responses.insert(response.id.clone(), response);
+    }
+
+    pub fn get_response(&self, response_id: &str) -> Option<StoredResponse> {
+        self.responses.get(response_id).map(|response| response.value().clone())
+    }
+
+    /// Forget a response: it can no longer be read nor continued. Its items stay in the session.
+    pub fn delete_response(&self, response_id: &str) -> bool {
+        let Some((_, response)) = self.responses.remove(response_id) else {
+            return false;
+        };
+        self.response_to_session.remove_if(response_id, |_, sid| *sid == response.session_id);
+        true
+    }
+
+    pub(crate) fn last_accessed(&self, session_id: &str) -> Option<SystemTime> {
+        self.sessions.get(session_id).map(|session| session.last_accessed)
+    }
+
+    /// Remove a session and every response id resolving to it
+    pub fn remove_session(&self, session_id: &str) -> bool {
+        let removed = self.sessions.remove(session_id).is_some();
+        self.response_to_session.retain(|_, sid| sid != session_id);
+        self.responses.retain(|_, response| response.session_id != session_id);
+        removed
+    }
+
+    /// Remove the sessions idle for longer than the TTL, then the least recently used ones
+    /// beyond `max_sessions`. Returns the ids of the removed sessions.
+    pub fn evict_expired(&self) -> Vec<String> {
+        self.evict_at(SystemTime::now())
+    }
+
+    fn evict_at(&self, now: SystemTime) -> Vec<String> {
+        let live = self
+            .sessions
+            .iter()
+            .map(|session| (session.last_accessed, session.key().clone()))
+            .collect();
+        let evicted = self.limits.select_evictions(now, live);
+        self.remove_sessions(&evicted);
+        evicted
+    }
+
+    /// Remove several sessions with a single pass over the response index
+    pub(crate) fn remove_sessions(&self, session_ids: &[String]) {
+        if session_ids.is_empty() {
+            return;
+        }
+        for id in session_ids {
+            self.sessions.remove(id);
+        }
+        self.remove_responses(session_ids);
+    }
+
+    /// Remove the responses and response ids of several sessions, keeping the sessions
+    pub(crate) fn remove_responses(&self, session_ids: &[String]) {
+        if session_ids.is_empty() {
+            return;
+        }
+        let ids: HashSet<&str> = session_ids.iter().map(String::as_str).collect();
+        self.response_to_session.retain(|_, sid| !ids.contains(sid.as_str()));
+        self.responses.retain(|_, response| !ids.contains(response.session_id.as_str()));
+    }
+}
+
+/// Whether `id` comes after `cursor` in ascending or `descending` id order
+pub(crate) fn follows(id: &str, cursor: &str, descending: bool) -> bool {
+    if descending { id < cursor } else { id > cursor }
 }
 
+/// Remove the elements at `indices`, given in increasing order
+pub(crate) fn remove_indices<T>(items: &mut Vec<T>, indices: &[usize]) {
+    if indices.is_empty() {
+        return;
+    }
+    let mut index = 0;
+    let mut removed = indices.iter().peekable();
+    items.retain(|_| {
+        let keep = removed.next_if_eq(&&index).is_none();
+        index += 1;
+        keep
+    });
+}
+
+impl SessionLimits {
+    /// Ids of the sessions to evict at `now` among `live` sessions and their last access
+    pub(crate) fn select_evictions(&self, now: SystemTime, mut live: Vec<(SystemTime, String)>) -> Vec<String> {
+        let mut evicted = Vec::new();
+        if let Some(ttl) = self.ttl {
+            let (expired, kept): (Vec<_>, Vec<_>) = live
+                .into_iter()
+                .partition(|(accessed, _)| now.duration_since(*accessed).unwrap_or_default() > ttl);
+            evicted.extend(expired.into_iter().map(|(_, id)| id));
+            live = kept;
+        }
+        if let Some(max) = self.max_sessions
+            && live.len() > max
+        {
+            live.sort();
+            let excess = live.len() - max;
+            evicted.extend(live.drain(..excess).map(|(_, id)| id));
+        }
+        evicted
+    }
+}
+
+pub mod encoding;
 pub mod persistent_store;
-pub use persistent_store::PersistentSessionStore;
+pub use persistent_store::{PersistentSessionStore, WriteMode, DEFAULT_CACHE_IDLE};
+pub mod history;
+pub use history::{HistoryPolicy, HistorySummarizer};
+
+/// Failure of a session store to read or write its backing storage
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum SessionError {
+    #[error("session storage failed: {0}")]
+    Storage(String),
+    #[error("stored session data is corrupted: {0}")]
+    Corrupted(#[from] encoding::DecodeError),
+}
+
+/// Write counters of a session store, for monitoring
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct SessionStoreStats {
+    /// Transactions committed
+    pub writes: u64,
+    /// Transactions that failed, whatever the write mode
+    pub write_failures: u64,
+    /// Updates waiting for the next write-behind flush
+    pub pending_writes: u64,
+    pub last_error: Option<String>,
+}
 
 #[async_trait::async_trait]
 pub trait SessionStoreApi: Send + Sync {
-    async fn resolve_session(&self, previous_response_id: Option<&str>) -> Session;
-    async fn append_items(&self, session_id: &str, items: &[ResponseItem]) -> Vec<ResponseItem>;
-    async fn get_history(&self, session_id: &str) -> Vec<ResponseItem>;
-    async fn set_parent_response_id(&self, session_id: &str, parent_response_id: String);
-    async fn get_parent_response_id(&self, session_id: &str) -> Option<String>;
+    async fn resolve_session(&self, previous_response_id: Option<&str>) -> Result<Session, SessionError>;
+    async fn append_items(&self, session_id: &str, items: &[ResponseItem]) -> Result<Vec<ResponseItem>, SessionError>;
+    async fn get_history(&self, session_id: &str) -> Result<Vec<ResponseItem>, SessionError>;
+    async fn set_parent_response_id(&self, session_id: &str, parent_response_id: String) -> Result<(), SessionError>;
+    async fn get_parent_response_id(&self, session_id: &str) -> Result<Option<String>, SessionError>;
+    async fn record_usage(&self, session_id: &str, usage: &ResponseUsage) -> Result<(), SessionError>;
+    async fn get_usage(&self, session_id: &str) -> Result<Option<ResponseUsage>, SessionError>;
+    /// Remove a session and its response ids; false when it did not exist
+    async fn remove_session(&self, session_id: &str) -> Result<bool, SessionError>;
+    /// Remove expired and least recently used sessions, returning their ids
+    async fn evict_expired(&self) -> Result<Vec<String>, SessionError>;
+    /// Up to `limit` sessions in id order, ascending or `descending`, following the id `after`,
+    /// which need not be a stored session
+    async fn list_sessions(
+        &self,
+        after: Option<&str>,
+        descending: bool,
+        limit: usize,
+    ) -> Result<Vec<SessionSummary>, SessionError>;
+    /// A session with its items, `None` when it does not exist; its last access is not refreshed
+    async fn get_session(&self, session_id: &str) -> Result<Option<Session>, SessionError>;
+    /// Keep `response` to serve it by id; its id also continues the session
+    async fn save_response(&self, response: StoredResponse) -> Result<(), SessionError>;
+    async fn get_response(&self, response_id: &str) -> Result<Option<StoredResponse>, SessionError>;
+    /// Forget a response, false when it did not exist. Its items stay in the session history.
+    async fn delete_response(&self, response_id: &str) -> Result<bool, SessionError>;
+
+    /// Record a finished turn: append its output items, add its usage to the session, keep the
+    /// response and make its id the parent of the session. Stores with backing storage write it
+    /// all at once.
+    async fn save_turn(&self, response: StoredResponse, output_items: &[ResponseItem]) -> Result<(), SessionError> {
+        let session_id = response.session_id.clone();
+        self.append_items(&session_id, output_items).await?;
+        self.set_parent_response_id(&session_id, response.id.clone()).await?;
+        if let Some(usage) = &response.usage {
+            self.record_usage(&session_id, usage).await?;
+        }
+        self.save_response(response).await
+    }
+
+    /// Write counters; stores without backing storage have none
+    fn stats(&self) -> SessionStoreStats {
+        SessionStoreStats::default()
+    }
+}
+
+/// Evict expired sessions of `store` every `interval`, passing the ids of the evicted sessions to
+/// `on_evict`. The task ends on its own once the store is dropped.
+pub fn spawn_session_reaper(
+    store: &Arc<dyn SessionStoreApi>,
+    interval: Duration,
+    on_evict: impl Fn(&[String]) + Send + 'static,
+) -> tokio::task::JoinHandle<()> {
+    let store: Weak<dyn SessionStoreApi> = Arc::downgrade(store);
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            let Some(store) = store.upgrade() else { break };
+            match store.evict_expired().await {
+                Ok(evicted) if !evicted.is_empty() => {
+                    tracing::debug!("Evicted {} expired sessions", evicted.len());
+                    on_evict(&evicted);
+                }
+                Ok(_) => {}
+                Err(err) => tracing::warn!("Session eviction failed: {}", err),
+            }
+        }
+    })
 }
 
 #[async_trait::async_trait]
 impl SessionStoreApi for SessionStore {
-    async fn resolve_session(&self, previous_response_id: Option<&str>) -> Session {
-        self.resolve_session(previous_response_id).await
+    async fn resolve_session(&self, previous_response_id: Option<&str>) -> Result<Session, SessionError> {
+        Ok(self.resolve_session(previous_response_id).await)
     }
 
-    async fn append_items(&self, session_id: &str, items: &[ResponseItem]) -> Vec<ResponseItem> {
-        self.append_items(session_id, items).await
+    async fn append_items(&self, session_id: &str, items: &[ResponseItem]) -> Result<Vec<ResponseItem>, SessionError> {
+        Ok(self.append_items(session_id, items).await)
     }
 
-    async fn get_history(&self, session_id: &str) -> Vec<ResponseItem> {
-        self.get_history(session_id).await
+    async fn get_history(&self, session_id: &str) -> Result<Vec<ResponseItem>, SessionError> {
+        Ok(self.get_history(session_id).await)
     }
 
-    async fn set_parent_response_id(&self, session_id: &str, parent_response_id: String) {
+    async fn set_parent_response_id(&self, session_id: &str, parent_response_id: String) -> Result<(), SessionError> {
         self.set_parent_response_id(session_id, parent_response_id).await;
+        Ok(())
+    }
+
+    async fn get_parent_response_id(&self, session_id: &str) -> Result<Option<String>, SessionError> {
+        Ok(self.get_parent_response_id(session_id).await)
+    }
+
+    async fn record_usage(&self, session_id: &str, usage: &ResponseUsage) -> Result<(), SessionError> {
+        self.record_usage(session_id, usage).await;
+        Ok(())
+    }
+
+    async fn get_usage(&self, session_id: &str) -> Result<Option<ResponseUsage>, SessionError> {
+        Ok(self.get_usage(session_id).await)
+    }
+
+    async fn remove_session(&self, session_id: &str) -> Result<bool, SessionError> {
+        Ok(self.remove_session(session_id))
+    }
+
+    async fn evict_expired(&self) -> Result<Vec<String>, SessionError> {
+        Ok(self.evict_expired())
+    }
+
+    async fn list_sessions(
+        &self,
+        after: Option<&str>,
+        descending: bool,
+        limit: usize,
+    ) -> Result<Vec<SessionSummary>, SessionError> {
+        Ok(self.list_sessions(after, descending, limit).await)
+    }
+
+    async fn get_session(&self, session_id: &str) -> Result<Option<Session>, SessionError> {
+        Ok(self.get_session(session_id))
+    }
+
+    async fn save_response(&self, response: StoredResponse) -> Result<(), SessionError> {
+        self.save_response(response);
+        Ok(())
+    }
+
+    async fn get_response(&self, response_id: &str) -> Result<Option<StoredResponse>, SessionError> {
+        Ok(self.get_response(response_id))
     }
 
-    async fn get_parent_response_id(&self, session_id: &str) -> Option<String> {
-        self.get_parent_response_id(session_id).await
+    async fn delete_response(&self, response_id: &str) -> Result<bool, SessionError> {
+        Ok(self.delete_response(response_id))
     }
 }
 
@@ -152,7 +546,7 @@ mod tests {
             }],
         };
 
-        let history = store.append_items(session_id, &[item1.clone()]).await;
+        let history = store.append_items(session_id, std::slice::from_ref(&item1)).await;
         assert_eq!(history.len(), 1);
         assert_eq!(history[0], item1);
 
@@ -210,4 +604,111 @@ mod tests {
         let history = store.get_history(session_id).await;
         assert_eq!(history.len(), 10);
     }
+
+    fn message(id: &str) -> ResponseItem {
+        ResponseItem::Message {
+            id: id.to_string(),
+            role: Role::User,
+            content: vec![ContentPart::Text { text: id.to_string() }],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_session_store_evicts_idle_and_least_recently_used_sessions() {
+        let store = SessionStore::new().with_limits(SessionLimits {
+            ttl: Some(Duration::from_secs(60)),
+            max_sessions: Some(2),
+            max_history_items: None,
+        });
+        for id in ["s1", "s2", "s3", "s4"] {
+            store.append_items(id, &[message(&format!("msg_{id}"))]).await;
+            store.set_parent_response_id(id, format!("resp_{id}")).await;
+        }
+        let now = SystemTime::now();
+        let age = |id: &str, secs: u64| store.sessions.get_mut(id).unwrap().last_accessed = now - Duration::from_secs(secs);
+        age("s1", 120);
+        age("s2", 30);
+        age("s3", 20);
+        age("s4", 10);
+
+        let mut evicted = store.evict_at(now);
+        evicted.sort();
+        assert_eq!(evicted, vec!["s1", "s2"]);
+        assert!(store.get_history("s1").await.is_empty());
+        assert!(!store.response_to_session.contains_key("resp_s1"));
+        assert!(!store.response_to_session.contains_key("msg_s2"));
+        assert_eq!(store.resolve_session(Some("resp_s3")).await.id, "s3");
+        assert_eq!(store.response_to_session.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_session_reaper_stops_with_store() {
+        let store = Arc::new(SessionStore::new().with_limits(SessionLimits {
+            ttl: Some(Duration::from_millis(10)),
+            ..SessionLimits::default()
+        }));
+        store.append_items("idle", &[message("msg_idle")]).await;
+        let api: Arc<dyn SessionStoreApi> = store.clone();
+        let evicted = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = evicted.clone();
+        let reaper = spawn_session_reaper(&api, Duration::from_millis(10), move |ids| {
+            seen.lock().unwrap().extend_from_slice(ids)
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(store.sessions.is_empty());
+        assert_eq!(*evicted.lock().unwrap(), vec!["idle"]);
+        assert!(store.response_to_session.is_empty());
+
+        drop(api);
+        drop(store);
+        tokio::time::timeout(Duration::from_secs(1), reaper).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_session_store_trims_whole_exchanges_and_keeps_system_messages() {
+        let store = SessionStore::new().with_limits(SessionLimits { max_history_items: Some(3), ..SessionLimits::default() });
+        let system = ResponseItem::Message {
+            id: "sys".to_string(),
+            role: Role::System,
+            content: vec![ContentPart::Text { text: "Be brief.".to_string() }],
+        };
+        let call = ResponseItem::FunctionCall {
+            id: "fc1".to_string(),
+            call_id: "call_1".to_string(),
+            name: "weather".to_string(),
+            arguments: "{}".to_string(),
+        };
+        let output = ResponseItem::FunctionCallOutput {
+            id: "fco1".to_string(),
+            call_id: "call_1".to_string(),
+            output: "Sunny".to_string(),
+            is_error: false,
+        };
+        store.append_items("s", &[system, message("u1"), call, output]).await;
+        assert_eq!(store.trimmed_items("s", &[message("a1")]).await, vec![(1, "u1".to_string())]);
+        let (history, dropped) = store.append_items_trimmed("s", &[message("a1")]).await;
+        assert_eq!(history.iter().map(|i| i.id()).collect::<Vec<_>>(), vec!["sys", "fc1", "fco1", "a1"]);
+        assert_eq!(dropped, vec!["u1"]);
+
+        // Keeping "fco1" alone would split it from its call
+        let (history, dropped) = store.append_items_trimmed("s", &[message("u2"), message("a2")]).await;
+        assert_eq!(history.iter().map(|i| i.id()).collect::<Vec<_>>(), vec!["sys", "a1", "u2", "a2"]);
+        assert_eq!(dropped, vec!["fc1", "fco1"]);
+        assert_eq!(store.resolve_session(Some("sys")).await.id, "s");
+    }
+
+    #[tokio::test]
+    async fn test_session_store_trims_history_and_removes_sessions() {
+        let store = SessionStore::new().with_limits(SessionLimits { max_history_items: Some(2), ..SessionLimits::default() });
+        let (history, dropped) = store
+            .append_items_trimmed("s", &[message("m1"), message("m2"), message("m3")])
+            .await;
+        assert_eq!(history.iter().map(|i| i.id()).collect::<Vec<_>>(), vec!["m2", "m3"]);
+        assert_eq!(dropped, vec!["m1"]);
+        assert!(!store.response_to_session.contains_key("m1"));
+
+        assert!(store.remove_session("s"));
+        assert!(!store.remove_session("s"));
+        assert!(store.response_to_session.is_empty());
+    }
 }
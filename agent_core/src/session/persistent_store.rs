@@ -1,158 +1,1043 @@
+//! redb-backed session store.
+//!
+//! Sessions are stored append-only: one row per item in `session_items`, keyed by
+//! `(session_id, seq)`, and one small row per session in `session_meta` holding the parent
+//! response id, usage, last access and the live `seq` range. Responses are kept in `responses`
+//! with the ids of their items. An append writes the new rows and
+//! the metadata only, whatever the length of the conversation. Values use the compact binary
+//! encoding of [`crate::session::encoding`].
+//!
+//! Nothing is loaded at startup: a session is read into the in-memory [`SessionStore`] the
+//! first time it is used. Databases written with the former `sessions` table (one JSON blob per
+//! session) are migrated when opened.
+//!
+//! The [`WriteMode`] decides when updates reach the disk: before the call returns, in batches
+//! flushed in the background, or at once with failures only counted.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Bound;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use redb::{Database, TableDefinition, ReadableTable, ReadableDatabase};
-use agent_models::response_item::ResponseItem;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use agent_models::response_item::{ResponseItem, ResponseUsage};
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use redb::{
+    Database, MultimapTableDefinition, ReadableDatabase, ReadableTable, TableDefinition, TableHandle, WriteTransaction,
+};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use tokio::sync::Mutex;
-use crate::session::{Session, SessionStore, SessionStoreApi};
+use tokio::sync::{Mutex, OwnedMutexGuard, RwLock};
+
+use crate::session::encoding::{decode_item, encode_item, DecodeError, Reader, Writer};
+use crate::session::{
+    remove_indices, Session, SessionError, SessionLimits, SessionStore, SessionStoreApi, SessionStoreStats, SessionSummary,
+    StoredResponse,
+};
 
-const SESSIONS_TABLE: TableDefinition<&str, Vec<u8>> = TableDefinition::new("sessions");
+/// Items of every session, one row per item
+const ITEMS_TABLE: TableDefinition<(&str, u64), &[u8]> = TableDefinition::new("session_items");
+/// One [`SessionMeta`] row per session
+const META_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("session_meta");
+/// Response id -> encoded [`StoredResponse`]
+const RESPONSES_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("responses");
+/// Response or item id -> session id
 const RESPONSE_INDEX_TABLE: TableDefinition<&str, &str> = TableDefinition::new("response_index");
+/// Session id -> response ids registered with `set_parent_response_id`, to clean the index on removal
+const SESSION_RESPONSES_TABLE: MultimapTableDefinition<&str, &str> = MultimapTableDefinition::new("session_responses");
+/// Former layout: the whole session as one JSON [`PersistentSessionRecord`]
+const LEGACY_SESSIONS_TABLE: TableDefinition<&str, Vec<u8>> = TableDefinition::new("sessions");
 
+/// A session in the former `sessions` table, read when migrating
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PersistentSessionRecord {
     pub id: String,
     pub parent_response_id: Option<String>,
     pub items: Vec<ResponseItem>,
     pub metadata: HashMap<String, String>,
+    #[serde(default)]
+    pub usage: Option<ResponseUsage>,
+    /// Unix time in seconds of the last access; records without one count as accessed at startup
+    #[serde(default)]
+    pub last_accessed: Option<u64>,
+}
+
+/// Everything stored about a session besides its items
+#[derive(Debug, Clone, Default, PartialEq)]
+struct SessionMeta {
+    parent_response_id: Option<String>,
+    usage: Option<ResponseUsage>,
+    metadata: HashMap<String, String>,
+    /// Unix time in milliseconds
+    last_accessed: u64,
+    /// Items live in `first_seq..next_seq`, with gaps where trimming kept system messages
+    first_seq: u64,
+    next_seq: u64,
+    item_count: u64,
+}
+
+impl SessionMeta {
+    fn encode(&self) -> Vec<u8> {
+        let mut w = Writer::new();
+        w.opt_str(self.parent_response_id.as_deref());
+        write_usage(&mut w, self.usage.as_ref());
+        w.varint(self.metadata.len() as u64);
+        for (key, value) in &self.metadata {
+            w.str(key);
+            w.str(value);
+        }
+        w.varint(self.last_accessed);
+        w.varint(self.first_seq);
+        w.varint(self.next_seq);
+        w.varint(self.item_count);
+        w.finish()
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, DecodeError> {
+        let mut r = Reader::new(data)?;
+        let parent_response_id = r.opt_str()?;
+        let usage = read_usage(&mut r)?;
+        let mut metadata = HashMap::new();
+        for _ in 0..r.varint()? {
+            metadata.insert(r.str()?, r.str()?);
+        }
+        let last_accessed = r.varint()?;
+        let (first_seq, next_seq) = (r.varint()?, r.varint()?);
+        let span = next_seq.checked_sub(first_seq).ok_or(DecodeError::Range(first_seq, next_seq))?;
+        // Rows written before trimming could leave gaps have no count
+        let item_count = if r.is_empty() { span } else { r.varint()? };
+        Ok(Self { parent_response_id, usage, metadata, last_accessed, first_seq, next_seq, item_count })
+    }
+}
+
+fn write_usage(w: &mut Writer, usage: Option<&ResponseUsage>) {
+    match usage {
+        Some(usage) => {
+            w.u8(1);
+            w.varint(usage.input_tokens.into());
+            w.varint(usage.output_tokens.into());
+            w.varint(usage.total_tokens.into());
+        }
+        None => w.u8(0),
+    }
+}
+
+fn read_usage(r: &mut Reader<'_>) -> Result<Option<ResponseUsage>, DecodeError> {
+    match r.u8()? {
+        0 => Ok(None),
+        1 => Ok(Some(ResponseUsage { input_tokens: r.u32()?, output_tokens: r.u32()?, total_tokens: r.u32()? })),
+        tag => Err(DecodeError::Tag("usage", tag)),
+    }
+}
+
+fn write_ids(w: &mut Writer, ids: &[String]) {
+    w.varint(ids.len() as u64);
+    for id in ids {
+        w.str(id);
+    }
+}
+
+fn read_ids(r: &mut Reader<'_>) -> Result<Vec<String>, DecodeError> {
+    let mut ids = Vec::new();
+    for _ in 0..r.varint()? {
+        ids.push(r.str()?);
+    }
+    Ok(ids)
+}
+
+/// A [`StoredResponse`] without its id, which is the row key
+fn encode_response(response: &StoredResponse) -> Vec<u8> {
+    let mut w = Writer::new();
+    w.str(&response.session_id);
+    w.varint(response.created);
+    w.str(&response.model);
+    write_ids(&mut w, &response.input_item_ids);
+    write_ids(&mut w, &response.output_item_ids);
+    write_usage(&mut w, response.usage.as_ref());
+    w.finish()
+}
+
+fn decode_response(id: &str, data: &[u8]) -> Result<StoredResponse, DecodeError> {
+    let mut r = Reader::new(data)?;
+    Ok(StoredResponse {
+        id: id.to_string(),
+        session_id: r.str()?,
+        created: r.varint()?,
+        model: r.str()?,
+        input_item_ids: read_ids(&mut r)?,
+        output_item_ids: read_ids(&mut r)?,
+        usage: read_usage(&mut r)?,
+    })
+}
+
+/// Metadata, items and item seqs of a stored session
+type StoredSession = (SessionMeta, Vec<ResponseItem>, Vec<u64>);
+
+/// Seqs of the live items of a cached session; its lock orders the writes of the session
+#[derive(Debug, Default)]
+struct SeqRange {
+    /// Seq of each item of the cached history
+    seqs: Vec<u64>,
+    next: u64,
+}
+
+impl SeqRange {
+    /// Seq of the item at `index` in the history followed by the items about to be appended
+    fn seq_at(&self, index: usize) -> u64 {
+        self.seqs.get(index).copied().unwrap_or_else(|| self.next + (index - self.seqs.len()) as u64)
+    }
+
+    /// Record an append of `count` items that removed the items at `trimmed`
+    fn appended(&mut self, count: usize, trimmed: &[usize]) {
+        let next = self.next + count as u64;
+        self.seqs.extend(self.next..next);
+        remove_indices(&mut self.seqs, trimmed);
+        self.next = next;
+    }
+}
+
+/// Idle time after which a session is dropped from the cache, staying on disk
+pub const DEFAULT_CACHE_IDLE: Duration = Duration::from_secs(600);
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
+}
+
+macro_rules! storage_error {
+    ($($error:ty),*) => {
+        $(impl From<$error> for SessionError {
+            fn from(err: $error) -> Self {
+                SessionError::Storage(err.to_string())
+            }
+        })*
+    };
+}
+
+storage_error!(
+    redb::Error,
+    redb::DatabaseError,
+    redb::TransactionError,
+    redb::TableError,
+    redb::StorageError,
+    redb::CommitError
+);
+
+/// When updates of a [`PersistentSessionStore`] are written to disk
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WriteMode {
+    /// Each update is committed before the call returns; failures are returned to the caller
+    #[default]
+    Sync,
+    /// Updates are queued and committed together every `flush_interval` by
+    /// [`PersistentSessionStore::spawn_flusher`]; failed batches are kept and retried
+    WriteBehind { flush_interval: Duration },
+    /// Each update is committed at once, but failures are only logged and counted
+    BestEffort,
+}
+
+/// One update of the database, committed alone or in a write-behind batch
+#[derive(Debug)]
+enum WriteOp {
+    /// New items stored from `first_seq`, the rows of the `trimmed` seqs and the index entries of
+    /// `dropped` removed
+    Append {
+        session_id: String,
+        first_seq: u64,
+        items: Vec<ResponseItem>,
+        trimmed: Vec<u64>,
+        dropped: Vec<String>,
+        meta: SessionMeta,
+    },
+    Parent { session_id: String, parent_response_id: String, meta: SessionMeta },
+    Meta { session_id: String, meta: SessionMeta },
+    Response(StoredResponse),
+    DeleteResponse { response_id: String, session_id: String },
+    /// Rows of the sessions, their responses and every index entry pointing to them
+    Delete { session_ids: Vec<String> },
 }
 
 pub struct PersistentSessionStore {
     cache: SessionStore,
     db: Arc<Database>,
-    write_lock: Mutex<()>,
+    /// Sessions loaded in `cache`
+    ranges: DashMap<String, Arc<Mutex<SeqRange>>>,
+    /// Bounds of `cache`, whose idle sessions are unloaded but kept on disk
+    cache_limits: SessionLimits,
+    mode: WriteMode,
+    /// Updates waiting for the next write-behind flush, oldest first
+    pending: std::sync::Mutex<Vec<WriteOp>>,
+    /// Held while a batch is taken and committed, so batches reach the disk in order
+    flush_lock: std::sync::Mutex<()>,
+    writes: AtomicU64,
+    write_failures: AtomicU64,
+    last_error: std::sync::Mutex<Option<String>>,
 }
 
 impl PersistentSessionStore {
     /// Open or create a redb-backed persistent session store at the given path
     pub fn new(db_path: &str) -> anyhow::Result<Self> {
         // Ensure parent directory exists if specified
-        if let Some(parent) = std::path::Path::new(db_path).parent() {
-            if !parent.as_os_str().is_empty() {
-                std::fs::create_dir_all(parent)?;
-            }
+        if let Some(parent) = std::path::Path::new(db_path).parent()
+            && !parent.as_os_str().is_empty()
+        {
+            std::fs::create_dir_all(parent)?;
         }
 
         let db = Arc::new(Database::create(db_path)?);
 
-        // Ensure tables exist
+        // Ensure tables exist and migrate the former layout
+        let write_txn = db.begin_write()?;
         {
-            let write_txn = db.begin_write()?;
-            {
-                let _ = write_txn.open_table(SESSIONS_TABLE)?;
-                let _ = write_txn.open_table(RESPONSE_INDEX_TABLE)?;
+            let _ = write_txn.open_table(ITEMS_TABLE)?;
+            let _ = write_txn.open_table(META_TABLE)?;
+            let _ = write_txn.open_table(RESPONSES_TABLE)?;
+            let _ = write_txn.open_table(RESPONSE_INDEX_TABLE)?;
+            let _ = write_txn.open_multimap_table(SESSION_RESPONSES_TABLE)?;
+        }
+        let migrated = Self::migrate_legacy_sessions(&write_txn)?;
+        write_txn.commit()?;
+        if migrated > 0 {
+            tracing::info!("Migrated {} sessions to the append-only layout", migrated);
+        }
+
+        Ok(Self {
+            cache: SessionStore::new(),
+            db,
+            ranges: DashMap::new(),
+            cache_limits: SessionLimits { ttl: Some(DEFAULT_CACHE_IDLE), ..SessionLimits::default() },
+            mode: WriteMode::default(),
+            pending: std::sync::Mutex::new(Vec::new()),
+            flush_lock: std::sync::Mutex::new(()),
+            writes: AtomicU64::new(0),
+            write_failures: AtomicU64::new(0),
+            last_error: std::sync::Mutex::new(None),
+        })
+    }
+
+    /// Expire and trim sessions according to `limits`
+    pub fn with_limits(mut self, limits: SessionLimits) -> Self {
+        self.cache = std::mem::take(&mut self.cache).with_limits(limits);
+        self
+    }
+
+    /// Keep sessions in memory according to the `ttl` and `max_sessions` of `limits`, unloading the
+    /// others on eviction; they stay on disk and are loaded again on their next use. The cache keeps
+    /// sessions idle for up to [`DEFAULT_CACHE_IDLE`] by default.
+    pub fn with_cache_limits(mut self, limits: SessionLimits) -> Self {
+        self.cache_limits = SessionLimits { max_history_items: None, ..limits };
+        self
+    }
+
+    /// Write updates to disk according to `mode`
+    pub fn with_write_mode(mut self, mode: WriteMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn write_mode(&self) -> WriteMode {
+        self.mode
+    }
+
+    /// Flush the write-behind updates of `store` every flush interval; `None` in the other modes.
+    /// The task ends once the store is dropped, which flushes what is left.
+    pub fn spawn_flusher(store: &Arc<Self>) -> Option<tokio::task::JoinHandle<()>> {
+        let WriteMode::WriteBehind { flush_interval } = store.mode else {
+            return None;
+        };
+        let store = Arc::downgrade(store);
+        Some(tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(flush_interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let Some(store) = store.upgrade() else { break };
+                // Failures are counted and the batch is retried on the next tick
+                let _ = store.flush();
             }
-            write_txn.commit()?;
+        }))
+    }
+
+    /// Commit the queued write-behind updates; on failure they stay queued for the next flush
+    pub fn flush(&self) -> Result<(), SessionError> {
+        let _flushing = self.flush_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let batch = std::mem::take(&mut *self.lock_pending());
+        if batch.is_empty() {
+            return Ok(());
+        }
+        let result = self.commit(&batch);
+        if result.is_err() {
+            self.requeue(batch);
         }
+        result
+    }
 
-        let cache = SessionStore::new();
+    fn lock_pending(&self) -> std::sync::MutexGuard<'_, Vec<WriteOp>> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
 
-        // Hydrate in-memory cache from database
-        let read_txn = db.begin_read()?;
-        if let Ok(table) = read_txn.open_table(SESSIONS_TABLE) {
-            for item in table.iter()? {
-                let (key, val) = item?;
-                let session_id = key.value();
-                if let Ok(record) = serde_json::from_slice::<PersistentSessionRecord>(&val.value()) {
-                    let session = cache.get_or_create(session_id);
-                    // Set items in cache
-                    if let Ok(mut items_guard) = session.items.try_write() {
-                        *items_guard = record.items.clone();
-                    }
+    /// Put a failed batch back ahead of the updates queued since
+    fn requeue(&self, mut batch: Vec<WriteOp>) {
+        let mut pending = self.lock_pending();
+        batch.append(&mut pending);
+        *pending = batch;
+    }
+
+    /// Commit `ops` in one transaction and count the outcome
+    fn commit(&self, ops: &[WriteOp]) -> Result<(), SessionError> {
+        let result = (|| -> Result<(), SessionError> {
+            let txn = self.db.begin_write()?;
+            for op in ops {
+                Self::apply(&txn, op)?;
+            }
+            txn.commit()?;
+            Ok(())
+        })();
+        match &result {
+            Ok(()) => {
+                self.writes.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(err) => {
+                self.write_failures.fetch_add(1, Ordering::Relaxed);
+                *self.last_error.lock().unwrap_or_else(|e| e.into_inner()) = Some(err.to_string());
+                tracing::warn!("Failed to write {} session updates: {}", ops.len(), err);
+            }
+        }
+        result
+    }
+
+    /// Write `op` according to the write mode
+    fn submit(&self, op: WriteOp) -> Result<(), SessionError> {
+        self.submit_all(vec![op])
+    }
+
+    /// Write `ops` in one transaction according to the write mode
+    fn submit_all(&self, ops: Vec<WriteOp>) -> Result<(), SessionError> {
+        match self.mode {
+            WriteMode::Sync => self.commit(&ops),
+            WriteMode::WriteBehind { .. } => {
+                self.lock_pending().extend(ops);
+                Ok(())
+            }
+            WriteMode::BestEffort => {
+                let _ = self.commit(&ops);
+                Ok(())
+            }
+        }
+    }
 
-                    if let Some(parent_id) = &record.parent_response_id {
-                        cache.response_to_session.insert(parent_id.clone(), session_id.to_string());
+    fn apply(txn: &WriteTransaction, op: &WriteOp) -> Result<(), SessionError> {
+        match op {
+            WriteOp::Append { session_id, first_seq, items, trimmed, dropped, meta } => {
+                let session_id = session_id.as_str();
+                {
+                    let mut items_table = txn.open_table(ITEMS_TABLE)?;
+                    let mut index = txn.open_table(RESPONSE_INDEX_TABLE)?;
+                    for (seq, item) in (*first_seq..).zip(items) {
+                        items_table.insert((session_id, seq), encode_item(item).as_slice())?;
+                        index.insert(item.id(), session_id)?;
+                    }
+                    for &seq in trimmed {
+                        items_table.remove((session_id, seq))?;
+                    }
+                    for item_id in dropped {
+                        index.remove(item_id.as_str())?;
+                    }
+                }
+                Self::put_meta(txn, session_id, meta)
+            }
+            WriteOp::Parent { session_id, parent_response_id, meta } => {
+                txn.open_table(RESPONSE_INDEX_TABLE)?.insert(parent_response_id.as_str(), session_id.as_str())?;
+                txn.open_multimap_table(SESSION_RESPONSES_TABLE)?
+                    .insert(session_id.as_str(), parent_response_id.as_str())?;
+                Self::put_meta(txn, session_id, meta)
+            }
+            WriteOp::Meta { session_id, meta } => Self::put_meta(txn, session_id, meta),
+            WriteOp::Response(response) => {
+                let (id, session_id) = (response.id.as_str(), response.session_id.as_str());
+                txn.open_table(RESPONSES_TABLE)?.insert(id, encode_response(response).as_slice())?;
+                txn.open_table(RESPONSE_INDEX_TABLE)?.insert(id, session_id)?;
+                txn.open_multimap_table(SESSION_RESPONSES_TABLE)?.insert(session_id, id)?;
+                Ok(())
+            }
+            WriteOp::DeleteResponse { response_id, session_id } => {
+                let (id, session_id) = (response_id.as_str(), session_id.as_str());
+                txn.open_table(RESPONSES_TABLE)?.remove(id)?;
+                txn.open_table(RESPONSE_INDEX_TABLE)?.remove(id)?;
+                txn.open_multimap_table(SESSION_RESPONSES_TABLE)?.remove(session_id, id)?;
+                Ok(())
+            }
+            WriteOp::Delete { session_ids } => {
+                let mut items_table = txn.open_table(ITEMS_TABLE)?;
+                let mut meta_table = txn.open_table(META_TABLE)?;
+                let mut responses_table = txn.open_table(RESPONSES_TABLE)?;
+                let mut index = txn.open_table(RESPONSE_INDEX_TABLE)?;
+                let mut responses = txn.open_multimap_table(SESSION_RESPONSES_TABLE)?;
+                for id in session_ids {
+                    let id = id.as_str();
+                    let mut indexed = Vec::new();
+                    for row in items_table.extract_from_if((id, 0)..=(id, u64::MAX), |_, _| true)? {
+                        indexed.push(decode_item(row?.1.value())?.id().to_string());
+                    }
+                    for response_id in responses.remove_all(id)? {
+                        indexed.push(response_id?.value().to_string());
                     }
-                    for item in &record.items {
-                        let item_id = match item {
-                            ResponseItem::Message { id, .. } => id.clone(),
-                            ResponseItem::Reasoning { id, .. } => id.clone(),
-                            ResponseItem::FunctionCall { id, .. } => id.clone(),
-                            ResponseItem::FunctionCallOutput { id, .. } => id.clone(),
-                        };
-                        cache.response_to_session.insert(item_id, session_id.to_string());
+                    for key in &indexed {
+                        index.remove(key.as_str())?;
+                        responses_table.remove(key.as_str())?;
                     }
+                    meta_table.remove(id)?;
                 }
+                Ok(())
             }
         }
+    }
 
-        Ok(Self {
-            cache,
-            db,
-            write_lock: Mutex::new(()),
-        })
+    /// Rewrite the sessions of the former `sessions` table as item rows, then drop the table.
+    /// A session that cannot be read fails the migration, which leaves the database untouched.
+    fn migrate_legacy_sessions(txn: &WriteTransaction) -> anyhow::Result<usize> {
+        if !txn.list_tables()?.any(|table| table.name() == LEGACY_SESSIONS_TABLE.name()) {
+            return Ok(0);
+        }
+        let mut records = Vec::new();
+        {
+            let legacy = txn.open_table(LEGACY_SESSIONS_TABLE)?;
+            for row in legacy.iter()? {
+                let (key, value) = row?;
+                let record = serde_json::from_slice::<PersistentSessionRecord>(&value.value()).map_err(|err| {
+                    anyhow::anyhow!("cannot migrate unreadable session {}: {}", key.value(), err)
+                })?;
+                records.push(record);
+            }
+        }
+
+        let now = unix_millis(SystemTime::now());
+        {
+            let mut items_table = txn.open_table(ITEMS_TABLE)?;
+            let mut meta_table = txn.open_table(META_TABLE)?;
+            let mut index = txn.open_table(RESPONSE_INDEX_TABLE)?;
+            let mut responses = txn.open_multimap_table(SESSION_RESPONSES_TABLE)?;
+            for record in &records {
+                let id = record.id.as_str();
+                for (seq, item) in record.items.iter().enumerate() {
+                    items_table.insert((id, seq as u64), encode_item(item).as_slice())?;
+                    index.insert(item.id(), id)?;
+                }
+                if let Some(parent) = &record.parent_response_id {
+                    index.insert(parent.as_str(), id)?;
+                    responses.insert(id, parent.as_str())?;
+                }
+                let meta = SessionMeta {
+                    parent_response_id: record.parent_response_id.clone(),
+                    usage: record.usage.clone(),
+                    metadata: record.metadata.clone(),
+                    last_accessed: record.last_accessed.map_or(now, |secs| secs * 1000),
+                    first_seq: 0,
+                    next_seq: record.items.len() as u64,
+                    item_count: record.items.len() as u64,
+                };
+                meta_table.insert(id, meta.encode().as_slice())?;
+            }
+        }
+        txn.delete_table(LEGACY_SESSIONS_TABLE)?;
+        Ok(records.len())
     }
 
-    async fn persist_session(&self, session_id: &str) -> anyhow::Result<()> {
-        let _lock = self.write_lock.lock().await;
-        let history = self.cache.get_history(session_id).await;
-        let parent_id = self.cache.get_parent_response_id(session_id).await;
+    /// Session of a response id, item id or session id, looked up in memory then on disk
+    fn lookup_session(&self, id: &str) -> Result<Option<String>, SessionError> {
+        if let Some(session_id) = self.cache.response_to_session.get(id) {
+            return Ok(Some(session_id.clone()));
+        }
+        if self.cache.sessions.contains_key(id) {
+            return Ok(Some(id.to_string()));
+        }
+        let read_txn = self.db.begin_read()?;
+        if let Some(session_id) = read_txn.open_table(RESPONSE_INDEX_TABLE)?.get(id)? {
+            return Ok(Some(session_id.value().to_string()));
+        }
+        if read_txn.open_table(META_TABLE)?.get(id)?.is_some() {
+            return Ok(Some(id.to_string()));
+        }
+        Ok(None)
+    }
 
-        let record = PersistentSessionRecord {
-            id: session_id.to_string(),
-            parent_response_id: parent_id.clone(),
-            items: history.clone(),
-            metadata: HashMap::new(),
+    /// Range lock of a session, loading the session into the cache when it is stored but not cached.
+    /// Sessions stored nowhere get an empty range when `create` is set, to be written by their first update.
+    fn load(&self, session_id: &str, create: bool) -> Result<Option<Arc<Mutex<SeqRange>>>, SessionError> {
+        if let Some(range) = self.ranges.get(session_id) {
+            return Ok(Some(range.clone()));
+        }
+
+        // Read without holding the shard of `ranges`; a concurrent load of the session wins
+        let stored = self.read_session(session_id)?;
+        let entry = match self.ranges.entry(session_id.to_string()) {
+            Entry::Occupied(entry) => return Ok(Some(entry.get().clone())),
+            Entry::Vacant(entry) => entry,
+        };
+        let Some((meta, items, seqs)) = stored else {
+            return Ok(create.then(|| entry.insert(Arc::new(Mutex::new(SeqRange::default()))).clone()));
         };
 
-        let encoded = serde_json::to_vec(&record)?;
+        for item in &items {
+            self.cache.response_to_session.insert(item.id().to_string(), session_id.to_string());
+        }
+        if let Some(parent) = &meta.parent_response_id {
+            self.cache.response_to_session.insert(parent.clone(), session_id.to_string());
+        }
+        self.cache.sessions.insert(
+            session_id.to_string(),
+            Session {
+                id: session_id.to_string(),
+                parent_response_id: meta.parent_response_id,
+                items: Arc::new(RwLock::new(items)),
+                metadata: meta.metadata,
+                usage: meta.usage,
+                last_accessed: UNIX_EPOCH + Duration::from_millis(meta.last_accessed),
+            },
+        );
+        let range = SeqRange { seqs, next: meta.next_seq };
+        Ok(Some(entry.insert(Arc::new(Mutex::new(range))).clone()))
+    }
 
-        let write_txn = self.db.begin_write()?;
-        {
-            let mut sess_table = write_txn.open_table(SESSIONS_TABLE)?;
-            sess_table.insert(session_id, encoded)?;
+    /// The session as stored, `None` when it is not
+    fn read_session(&self, session_id: &str) -> Result<Option<StoredSession>, SessionError> {
+        let read_txn = self.db.begin_read()?;
+        let Some(meta) = read_txn.open_table(META_TABLE)?.get(session_id)? else {
+            return Ok(None);
+        };
+        let meta = SessionMeta::decode(meta.value())?;
+        // The count comes from disk, so it only bounds the preallocation
+        let capacity = meta.item_count.min(1024) as usize;
+        let (mut items, mut seqs) = (Vec::with_capacity(capacity), Vec::with_capacity(capacity));
+        let items_table = read_txn.open_table(ITEMS_TABLE)?;
+        for row in items_table.range((session_id, meta.first_seq)..(session_id, meta.next_seq))? {
+            let (key, value) = row?;
+            seqs.push(key.value().1);
+            items.push(decode_item(value.value())?);
+        }
+        Ok(Some((meta, items, seqs)))
+    }
 
-            let mut resp_table = write_txn.open_table(RESPONSE_INDEX_TABLE)?;
-            if let Some(parent) = parent_id {
-                resp_table.insert(parent.as_str(), session_id)?;
+    /// Whether `range` is the range of the session as loaded in the cache
+    fn is_loaded(&self, session_id: &str, range: &Arc<Mutex<SeqRange>>) -> bool {
+        self.ranges.get(session_id).is_some_and(|current| Arc::ptr_eq(&current, range))
+    }
+
+    /// Range lock of a session about to be written, held. A session unloaded while the lock was
+    /// awaited is loaded again, so the write starts from the session as stored.
+    async fn lock_range(&self, session_id: &str) -> Result<OwnedMutexGuard<SeqRange>, SessionError> {
+        loop {
+            let range = self
+                .load(session_id, true)?
+                .ok_or_else(|| SessionError::Storage(format!("session {} has no item range", session_id)))?;
+            let guard = range.clone().lock_owned().await;
+            if self.is_loaded(session_id, &range) {
+                return Ok(guard);
             }
-            for item in &history {
-                let item_id = match item {
-                    ResponseItem::Message { id, .. } => id.as_str(),
-                    ResponseItem::Reasoning { id, .. } => id.as_str(),
-                    ResponseItem::FunctionCall { id, .. } => id.as_str(),
-                    ResponseItem::FunctionCallOutput { id, .. } => id.as_str(),
-                };
-                resp_table.insert(item_id, session_id)?;
+        }
+    }
+
+    /// Whether a read of the cache after `load` returned `range` saw the session; reads made
+    /// while the session was unloaded are repeated
+    fn read_loaded(&self, session_id: &str, range: Option<Arc<Mutex<SeqRange>>>) -> bool {
+        range.is_none_or(|range| self.is_loaded(session_id, &range))
+    }
+
+    /// Drop the cached sessions beyond the cache limits from memory, keeping them on disk. Sessions
+    /// being written stay; the queued updates are flushed first, as an unloaded session is read
+    /// back from disk.
+    fn unload_idle(&self) -> Result<Vec<String>, SessionError> {
+        let cached = self.cache.sessions.iter().map(|s| (s.last_accessed, s.key().clone())).collect();
+        let idle = self.cache_limits.select_evictions(SystemTime::now(), cached);
+        let locked: Vec<_> = idle
+            .into_iter()
+            .filter_map(|id| {
+                let range = self.ranges.get(&id)?.clone();
+                let guard = range.clone().try_lock_owned().ok()?;
+                Some((id, range, guard))
+            })
+            .collect();
+        if locked.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.flush()?;
+        let mut unloaded = Vec::new();
+        for (id, range, _guard) in &locked {
+            // Removed under the entry, so a concurrent load sees the session either loaded or not
+            if let Entry::Occupied(entry) = self.ranges.entry(id.clone())
+                && Arc::ptr_eq(entry.get(), range)
+            {
+                self.cache.sessions.remove(id);
+                entry.remove();
+                unloaded.push(id.clone());
             }
         }
-        write_txn.commit()?;
+        self.cache.remove_responses(&unloaded);
+        Ok(unloaded)
+    }
+
+    /// The append of `items` to a session whose range lock is held, storing `meta` with the new
+    /// item range, and the positions of the items it trims. The range lock serializes the writes
+    /// of the session, so the trim computed here is the one the cache applies once the rows are stored.
+    async fn append_op(
+        &self,
+        session_id: &str,
+        range: &SeqRange,
+        items: &[ResponseItem],
+        mut meta: SessionMeta,
+    ) -> (WriteOp, Vec<usize>) {
+        let (trimmed, dropped): (Vec<usize>, Vec<String>) =
+            self.cache.trimmed_items(session_id, items).await.into_iter().unzip();
+        // The positions increase, so the first item kept is the first position they skip
+        let first_kept = trimmed.iter().enumerate().take_while(|(position, index)| position == *index).count();
+        meta.first_seq = range.seq_at(first_kept);
+        meta.next_seq = range.next + items.len() as u64;
+        meta.item_count = (range.seqs.len() + items.len() - trimmed.len()) as u64;
+        meta.last_accessed = unix_millis(SystemTime::now());
+        let append = WriteOp::Append {
+            session_id: session_id.to_string(),
+            first_seq: range.next,
+            items: items.to_vec(),
+            trimmed: trimmed.iter().map(|&index| range.seq_at(index)).collect(),
+            dropped,
+            meta,
+        };
+        (append, trimmed)
+    }
+
+    /// Metadata row of a cached session
+    fn meta(&self, session_id: &str, range: &SeqRange) -> SessionMeta {
+        let session = self.cache.sessions.get(session_id);
+        SessionMeta {
+            parent_response_id: session.as_ref().and_then(|s| s.parent_response_id.clone()),
+            usage: session.as_ref().and_then(|s| s.usage.clone()),
+            metadata: session.as_ref().map(|s| s.metadata.clone()).unwrap_or_default(),
+            last_accessed: unix_millis(session.map_or_else(SystemTime::now, |s| s.last_accessed)),
+            first_seq: range.seq_at(0),
+            next_seq: range.next,
+            item_count: range.seqs.len() as u64,
+        }
+    }
+
+    fn put_meta(txn: &WriteTransaction, session_id: &str, meta: &SessionMeta) -> Result<(), SessionError> {
+        txn.open_table(META_TABLE)?.insert(session_id, meta.encode().as_slice())?;
         Ok(())
     }
+
+    /// Store the metadata of a cached session
+    async fn persist_meta(&self, session_id: &str) -> Result<(), SessionError> {
+        let range = self.lock_range(session_id).await?;
+        let meta = self.meta(session_id, &range);
+        self.submit(WriteOp::Meta { session_id: session_id.to_string(), meta })
+    }
+
+    /// Write a deletion. In write-behind mode it is committed at once, after the queued updates
+    /// and in the same transaction, so they cannot bring back what it deletes. A failure is
+    /// returned as in sync mode; the queued updates are then kept for the next flush, the
+    /// deletion is not.
+    fn submit_delete(&self, delete: WriteOp) -> Result<(), SessionError> {
+        if !matches!(self.mode, WriteMode::WriteBehind { .. }) {
+            return self.submit(delete);
+        }
+        let _flushing = self.flush_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut batch = std::mem::take(&mut *self.lock_pending());
+        batch.push(delete);
+        let result = self.commit(&batch);
+        if result.is_err() {
+            batch.pop();
+            self.requeue(batch);
+        }
+        result
+    }
+
+    /// Last access of every session, stored or only cached, the cached time being newer for loaded sessions
+    fn last_accesses(&self) -> Result<Vec<(SystemTime, String)>, SessionError> {
+        let read_txn = self.db.begin_read()?;
+        let mut sessions = Vec::new();
+        for row in read_txn.open_table(META_TABLE)?.iter()? {
+            let (key, value) = row?;
+            let id = key.value().to_string();
+            let stored = UNIX_EPOCH + Duration::from_millis(SessionMeta::decode(value.value())?.last_accessed);
+            let accessed = self.cache.last_accessed(&id).map_or(stored, |cached| cached.max(stored));
+            sessions.push((accessed, id));
+        }
+        // Sessions whose first update is still queued
+        let stored: std::collections::HashSet<String> = sessions.iter().map(|(_, id)| id.clone()).collect();
+        for session in self.cache.sessions.iter() {
+            if !stored.contains(session.key()) {
+                sessions.push((session.last_accessed, session.key().clone()));
+            }
+        }
+        Ok(sessions)
+    }
+}
+
+impl Drop for PersistentSessionStore {
+    fn drop(&mut self) {
+        let pending = self.lock_pending().len();
+        if self.flush().is_err() {
+            tracing::error!("Lost {} queued session updates on shutdown", pending);
+        }
+    }
 }
 
 #[async_trait::async_trait]
 impl SessionStoreApi for PersistentSessionStore {
-    async fn resolve_session(&self, previous_response_id: Option<&str>) -> Session {
+    async fn resolve_session(&self, previous_response_id: Option<&str>) -> Result<Session, SessionError> {
+        if let Some(previous) = previous_response_id
+            && let Some(session_id) = self.lookup_session(previous)?
+        {
+            loop {
+                let range = self.load(&session_id, false)?;
+                let session = self.cache.get_or_create(&session_id);
+                if self.read_loaded(&session_id, range) {
+                    return Ok(session);
+                }
+            }
+        }
         let session = self.cache.resolve_session(previous_response_id).await;
-        let _ = self.persist_session(&session.id).await;
-        session
+        if let Err(err) = self.persist_meta(&session.id).await {
+            // A session that could not be stored is not handed out
+            self.cache.remove_session(&session.id);
+            self.ranges.remove(&session.id);
+            return Err(err);
+        }
+        Ok(session)
     }
 
-    async fn append_items(&self, session_id: &str, items: &[ResponseItem]) -> Vec<ResponseItem> {
-        let updated = self.cache.append_items(session_id, items).await;
-        let _ = self.persist_session(session_id).await;
-        updated
+    async fn append_items(&self, session_id: &str, items: &[ResponseItem]) -> Result<Vec<ResponseItem>, SessionError> {
+        let mut range = self.lock_range(session_id).await?;
+        let meta = self.meta(session_id, &range);
+        let (append, trimmed) = self.append_op(session_id, &range, items, meta).await;
+        self.submit(append)?;
+        range.appended(items.len(), &trimmed);
+        Ok(self.cache.append_items(session_id, items).await)
     }
 
-    async fn get_history(&self, session_id: &str) -> Vec<ResponseItem> {
-        self.cache.get_history(session_id).await
+    async fn get_history(&self, session_id: &str) -> Result<Vec<ResponseItem>, SessionError> {
+        loop {
+            let range = self.load(session_id, false)?;
+            let history = self.cache.get_history(session_id).await;
+            if self.read_loaded(session_id, range) {
+                return Ok(history);
+            }
+        }
     }
 
-    async fn set_parent_response_id(&self, session_id: &str, parent_response_id: String) {
+    async fn set_parent_response_id(&self, session_id: &str, parent_response_id: String) -> Result<(), SessionError> {
+        let range = self.lock_range(session_id).await?;
+        let mut meta = self.meta(session_id, &range);
+        meta.parent_response_id = Some(parent_response_id.clone());
+        self.submit(WriteOp::Parent {
+            session_id: session_id.to_string(),
+            parent_response_id: parent_response_id.clone(),
+            meta,
+        })?;
         self.cache.set_parent_response_id(session_id, parent_response_id).await;
-        let _ = self.persist_session(session_id).await;
+        Ok(())
     }
 
-    async fn get_parent_response_id(&self, session_id: &str) -> Option<String> {
-        self.cache.get_parent_response_id(session_id).await
+    async fn get_parent_response_id(&self, session_id: &str) -> Result<Option<String>, SessionError> {
+        loop {
+            let range = self.load(session_id, false)?;
+            let parent = self.cache.get_parent_response_id(session_id).await;
+            if self.read_loaded(session_id, range) {
+                return Ok(parent);
+            }
+        }
+    }
+
+    async fn record_usage(&self, session_id: &str, usage: &ResponseUsage) -> Result<(), SessionError> {
+        let range = self.lock_range(session_id).await?;
+        let mut meta = self.meta(session_id, &range);
+        let total = meta.usage.get_or_insert(ResponseUsage { input_tokens: 0, output_tokens: 0, total_tokens: 0 });
+        total.input_tokens += usage.input_tokens;
+        total.output_tokens += usage.output_tokens;
+        total.total_tokens += usage.total_tokens;
+        self.submit(WriteOp::Meta { session_id: session_id.to_string(), meta })?;
+        self.cache.record_usage(session_id, usage).await;
+        Ok(())
+    }
+
+    async fn get_usage(&self, session_id: &str) -> Result<Option<ResponseUsage>, SessionError> {
+        loop {
+            let range = self.load(session_id, false)?;
+            let usage = self.cache.get_usage(session_id).await;
+            if self.read_loaded(session_id, range) {
+                return Ok(usage);
+            }
+        }
+    }
+
+    async fn remove_session(&self, session_id: &str) -> Result<bool, SessionError> {
+        let stored = self.lookup_session(session_id)?.is_some_and(|id| id == session_id);
+        // A failed deletion leaves the session in the cache, where it can still be saved
+        self.submit_delete(WriteOp::Delete { session_ids: vec![session_id.to_string()] })?;
+        let removed = self.cache.remove_session(session_id) || stored;
+        self.ranges.remove(session_id);
+        Ok(removed)
+    }
+
+    async fn evict_expired(&self) -> Result<Vec<String>, SessionError> {
+        let sessions = self.last_accesses()?;
+        let evicted = self.cache.limits().select_evictions(SystemTime::now(), sessions);
+        if !evicted.is_empty() {
+            self.submit_delete(WriteOp::Delete { session_ids: evicted.clone() })?;
+            self.cache.remove_sessions(&evicted);
+            for id in &evicted {
+                self.ranges.remove(id);
+            }
+        }
+        // Unloaded sessions are still stored, so they are not reported as evicted
+        if let Err(err) = self.unload_idle() {
+            tracing::warn!("Kept idle sessions cached: {}", err);
+        }
+        Ok(evicted)
+    }
+
+    async fn list_sessions(
+        &self,
+        after: Option<&str>,
+        descending: bool,
+        limit: usize,
+    ) -> Result<Vec<SessionSummary>, SessionError> {
+        let mut sessions = BTreeMap::new();
+        {
+            let read_txn = self.db.begin_read()?;
+            let meta_table = read_txn.open_table(META_TABLE)?;
+            let bounds = match (after, descending) {
+                (Some(cursor), false) => (Bound::Excluded(cursor), Bound::Unbounded),
+                (Some(cursor), true) => (Bound::Unbounded, Bound::Excluded(cursor)),
+                (None, _) => (Bound::Unbounded, Bound::Unbounded),
+            };
+            let rows = meta_table.range::<&str>(bounds)?;
+            let rows: Box<dyn Iterator<Item = _>> = if descending { Box::new(rows.rev()) } else { Box::new(rows) };
+            for row in rows.take(limit) {
+                let (key, value) = row?;
+                let meta = SessionMeta::decode(value.value())?;
+                let summary = SessionSummary {
+                    id: key.value().to_string(),
+                    parent_response_id: meta.parent_response_id,
+                    metadata: meta.metadata,
+                    usage: meta.usage,
+                    item_count: meta.item_count as usize,
+                    last_accessed: UNIX_EPOCH + Duration::from_millis(meta.last_accessed),
+                };
+                sessions.insert(summary.id.clone(), summary);
+            }
+        }
+        // Loaded sessions are up to date in the cache, including updates still queued
+        for summary in self.cache.list_sessions(after, descending, limit).await {
+            sessions.insert(summary.id.clone(), summary);
+        }
+        let mut page: Vec<SessionSummary> = sessions.into_values().collect();
+        if descending {
+            page.reverse();
+        }
+        page.truncate(limit);
+        Ok(page)
+    }
+
+    async fn get_session(&self, session_id: &str) -> Result<Option<Session>, SessionError> {
+        loop {
+            let range = self.load(session_id, false)?;
+            let session = self.cache.get_session(session_id);
+            if self.read_loaded(session_id, range) {
+                return Ok(session);
+            }
+        }
+    }
+
+    async fn save_response(&self, response: StoredResponse) -> Result<(), SessionError> {
+        self.submit(WriteOp::Response(response.clone()))?;
+        self.cache.save_response(response);
+        Ok(())
+    }
+
+    async fn get_response(&self, response_id: &str) -> Result<Option<StoredResponse>, SessionError> {
+        if let Some(response) = self.cache.get_response(response_id) {
+            return Ok(Some(response));
+        }
+        let read_txn = self.db.begin_read()?;
+        match read_txn.open_table(RESPONSES_TABLE)?.get(response_id)? {
+            Some(row) => Ok(Some(decode_response(response_id, row.value())?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn delete_response(&self, response_id: &str) -> Result<bool, SessionError> {
+        let Some(response) = self.get_response(response_id).await? else {
+            return Ok(false);
+        };
+        self.submit_delete(WriteOp::DeleteResponse {
+            response_id: response_id.to_string(),
+            session_id: response.session_id.clone(),
+        })?;
+        self.cache.delete_response(response_id);
+        self.cache.response_to_session.remove_if(response_id, |_, sid| *sid == response.session_id);
+        Ok(true)
+    }
+
+    async fn save_turn(&self, response: StoredResponse, output_items: &[ResponseItem]) -> Result<(), SessionError> {
+        let session_id = response.session_id.clone();
+        let mut range = self.lock_range(&session_id).await?;
+        let mut meta = self.meta(&session_id, &range);
+        meta.parent_response_id = Some(response.id.clone());
+        if let Some(usage) = &response.usage {
+            let total = meta.usage.get_or_insert(ResponseUsage { input_tokens: 0, output_tokens: 0, total_tokens: 0 });
+            total.input_tokens += usage.input_tokens;
+            total.output_tokens += usage.output_tokens;
+            total.total_tokens += usage.total_tokens;
+        }
+        let (append, trimmed) = self.append_op(&session_id, &range, output_items, meta).await;
+        // The response row also indexes its id under the session
+        self.submit_all(vec![append, WriteOp::Response(response.clone())])?;
+        range.appended(output_items.len(), &trimmed);
+
+        self.cache.append_items(&session_id, output_items).await;
+        self.cache.set_parent_response_id(&session_id, response.id.clone()).await;
+        if let Some(usage) = &response.usage {
+            self.cache.record_usage(&session_id, usage).await;
+        }
+        self.cache.save_response(response);
+        Ok(())
+    }
+
+    fn stats(&self) -> SessionStoreStats {
+        SessionStoreStats {
+            writes: self.writes.load(Ordering::Relaxed),
+            write_failures: self.write_failures.load(Ordering::Relaxed),
+            pending_writes: self.lock_pending().len() as u64,
+            last_error: self.last_error.lock().unwrap_or_else(|e| e.into_inner()).clone(),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::session::encoding::FORMAT_VERSION;
     use agent_models::response_item::{ContentPart, Role};
+    use redb::ReadableTableMetadata;
+
+    fn temp_db() -> (std::path::PathBuf, String) {
+        let temp_dir = std::env::temp_dir().join(format!("swarm_test_redb_{}", uuid::Uuid::new_v4()));
+        let db_path = temp_dir.join("test_session.redb").to_str().unwrap().to_string();
+        (temp_dir, db_path)
+    }
+
+    fn msg(id: &str) -> ResponseItem {
+        ResponseItem::Message {
+            id: id.to_string(),
+            role: Role::User,
+            content: vec![ContentPart::Text { text: id.to_string() }],
+        }
+    }
+
+    fn index_keys(store: &PersistentSessionStore) -> Vec<String> {
+        let read_txn = store.db.begin_read().unwrap();
+        let index = read_txn.open_table(RESPONSE_INDEX_TABLE).unwrap();
+        index.iter().unwrap().map(|e| e.unwrap().0.value().to_string()).collect()
+    }
 
     #[tokio::test]
     async fn test_persistent_session_store_lifecycle() {
@@ -163,7 +1048,7 @@ mod tests {
         // 1. Create and write to store
         {
             let store = PersistentSessionStore::new(db_path_str).unwrap();
-            let session = store.resolve_session(None).await;
+            let session = store.resolve_session(None).await.unwrap();
             let msg = ResponseItem::Message {
                 id: "msg_persist_1".to_string(),
                 role: Role::User,
@@ -171,16 +1056,20 @@ mod tests {
                     text: "Persistent greeting".to_string(),
                 }],
             };
-            store.append_items(&session.id, &[msg]).await;
-            store.set_parent_response_id(&session.id, "resp_parent_100".to_string()).await;
+            store.append_items(&session.id, &[msg]).await.unwrap();
+            store.set_parent_response_id(&session.id, "resp_parent_100".to_string()).await.unwrap();
+            let usage = ResponseUsage { input_tokens: 3, output_tokens: 4, total_tokens: 7 };
+            store.record_usage(&session.id, &usage).await.unwrap();
+            store.record_usage(&session.id, &usage).await.unwrap();
         }
 
         // 2. Re-open and verify persistence
         {
             let store2 = PersistentSessionStore::new(db_path_str).unwrap();
-            let resolved = store2.resolve_session(Some("resp_parent_100")).await;
-            let history = store2.get_history(&resolved.id).await;
+            let resolved = store2.resolve_session(Some("resp_parent_100")).await.unwrap();
+            let history = store2.get_history(&resolved.id).await.unwrap();
             assert_eq!(history.len(), 1);
+            assert_eq!(store2.get_usage(&resolved.id).await.unwrap().map(|u| u.total_tokens), Some(14));
             if let ResponseItem::Message { content, .. } = &history[0] {
                 if let ContentPart::Text { text } = &content[0] {
                     assert_eq!(text, "Persistent greeting");
@@ -194,4 +1083,381 @@ mod tests {
 
         let _ = std::fs::remove_dir_all(temp_dir);
     }
+
+    #[tokio::test]
+    async fn test_idle_sessions_leave_the_cache_but_stay_stored() {
+        let (temp_dir, db_path) = temp_db();
+        let mode = WriteMode::WriteBehind { flush_interval: Duration::from_secs(3600) };
+        let cache_limits = SessionLimits { max_sessions: Some(1), ..SessionLimits::default() };
+        let store = PersistentSessionStore::new(&db_path).unwrap().with_write_mode(mode).with_cache_limits(cache_limits);
+        store.append_items("idle", &[msg("msg_idle_1")]).await.unwrap();
+        let written = SystemTime::now();
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        store.append_items("busy", &[msg("msg_busy")]).await.unwrap();
+
+        // Unloading is no eviction, and flushes the queued updates of the session first
+        assert!(store.evict_expired().await.unwrap().is_empty());
+        assert_eq!(store.cache.sessions.len(), 1);
+        assert!(!store.ranges.contains_key("idle"));
+
+        // Loaded again with its stored last access, and written after its stored items
+        let session = store.get_session("idle").await.unwrap().unwrap();
+        assert!(session.last_accessed <= written);
+        store.append_items("idle", &[msg("msg_idle_2")]).await.unwrap();
+        store.flush().unwrap();
+        let ids: Vec<String> = store.get_history("idle").await.unwrap().iter().map(|item| item.id().to_string()).collect();
+        assert_eq!(ids, ["msg_idle_1", "msg_idle_2"]);
+        assert_eq!(store.read_session("idle").unwrap().unwrap().1.len(), 2);
+
+        let _ = std::fs::remove_dir_all(temp_dir);
+    }
+
+    #[tokio::test]
+    async fn test_persistent_session_store_deletes_evicted_sessions() {
+        let (temp_dir, db_path) = temp_db();
+        let limits = SessionLimits { max_sessions: Some(1), max_history_items: Some(1), ..SessionLimits::default() };
+
+        {
+            let store = PersistentSessionStore::new(&db_path).unwrap().with_limits(limits.clone());
+            store.append_items("old", &[msg("msg_old")]).await.unwrap();
+            store.set_parent_response_id("old", "resp_old".to_string()).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            store.append_items("new", &[msg("msg_new_1"), msg("msg_new_2")]).await.unwrap();
+            assert_eq!(store.evict_expired().await.unwrap(), vec!["old"]);
+        }
+
+        let store = PersistentSessionStore::new(&db_path).unwrap();
+        assert!(store.get_history("old").await.unwrap().is_empty());
+        assert_eq!(store.get_history("new").await.unwrap().len(), 1);
+        assert_eq!(index_keys(&store), vec!["msg_new_2"]);
+        drop(store);
+
+        let _ = std::fs::remove_dir_all(temp_dir);
+    }
+
+    #[tokio::test]
+    async fn test_persistent_session_store_keeps_system_messages_when_trimming() {
+        let (temp_dir, db_path) = temp_db();
+        let limits = SessionLimits { max_history_items: Some(2), ..SessionLimits::default() };
+        let system = ResponseItem::Message {
+            id: "sys".to_string(),
+            role: Role::System,
+            content: vec![ContentPart::Text { text: "Be brief.".to_string() }],
+        };
+        {
+            let store = PersistentSessionStore::new(&db_path).unwrap().with_limits(limits.clone());
+            store.append_items("s", &[system.clone(), msg("u1"), msg("u2")]).await.unwrap();
+            store.append_items("s", &[msg("u3")]).await.unwrap();
+            assert_eq!(store.get_history("s").await.unwrap(), vec![system.clone(), msg("u2"), msg("u3")]);
+        }
+
+        let store = PersistentSessionStore::new(&db_path).unwrap().with_limits(limits);
+        {
+            let read_txn = store.db.begin_read().unwrap();
+            let items = read_txn.open_table(ITEMS_TABLE).unwrap();
+            let seqs: Vec<u64> = items.iter().unwrap().map(|r| r.unwrap().0.value().1).collect();
+            assert_eq!(seqs, vec![0, 2, 3]);
+        }
+        assert_eq!(store.list_sessions(None, false, 1).await.unwrap()[0].item_count, 3);
+        assert_eq!(store.get_history("s").await.unwrap(), vec![system.clone(), msg("u2"), msg("u3")]);
+        store.append_items("s", &[msg("u4")]).await.unwrap();
+        assert_eq!(store.get_history("s").await.unwrap(), vec![system, msg("u3"), msg("u4")]);
+        assert_eq!(index_keys(&store), vec!["sys", "u3", "u4"]);
+        drop(store);
+
+        let _ = std::fs::remove_dir_all(temp_dir);
+    }
+
+    #[tokio::test]
+    async fn test_persistent_session_store_appends_rows_and_loads_lazily() {
+        let (temp_dir, db_path) = temp_db();
+        {
+            let store = PersistentSessionStore::new(&db_path).unwrap();
+            for i in 0..20 {
+                store.append_items("s", &[msg(&format!("m{i}"))]).await.unwrap();
+            }
+            store.set_parent_response_id("s", "resp_1".to_string()).await.unwrap();
+            store.set_parent_response_id("s", "resp_2".to_string()).await.unwrap();
+        }
+
+        let store = PersistentSessionStore::new(&db_path).unwrap();
+        assert!(store.cache.sessions.is_empty());
+        {
+            let read_txn = store.db.begin_read().unwrap();
+            let items = read_txn.open_table(ITEMS_TABLE).unwrap();
+            let seqs: Vec<u64> = items.iter().unwrap().map(|r| r.unwrap().0.value().1).collect();
+            assert_eq!(seqs, (0..20).collect::<Vec<_>>());
+        }
+
+        // An older response id still resolves to its session, which is then loaded
+        assert_eq!(store.resolve_session(Some("resp_1")).await.unwrap().id, "s");
+        assert_eq!(store.cache.sessions.len(), 1);
+        assert_eq!(store.get_history("s").await.unwrap().len(), 20);
+        assert_eq!(store.get_parent_response_id("s").await.unwrap().as_deref(), Some("resp_2"));
+
+        assert!(store.remove_session("s").await.unwrap());
+        assert!(index_keys(&store).is_empty());
+        drop(store);
+        let store = PersistentSessionStore::new(&db_path).unwrap();
+        assert!(store.get_history("s").await.unwrap().is_empty());
+        assert_eq!(store.resolve_session(Some("resp_2")).await.unwrap().id, "resp_2");
+        drop(store);
+
+        let _ = std::fs::remove_dir_all(temp_dir);
+    }
+
+    #[tokio::test]
+    async fn test_persistent_session_store_migrates_legacy_sessions() {
+        let (temp_dir, db_path) = temp_db();
+        {
+            std::fs::create_dir_all(&temp_dir).unwrap();
+            let db = Database::create(&db_path).unwrap();
+            let write_txn = db.begin_write().unwrap();
+            {
+                let mut legacy = write_txn.open_table(LEGACY_SESSIONS_TABLE).unwrap();
+                let record = PersistentSessionRecord {
+                    id: "legacy".to_string(),
+                    parent_response_id: Some("resp_legacy".to_string()),
+                    items: vec![msg("m1"), msg("m2")],
+                    metadata: HashMap::new(),
+                    usage: Some(ResponseUsage { input_tokens: 1, output_tokens: 2, total_tokens: 3 }),
+                    last_accessed: None,
+                };
+                legacy.insert("legacy", serde_json::to_vec(&record).unwrap()).unwrap();
+            }
+            write_txn.commit().unwrap();
+        }
+
+        let store = PersistentSessionStore::new(&db_path).unwrap();
+        let session = store.resolve_session(Some("resp_legacy")).await.unwrap();
+        assert_eq!(session.id, "legacy");
+        assert_eq!(store.get_history("legacy").await.unwrap(), vec![msg("m1"), msg("m2")]);
+        assert_eq!(store.get_usage("legacy").await.unwrap().map(|u| u.total_tokens), Some(3));
+        store.append_items("legacy", &[msg("m3")]).await.unwrap();
+        {
+            let read_txn = store.db.begin_read().unwrap();
+            assert!(read_txn.list_tables().unwrap().all(|t| t.name() != LEGACY_SESSIONS_TABLE.name()));
+            assert_eq!(read_txn.open_table(ITEMS_TABLE).unwrap().len().unwrap(), 3);
+        }
+        drop(store);
+
+        let _ = std::fs::remove_dir_all(temp_dir);
+    }
+
+    #[test]
+    fn test_persistent_session_store_keeps_legacy_table_when_migration_fails() {
+        let (temp_dir, db_path) = temp_db();
+        std::fs::create_dir_all(&temp_dir).unwrap();
+        {
+            let db = Database::create(&db_path).unwrap();
+            let write_txn = db.begin_write().unwrap();
+            {
+                let mut legacy = write_txn.open_table(LEGACY_SESSIONS_TABLE).unwrap();
+                let record = PersistentSessionRecord {
+                    id: "good".to_string(),
+                    parent_response_id: None,
+                    items: vec![msg("m1")],
+                    metadata: HashMap::new(),
+                    usage: None,
+                    last_accessed: None,
+                };
+                legacy.insert("good", serde_json::to_vec(&record).unwrap()).unwrap();
+                legacy.insert("truncated", b"{\"id\": \"trunc".to_vec()).unwrap();
+            }
+            write_txn.commit().unwrap();
+        }
+
+        let err = PersistentSessionStore::new(&db_path).err().unwrap();
+        assert!(err.to_string().contains("truncated"), "{err}");
+        let db = Database::create(&db_path).unwrap();
+        let read_txn = db.begin_read().unwrap();
+        assert_eq!(read_txn.open_table(LEGACY_SESSIONS_TABLE).unwrap().len().unwrap(), 2);
+        assert!(read_txn.list_tables().unwrap().all(|t| t.name() != ITEMS_TABLE.name()));
+        drop(read_txn);
+        drop(db);
+
+        let _ = std::fs::remove_dir_all(temp_dir);
+    }
+
+    #[tokio::test]
+    async fn test_persistent_session_store_write_behind_batches_updates() {
+        let (temp_dir, db_path) = temp_db();
+        let mode = WriteMode::WriteBehind { flush_interval: Duration::from_millis(10) };
+        let store = Arc::new(PersistentSessionStore::new(&db_path).unwrap().with_write_mode(mode));
+        store.append_items("s", &[msg("m1")]).await.unwrap();
+        store.set_parent_response_id("s", "resp_1".to_string()).await.unwrap();
+        assert_eq!(store.stats().pending_writes, 2);
+        assert!(index_keys(&store).is_empty());
+        // Queued updates are already visible through the cache
+        assert_eq!(store.get_history("s").await.unwrap(), vec![msg("m1")]);
+
+        let flusher = PersistentSessionStore::spawn_flusher(&store).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let stats = store.stats();
+        assert_eq!((stats.writes, stats.write_failures, stats.pending_writes), (1, 0, 0));
+        assert_eq!(index_keys(&store), vec!["m1", "resp_1"]);
+
+        // A removal commits the queued updates first, so they cannot bring the session back
+        store.append_items("t", &[msg("t1")]).await.unwrap();
+        assert!(store.remove_session("t").await.unwrap());
+        assert_eq!(store.stats().pending_writes, 0);
+
+        // Updates still queued are flushed when the store is dropped
+        store.append_items("s", &[msg("m2")]).await.unwrap();
+        drop(store);
+        flusher.await.unwrap();
+        let store = PersistentSessionStore::new(&db_path).unwrap();
+        assert_eq!(store.get_history("s").await.unwrap(), vec![msg("m1"), msg("m2")]);
+        assert!(store.get_history("t").await.unwrap().is_empty());
+        assert_eq!(store.stats(), SessionStoreStats::default());
+        drop(store);
+
+        let _ = std::fs::remove_dir_all(temp_dir);
+    }
+
+    #[tokio::test]
+    async fn test_persistent_session_store_saves_a_turn_in_one_transaction() {
+        let (temp_dir, db_path) = temp_db();
+        let response = StoredResponse {
+            id: "resp_1".to_string(),
+            session_id: "s".to_string(),
+            created: 1_700_000_000,
+            model: "swarm-fast-v1".to_string(),
+            input_item_ids: vec!["in_1".to_string()],
+            output_item_ids: vec!["out_1".to_string(), "out_2".to_string()],
+            usage: Some(ResponseUsage { input_tokens: 1, output_tokens: 2, total_tokens: 3 }),
+        };
+        {
+            let store = PersistentSessionStore::new(&db_path).unwrap();
+            store.append_items("s", &[msg("in_1")]).await.unwrap();
+            let writes = store.stats().writes;
+            store.save_turn(response.clone(), &[msg("out_1"), msg("out_2")]).await.unwrap();
+            assert_eq!(store.stats().writes, writes + 1);
+        }
+
+        let store = PersistentSessionStore::new(&db_path).unwrap();
+        assert_eq!(store.resolve_session(Some("resp_1")).await.unwrap().id, "s");
+        assert_eq!(store.resolve_session(Some("out_2")).await.unwrap().id, "s");
+        assert_eq!(store.get_history("s").await.unwrap(), vec![msg("in_1"), msg("out_1"), msg("out_2")]);
+        assert_eq!(store.get_parent_response_id("s").await.unwrap().as_deref(), Some("resp_1"));
+        assert_eq!(store.get_usage("s").await.unwrap().map(|u| u.total_tokens), Some(3));
+        assert_eq!(store.get_response("resp_1").await.unwrap(), Some(response));
+        drop(store);
+
+        let _ = std::fs::remove_dir_all(temp_dir);
+    }
+
+    #[tokio::test]
+    async fn test_persistent_session_store_reports_failed_deletions() {
+        let (temp_dir, db_path) = temp_db();
+        let mode = WriteMode::WriteBehind { flush_interval: Duration::from_secs(60) };
+        let store = PersistentSessionStore::new(&db_path).unwrap().with_write_mode(mode);
+        store.append_items("s", &[msg("m1")]).await.unwrap();
+        store.flush().unwrap();
+        store.append_items("s", &[msg("m2")]).await.unwrap();
+        {
+            // An unreadable row makes the deletion fail
+            let write_txn = store.db.begin_write().unwrap();
+            write_txn.open_table(ITEMS_TABLE).unwrap().insert(("s", 0), [FORMAT_VERSION, 99].as_slice()).unwrap();
+            write_txn.commit().unwrap();
+        }
+
+        let err = store.remove_session("s").await.unwrap_err();
+        assert!(matches!(err, SessionError::Corrupted(_)), "{err}");
+        // The session is still served and the queued append is kept for the next flush
+        assert_eq!(store.get_history("s").await.unwrap(), vec![msg("m1"), msg("m2")]);
+        assert_eq!(store.stats().pending_writes, 1);
+        assert_eq!(store.stats().write_failures, 1);
+        drop(store);
+
+        let _ = std::fs::remove_dir_all(temp_dir);
+    }
+
+    #[tokio::test]
+    async fn test_persistent_session_store_returns_corruption_errors() {
+        let (temp_dir, db_path) = temp_db();
+        let store = PersistentSessionStore::new(&db_path).unwrap();
+        {
+            let write_txn = store.db.begin_write().unwrap();
+            write_txn.open_table(META_TABLE).unwrap().insert("broken", [FORMAT_VERSION, 7].as_slice()).unwrap();
+            write_txn.commit().unwrap();
+        }
+
+        let corrupted = SessionError::Corrupted(DecodeError::Tag("option", 7));
+        assert_eq!(store.get_history("broken").await.unwrap_err(), corrupted);
+        assert_eq!(store.resolve_session(Some("broken")).await.unwrap_err(), corrupted);
+        assert_eq!(store.append_items("broken", &[msg("m1")]).await.unwrap_err(), corrupted);
+        assert!(store.cache.sessions.is_empty());
+        assert!(store.ranges.is_empty());
+
+        {
+            let reversed = SessionMeta { first_seq: 5, next_seq: 2, ..SessionMeta::default() };
+            let write_txn = store.db.begin_write().unwrap();
+            {
+                let mut meta_table = write_txn.open_table(META_TABLE).unwrap();
+                meta_table.remove("broken").unwrap();
+                meta_table.insert("reversed", reversed.encode().as_slice()).unwrap();
+            }
+            write_txn.commit().unwrap();
+        }
+        let corrupted = SessionError::Corrupted(DecodeError::Range(5, 2));
+        assert_eq!(store.get_history("reversed").await.unwrap_err(), corrupted);
+        assert_eq!(store.list_sessions(None, false, 10).await.unwrap_err(), corrupted);
+        drop(store);
+
+        let _ = std::fs::remove_dir_all(temp_dir);
+    }
+
+    #[tokio::test]
+    async fn test_persistent_session_store_keeps_responses_and_lists_sessions() {
+        let (temp_dir, db_path) = temp_db();
+        let response = StoredResponse {
+            id: "resp_1".to_string(),
+            session_id: "s".to_string(),
+            created: 1_700_000_000,
+            model: "swarm-fast-v1".to_string(),
+            input_item_ids: vec!["in_1".to_string()],
+            output_item_ids: vec!["out_1".to_string()],
+            usage: Some(ResponseUsage { input_tokens: 1, output_tokens: 2, total_tokens: 3 }),
+        };
+        {
+            let store = PersistentSessionStore::new(&db_path).unwrap();
+            store.append_items("s", &[msg("in_1"), msg("out_1")]).await.unwrap();
+            store.save_response(response.clone()).await.unwrap();
+            store.save_response(StoredResponse { id: "resp_2".to_string(), ..response.clone() }).await.unwrap();
+            store.append_items("t", &[msg("t1")]).await.unwrap();
+        }
+
+        let store = PersistentSessionStore::new(&db_path).unwrap();
+        assert_eq!(store.get_response("resp_1").await.unwrap(), Some(response.clone()));
+        let sessions = store.list_sessions(None, false, 10).await.unwrap();
+        let counts: Vec<(&str, usize)> = sessions.iter().map(|s| (s.id.as_str(), s.item_count)).collect();
+        assert_eq!(counts, vec![("s", 2), ("t", 1)]);
+        assert!(store.cache.sessions.is_empty());
+
+        // Pages merge the sessions on disk with the ones loaded in the cache
+        store.append_items("ss", &[msg("ss1")]).await.unwrap();
+        store.append_items("t", &[msg("t2")]).await.unwrap();
+        let page = |sessions: Vec<SessionSummary>| -> Vec<(String, usize)> {
+            sessions.into_iter().map(|s| (s.id, s.item_count)).collect()
+        };
+        let sessions = store.list_sessions(Some("s"), false, 2).await.unwrap();
+        assert_eq!(page(sessions), vec![("ss".to_string(), 1), ("t".to_string(), 2)]);
+        let sessions = store.list_sessions(Some("t"), true, 2).await.unwrap();
+        assert_eq!(page(sessions), vec![("ss".to_string(), 1), ("s".to_string(), 2)]);
+        assert_eq!(store.list_sessions(None, true, 1).await.unwrap()[0].id, "t");
+
+        // A deleted response no longer resolves to its session
+        assert!(store.delete_response("resp_1").await.unwrap());
+        assert!(!store.delete_response("resp_1").await.unwrap());
+        assert_eq!(store.resolve_session(Some("resp_1")).await.unwrap().id, "resp_1");
+        assert_eq!(store.resolve_session(Some("resp_2")).await.unwrap().id, "s");
+
+        assert!(store.remove_session("s").await.unwrap());
+        assert_eq!(store.get_response("resp_2").await.unwrap(), None);
+        assert!(store.get_session("s").await.unwrap().is_none());
+        drop(store);
+
+        let _ = std::fs::remove_dir_all(temp_dir);
+    }
 }
@@ -9,14 +9,14 @@ use tower::ServiceExt; // for oneshot
 
 use agent_core::server::gateway_server::GatewayServer;
 use agent_core::session::SessionStore;
-use agent_models::response_item::{ResponseItem, ResponseObject};
-use llm_api::chat::ChatCompletionResponse;
+use agent_models::response_item::{ContentPart, ResponseItem, ResponseObject};
+use llm_api::chat::{ChatCompletionChunk, ChatCompletionResponse};
 use llm_api::google_interactions::GoogleInteractionsAdapter;
 
 #[tokio::test]
 async fn test_chat_completions_stateless_endpoint() {
     let session_store = Arc::new(SessionStore::new());
-    let server = GatewayServer::with_default_backend(session_store);
+    let server = GatewayServer::with_default_backend(session_store.clone());
     let app = server.router();
 
     let request_body = json!({
@@ -47,6 +47,8 @@ async fn test_chat_completions_stateless_endpoint() {
     assert!(chat_resp.usage.prompt_tokens > 0);
     assert_eq!(chat_resp.usage.completion_tokens, 10);
     assert_eq!(chat_resp.usage.total_tokens, chat_resp.usage.prompt_tokens + 10);
+    // Nothing of a chat completion is kept
+    assert!(session_store.list_sessions(None, false, 1).await.is_empty());
 }
 
 #[tokio::test]
@@ -148,10 +150,33 @@ async fn test_responses_sse_streaming() {
     let body_bytes = res.into_body().collect().await.unwrap().to_bytes();
     let body_str = String::from_utf8_lossy(&body_bytes);
 
-    assert!(body_str.contains("event: response.item"));
+    let events = sse_json_events(&body_str);
+    let types: Vec<&str> = events.iter().map(|e| e["type"].as_str().unwrap()).collect();
+    assert_eq!(
+        types,
+        vec![
+            "response.created",
+            "response.output_item.added",
+            "response.output_text.delta",
+            "response.output_item.done",
+            "response.completed",
+        ]
+    );
+    assert!(body_str.contains("event: response.output_text.delta"));
+    assert_eq!(events[4]["response"]["status"], "completed");
+    assert!(events[4]["response"]["usage"]["total_tokens"].as_u64().unwrap() > 0);
     assert!(body_str.contains("data: [DONE]"));
 }
 
+/// JSON payloads of the `data:` lines of an SSE body, without the `[DONE]` marker
+fn sse_json_events(body: &str) -> Vec<serde_json::Value> {
+    body.lines()
+        .filter_map(|line| line.strip_prefix("data: "))
+        .filter(|data| *data != "[DONE]")
+        .map(|data| serde_json::from_str(data).unwrap())
+        .collect()
+}
+
 #[tokio::test]
 async fn test_chat_completions_sse_streaming() {
     let session_store = Arc::new(SessionStore::new());
@@ -163,7 +188,8 @@ async fn test_chat_completions_sse_streaming() {
         "messages": [
             {"role": "user", "content": "Stream chat completion"}
         ],
-        "stream": true
+        "stream": true,
+        "stream_options": {"include_usage": true}
     });
 
     let req = Request::builder()
@@ -181,4 +207,1512 @@ async fn test_chat_completions_sse_streaming() {
 
     assert!(body_str.contains("data:"));
     assert!(body_str.contains("[DONE]"));
+
+    // The echo backend yields the same chunk shapes as an OpenAI upstream
+    let chunks: Vec<ChatCompletionChunk> = sse_json_events(&body_str)
+        .into_iter()
+        .map(|c| serde_json::from_value(c).unwrap())
+        .collect();
+    assert_eq!(chunks.len(), 4);
+    assert_eq!(chunks[0].choices[0].delta.role.as_deref(), Some("assistant"));
+    assert!(chunks[1].choices[0].delta.content.as_deref().unwrap().contains("Stream chat completion"));
+    assert_eq!(chunks[2].choices[0].finish_reason.as_deref(), Some("stop"));
+    assert!(chunks[3].choices.is_empty());
+    assert!(chunks[3].usage.as_ref().unwrap().total_tokens > 0);
+}
+
+/// Spawn a local OpenAI-compatible upstream: `/fail` answers 503, `/too_long` rejects the prompt as too long, `/empty` returns no choices, `/ok` answers normally,
+/// `/tools` calls the first tool it was given (echoing the received tool_choice as arguments),
+/// `/params` answers with the sampling parameters it received as JSON text,
+/// `/stream` streams a text delta followed by a tool call split over several argument fragments,
+/// `/gemini/{model}` answers Gemini `streamGenerateContent` with text chunks and a function call,
+/// `/anthropic/v1/messages` answers the Messages API (streamed when asked) with thinking, text and a tool_use,
+/// `/responses` answers the Open Responses API (streamed when asked), describing the chaining it received
+async fn spawn_mock_upstream() -> String {
+    use axum::{routing::post, Router};
+
+    async fn fail() -> (StatusCode, &'static str) {
+        (StatusCode::SERVICE_UNAVAILABLE, "upstream overloaded")
+    }
+    async fn too_long() -> (StatusCode, axum::Json<serde_json::Value>) {
+        (StatusCode::BAD_REQUEST, axum::Json(json!({"error": {
+            "message": "This model's maximum context length is 8192 tokens",
+            "type": "invalid_request_error",
+            "code": "context_length_exceeded"
+        }})))
+    }
+    async fn empty() -> axum::Json<serde_json::Value> {
+        axum::Json(json!({
+            "id": "chatcmpl-empty", "object": "chat.completion", "created": 0, "model": "empty-model",
+            "choices": [],
+            "usage": {"prompt_tokens": 1, "completion_tokens": 0, "total_tokens": 1}
+        }))
+    }
+    async fn ok() -> axum::Json<serde_json::Value> {
+        axum::Json(json!({
+            "id": "chatcmpl-ok", "object": "chat.completion", "created": 0, "model": "backup-model",
+            "choices": [{"index": 0, "message": {"role": "assistant", "content": "served by backup"}, "finish_reason": "stop"}],
+            "usage": {"prompt_tokens": 3, "completion_tokens": 3, "total_tokens": 6}
+        }))
+    }
+
+    async fn tools(axum::Json(req): axum::Json<serde_json::Value>) -> axum::Json<serde_json::Value> {
+        let name = req["tools"][0]["function"]["name"].as_str().unwrap_or("no_tools_received");
+        let arguments = json!({"tool_choice": req["tool_choice"]}).to_string();
+        axum::Json(json!({
+            "id": "chatcmpl-tools", "object": "chat.completion", "created": 0, "model": "tool-model",
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": null, "tool_calls": [
+                    {"id": "call_upstream_1", "type": "function", "function": {"name": name, "arguments": arguments}}
+                ]},
+                "finish_reason": "tool_calls"
+            }],
+            "usage": {"prompt_tokens": 5, "completion_tokens": 5, "total_tokens": 10}
+        }))
+    }
+
+    async fn params(axum::Json(req): axum::Json<serde_json::Value>) -> axum::Json<serde_json::Value> {
+        let received = json!({
+            "temperature": req["temperature"],
+            "top_p": req["top_p"],
+            "max_tokens": req["max_tokens"],
+            "stop": req["stop"],
+        });
+        axum::Json(json!({
+            "id": "chatcmpl-params", "object": "chat.completion", "created": 0, "model": "param-model",
+            "choices": [{"index": 0, "message": {"role": "assistant", "content": received.to_string()}, "finish_reason": "stop"}],
+            "usage": {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2}
+        }))
+    }
+
+    async fn stream() -> impl axum::response::IntoResponse {
+        let chunks = [
+            json!({"choices": [{"index": 0, "delta": {"role": "assistant", "content": "Looking "}}]}),
+            json!({"choices": [{"index": 0, "delta": {"content": "it up"}}]}),
+            json!({"choices": [{"index": 0, "delta": {"tool_calls": [
+                {"index": 0, "id": "call_s1", "type": "function", "function": {"name": "lookup", "arguments": ""}}
+            ]}}]}),
+            json!({"choices": [{"index": 0, "delta": {"tool_calls": [{"index": 0, "function": {"arguments": "{\"q\":"}}]}}]}),
+            json!({"choices": [{"index": 0, "delta": {"tool_calls": [{"index": 0, "function": {"arguments": "\"rust\"}"}}]}}]}),
+            json!({
+                "choices": [{"index": 0, "delta": {}, "finish_reason": "tool_calls"}],
+                "usage": {"prompt_tokens": 4, "completion_tokens": 6, "total_tokens": 10}
+            }),
+        ];
+        let mut body: String = chunks.iter().map(|c| format!("data: {}\n\n", c)).collect();
+        body.push_str("data: [DONE]\n\n");
+        ([("content-type", "text/event-stream")], body)
+    }
+
+    async fn gemini_stream(axum::extract::Path(model): axum::extract::Path<String>) -> impl axum::response::IntoResponse {
+        assert_eq!(model, "gemini-stream-model:streamGenerateContent");
+        let chunks = [
+            json!({"candidates": [{"content": {"role": "model", "parts": [{"text": "Looking "}]}}]}),
+            json!({"candidates": [{"content": {"role": "model", "parts": [{"text": "it up"}]}}]}),
+            json!({
+                "candidates": [{"content": {"role": "model", "parts": [
+                    {"functionCall": {"id": "gem_call_1", "name": "lookup", "args": {"q": "rust"}}}
+                ]}, "finishReason": "STOP"}],
+                "usageMetadata": {"promptTokenCount": 4, "candidatesTokenCount": 6, "totalTokenCount": 10}
+            }),
+        ];
+        let body: String = chunks.iter().map(|c| format!("data: {}\r\n\r\n", c)).collect();
+        ([("content-type", "text/event-stream")], body)
+    }
+
+    async fn anthropic_messages(
+        headers: axum::http::HeaderMap,
+        axum::Json(body): axum::Json<serde_json::Value>,
+    ) -> axum::response::Response {
+        use axum::response::IntoResponse;
+
+        assert_eq!(headers["x-api-key"], "test-key");
+        assert_eq!(headers["anthropic-version"], "2023-06-01");
+        assert_eq!(body["model"], "claude-mock");
+        // System prompts travel outside the turns, which must alternate starting with the user
+        assert_eq!(body["system"], "Be brief.");
+        assert_eq!(body["messages"][0]["role"], "user");
+
+        if body["stream"] != true {
+            return axum::Json(json!({
+                "id": "msg_mock", "type": "message", "role": "assistant", "model": "claude-mock",
+                "content": [
+                    {"type": "thinking", "thinking": "Search needed.", "signature": "sig-mock"},
+                    {"type": "text", "text": "Looking it up"},
+                    {"type": "tool_use", "id": "toolu_mock", "name": "lookup", "input": {"q": "rust"}}
+                ],
+                "stop_reason": "tool_use",
+                "usage": {"input_tokens": 4, "output_tokens": 6}
+            }))
+            .into_response();
+        }
+        let events = [
+            json!({"type": "message_start", "message": {"id": "msg_mock", "type": "message", "role": "assistant", "model": "claude-mock", "content": [], "usage": {"input_tokens": 4, "output_tokens": 1}}}),
+            json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Looking "}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "it up"}}),
+            json!({"type": "content_block_stop", "index": 0}),
+            json!({"type": "content_block_start", "index": 1, "content_block": {"type": "tool_use", "id": "toolu_mock", "name": "lookup", "input": {}}}),
+            json!({"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "{\"q\":"}}),
+            json!({"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "\"rust\"}"}}),
+            json!({"type": "content_block_stop", "index": 1}),
+            json!({"type": "message_delta", "delta": {"stop_reason": "tool_use"}, "usage": {"output_tokens": 6}}),
+            json!({"type": "message_stop"}),
+        ];
+        let body: String = events
+            .iter()
+            .map(|e| format!("event: {}\ndata: {}\n\n", e["type"].as_str().unwrap(), e))
+            .collect();
+        ([("content-type", "text/event-stream")], body).into_response()
+    }
+
+    async fn responses(axum::Json(body): axum::Json<serde_json::Value>) -> axum::response::Response {
+        use axum::response::IntoResponse;
+
+        let input_len = body["input"].as_array().map(Vec::len).unwrap_or(0);
+        let previous = body["previous_response_id"].as_str().unwrap_or("none").to_string();
+        let response_id = format!("up_{}", previous);
+        let response = json!({
+            "id": response_id, "object": "response", "created": 0, "model": "responses-model",
+            "status": "completed",
+            "output": [{
+                "type": "message", "id": format!("up_msg_{}", previous), "role": "assistant",
+                "content": [{"type": "text", "text": format!("prev={} items={}", previous, input_len)}]
+            }],
+            "usage": {"input_tokens": 2, "output_tokens": 3, "total_tokens": 5}
+        });
+        if body["stream"] != true {
+            return axum::Json(response).into_response();
+        }
+        let events = [
+            json!({"type": "response.created", "sequence_number": 0,
+                "response": {"id": response_id, "object": "response", "created": 0, "model": "responses-model", "output": []}}),
+            json!({"type": "response.in_progress", "sequence_number": 1}),
+            json!({"type": "response.output_item.added", "sequence_number": 2, "output_index": 0,
+                "item": {"type": "message", "id": format!("up_msg_{}", previous), "role": "assistant", "content": []}}),
+            json!({"type": "response.output_text.delta", "sequence_number": 3, "item_id": format!("up_msg_{}", previous),
+                "output_index": 0, "content_index": 0, "delta": format!("prev={} ", previous)}),
+            json!({"type": "response.output_text.delta", "sequence_number": 4, "item_id": format!("up_msg_{}", previous),
+                "output_index": 0, "content_index": 0, "delta": format!("items={}", input_len)}),
+            json!({"type": "response.output_item.done", "sequence_number": 5, "output_index": 0, "item": response["output"][0]}),
+            json!({"type": "response.completed", "sequence_number": 6, "response": response}),
+        ];
+        let mut body: String = events
+            .iter()
+            .map(|e| format!("event: {}\ndata: {}\n\n", e["type"].as_str().unwrap(), e))
+            .collect();
+        body.push_str("data: [DONE]\n\n");
+        ([("content-type", "text/event-stream")], body).into_response()
+    }
+
+    // Every other call fails with 503, starting with the first one
+    let flaky_hits = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let flaky = move |axum::Json(body): axum::Json<serde_json::Value>| {
+        let hits = flaky_hits.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        async move {
+            use axum::response::IntoResponse;
+            if hits.is_multiple_of(2) {
+                return (StatusCode::SERVICE_UNAVAILABLE, "warming up").into_response();
+            }
+            if body["stream"] == true {
+                let chunk = json!({
+                    "id": "chatcmpl-flaky", "object": "chat.completion.chunk", "created": 0, "model": "flaky-model",
+                    "choices": [{"index": 0, "delta": {"content": "served after retry"}, "finish_reason": "stop"}]
+                });
+                return ([("content-type", "text/event-stream")], format!("data: {}\n\ndata: [DONE]\n\n", chunk)).into_response();
+            }
+            axum::Json(json!({
+                "id": "chatcmpl-flaky", "object": "chat.completion", "created": 0, "model": "flaky-model",
+                "choices": [{"index": 0, "message": {"role": "assistant", "content": "served after retry"}, "finish_reason": "stop"}],
+                "usage": {"prompt_tokens": 1, "completion_tokens": 3, "total_tokens": 4}
+            }))
+            .into_response()
+        }
+    };
+
+    let app = Router::new()
+        .route("/flaky", post(flaky))
+        .route("/responses", post(responses))
+        .route("/anthropic/v1/messages", post(anthropic_messages))
+        .route("/gemini/{model}", post(gemini_stream))
+        .route("/stream", post(stream))
+        .route("/params", post(params))
+        .route("/tools", post(tools))
+        .route("/fail", post(fail))
+        .route("/too_long", post(too_long))
+        .route("/empty", post(empty))
+        .route("/ok", post(ok));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    format!("http://{}", addr)
+}
+
+fn mock_gateway_backend(base_url: &str, mock_fallback: bool) -> agent_core::MultiModelGatewayBackend {
+    let config: agent_core::server::gateway_server::GatewayConfigFile = toml::from_str(&format!(r#"
+        [models]
+        mock_fallback = {mock_fallback}
+
+        [models.fallbacks]
+        "primary-model" = ["empty-model", "backup-model"]
+        "lonely-model" = ["empty-model"]
+        "failing-stream-model" = ["stream-model"]
+
+        [models.generation."param-model"]
+        temperature = 0.4
+        max_temperature = 1.0
+        max_output_tokens_limit = 100
+
+        [providers.primary]
+        api_url = "{base_url}/fail"
+        requires_api_key = false
+        retry = {{ max_attempts = 1 }}
+        recommended_models = ["primary-model", "lonely-model", "failing-stream-model"]
+
+        [providers.empty]
+        api_url = "{base_url}/empty"
+        requires_api_key = false
+        recommended_models = ["empty-model"]
+
+        [providers.overflow]
+        api_url = "{base_url}/too_long"
+        requires_api_key = false
+        recommended_models = ["overflow-model"]
+
+        [providers.backup]
+        api_url = "{base_url}/ok"
+        requires_api_key = false
+        recommended_models = ["backup-model"]
+
+        [providers.flaky]
+        api_url = "{base_url}/flaky"
+        requires_api_key = false
+        recommended_models = ["flaky-model"]
+        retry = {{ max_attempts = 2, base_delay_ms = 1 }}
+
+        [providers.tooling]
+        api_url = "{base_url}/tools"
+        requires_api_key = false
+        recommended_models = ["tool-model"]
+
+        [providers.sampling]
+        api_url = "{base_url}/params"
+        requires_api_key = false
+        recommended_models = ["param-model"]
+
+        [providers.streaming]
+        api_url = "{base_url}/stream"
+        requires_api_key = false
+        recommended_models = ["stream-model"]
+
+        [providers.gemini_mock]
+        protocol = "gemini"
+        api_url = "{base_url}/gemini"
+        api_key = "test-key"
+        recommended_models = ["gemini-stream-model"]
+
+        [providers.anthropic_mock]
+        protocol = "anthropic"
+        api_url = "{base_url}/anthropic/v1/messages"
+        api_key = "test-key"
+        recommended_models = ["claude-mock"]
+
+        [providers.responses_mock]
+        protocol = "openai_responses"
+        api_url = "{base_url}/responses"
+        requires_api_key = false
+        recommended_models = ["responses-model"]
+    "#)).unwrap();
+    agent_core::MultiModelGatewayBackend::from_config(&config)
+}
+
+#[tokio::test]
+async fn test_gateway_fallback_chain_failover() {
+    let base_url = spawn_mock_upstream().await;
+    let backend = Arc::new(mock_gateway_backend(&base_url, false));
+    let server = GatewayServer::new(Arc::new(SessionStore::new()), backend);
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/responses")
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_vec(&json!({"model": "primary-model", "input": "hello"})).unwrap()))
+        .unwrap();
+
+    let res = server.router().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body_bytes = res.into_body().collect().await.unwrap().to_bytes();
+    let resp_obj: ResponseObject = serde_json::from_slice(&body_bytes).unwrap();
+    // 503 on primary and empty output on the second hop both fail over to the backup
+    assert_eq!(resp_obj.model, "backup-model");
+    match &resp_obj.output[0] {
+        ResponseItem::Message { content, .. } => {
+            assert_eq!(content[0], agent_models::response_item::ContentPart::Text { text: "served by backup".to_string() });
+        }
+        _ => panic!("Expected message"),
+    }
+}
+
+#[tokio::test]
+async fn test_gateway_maps_upstream_errors_to_openai_errors() {
+    let base_url = spawn_mock_upstream().await;
+    let backend = Arc::new(mock_gateway_backend(&base_url, false));
+    let server = GatewayServer::new(Arc::new(SessionStore::new()), backend);
+
+    let chat_request = |model: &str| {
+        Request::builder()
+            .method("POST")
+            .uri("/v1/chat/completions")
+            .header("Content-Type", "application/json")
+            .body(Body::from(
+                serde_json::to_vec(&json!({"model": model, "messages": [{"role": "user", "content": "hello"}]})).unwrap(),
+            ))
+            .unwrap()
+    };
+    let error_body = |bytes: &[u8]| serde_json::from_slice::<serde_json::Value>(bytes).unwrap()["error"].clone();
+
+    // A client error is passed through and not failed over
+    let res = server.router().oneshot(chat_request("overflow-model")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let error = error_body(&res.into_body().collect().await.unwrap().to_bytes());
+    assert_eq!(error["code"], "context_length_exceeded");
+    assert_eq!(error["type"], "invalid_request_error");
+    assert!(error["message"].as_str().unwrap().contains("maximum context length"));
+
+    // The chain ends on the primary's 503 followed by an empty answer
+    let res = server.router().oneshot(chat_request("lonely-model")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let error = error_body(&res.into_body().collect().await.unwrap().to_bytes());
+    assert_eq!(error["type"], "server_error");
+    assert_eq!(error["code"], "upstream_error");
+}
+
+#[tokio::test]
+async fn test_gateway_retries_provider_per_retry_policy() {
+    let base_url = spawn_mock_upstream().await;
+    let backend = Arc::new(mock_gateway_backend(&base_url, false));
+    let server = GatewayServer::new(Arc::new(SessionStore::new()), backend);
+
+    for stream in [false, true] {
+        let req = Request::builder()
+            .method("POST")
+            .uri("/v1/chat/completions")
+            .header("Content-Type", "application/json")
+            .body(Body::from(
+                serde_json::to_vec(&json!({
+                    "model": "flaky-model",
+                    "stream": stream,
+                    "messages": [{"role": "user", "content": "hello"}]
+                }))
+                .unwrap(),
+            ))
+            .unwrap();
+        // The first attempt gets a 503 and the retry is answered
+        let res = server.router().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = String::from_utf8(res.into_body().collect().await.unwrap().to_bytes().to_vec()).unwrap();
+        assert!(body.contains("served after retry"), "unexpected body: {}", body);
+    }
+}
+
+#[tokio::test]
+async fn test_gateway_mock_fallback_is_opt_in() {
+    use agent_core::GatewayBackend;
+
+    let base_url = spawn_mock_upstream().await;
+    let history = vec![ResponseItem::Message {
+        id: "msg_1".to_string(),
+        role: agent_models::response_item::Role::User,
+        content: vec![agent_models::response_item::ContentPart::Text { text: "hello".to_string() }],
+    }];
+
+    let strict = mock_gateway_backend(&base_url, false);
+    let err = strict.process_turn("s1", &history, Some("lonely-model"), &[], &Default::default()).await.unwrap_err();
+    let err = err.to_string();
+    assert!(err.contains("All providers failed for model 'lonely-model'"));
+    assert!(err.contains("returned no output"));
+
+    let lenient = mock_gateway_backend(&base_url, true);
+    let result = lenient.process_turn("s2", &history, Some("lonely-model"), &[], &Default::default()).await.unwrap();
+    assert_eq!(result.items.len(), 1);
+}
+
+#[tokio::test]
+async fn test_chat_completions_forward_tools_upstream() {
+    let base_url = spawn_mock_upstream().await;
+    let backend = Arc::new(mock_gateway_backend(&base_url, false));
+    let server = GatewayServer::new(Arc::new(SessionStore::new()), backend);
+
+    let request_body = json!({
+        "model": "tool-model",
+        "messages": [{"role": "user", "content": "Weather in Paris?"}],
+        "tools": [{
+            "type": "function",
+            "function": {
+                "name": "get_weather",
+                "description": "Current weather for a city",
+                "parameters": {"type": "object", "properties": {"city": {"type": "string"}}, "required": ["city"]}
+            }
+        }],
+        "tool_choice": "required"
+    });
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/chat/completions")
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_vec(&request_body).unwrap()))
+        .unwrap();
+
+    let res = server.router().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body_bytes = res.into_body().collect().await.unwrap().to_bytes();
+    let chat_resp: ChatCompletionResponse = serde_json::from_slice(&body_bytes).unwrap();
+    assert_eq!(chat_resp.choices[0].finish_reason, "tool_calls");
+    let tool_calls = chat_resp.choices[0].message.tool_calls.as_ref().unwrap();
+    assert_eq!(tool_calls[0].id, "call_upstream_1");
+    assert_eq!(tool_calls[0].function.name, "get_weather");
+    assert!(tool_calls[0].function.arguments.contains("required"));
+}
+
+#[tokio::test]
+async fn test_responses_forward_tools_upstream() {
+    let base_url = spawn_mock_upstream().await;
+    let backend = Arc::new(mock_gateway_backend(&base_url, false));
+    let server = GatewayServer::new(Arc::new(SessionStore::new()), backend);
+
+    let request_body = json!({
+        "model": "tool-model",
+        "input": "Weather in Paris?",
+        "tools": [{
+            "type": "function",
+            "name": "get_weather",
+            "description": "Current weather for a city",
+            "parameters": {"type": "object", "properties": {"city": {"type": "string"}}}
+        }],
+        "tool_choice": {"type": "function", "name": "get_weather"}
+    });
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/responses")
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_vec(&request_body).unwrap()))
+        .unwrap();
+
+    let res = server.router().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body_bytes = res.into_body().collect().await.unwrap().to_bytes();
+    let resp_obj: ResponseObject = serde_json::from_slice(&body_bytes).unwrap();
+    match &resp_obj.output[0] {
+        ResponseItem::FunctionCall { call_id, name, arguments, .. } => {
+            assert_eq!(call_id, "call_upstream_1");
+            assert_eq!(name, "get_weather");
+            // The flat Responses tool_choice reaches upstream in chat completions form
+            let echoed: serde_json::Value = serde_json::from_str(arguments).unwrap();
+            assert_eq!(echoed["tool_choice"]["function"]["name"], "get_weather");
+        }
+        other => panic!("Expected function call, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_chat_completions_generation_params_are_clamped() {
+    let base_url = spawn_mock_upstream().await;
+    let backend = Arc::new(mock_gateway_backend(&base_url, false));
+    let server = GatewayServer::new(Arc::new(SessionStore::new()), backend);
+
+    let request_body = json!({
+        "model": "param-model",
+        "messages": [{"role": "user", "content": "hi"}],
+        "temperature": 1.7,
+        "top_p": 0.9,
+        "max_tokens": 500,
+        "stop": "END"
+    });
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/chat/completions")
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_vec(&request_body).unwrap()))
+        .unwrap();
+
+    let res = server.router().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body_bytes = res.into_body().collect().await.unwrap().to_bytes();
+    let chat_resp: ChatCompletionResponse = serde_json::from_slice(&body_bytes).unwrap();
+    let received: serde_json::Value =
+        serde_json::from_str(chat_resp.choices[0].message.content.as_deref().unwrap()).unwrap();
+    assert_eq!(received["temperature"], 1.0);
+    assert_eq!(received["max_tokens"], 100);
+    assert_eq!(received["stop"], json!(["END"]));
+    assert!((received["top_p"].as_f64().unwrap() - 0.9).abs() < 1e-6);
+}
+
+#[tokio::test]
+async fn test_responses_generation_defaults_from_config() {
+    let base_url = spawn_mock_upstream().await;
+    let backend = Arc::new(mock_gateway_backend(&base_url, false));
+    let server = GatewayServer::new(Arc::new(SessionStore::new()), backend);
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/responses")
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_vec(&json!({
+            "model": "param-model",
+            "input": "hi",
+            "max_output_tokens": 32
+        })).unwrap()))
+        .unwrap();
+
+    let res = server.router().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body_bytes = res.into_body().collect().await.unwrap().to_bytes();
+    let resp_obj: ResponseObject = serde_json::from_slice(&body_bytes).unwrap();
+    let text = match &resp_obj.output[0] {
+        ResponseItem::Message { content, .. } => match &content[0] {
+            agent_models::response_item::ContentPart::Text { text } => text.clone(),
+            _ => panic!("Expected text"),
+        },
+        _ => panic!("Expected message"),
+    };
+    let received: serde_json::Value = serde_json::from_str(&text).unwrap();
+    // Unset temperature takes the configured default, no hard-coded value is sent
+    assert!((received["temperature"].as_f64().unwrap() - 0.4).abs() < 1e-6);
+    assert_eq!(received["max_tokens"], 32);
+    assert!(received["top_p"].is_null());
+}
+
+#[tokio::test]
+async fn test_models_endpoints() {
+    let base_url = spawn_mock_upstream().await;
+    let backend = Arc::new(mock_gateway_backend(&base_url, false));
+    let server = GatewayServer::new(Arc::new(SessionStore::new()), backend);
+
+    let req = Request::builder().method("GET").uri("/v1/models").body(Body::empty()).unwrap();
+    let res = server.router().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body_bytes = res.into_body().collect().await.unwrap().to_bytes();
+    let list: serde_json::Value = serde_json::from_slice(&body_bytes).unwrap();
+    assert_eq!(list["object"], "list");
+    let tool_model = list["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|m| m["id"] == "tool-model")
+        .expect("configured provider models are listed");
+    assert_eq!(tool_model["owned_by"], "tooling");
+    assert_eq!(tool_model["credentials_present"], true);
+    assert_eq!(tool_model["capabilities"]["streaming"], true);
+
+    // Ids containing slashes resolve through the wildcard route
+    let req = Request::builder()
+        .method("GET")
+        .uri("/v1/models/groq/llama-3.3-70b-versatile")
+        .body(Body::empty())
+        .unwrap();
+    let res = server.router().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body_bytes = res.into_body().collect().await.unwrap().to_bytes();
+    let model: serde_json::Value = serde_json::from_slice(&body_bytes).unwrap();
+    assert_eq!(model["owned_by"], "groq");
+    assert_eq!(model["context_window"], 131072);
+    assert_eq!(model["capabilities"]["tools"], true);
+
+    let req = Request::builder().method("GET").uri("/v1/models/no-such-model").body(Body::empty()).unwrap();
+    let res = server.router().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_responses_stream_typed_events_from_upstream_deltas() {
+    let base_url = spawn_mock_upstream().await;
+    let backend = Arc::new(mock_gateway_backend(&base_url, false));
+    let server = GatewayServer::new(Arc::new(SessionStore::new()), backend);
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/responses")
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_vec(&json!({
+            "model": "stream-model",
+            "input": "search rust",
+            "stream": true
+        })).unwrap()))
+        .unwrap();
+
+    let res = server.router().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body_bytes = res.into_body().collect().await.unwrap().to_bytes();
+    let events = sse_json_events(&String::from_utf8_lossy(&body_bytes));
+
+    let types: Vec<&str> = events.iter().map(|e| e["type"].as_str().unwrap()).collect();
+    assert_eq!(
+        types,
+        vec![
+            "response.created",
+            "response.output_item.added",
+            "response.output_text.delta",
+            "response.output_text.delta",
+            "response.output_item.done",
+            "response.output_item.added",
+            "response.function_call_arguments.delta",
+            "response.function_call_arguments.delta",
+            "response.output_item.done",
+            "response.completed",
+        ]
+    );
+
+    let completed = &events[9]["response"];
+    assert_eq!(completed["output"][0]["content"][0]["text"], "Looking it up");
+    assert_eq!(completed["output"][1]["call_id"], "call_s1");
+    assert_eq!(completed["output"][1]["arguments"], "{\"q\":\"rust\"}");
+    assert_eq!(completed["usage"]["total_tokens"], 10);
+    let sequence: Vec<u64> = events.iter().map(|e| e["sequence_number"].as_u64().unwrap()).collect();
+    assert_eq!(sequence, (0..10).collect::<Vec<u64>>());
+}
+
+#[tokio::test]
+async fn test_chat_completions_stream_tool_call_chunks() {
+    let base_url = spawn_mock_upstream().await;
+    let backend = Arc::new(mock_gateway_backend(&base_url, false));
+    let server = GatewayServer::new(Arc::new(SessionStore::new()), backend);
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/chat/completions")
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_vec(&json!({
+            "model": "stream-model",
+            "messages": [{"role": "user", "content": "search rust"}],
+            "stream": true
+        })).unwrap()))
+        .unwrap();
+
+    let res = server.router().oneshot(req).await.unwrap();
+    let body_bytes = res.into_body().collect().await.unwrap().to_bytes();
+    let chunks = sse_json_events(&String::from_utf8_lossy(&body_bytes));
+
+    assert!(chunks.iter().all(|c| c["object"] == "chat.completion.chunk"));
+    assert_eq!(chunks[0]["choices"][0]["delta"]["role"], "assistant");
+    let tool_start = chunks.iter().find(|c| c["choices"][0]["delta"]["tool_calls"][0]["id"] == "call_s1").unwrap();
+    assert_eq!(tool_start["choices"][0]["delta"]["tool_calls"][0]["index"], 0);
+    assert_eq!(chunks.last().unwrap()["choices"][0]["finish_reason"], "tool_calls");
+}
+
+#[tokio::test]
+async fn test_responses_stream_is_stored_for_chaining() {
+    let base_url = spawn_mock_upstream().await;
+    let session_store = Arc::new(SessionStore::new());
+    let backend = Arc::new(mock_gateway_backend(&base_url, false));
+    let server = GatewayServer::new(session_store.clone(), backend);
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/responses")
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_vec(&json!({
+            "model": "stream-model",
+            "input": "search rust",
+            "stream": true
+        })).unwrap()))
+        .unwrap();
+    let res = server.router().oneshot(req).await.unwrap();
+    let body_bytes = res.into_body().collect().await.unwrap().to_bytes();
+    let events = sse_json_events(&String::from_utf8_lossy(&body_bytes));
+
+    // The id announced up front is the one stored for chaining
+    let response_id = events[0]["response"]["id"].as_str().unwrap().to_string();
+    assert_eq!(events.last().unwrap()["response"]["id"], response_id.as_str());
+
+    let session = session_store.resolve_session(Some(&response_id)).await;
+    let history = session_store.get_history(&session.id).await;
+    assert_eq!(history.len(), 3);
+    match &history[2] {
+        ResponseItem::FunctionCall { call_id, arguments, .. } => {
+            assert_eq!(call_id, "call_s1");
+            assert_eq!(arguments, "{\"q\":\"rust\"}");
+        }
+        other => panic!("Expected function call, got {:?}", other),
+    }
+    assert_eq!(session_store.get_usage(&session.id).await.map(|u| u.total_tokens), Some(10));
+
+    // A follow-up turn continues the streamed conversation
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/responses")
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_vec(&json!({
+            "model": "backup-model",
+            "input": "thanks",
+            "previous_response_id": response_id
+        })).unwrap()))
+        .unwrap();
+    let res = server.router().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(session_store.get_history(&session.id).await.len(), 5);
+}
+
+#[tokio::test]
+async fn test_responses_stream_reports_the_fallback_model() {
+    let base_url = spawn_mock_upstream().await;
+    let backend = Arc::new(mock_gateway_backend(&base_url, false));
+    let server = GatewayServer::new(Arc::new(SessionStore::new()), backend).with_admin_api_key("admin-key");
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/responses")
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_vec(&json!({
+            "model": "failing-stream-model",
+            "input": "search rust",
+            "stream": true
+        })).unwrap()))
+        .unwrap();
+    let res = server.router().oneshot(req).await.unwrap();
+    let body_bytes = res.into_body().collect().await.unwrap().to_bytes();
+    let events = sse_json_events(&String::from_utf8_lossy(&body_bytes));
+
+    // Only the model that answered is known at the end of the stream
+    assert_eq!(events[0]["response"]["model"], "failing-stream-model");
+    let completed = events.last().unwrap();
+    assert_eq!(completed["type"], "response.completed");
+    assert_eq!(completed["response"]["model"], "stream-model");
+
+    let response_id = completed["response"]["id"].as_str().unwrap();
+    let req = Request::builder()
+        .uri(format!("/v1/responses/{}", response_id))
+        .header("Authorization", "Bearer admin-key")
+        .body(Body::empty())
+        .unwrap();
+    let res = server.router().oneshot(req).await.unwrap();
+    let stored: ResponseObject = serde_json::from_slice(&res.into_body().collect().await.unwrap().to_bytes()).unwrap();
+    assert_eq!(stored.model, "stream-model");
+}
+
+#[tokio::test]
+async fn test_responses_stream_native_gemini() {
+    let base_url = spawn_mock_upstream().await;
+    let backend = Arc::new(mock_gateway_backend(&base_url, false));
+    let server = GatewayServer::new(Arc::new(SessionStore::new()), backend);
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/responses")
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_vec(&json!({
+            "model": "gemini-stream-model",
+            "input": "search rust",
+            "stream": true
+        })).unwrap()))
+        .unwrap();
+
+    let res = server.router().oneshot(req).await.unwrap();
+    let body_bytes = res.into_body().collect().await.unwrap().to_bytes();
+    let events = sse_json_events(&String::from_utf8_lossy(&body_bytes));
+
+    // Same event sequence as the OpenAI-compatible upstream streaming the same turn
+    let types: Vec<&str> = events.iter().map(|e| e["type"].as_str().unwrap()).collect();
+    assert_eq!(
+        types,
+        vec![
+            "response.created",
+            "response.output_item.added",
+            "response.output_text.delta",
+            "response.output_text.delta",
+            "response.output_item.done",
+            "response.output_item.added",
+            "response.function_call_arguments.delta",
+            "response.output_item.done",
+            "response.completed",
+        ]
+    );
+    let completed = &events[8]["response"];
+    assert_eq!(completed["output"][0]["content"][0]["text"], "Looking it up");
+    assert_eq!(completed["output"][1]["call_id"], "gem_call_1");
+    assert_eq!(completed["output"][1]["arguments"], "{\"q\":\"rust\"}");
+    assert_eq!(completed["usage"]["total_tokens"], 10);
+}
+
+#[tokio::test]
+async fn test_chat_completions_via_anthropic_provider() {
+    let base_url = spawn_mock_upstream().await;
+    let backend = Arc::new(mock_gateway_backend(&base_url, false));
+    let server = GatewayServer::new(Arc::new(SessionStore::new()), backend);
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/chat/completions")
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_vec(&json!({
+            "model": "claude-mock",
+            "messages": [
+                {"role": "system", "content": "Be brief."},
+                {"role": "user", "content": "search rust"}
+            ]
+        })).unwrap()))
+        .unwrap();
+
+    let res = server.router().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body_bytes = res.into_body().collect().await.unwrap().to_bytes();
+    let body: serde_json::Value = serde_json::from_slice(&body_bytes).unwrap();
+    let message = &body["choices"][0]["message"];
+    assert_eq!(message["content"], "Looking it up");
+    assert_eq!(message["tool_calls"][0]["id"], "toolu_mock");
+    assert_eq!(message["tool_calls"][0]["function"]["arguments"], "{\"q\":\"rust\"}");
+    assert_eq!(body["usage"]["total_tokens"], 10);
+}
+
+#[tokio::test]
+async fn test_responses_stream_native_anthropic() {
+    let base_url = spawn_mock_upstream().await;
+    let backend = Arc::new(mock_gateway_backend(&base_url, false));
+    let server = GatewayServer::new(Arc::new(SessionStore::new()), backend);
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/responses")
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_vec(&json!({
+            "model": "claude-mock",
+            "input": [
+                {"type": "message", "id": "m_sys", "role": "system", "content": [{"type": "text", "text": "Be brief."}]},
+                {"type": "message", "id": "m_usr", "role": "user", "content": [{"type": "text", "text": "search rust"}]}
+            ],
+            "stream": true
+        })).unwrap()))
+        .unwrap();
+
+    let res = server.router().oneshot(req).await.unwrap();
+    let body_bytes = res.into_body().collect().await.unwrap().to_bytes();
+    let events = sse_json_events(&String::from_utf8_lossy(&body_bytes));
+
+    let types: Vec<&str> = events.iter().map(|e| e["type"].as_str().unwrap()).collect();
+    assert_eq!(
+        types,
+        vec![
+            "response.created",
+            "response.output_item.added",
+            "response.output_text.delta",
+            "response.output_text.delta",
+            "response.output_item.done",
+            "response.output_item.added",
+            "response.function_call_arguments.delta",
+            "response.function_call_arguments.delta",
+            "response.output_item.done",
+            "response.completed",
+        ]
+    );
+    let completed = &events[9]["response"];
+    assert_eq!(completed["output"][0]["content"][0]["text"], "Looking it up");
+    assert_eq!(completed["output"][1]["call_id"], "toolu_mock");
+    assert_eq!(completed["output"][1]["arguments"], "{\"q\":\"rust\"}");
+    assert_eq!(completed["usage"]["total_tokens"], 10);
+}
+
+#[tokio::test]
+async fn test_responses_provider_chains_upstream_turns() {
+    let base_url = spawn_mock_upstream().await;
+    let backend = Arc::new(mock_gateway_backend(&base_url, false));
+    let server = GatewayServer::new(Arc::new(SessionStore::new()), backend);
+
+    let responses_request = |body: serde_json::Value| {
+        Request::builder()
+            .method("POST")
+            .uri("/v1/responses")
+            .header("Content-Type", "application/json")
+            .body(Body::from(serde_json::to_vec(&body).unwrap()))
+            .unwrap()
+    };
+
+    // First turn is streamed and sends the whole (one item) history
+    let res = server
+        .router()
+        .oneshot(responses_request(json!({"model": "responses-model", "input": "hello", "stream": true})))
+        .await
+        .unwrap();
+    let body_bytes = res.into_body().collect().await.unwrap().to_bytes();
+    let events = sse_json_events(&String::from_utf8_lossy(&body_bytes));
+    let completed = events.last().unwrap();
+    assert_eq!(completed["type"], "response.completed");
+    assert_eq!(completed["response"]["output"][0]["content"][0]["text"], "prev=none items=1");
+    assert_eq!(completed["response"]["usage"]["total_tokens"], 5);
+    let first_id = completed["response"]["id"].as_str().unwrap().to_string();
+
+    // Second turn only sends the new input and chains on the upstream response
+    let res = server
+        .router()
+        .oneshot(responses_request(json!({
+            "model": "responses-model",
+            "input": "and again",
+            "previous_response_id": first_id
+        })))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body_bytes = res.into_body().collect().await.unwrap().to_bytes();
+    let second: ResponseObject = serde_json::from_slice(&body_bytes).unwrap();
+    match &second.output[0] {
+        ResponseItem::Message { content, .. } => {
+            assert_eq!(content[0], ContentPart::Text { text: "prev=up_none items=1".to_string() })
+        }
+        other => panic!("expected message, got {:?}", other),
+    }
+
+    // A new conversation starts a new upstream chain
+    let res = server
+        .router()
+        .oneshot(responses_request(json!({"model": "responses-model", "input": "fresh start"})))
+        .await
+        .unwrap();
+    let body_bytes = res.into_body().collect().await.unwrap().to_bytes();
+    let third: ResponseObject = serde_json::from_slice(&body_bytes).unwrap();
+    assert!(matches!(
+        &third.output[0],
+        ResponseItem::Message { content, .. } if content[0] == ContentPart::Text { text: "prev=none items=1".to_string() }
+    ));
+}
+
+#[tokio::test]
+async fn test_gateway_accepts_and_limits_media_parts() {
+    use agent_core::server::media::MediaLimits;
+
+    let server = GatewayServer::with_default_backend(Arc::new(SessionStore::new()))
+        .with_media_limits(MediaLimits { max_part_bytes: 16, ..MediaLimits::default() });
+    let post = |uri: &str, body: serde_json::Value| {
+        Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header("Content-Type", "application/json")
+            .body(Body::from(serde_json::to_vec(&body).unwrap()))
+            .unwrap()
+    };
+
+    let chat = json!({
+        "model": "swarm-fast-v1",
+        "messages": [{"role": "user", "content": [
+            {"type": "text", "text": "Describe this"},
+            {"type": "image_url", "image_url": {"url": "data:image/png;base64,iVBORw0KGgo="}}
+        ]}]
+    });
+    let res = server.router().oneshot(post("/v1/chat/completions", chat)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = res.into_body().collect().await.unwrap().to_bytes();
+    let chat_resp: ChatCompletionResponse = serde_json::from_slice(&body).unwrap();
+    assert!(chat_resp.choices[0].message.content.as_deref().unwrap().contains("Describe this"));
+
+    let svg = json!({"input": [{"type": "message", "id": "m1", "role": "user", "content": [
+        {"type": "text", "text": "hi"},
+        {"type": "image", "media_type": "image/svg+xml", "data_base64": "PHN2Zz4="}
+    ]}]});
+    let res = server.router().oneshot(post("/v1/responses", svg)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    let body: serde_json::Value = serde_json::from_slice(&res.into_body().collect().await.unwrap().to_bytes()).unwrap();
+    assert_eq!(body["error"]["param"], "input[0].content[1]");
+    assert_eq!(body["error"]["type"], "invalid_request_error");
+
+    let large_pdf = json!({"input": [{"type": "message", "id": "m1", "role": "user", "content": [
+        {"type": "input_file", "filename": "big.pdf", "media_type": "application/pdf", "data_base64": "A".repeat(64)}
+    ]}]});
+    let res = server.router().oneshot(post("/v1/responses", large_pdf)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    let uploaded = json!({
+        "model": "swarm-fast-v1",
+        "messages": [{"role": "user", "content": [{"type": "file", "file": {"file_id": "file-abc"}}]}]
+    });
+    let res = server.router().oneshot(post("/v1/chat/completions", uploaded)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_responses_history_policy_truncates_and_summarizes() {
+    use agent_core::server::gateway_server::{BackendTurnResult, GatewayError, GatewaySessionSection};
+    use agent_core::GatewayBackend;
+    use agent_models::response_item::Role;
+    use llm_api::generation::GenerationParams;
+    use llm_api::tools::Tool;
+    use std::sync::Mutex;
+
+    /// Records the history of every turn and answers summary requests with a fixed text
+    #[derive(Default)]
+    struct RecordingBackend {
+        turns: Mutex<Vec<(String, Vec<ResponseItem>)>>,
+    }
+
+    #[async_trait::async_trait]
+    impl GatewayBackend for RecordingBackend {
+        async fn process_turn(
+            &self,
+            session_id: &str,
+            history: &[ResponseItem],
+            _model: Option<&str>,
+            _tools: &[Tool],
+            _params: &GenerationParams,
+        ) -> Result<BackendTurnResult, GatewayError> {
+            let mut turns = self.turns.lock().unwrap();
+            turns.push((session_id.to_string(), history.to_vec()));
+            let text = if session_id.starts_with("summary_") { "SUMMARY".to_string() } else { format!("reply {}", turns.len()) };
+            Ok(BackendTurnResult {
+                items: vec![ResponseItem::Message {
+                    id: format!("resp_msg_{}", uuid::Uuid::new_v4()),
+                    role: Role::Assistant,
+                    content: vec![ContentPart::Text { text }],
+                }],
+                usage: None,
+                model: None,
+            })
+        }
+    }
+
+    let backend = Arc::new(RecordingBackend::default());
+    let section = GatewaySessionSection {
+        max_prompt_items: Some(3),
+        summarize_history: Some(true),
+        ..GatewaySessionSection::default()
+    };
+    let policy = section.history_policy(&(backend.clone() as Arc<dyn GatewayBackend>));
+    let server = GatewayServer::new(Arc::new(SessionStore::new()), backend.clone()).with_history_policy(policy);
+
+    let mut previous: Option<String> = None;
+    let inputs = [
+        json!([{"type": "message", "id": "sys", "role": "system", "content": [{"type": "text", "text": "Be brief."}]},
+               {"type": "message", "id": "u1", "role": "user", "content": [{"type": "text", "text": "One"}]}]),
+        json!("Two"),
+        json!("Three"),
+    ];
+    for input in inputs {
+        let body = json!({"model": "swarm-fast-v1", "input": input, "previous_response_id": previous});
+        let req = Request::builder()
+            .method("POST")
+            .uri("/v1/responses")
+            .header("Content-Type", "application/json")
+            .body(Body::from(serde_json::to_vec(&body).unwrap()))
+            .unwrap();
+        let res = server.router().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let resp: ResponseObject = serde_json::from_slice(&res.into_body().collect().await.unwrap().to_bytes()).unwrap();
+        previous = Some(resp.id);
+    }
+
+    let turns = backend.turns.lock().unwrap();
+    // Turn 2 fits; turn 3 drops "u1" and "reply 1", summarized by one extra backend call
+    assert_eq!(turns.len(), 4);
+    assert_eq!(turns[1].1.len(), 4);
+    assert!(turns[2].0.starts_with("summary_"));
+    let sent = &turns[3].1;
+    assert_eq!(sent.len(), 5);
+    assert_eq!(sent[0].id(), "sys");
+    let ResponseItem::Message { role: Role::System, content, .. } = &sent[1] else { panic!("Expected summary message") };
+    assert_eq!(content[0], ContentPart::Text { text: "Summary of the earlier conversation:\nSUMMARY".to_string() });
+}
+
+#[tokio::test]
+async fn test_responses_fit_history_to_the_default_model_context_window() {
+    use agent_core::server::gateway_server::{BackendTurnResult, GatewayError, SimpleGatewayBackend};
+    use agent_core::server::model_catalog::{ModelCapabilities, ModelObject};
+    use agent_core::GatewayBackend;
+    use llm_api::generation::GenerationParams;
+    use llm_api::tools::Tool;
+    use std::sync::Mutex;
+
+    /// Serves requests naming no model with a small model and records how many items it was sent
+    #[derive(Default)]
+    struct SmallDefaultBackend {
+        sent: Mutex<Vec<usize>>,
+    }
+
+    #[async_trait::async_trait]
+    impl GatewayBackend for SmallDefaultBackend {
+        async fn process_turn(
+            &self,
+            session_id: &str,
+            history: &[ResponseItem],
+            model: Option<&str>,
+            tools: &[Tool],
+            params: &GenerationParams,
+        ) -> Result<BackendTurnResult, GatewayError> {
+            self.sent.lock().unwrap().push(history.len());
+            SimpleGatewayBackend.process_turn(session_id, history, model, tools, params).await
+        }
+
+        fn list_models(&self) -> Vec<ModelObject> {
+            vec![ModelObject {
+                id: "small-model".to_string(),
+                object: "model".to_string(),
+                created: 0,
+                owned_by: "test".to_string(),
+                context_window: Some(100),
+                credentials_present: true,
+                capabilities: ModelCapabilities { tools: false, vision: false, streaming: true },
+            }]
+        }
+
+        fn default_model(&self) -> Option<&str> {
+            Some("small-model")
+        }
+    }
+
+    let backend = Arc::new(SmallDefaultBackend::default());
+    let server = GatewayServer::new(Arc::new(SessionStore::new()), backend.clone());
+    // Ten messages of 14 estimated tokens; 50 tokens are left once the answer is reserved
+    let input: Vec<serde_json::Value> = (0..10)
+        .map(|i| json!({"type": "message", "id": format!("u{i}"), "role": "user", "content": [{"type": "text", "text": "x".repeat(40)}]}))
+        .collect();
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/responses")
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_vec(&json!({"input": input, "max_output_tokens": 50})).unwrap()))
+        .unwrap();
+    let res = server.router().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let resp: ResponseObject = serde_json::from_slice(&res.into_body().collect().await.unwrap().to_bytes()).unwrap();
+    assert_eq!(resp.model, "small-model");
+    assert_eq!(*backend.sent.lock().unwrap(), vec![3]);
+}
+
+#[test]
+fn test_history_fits_the_smallest_context_window_of_the_fallback_chain() {
+    use agent_core::GatewayBackend;
+
+    let config: agent_core::server::gateway_server::GatewayConfigFile = toml::from_str(r#"
+        [models.fallbacks]
+        "large-model" = ["small-model", "unknown-model"]
+
+        [models.catalog."large-model"]
+        context_window = 128000
+
+        [models.catalog."small-model"]
+        context_window = 8000
+
+        [providers.local]
+        api_url = "http://127.0.0.1:1/v1/chat/completions"
+        requires_api_key = false
+        recommended_models = ["large-model", "small-model"]
+    "#).unwrap();
+    let backend = agent_core::MultiModelGatewayBackend::from_config(&config);
+
+    assert_eq!(backend.context_window("large-model"), Some(8000));
+    assert_eq!(backend.context_window("small-model"), Some(8000));
+}
+
+#[tokio::test]
+async fn test_gateway_reports_session_store_failures_as_503() {
+    use agent_core::session::{Session, SessionError, SessionStoreApi, SessionStoreStats, SessionSummary, StoredResponse};
+    use agent_models::response_item::ResponseUsage;
+
+    /// Reads work, every write fails as on a full disk
+    struct FullDiskStore(SessionStore);
+
+    fn disk_full() -> SessionError {
+        SessionError::Storage("No space left on device".to_string())
+    }
+
+    #[async_trait::async_trait]
+    impl SessionStoreApi for FullDiskStore {
+        async fn resolve_session(&self, previous_response_id: Option<&str>) -> Result<Session, SessionError> {
+            Ok(self.0.resolve_session(previous_response_id).await)
+        }
+        async fn append_items(&self, _session_id: &str, _items: &[ResponseItem]) -> Result<Vec<ResponseItem>, SessionError> {
+            Err(disk_full())
+        }
+        async fn get_history(&self, session_id: &str) -> Result<Vec<ResponseItem>, SessionError> {
+            Ok(self.0.get_history(session_id).await)
+        }
+        async fn set_parent_response_id(&self, _session_id: &str, _parent_response_id: String) -> Result<(), SessionError> {
+            Err(disk_full())
+        }
+        async fn get_parent_response_id(&self, session_id: &str) -> Result<Option<String>, SessionError> {
+            Ok(self.0.get_parent_response_id(session_id).await)
+        }
+        async fn record_usage(&self, _session_id: &str, _usage: &ResponseUsage) -> Result<(), SessionError> {
+            Err(disk_full())
+        }
+        async fn get_usage(&self, session_id: &str) -> Result<Option<ResponseUsage>, SessionError> {
+            Ok(self.0.get_usage(session_id).await)
+        }
+        async fn remove_session(&self, session_id: &str) -> Result<bool, SessionError> {
+            Ok(self.0.remove_session(session_id))
+        }
+        async fn evict_expired(&self) -> Result<Vec<String>, SessionError> {
+            Ok(self.0.evict_expired())
+        }
+        async fn list_sessions(
+            &self,
+            after: Option<&str>,
+            descending: bool,
+            limit: usize,
+        ) -> Result<Vec<SessionSummary>, SessionError> {
+            Ok(self.0.list_sessions(after, descending, limit).await)
+        }
+        async fn get_session(&self, session_id: &str) -> Result<Option<Session>, SessionError> {
+            Ok(self.0.get_session(session_id))
+        }
+        async fn save_response(&self, _response: StoredResponse) -> Result<(), SessionError> {
+            Err(disk_full())
+        }
+        async fn get_response(&self, response_id: &str) -> Result<Option<StoredResponse>, SessionError> {
+            Ok(self.0.get_response(response_id))
+        }
+        async fn delete_response(&self, _response_id: &str) -> Result<bool, SessionError> {
+            Err(disk_full())
+        }
+        fn stats(&self) -> SessionStoreStats {
+            SessionStoreStats { write_failures: 3, last_error: Some(disk_full().to_string()), ..SessionStoreStats::default() }
+        }
+    }
+
+    let server = GatewayServer::with_default_backend(Arc::new(FullDiskStore(SessionStore::new())));
+    let post = |uri: &str, body: serde_json::Value| {
+        Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header("Content-Type", "application/json")
+            .body(Body::from(serde_json::to_vec(&body).unwrap()))
+            .unwrap()
+    };
+
+    let res = server
+        .router()
+        .oneshot(post("/v1/responses", json!({"model": "swarm-fast-v1", "input": "Hello"})))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let body: serde_json::Value = serde_json::from_slice(&res.into_body().collect().await.unwrap().to_bytes()).unwrap();
+    assert_eq!(body["error"]["code"], "session_store_unavailable");
+    assert_eq!(body["error"]["type"], "server_error");
+
+    // Chat completions store nothing, so they are served whatever the state of the store
+    let chat = json!({"model": "swarm-fast-v1", "messages": [{"role": "user", "content": "Hello"}]});
+    let res = server.router().oneshot(post("/v1/chat/completions", chat)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // Without input nothing is stored before the turn; the stream then fails instead of completing
+    let res = server
+        .router()
+        .oneshot(post("/v1/responses", json!({"model": "swarm-fast-v1", "stream": true})))
+        .await
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = String::from_utf8(res.into_body().collect().await.unwrap().to_bytes().to_vec()).unwrap();
+    assert!(body.contains("event: response.failed"));
+    assert!(body.contains("session_store_unavailable"));
+    assert!(!body.contains("event: response.completed"));
+
+    let req = Request::builder().uri("/health").body(Body::empty()).unwrap();
+    let res = server.router().oneshot(req).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&res.into_body().collect().await.unwrap().to_bytes()).unwrap();
+    assert_eq!(body["session_store"]["write_failures"], 3);
+    assert_eq!(body["session_store"]["pending_writes"], 0);
+}
+
+#[tokio::test]
+async fn test_session_and_response_management_endpoints() {
+    use agent_core::server::session_api::{DeletedObject, ListPage, SessionObject};
+
+    let session_store = Arc::new(SessionStore::new());
+    let server = GatewayServer::with_default_backend(session_store.clone()).with_admin_api_key("admin-key");
+    let send = |method: &str, uri: &str, body: Option<serde_json::Value>| {
+        let body = body.map_or_else(Body::empty, |b| Body::from(serde_json::to_vec(&b).unwrap()));
+        let req = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("Content-Type", "application/json")
+            .header("Authorization", "Bearer admin-key")
+            .body(body)
+            .unwrap();
+        let app = server.router();
+        async move {
+            let res = app.oneshot(req).await.unwrap();
+            let status = res.status();
+            let bytes = res.into_body().collect().await.unwrap().to_bytes();
+            (status, serde_json::from_slice::<serde_json::Value>(&bytes).unwrap())
+        }
+    };
+
+    let input = json!([
+        {"type": "message", "id": "in_1", "role": "user", "content": [{"type": "text", "text": "One"}]},
+        {"type": "message", "id": "in_2", "role": "user", "content": [{"type": "text", "text": "Two"}]},
+        {"type": "message", "id": "in_3", "role": "user", "content": [{"type": "text", "text": "Three"}]}
+    ]);
+    let (status, first) = send("POST", "/v1/responses", Some(json!({"model": "swarm-fast-v1", "input": input}))).await;
+    assert_eq!(status, StatusCode::OK);
+    let first: ResponseObject = serde_json::from_value(first).unwrap();
+    let (_, second) = send(
+        "POST",
+        "/v1/responses",
+        Some(json!({"model": "swarm-fast-v1", "input": "Four", "previous_response_id": first.id})),
+    )
+    .await;
+    let second: ResponseObject = serde_json::from_value(second).unwrap();
+    send("POST", "/v1/responses", Some(json!({"model": "swarm-fast-v1", "input": "Other"}))).await;
+
+    // A response is served again with the items it produced
+    let (status, fetched) = send("GET", &format!("/v1/responses/{}", first.id), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let fetched: ResponseObject = serde_json::from_value(fetched).unwrap();
+    assert_eq!((fetched.id.as_str(), fetched.created), (first.id.as_str(), first.created));
+    assert_eq!(fetched.output, first.output);
+    assert_eq!(fetched.usage, first.usage);
+
+    // Input items, newest first by default
+    let uri = format!("/v1/responses/{}/input_items?limit=2", first.id);
+    let (_, page) = send("GET", &uri, None).await;
+    let page: ListPage<ResponseItem> = serde_json::from_value(page).unwrap();
+    let page_ids: Vec<&str> = page.data.iter().map(ResponseItem::id).collect();
+    assert_eq!((page_ids, page.has_more), (vec!["in_3", "in_2"], true));
+    let uri = format!("/v1/responses/{}/input_items?order=asc&after=in_1", first.id);
+    let (_, page) = send("GET", &uri, None).await;
+    assert_eq!(page["first_id"], "in_2");
+    assert_eq!(page["last_id"], "in_3");
+    assert_eq!(page["has_more"], false);
+
+    let (_, sessions) = send("GET", "/v1/sessions", None).await;
+    let sessions: ListPage<SessionObject> = serde_json::from_value(sessions).unwrap();
+    assert_eq!(sessions.data.len(), 2);
+    let chained = sessions.data.iter().find(|s| s.item_count == 6).expect("chained session");
+    assert_eq!(chained.parent_response_id.as_deref(), Some(second.id.as_str()));
+    assert!(chained.items.is_none());
+    let ids: Vec<&str> = sessions.data.iter().map(|s| s.id.as_str()).collect();
+    assert!(ids[0] < ids[1]);
+
+    // Sessions are paged by id cursor
+    let (_, page) = send("GET", "/v1/sessions?limit=1", None).await;
+    assert_eq!((page["first_id"].as_str(), page["has_more"].as_bool()), (Some(ids[0]), Some(true)));
+    let (_, page) = send("GET", &format!("/v1/sessions?limit=1&after={}", ids[0]), None).await;
+    assert_eq!((page["first_id"].as_str(), page["has_more"].as_bool()), (Some(ids[1]), Some(false)));
+    let (_, page) = send("GET", &format!("/v1/sessions?before={}", ids[1]), None).await;
+    assert_eq!((page["last_id"].as_str(), page["has_more"].as_bool()), (Some(ids[0]), Some(false)));
+    let (_, page) = send("GET", "/v1/sessions?order=desc", None).await;
+    assert_eq!((page["first_id"].as_str(), page["last_id"].as_str()), (Some(ids[1]), Some(ids[0])));
+
+    let (status, session) = send("GET", &format!("/v1/sessions/{}", chained.id), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let session: SessionObject = serde_json::from_value(session).unwrap();
+    assert_eq!(session.items.unwrap().len(), 6);
+
+    // A deleted response can no longer be read nor continued
+    let (status, deleted) = send("DELETE", &format!("/v1/responses/{}", first.id), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let deleted: DeletedObject = serde_json::from_value(deleted).unwrap();
+    assert_eq!((deleted.object.as_str(), deleted.deleted), ("response", true));
+    let (status, error) = send("GET", &format!("/v1/responses/{}", first.id), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(error["error"]["code"], "not_found");
+    assert!(session_store.resolve_session(Some(&first.id)).await.id != chained.id);
+
+    let (status, _) = send("DELETE", &format!("/v1/sessions/{}", chained.id), None).await;
+    assert_eq!(status, StatusCode::OK);
+    for uri in [format!("/v1/sessions/{}", chained.id), format!("/v1/responses/{}", second.id)] {
+        assert_eq!(send("GET", &uri, None).await.0, StatusCode::NOT_FOUND);
+    }
+    assert_eq!(send("DELETE", &format!("/v1/sessions/{}", chained.id), None).await.0, StatusCode::NOT_FOUND);
+    let (status, error) = send("GET", "/v1/sessions?limit=500", None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error["error"]["param"], "limit");
+}
+
+#[tokio::test]
+async fn test_session_endpoints_require_the_admin_key() {
+    let session_store = Arc::new(SessionStore::new());
+    let get = |router: axum::Router, authorization: Option<&str>| {
+        let mut req = Request::builder().uri("/v1/sessions");
+        if let Some(authorization) = authorization {
+            req = req.header("Authorization", authorization);
+        }
+        let req = req.body(Body::empty()).unwrap();
+        async move { router.oneshot(req).await.unwrap() }
+    };
+
+    // Not served without an admin key
+    let server = GatewayServer::with_default_backend(session_store.clone());
+    assert_eq!(get(server.router(), Some("Bearer admin-key")).await.status(), StatusCode::NOT_FOUND);
+    let server = GatewayServer::with_default_backend(session_store.clone()).with_admin_api_key("");
+    assert_eq!(get(server.router(), None).await.status(), StatusCode::NOT_FOUND);
+
+    let server = GatewayServer::with_default_backend(session_store).with_admin_api_key("admin-key");
+    for authorization in [None, Some("Bearer other-key"), Some("admin-key")] {
+        let res = get(server.router(), authorization).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        let bytes = res.into_body().collect().await.unwrap().to_bytes();
+        let error: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(error["error"]["code"], "invalid_api_key");
+    }
+    assert_eq!(get(server.router(), Some("Bearer admin-key")).await.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_gateway_reaper_evicts_sessions_beyond_the_limit() {
+    use agent_core::server::gateway_server::{
+        BackendTurnResult, GatewayError, GatewaySessionSection, SimpleGatewayBackend,
+    };
+    use agent_core::GatewayBackend;
+    use llm_api::generation::GenerationParams;
+    use llm_api::tools::Tool;
+    use std::sync::Mutex;
+
+    /// Echoes like the default backend and records the sessions it is told to forget
+    #[derive(Default)]
+    struct ForgettingBackend {
+        forgotten: Mutex<Vec<String>>,
+    }
+
+    #[async_trait::async_trait]
+    impl GatewayBackend for ForgettingBackend {
+        async fn process_turn(
+            &self,
+            session_id: &str,
+            history: &[ResponseItem],
+            model: Option<&str>,
+            tools: &[Tool],
+            params: &GenerationParams,
+        ) -> Result<BackendTurnResult, GatewayError> {
+            SimpleGatewayBackend.process_turn(session_id, history, model, tools, params).await
+        }
+
+        fn forget_session(&self, session_id: &str) {
+            self.forgotten.lock().unwrap().push(session_id.to_string());
+        }
+    }
+
+    let recorder = Arc::new(ForgettingBackend::default());
+    let backend: Arc<dyn GatewayBackend> = recorder.clone();
+    let section = GatewaySessionSection {
+        max_sessions: Some(1),
+        reap_interval_seconds: Some(1),
+        ..GatewaySessionSection::default()
+    };
+    let store = section.open_store(&backend).unwrap();
+    let server = GatewayServer::new(store.clone(), backend);
+
+    let mut session_ids = Vec::new();
+    for input in ["first", "second"] {
+        let req = Request::builder()
+            .method("POST")
+            .uri("/v1/responses")
+            .header("Content-Type", "application/json")
+            .body(Body::from(serde_json::to_vec(&json!({"model": "swarm-fast-v1", "input": input})).unwrap()))
+            .unwrap();
+        let res = server.router().oneshot(req).await.unwrap();
+        let body: ResponseObject = serde_json::from_slice(&res.into_body().collect().await.unwrap().to_bytes()).unwrap();
+        session_ids.push(store.get_response(&body.id).await.unwrap().unwrap().session_id);
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+    }
+
+    // The reaper evicts the least recently used session on its next run and the backend forgets it
+    tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+    assert!(store.get_session(&session_ids[0]).await.unwrap().is_none());
+    assert!(store.get_session(&session_ids[1]).await.unwrap().is_some());
+    assert_eq!(*recorder.forgotten.lock().unwrap(), vec![session_ids[0].clone()]);
 }
@@ -72,6 +72,12 @@ pub struct FactoryAgentConfigBuilder {
     factory_agent_executor_url: Option<String>,
 }
 
+impl Default for FactoryAgentConfigBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl FactoryAgentConfigBuilder {
     pub fn new() -> Self {
         FactoryAgentConfigBuilder {
@@ -247,6 +253,12 @@ pub struct FactoryMcpRuntimeConfigBuilder {
     factory_mcp_server_api_key: Option<String>,
 }
 
+impl Default for FactoryMcpRuntimeConfigBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl FactoryMcpRuntimeConfigBuilder {
     pub fn new() -> Self {
         FactoryMcpRuntimeConfigBuilder {
@@ -8,7 +8,7 @@ pub enum AgentStatus {
     Unavailable,
 }
 
-#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, schemars::JsonSchema)]
 pub enum ActivityType {
     #[serde(rename = "delegation_agent")]
     DelegationAgent,
@@ -19,7 +19,7 @@ pub enum ActivityType {
 }
 
 // New structs for the input JSON schema
-#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, schemars::JsonSchema)]
 pub struct AgentConfigInput {
     pub skill_to_use: Option<String>,
     pub assigned_agent_id_preference: Option<String>,
@@ -27,7 +27,7 @@ pub struct AgentConfigInput {
     pub agent_context: Option<serde_json::Value>, // Added agent_context
 }
 
-#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, schemars::JsonSchema)]
 pub struct ToolConfigInput {
     pub tool_to_use: Option<String>,
     #[serde(default)]
@@ -35,14 +35,14 @@ pub struct ToolConfigInput {
 }
 
 // New struct for Task configuration
-#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, schemars::JsonSchema)]
 pub struct TaskConfigInput {
     pub task_to_use: Option<String>,
     #[serde(default)]
     pub task_parameters: serde_json::Value,
 }
 
-#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, schemars::JsonSchema)]
 pub struct ActivityInput {
     pub activity_type: ActivityType,
     pub id: String,
@@ -59,7 +59,7 @@ pub struct ActivityInput {
     pub expected_outcome: String, // 'expected_outcome' is required in the schema
 }
 
-#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, schemars::JsonSchema)]
 pub struct WorkflowPlanInput {
     pub plan_name: String,
     pub activities: Vec<ActivityInput>,
@@ -88,7 +88,7 @@ pub struct Activity {
     pub activity_output: Option<String>,
 }
 
-#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, schemars::JsonSchema)]
 pub struct Dependency {
     pub source: String,
     pub condition: Option<String>, // Added condition to Dependency
@@ -195,8 +195,8 @@ impl From<WorkflowPlanInput> for Graph {
                 skill_to_use: activity_input.agent.as_ref().and_then(|a| a.skill_to_use.clone()),
                 assigned_agent_id_preference: activity_input.agent.as_ref().and_then(|a| a.assigned_agent_id_preference.clone()),
                 agent_context: activity_input.agent.as_ref().and_then(|a| a.agent_context.clone()), // Mapped agent_context
-                tool_to_use: activity_input.tools.as_ref().and_then(|t_vec| t_vec.get(0)).and_then(|t| t.tool_to_use.clone()),
-                tool_parameters: activity_input.tools.as_ref().and_then(|t_vec| t_vec.get(0)).map(|t| t.tool_parameters.clone()),
+                tool_to_use: activity_input.tools.as_ref().and_then(|t_vec| t_vec.first()).and_then(|t| t.tool_to_use.clone()),
+                tool_parameters: activity_input.tools.as_ref().and_then(|t_vec| t_vec.first()).map(|t| t.tool_parameters.clone()),
                 tasks: activity_input.tasks.clone(),
                 dependencies: activity_input.dependencies.clone(),
                 expected_outcome: Some(activity_input.expected_outcome),
@@ -1,3 +1,5 @@
+use std::borrow::Cow;
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -31,6 +33,18 @@ pub enum ResponseItem {
     },
 }
 
+impl ResponseItem {
+    /// Item id, unique within a session
+    pub fn id(&self) -> &str {
+        match self {
+            ResponseItem::Message { id, .. }
+            | ResponseItem::Reasoning { id, .. }
+            | ResponseItem::FunctionCall { id, .. }
+            | ResponseItem::FunctionCallOutput { id, .. } => id,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum Role {
@@ -45,6 +59,39 @@ pub enum Role {
 pub enum ContentPart {
     Text { text: String },
     Image { media_type: String, data_base64: String },
+    /// Image referenced by an http(s) URL, fetched by the provider
+    ImageUrl {
+        url: String,
+        /// OpenAI `detail`: "low", "high" or "auto"
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        detail: Option<String>,
+    },
+    /// Document such as a PDF, sent inline as base64 or referenced by URL
+    #[serde(alias = "input_file")]
+    File {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        filename: Option<String>,
+        media_type: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        data_base64: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        url: Option<String>,
+    },
+    Audio { media_type: String, data_base64: String },
+}
+
+impl ContentPart {
+    /// `data:` URL carrying base64 content
+    pub fn data_url(media_type: &str, data_base64: &str) -> String {
+        format!("data:{};base64,{}", media_type, data_base64)
+    }
+
+    /// Media type and base64 payload of a `data:<type>;base64,<data>` URL
+    pub fn parse_data_url(url: &str) -> Option<(&str, &str)> {
+        let (header, data) = url.strip_prefix("data:")?.split_once(',')?;
+        let media_type = header.strip_suffix(";base64")?;
+        Some((media_type, data))
+    }
 }
 
 /// Open Responses: Input format can be a single prompt string or a list of ResponseItems
@@ -55,6 +102,28 @@ pub enum ResponsesInput {
     Items(Vec<ResponseItem>),
 }
 
+/// Open Responses: function tool made available to the model (flat `{type, name, description, parameters}` form)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ResponseTool {
+    pub r#type: String, // "function"
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// JSON Schema of the function arguments
+    #[serde(default, skip_serializing_if = "serde_json::Value::is_null")]
+    pub parameters: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strict: Option<bool>,
+}
+
+/// Open Responses: "auto", "none", "required" or a specific function
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum ResponseToolChoice {
+    Mode(String),
+    Function { r#type: String, name: String },
+}
+
 /// Open Responses: Request schema for POST /v1/responses
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct CreateResponseRequest {
@@ -68,7 +137,70 @@ pub struct CreateResponseRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub temperature: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub max_output_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<ResponseTool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ResponseToolChoice>,
+    /// Gateway extension choosing what happens to the model's reasoning, never sent upstream
+    #[serde(default, skip_serializing)]
+    pub reasoning: Option<ReasoningOptions>,
+}
+
+/// What happens to the model's reasoning: `<think>` tags, `reasoning_content`, Gemini thought
+/// parts and Anthropic thinking blocks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReasoningOptions {
+    /// Return reasoning as `ResponseItem::Reasoning` items. Signatures are part of the
+    /// reasoning, so leaving it out also leaves nothing to forward.
+    #[serde(default = "default_true")]
+    pub include: bool,
+    /// Send the signed reasoning of the history back upstream. Anthropic and Gemini need it to
+    /// continue tool use with thinking enabled. Unsigned reasoning is never sent.
+    #[serde(default = "default_true")]
+    pub forward_signed: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for ReasoningOptions {
+    fn default() -> Self {
+        Self {
+            include: true,
+            forward_signed: true,
+        }
+    }
+}
+
+impl ReasoningOptions {
+    /// Turn output with the reasoning items removed unless they are included
+    pub fn output(&self, items: Vec<ResponseItem>) -> Vec<ResponseItem> {
+        if self.include {
+            return items;
+        }
+        items
+            .into_iter()
+            .filter(|item| !matches!(item, ResponseItem::Reasoning { .. }))
+            .collect()
+    }
+
+    /// History as sent upstream, without signed reasoning unless it is forwarded
+    pub fn upstream_history<'a>(&self, history: &'a [ResponseItem]) -> Cow<'a, [ResponseItem]> {
+        if self.forward_signed {
+            return Cow::Borrowed(history);
+        }
+        Cow::Owned(
+            history
+                .iter()
+                .filter(|item| !matches!(item, ResponseItem::Reasoning { signature: Some(_), .. }))
+                .cloned()
+                .collect(),
+        )
+    }
 }
 
 /// Open Responses: Response schema for POST /v1/responses (non-streaming)
@@ -81,6 +213,17 @@ pub struct ResponseObject {
     pub output: Vec<ResponseItem>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub usage: Option<ResponseUsage>,
+    /// "in_progress", "completed" or "failed"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ResponseError>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ResponseError {
+    pub code: String,
+    pub message: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
@@ -90,6 +233,79 @@ pub struct ResponseUsage {
     pub total_tokens: u32,
 }
 
+/// Open Responses: Server-sent events for POST /v1/responses with `stream: true`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type")]
+pub enum ResponseStreamEvent {
+    #[serde(rename = "response.created")]
+    Created {
+        sequence_number: u64,
+        response: ResponseObject,
+    },
+    #[serde(rename = "response.output_item.added")]
+    OutputItemAdded {
+        sequence_number: u64,
+        output_index: usize,
+        item: ResponseItem,
+    },
+    #[serde(rename = "response.output_text.delta")]
+    OutputTextDelta {
+        sequence_number: u64,
+        item_id: String,
+        output_index: usize,
+        content_index: usize,
+        delta: String,
+    },
+    /// Text appended to a reasoning item
+    #[serde(rename = "response.reasoning_text.delta")]
+    ReasoningTextDelta {
+        sequence_number: u64,
+        item_id: String,
+        output_index: usize,
+        content_index: usize,
+        delta: String,
+    },
+    #[serde(rename = "response.function_call_arguments.delta")]
+    FunctionCallArgumentsDelta {
+        sequence_number: u64,
+        item_id: String,
+        output_index: usize,
+        delta: String,
+    },
+    #[serde(rename = "response.output_item.done")]
+    OutputItemDone {
+        sequence_number: u64,
+        output_index: usize,
+        item: ResponseItem,
+    },
+    #[serde(rename = "response.completed")]
+    Completed {
+        sequence_number: u64,
+        response: ResponseObject,
+    },
+    #[serde(rename = "response.failed")]
+    Failed {
+        sequence_number: u64,
+        response: ResponseObject,
+    },
+}
+
+impl ResponseStreamEvent {
+    /// The `type` tag, also used as the SSE event name
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            ResponseStreamEvent::Created { .. } => "response.created",
+            ResponseStreamEvent::OutputItemAdded { .. } => "response.output_item.added",
+            ResponseStreamEvent::OutputTextDelta { .. } => "response.output_text.delta",
+            ResponseStreamEvent::ReasoningTextDelta { .. } => "response.reasoning_text.delta",
+            ResponseStreamEvent::FunctionCallArgumentsDelta { .. } => "response.function_call_arguments.delta",
+            ResponseStreamEvent::OutputItemDone { .. } => "response.output_item.done",
+            ResponseStreamEvent::Completed { .. } => "response.completed",
+            ResponseStreamEvent::Failed { .. } => "response.failed",
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -112,6 +328,23 @@ mod tests {
         assert_eq!(item, deserialized);
     }
 
+    #[test]
+    fn test_multimodal_content_serde() {
+        let parts: Vec<ContentPart> = serde_json::from_value(serde_json::json!([
+            {"type": "image_url", "url": "https://example.com/cat.png"},
+            {"type": "input_file", "filename": "report.pdf", "media_type": "application/pdf", "data_base64": "JVBERi0="},
+            {"type": "audio", "media_type": "audio/wav", "data_base64": "UklGRg=="}
+        ]))
+        .unwrap();
+        assert_eq!(parts[0], ContentPart::ImageUrl { url: "https://example.com/cat.png".to_string(), detail: None });
+        assert!(matches!(&parts[1], ContentPart::File { filename: Some(name), url: None, .. } if name == "report.pdf"));
+        assert_eq!(serde_json::to_value(&parts[1]).unwrap()["type"], "file");
+
+        let url = ContentPart::data_url("image/png", "iVBORw0KGgo=");
+        assert_eq!(ContentPart::parse_data_url(&url), Some(("image/png", "iVBORw0KGgo=")));
+        assert_eq!(ContentPart::parse_data_url("https://example.com/cat.png"), None);
+    }
+
     #[test]
     fn test_reasoning_serde() {
         let item = ResponseItem::Reasoning {
@@ -186,4 +419,48 @@ mod tests {
             _ => panic!("Expected items input"),
         }
     }
+
+    #[test]
+    fn test_open_responses_tools_serde() {
+        let req_str = r#"{
+            "input": "Weather in Paris?",
+            "tools": [{
+                "type": "function",
+                "name": "get_weather",
+                "description": "Current weather for a city",
+                "parameters": {"type": "object", "properties": {"city": {"type": "string"}}, "required": ["city"]}
+            }],
+            "tool_choice": {"type": "function", "name": "get_weather"}
+        }"#;
+
+        let req: CreateResponseRequest = serde_json::from_str(req_str).unwrap();
+        let tools = req.tools.unwrap();
+        assert_eq!(tools[0].name, "get_weather");
+        assert_eq!(tools[0].parameters["required"][0], "city");
+        assert_eq!(
+            req.tool_choice,
+            Some(ResponseToolChoice::Function { r#type: "function".to_string(), name: "get_weather".to_string() })
+        );
+
+        let auto: ResponseToolChoice = serde_json::from_str("\"auto\"").unwrap();
+        assert_eq!(auto, ResponseToolChoice::Mode("auto".to_string()));
+    }
+
+    #[test]
+    fn test_stream_event_serde() {
+        let event = ResponseStreamEvent::OutputTextDelta {
+            sequence_number: 3,
+            item_id: "msg_1".to_string(),
+            output_index: 0,
+            content_index: 0,
+            delta: "Hel".to_string(),
+        };
+
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["type"], event.event_type());
+        assert_eq!(json["delta"], "Hel");
+
+        let deserialized: ResponseStreamEvent = serde_json::from_value(json).unwrap();
+        assert_eq!(event, deserialized);
+    }
 }
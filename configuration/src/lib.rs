@@ -1,4 +1,4 @@
-use serde::{Serialize,Deserialize};
+use serde::{Serialize,Deserialize,Deserializer};
 use std::fs; // Assuming you might want logging here too
 
 use tracing_subscriber::{prelude::*, fmt, layer::Layer, Registry};
@@ -42,6 +42,13 @@ pub struct McpRuntimeConfig {
     /// Adds one extra LLM round-trip per tool cycle. Default: false.
     #[serde(default)]
     pub agent_mcp_enable_evaluation: Option<bool>,
+    /// Retries and timeouts of the calls to `agent_mcp_llm_url`, e.g.
+    /// `{ max_attempts = 5, request_timeout_ms = 15000 }`. Unset fields keep the client defaults.
+    #[serde(default)]
+    pub agent_mcp_llm_retry: Option<RetryConfig>,
+    /// Connect and read timeouts of the client calling `agent_mcp_llm_url`
+    #[serde(default)]
+    pub agent_mcp_llm_http: Option<HttpClientConfig>,
 }
 
 impl McpRuntimeConfig {
@@ -55,6 +62,48 @@ impl McpRuntimeConfig {
     }
 }
 
+//////////////////////////////////////////////////////////////////////
+// CONFIG FOR LLM CLIENTS
+//////////////////////////////////////////////////////////////////////
+
+
+/// Retry policy of an LLM client, as written in TOML. Unset fields keep the client defaults.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct RetryConfig {
+    /// Attempts in total, the first one included; 1 disables retries
+    pub max_attempts: Option<u32>,
+    /// Delay before the first retry, doubled on every following one
+    pub base_delay_ms: Option<u64>,
+    /// Upper bound of any single delay, `Retry-After` included
+    pub max_delay_ms: Option<u64>,
+    /// Fraction of each delay removed at random (0.0 to 1.0)
+    #[serde(default, deserialize_with = "fraction")]
+    pub jitter: Option<f64>,
+    /// HTTP statuses worth retrying
+    pub retryable_statuses: Option<Vec<u16>>,
+    /// Time budget for all attempts and delays together
+    pub deadline_ms: Option<u64>,
+    /// Time each attempt may wait for the response headers
+    pub request_timeout_ms: Option<u64>,
+}
+
+fn fraction<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+    let value = f64::deserialize(deserializer)?;
+    if !(0.0..=1.0).contains(&value) {
+        return Err(serde::de::Error::custom(format!("expected a fraction between 0.0 and 1.0, got {}", value)));
+    }
+    Ok(Some(value))
+}
+
+/// Timeouts of the HTTP client of an LLM endpoint. They bound connecting and each read, not
+/// whole responses, so long streams are not cut off.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct HttpClientConfig {
+    pub connect_timeout_ms: Option<u64>,
+    /// Longest wait for the next bytes of a response
+    pub read_timeout_ms: Option<u64>,
+}
+
 //////////////////////////////////////////////////////////////////////
 // CONFIG FOR ALL AGENTS
 //////////////////////////////////////////////////////////////////////
@@ -81,6 +130,10 @@ pub struct AgentConfig {
     pub agent_tags: Vec<String>,
     pub agent_examples: Vec<String>,
     pub agent_agents_references: Option<Vec<AgentReference>>,
+    /// Retries and timeouts of the calls to `agent_llm_url`. Unset fields keep the client defaults.
+    pub agent_llm_retry: Option<RetryConfig>,
+    /// Connect and read timeouts of the client calling `agent_llm_url`
+    pub agent_llm_http: Option<HttpClientConfig>,
 }
 
 impl AgentConfig {
@@ -114,6 +167,8 @@ impl AgentConfig {
     pub fn agent_tags(&self) -> Vec<String> { self.agent_tags.clone() }
     pub fn agent_examples(&self) -> Vec<String> { self.agent_examples.clone() }
     pub fn agent_agents_references(&self) -> Option<Vec<AgentReference>> { self.agent_agents_references.clone() }
+    pub fn agent_llm_retry(&self) -> Option<RetryConfig> { self.agent_llm_retry.clone() }
+    pub fn agent_llm_http(&self) -> Option<HttpClientConfig> { self.agent_llm_http.clone() }
 }
 
 pub struct AgentConfigBuilder {
@@ -136,6 +191,14 @@ pub struct AgentConfigBuilder {
     pub agent_tags: Option<Vec<String>>,
     pub agent_examples: Option<Vec<String>>,
     pub agent_agents_references: Option<Vec<AgentReference>>,
+    pub agent_llm_retry: Option<RetryConfig>,
+    pub agent_llm_http: Option<HttpClientConfig>,
+}
+
+impl Default for AgentConfigBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl AgentConfigBuilder {
@@ -160,6 +223,8 @@ impl AgentConfigBuilder {
             agent_tags: None,
             agent_examples: None,
             agent_agents_references: None,
+            agent_llm_retry: None,
+            agent_llm_http: None,
         }
     }
 
@@ -259,6 +324,16 @@ impl AgentConfigBuilder {
         self
     }
 
+    pub fn agent_llm_retry(mut self, agent_llm_retry: RetryConfig) -> Self {
+        self.agent_llm_retry = Some(agent_llm_retry);
+        self
+    }
+
+    pub fn agent_llm_http(mut self, agent_llm_http: HttpClientConfig) -> Self {
+        self.agent_llm_http = Some(agent_llm_http);
+        self
+    }
+
 
     pub fn build(self) -> anyhow::Result<AgentConfig> {
         Ok(AgentConfig {
@@ -281,6 +356,8 @@ impl AgentConfigBuilder {
             agent_tags: self.agent_tags.unwrap_or_default(),
             agent_examples: self.agent_examples.unwrap_or_default(),
             agent_agents_references: self.agent_agents_references,
+            agent_llm_retry: self.agent_llm_retry,
+            agent_llm_http: self.agent_llm_http,
         })
     }
 }
@@ -302,8 +379,8 @@ impl AgentReference {
     pub fn new(id: String, url: String) -> anyhow::Result<AgentReference> {
         // Create the agent card
         Ok(AgentReference {
-            id: id,
-            url: url,
+            id,
+            url,
             is_default:None,
         })
     }
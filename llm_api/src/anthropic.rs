@@ -0,0 +1,1001 @@
+//! Anthropic Messages API (`/v1/messages`) adapter.
+//!
+//! Converts `ResponseItem` histories into Messages requests and Messages responses (or their
+//! SSE event streams) back into `ResponseItem`s.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use agent_models::response_item::{ContentPart, ResponseItem, ResponseUsage, Role};
+
+use crate::chat::ToolChoice;
+use crate::client::{LlmClient, LlmError, LlmTurn, StreamDelta};
+use crate::generation::GenerationParams;
+use crate::retry::{http_client, send_with_retry, RetryPolicy};
+use configuration::HttpClientConfig;
+use crate::sse::{split_sse_event, sse_event_data};
+use crate::tools::Tool;
+
+/// Value of the required `anthropic-version` header
+pub const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// `max_tokens` is mandatory on the Messages API; used when the caller sets no limit
+pub const DEFAULT_MAX_TOKENS: u32 = 4096;
+
+// Data structures for Anthropic's /v1/messages API
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AnthropicMessagesRequest {
+    pub model: String,
+    pub max_tokens: u32,
+    pub messages: Vec<AnthropicMessage>,
+    /// System messages of the history, joined with blank lines
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<AnthropicTool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<AnthropicToolChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_sequences: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AnthropicMessage {
+    pub role: String, // "user" or "assistant"
+    pub content: Vec<AnthropicContentBlock>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnthropicContentBlock {
+    Text {
+        text: String,
+    },
+    Image {
+        source: AnthropicSource,
+    },
+    /// PDF document
+    Document {
+        source: AnthropicSource,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        #[serde(default)]
+        input: serde_json::Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        is_error: bool,
+    },
+    /// Extended thinking; the signature must be sent back unchanged with the block
+    Thinking {
+        thinking: String,
+        #[serde(default)]
+        signature: String,
+    },
+    /// Thinking withheld by safety systems, carried as opaque data
+    RedactedThinking {
+        data: String,
+    },
+}
+
+/// Where the content of an image or document block comes from
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnthropicSource {
+    Base64 { media_type: String, data: String },
+    Url { url: String },
+}
+
+impl AnthropicSource {
+    /// Inline base64 source, also for `data:` URLs which the API does not fetch
+    fn from_url(url: &str) -> Self {
+        match ContentPart::parse_data_url(url) {
+            Some((media_type, data)) => AnthropicSource::Base64 { media_type: media_type.to_string(), data: data.to_string() },
+            None => AnthropicSource::Url { url: url.to_string() },
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AnthropicTool {
+    pub name: String,
+    pub description: String,
+    pub input_schema: serde_json::Value,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnthropicToolChoice {
+    Auto,
+    Any,
+    None,
+    Tool { name: String },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct AnthropicMessagesResponse {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub model: String,
+    #[serde(default)]
+    pub role: String,
+    #[serde(default)]
+    pub content: Vec<AnthropicContentBlock>,
+    /// "end_turn", "max_tokens", "stop_sequence", "tool_use", "refusal", ...
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_sequence: Option<String>,
+    #[serde(default)]
+    pub usage: AnthropicUsage,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct AnthropicUsage {
+    #[serde(default)]
+    pub input_tokens: u32,
+    #[serde(default)]
+    pub output_tokens: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_creation_input_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_read_input_tokens: Option<u32>,
+}
+
+// --- Streaming (`"stream": true`) ---
+
+/// One server-sent event of a streamed Messages response
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnthropicStreamEvent {
+    /// Carries the message id, model and input token usage; content is empty
+    MessageStart { message: AnthropicMessagesResponse },
+    ContentBlockStart { index: u32, content_block: AnthropicContentBlock },
+    ContentBlockDelta { index: u32, delta: AnthropicBlockDelta },
+    ContentBlockStop { index: u32 },
+    /// Stop reason and cumulative output token usage
+    MessageDelta {
+        delta: AnthropicMessageDelta,
+        #[serde(default)]
+        usage: Option<AnthropicUsage>,
+    },
+    MessageStop,
+    Ping,
+    Error { error: AnthropicErrorBody },
+    /// Event types added after this adapter was written
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnthropicBlockDelta {
+    TextDelta { text: String },
+    /// Fragment of a tool_use block's JSON input
+    InputJsonDelta { partial_json: String },
+    ThinkingDelta { thinking: String },
+    SignatureDelta { signature: String },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct AnthropicMessageDelta {
+    #[serde(default)]
+    pub stop_reason: Option<String>,
+    #[serde(default)]
+    pub stop_sequence: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AnthropicErrorBody {
+    pub r#type: String,
+    pub message: String,
+}
+
+impl AnthropicErrorBody {
+    /// Classify an error sent as a stream event, using the status the API gives the same error type
+    pub fn to_llm_error(&self) -> LlmError {
+        let status = match self.r#type.as_str() {
+            "invalid_request_error" => 400,
+            "authentication_error" => 401,
+            "permission_error" => 403,
+            "not_found_error" => 404,
+            "request_too_large" => 413,
+            "rate_limit_error" => 429,
+            "overloaded_error" => 529,
+            _ => 500,
+        };
+        let body = serde_json::json!({ "type": "error", "error": self }).to_string();
+        LlmError::classify(status, None, &body)
+    }
+}
+
+/// Rebuilds the complete `AnthropicMessagesResponse` from a sequence of stream events
+#[derive(Debug, Default)]
+pub struct AnthropicStreamAccumulator {
+    response: AnthropicMessagesResponse,
+    /// Block position in `response.content` keyed by stream index
+    blocks: BTreeMap<u32, usize>,
+    /// Unparsed tool input fragments keyed by stream index
+    partial_json: BTreeMap<u32, String>,
+}
+
+impl AnthropicStreamAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, event: &AnthropicStreamEvent) {
+        match event {
+            AnthropicStreamEvent::MessageStart { message } => {
+                self.response.id = message.id.clone();
+                self.response.model = message.model.clone();
+                self.response.role = message.role.clone();
+                self.response.usage = message.usage.clone();
+            }
+            AnthropicStreamEvent::ContentBlockStart { index, content_block } => {
+                self.blocks.insert(*index, self.response.content.len());
+                self.response.content.push(content_block.clone());
+            }
+            AnthropicStreamEvent::ContentBlockDelta { index, delta } => {
+                if let AnthropicBlockDelta::InputJsonDelta { partial_json } = delta {
+                    self.partial_json.entry(*index).or_default().push_str(partial_json);
+                    return;
+                }
+                let Some(block) = self.blocks.get(index).and_then(|&pos| self.response.content.get_mut(pos)) else {
+                    return;
+                };
+                match (block, delta) {
+                    (AnthropicContentBlock::Text { text }, AnthropicBlockDelta::TextDelta { text: delta }) => {
+                        text.push_str(delta)
+                    }
+                    (AnthropicContentBlock::Thinking { thinking, .. }, AnthropicBlockDelta::ThinkingDelta { thinking: delta }) => {
+                        thinking.push_str(delta)
+                    }
+                    (AnthropicContentBlock::Thinking { signature, .. }, AnthropicBlockDelta::SignatureDelta { signature: delta }) => {
+                        signature.push_str(delta)
+                    }
+                    _ => {}
+                }
+            }
+            AnthropicStreamEvent::ContentBlockStop { index } => {
+                if let Some(json) = self.partial_json.remove(index)
+                    && let Some(AnthropicContentBlock::ToolUse { input, .. }) =
+                        self.blocks.get(index).and_then(|&pos| self.response.content.get_mut(pos))
+                {
+                    *input = serde_json::from_str(&json).unwrap_or_else(|_| serde_json::json!({}));
+                }
+            }
+            AnthropicStreamEvent::MessageDelta { delta, usage } => {
+                if delta.stop_reason.is_some() {
+                    self.response.stop_reason = delta.stop_reason.clone();
+                    self.response.stop_sequence = delta.stop_sequence.clone();
+                }
+                if let Some(usage) = usage {
+                    // message_delta usage is cumulative; input tokens only come with message_start
+                    self.response.usage.output_tokens = usage.output_tokens;
+                    if usage.input_tokens > 0 {
+                        self.response.usage.input_tokens = usage.input_tokens;
+                    }
+                }
+            }
+            AnthropicStreamEvent::MessageStop
+            | AnthropicStreamEvent::Ping
+            | AnthropicStreamEvent::Error { .. }
+            | AnthropicStreamEvent::Unknown => {}
+        }
+    }
+
+    /// The message as accumulated so far
+    pub fn response(&self) -> &AnthropicMessagesResponse {
+        &self.response
+    }
+
+    pub fn finish(self) -> AnthropicMessagesResponse {
+        self.response
+    }
+}
+
+// --- Adapter Logic ---
+
+pub struct AnthropicAdapter;
+
+impl AnthropicAdapter {
+    /// Converts a history of `ResponseItem`s into an `AnthropicMessagesRequest`.
+    /// System messages become the `system` prompt, function calls and outputs become
+    /// `tool_use`/`tool_result` blocks and consecutive turns of the same role are merged.
+    /// Reasoning is only sent back when it carries a signature, as the API requires.
+    pub fn to_messages_request(
+        history: &[ResponseItem],
+        model: &str,
+        tools: &[Tool],
+        params: &GenerationParams,
+    ) -> AnthropicMessagesRequest {
+        let mut messages: Vec<AnthropicMessage> = Vec::new();
+        let mut system_prompts: Vec<String> = Vec::new();
+
+        for item in history {
+            match item {
+                ResponseItem::Message { role, content, .. } => {
+                    let anthropic_role = match role {
+                        Role::User => "user",
+                        Role::Assistant => "assistant",
+                        Role::System => {
+                            system_prompts.extend(content.iter().filter_map(|part| match part {
+                                ContentPart::Text { text } if !text.is_empty() => Some(text.clone()),
+                                _ => None,
+                            }));
+                            continue;
+                        }
+                        Role::Tool => continue, // Handled by FunctionCallOutput
+                    };
+
+                    let blocks = content
+                        .iter()
+                        .filter_map(|part| match part {
+                            // Empty text blocks are rejected by the API
+                            ContentPart::Text { text } if text.is_empty() => None,
+                            ContentPart::Text { text } => Some(AnthropicContentBlock::Text { text: text.clone() }),
+                            ContentPart::Image { media_type, data_base64 } => Some(AnthropicContentBlock::Image {
+                                source: AnthropicSource::Base64 { media_type: media_type.clone(), data: data_base64.clone() },
+                            }),
+                            ContentPart::ImageUrl { url, .. } => {
+                                Some(AnthropicContentBlock::Image { source: AnthropicSource::from_url(url) })
+                            }
+                            ContentPart::File { filename, media_type, data_base64, url } => {
+                                let source = match (data_base64, url) {
+                                    (Some(data), _) => AnthropicSource::Base64 { media_type: media_type.clone(), data: data.clone() },
+                                    (None, Some(url)) => AnthropicSource::from_url(url),
+                                    (None, None) => {
+                                        tracing::warn!("Dropping file part {:?} without data or url", filename);
+                                        return None;
+                                    }
+                                };
+                                Some(AnthropicContentBlock::Document { source, title: filename.clone() })
+                            }
+                            ContentPart::Audio { media_type, .. } => {
+                                tracing::warn!("Dropping {} audio part, the Messages API has no audio input", media_type);
+                                None
+                            }
+                        })
+                        .collect();
+                    Self::push_turn(&mut messages, anthropic_role, blocks);
+                }
+                ResponseItem::Reasoning { thought_process, signature, .. } => {
+                    if let Some(signature) = signature {
+                        let block = AnthropicContentBlock::Thinking {
+                            thinking: thought_process.clone(),
+                            signature: signature.clone(),
+                        };
+                        Self::push_turn(&mut messages, "assistant", vec![block]);
+                    }
+                }
+                ResponseItem::FunctionCall { call_id, name, arguments, .. } => {
+                    let input = serde_json::from_str(arguments)
+                        .unwrap_or_else(|_| serde_json::json!({ "raw": arguments }));
+                    let block = AnthropicContentBlock::ToolUse {
+                        id: call_id.clone(),
+                        name: name.clone(),
+                        input,
+                    };
+                    Self::push_turn(&mut messages, "assistant", vec![block]);
+                }
+                ResponseItem::FunctionCallOutput { call_id, output, is_error, .. } => {
+                    let block = AnthropicContentBlock::ToolResult {
+                        tool_use_id: call_id.clone(),
+                        content: output.clone(),
+                        is_error: *is_error,
+                    };
+                    Self::push_turn(&mut messages, "user", vec![block]);
+                }
+            }
+        }
+
+        let tools = Self::to_anthropic_tools(tools);
+        let tool_choice = if tools.is_some() {
+            params.tool_choice.as_ref().map(Self::to_anthropic_tool_choice)
+        } else {
+            None
+        };
+
+        AnthropicMessagesRequest {
+            model: model.to_string(),
+            max_tokens: params.max_output_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+            messages,
+            system: (!system_prompts.is_empty()).then(|| system_prompts.join("\n\n")),
+            tools,
+            tool_choice,
+            temperature: params.temperature,
+            top_p: params.top_p,
+            stop_sequences: params.stop.clone().filter(|stop| !stop.is_empty()),
+            stream: None,
+        }
+    }
+
+    /// Append blocks as a turn of `role`, extending the last turn when it has the same role
+    fn push_turn(messages: &mut Vec<AnthropicMessage>, role: &str, blocks: Vec<AnthropicContentBlock>) {
+        if blocks.is_empty() {
+            return;
+        }
+        match messages.last_mut() {
+            Some(last) if last.role == role => last.content.extend(blocks),
+            _ => messages.push(AnthropicMessage {
+                role: role.to_string(),
+                content: blocks,
+            }),
+        }
+    }
+
+    /// Converts chat completion tools into Anthropic tool definitions, `None` when empty.
+    pub fn to_anthropic_tools(tools: &[Tool]) -> Option<Vec<AnthropicTool>> {
+        if tools.is_empty() {
+            return None;
+        }
+        let tools = tools
+            .iter()
+            .map(|tool| AnthropicTool {
+                name: tool.function.name.clone(),
+                description: tool.function.description.clone(),
+                input_schema: serde_json::to_value(&tool.function.parameters)
+                    .unwrap_or_else(|_| serde_json::json!({ "type": "object" })),
+            })
+            .collect();
+        Some(tools)
+    }
+
+    /// Maps an OpenAI-style tool_choice onto Anthropic's tool_choice.
+    pub fn to_anthropic_tool_choice(tool_choice: &ToolChoice) -> AnthropicToolChoice {
+        match tool_choice {
+            ToolChoice::String(mode) => match mode.as_str() {
+                "none" => AnthropicToolChoice::None,
+                "required" | "any" => AnthropicToolChoice::Any,
+                _ => AnthropicToolChoice::Auto,
+            },
+            ToolChoice::Function { function, .. } => AnthropicToolChoice::Tool {
+                name: function.name.clone(),
+            },
+        }
+    }
+
+    /// Converts a Messages response into `ResponseItem`s.
+    /// Thinking blocks become `Reasoning` with their signature, adjacent text and image blocks
+    /// share one message and `tool_use` ids are kept as `call_id`.
+    pub fn from_messages_response(response: &AnthropicMessagesResponse) -> Vec<ResponseItem> {
+        let mut items = Vec::new();
+        let mut message_parts: Vec<ContentPart> = Vec::new();
+
+        fn flush_message(items: &mut Vec<ResponseItem>, parts: &mut Vec<ContentPart>) {
+            if !parts.is_empty() {
+                items.push(ResponseItem::Message {
+                    id: format!("resp_msg_{}", uuid::Uuid::new_v4()),
+                    role: Role::Assistant,
+                    content: std::mem::take(parts),
+                });
+            }
+        }
+
+        for block in &response.content {
+            match block {
+                AnthropicContentBlock::Text { text } => match message_parts.last_mut() {
+                    Some(ContentPart::Text { text: existing }) => existing.push_str(text),
+                    _ => message_parts.push(ContentPart::Text { text: text.clone() }),
+                },
+                AnthropicContentBlock::Image { source } => message_parts.push(match source {
+                    AnthropicSource::Base64 { media_type, data } => {
+                        ContentPart::Image { media_type: media_type.clone(), data_base64: data.clone() }
+                    }
+                    AnthropicSource::Url { url } => ContentPart::ImageUrl { url: url.clone(), detail: None },
+                }),
+                AnthropicContentBlock::Document { source, title } => message_parts.push(match source {
+                    AnthropicSource::Base64 { media_type, data } => ContentPart::File {
+                        filename: title.clone(),
+                        media_type: media_type.clone(),
+                        data_base64: Some(data.clone()),
+                        url: None,
+                    },
+                    AnthropicSource::Url { url } => ContentPart::File {
+                        filename: title.clone(),
+                        media_type: "application/pdf".to_string(),
+                        data_base64: None,
+                        url: Some(url.clone()),
+                    },
+                }),
+                AnthropicContentBlock::ToolUse { id, name, input } => {
+                    flush_message(&mut items, &mut message_parts);
+                    items.push(ResponseItem::FunctionCall {
+                        id: format!("fc_{}", uuid::Uuid::new_v4()),
+                        call_id: id.clone(),
+                        name: name.clone(),
+                        arguments: input.to_string(),
+                    });
+                }
+                AnthropicContentBlock::Thinking { thinking, signature } => {
+                    flush_message(&mut items, &mut message_parts);
+                    items.push(ResponseItem::Reasoning {
+                        id: format!("rs_{}", uuid::Uuid::new_v4()),
+                        thought_process: thinking.clone(),
+                        signature: (!signature.is_empty()).then(|| signature.clone()),
+                    });
+                }
+                // Redacted thinking has no readable content and tool results never appear in responses
+                AnthropicContentBlock::RedactedThinking { .. } | AnthropicContentBlock::ToolResult { .. } => {}
+            }
+        }
+        flush_message(&mut items, &mut message_parts);
+        items
+    }
+
+    /// A POST to `url` with Anthropic's authentication and version headers
+    pub fn authorized_request(client: &reqwest::Client, url: &str, api_key: &str) -> reqwest::RequestBuilder {
+        client
+            .post(url)
+            .header("x-api-key", api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+    }
+
+    /// Posts `request` with `"stream": true` and forwards every parsed event to `tx`.
+    /// Error events sent by the API mid-stream are forwarded like any other event.
+    /// The request is retried under `retry_policy` only until the stream starts.
+    pub async fn stream_messages(
+        client: &reqwest::Client,
+        url: &str,
+        api_key: &str,
+        request: &AnthropicMessagesRequest,
+        retry_policy: &RetryPolicy,
+        tx: tokio::sync::mpsc::Sender<AnthropicStreamEvent>,
+    ) -> Result<(), LlmError> {
+        let mut request = request.clone();
+        request.stream = Some(true);
+        let mut response = send_with_retry(retry_policy, "Anthropic streaming API", || {
+            Self::authorized_request(client, url, api_key).json(&request)
+        })
+        .await?;
+        let mut buffer = String::new();
+
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| LlmError::from_reqwest("Anthropic stream interrupted", &e))?
+        {
+            buffer.push_str(&String::from_utf8_lossy(&chunk));
+
+            while let Some((event, rest)) = split_sse_event(&buffer) {
+                buffer = rest;
+                if let Some(event) = Self::parse_stream_event(&sse_event_data(&event))
+                    && tx.send(event).await.is_err()
+                {
+                    return Ok(());
+                }
+            }
+        }
+
+        // A final event without trailing blank line
+        if let Some(event) = Self::parse_stream_event(&sse_event_data(&buffer)) {
+            let _ = tx.send(event).await;
+        }
+        Ok(())
+    }
+
+    /// The `data:` payload of one SSE event; the `event:` line repeats its `type` and is ignored
+    fn parse_stream_event(data: &str) -> Option<AnthropicStreamEvent> {
+        if data.is_empty() {
+            return None;
+        }
+        match serde_json::from_str(data) {
+            Ok(event) => Some(event),
+            Err(e) => {
+                tracing::warn!("Skipping undecodable Anthropic stream event ({}): {}", e, data);
+                None
+            }
+        }
+    }
+}
+
+impl From<&AnthropicUsage> for ResponseUsage {
+    fn from(usage: &AnthropicUsage) -> Self {
+        ResponseUsage {
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+            total_tokens: usage.input_tokens + usage.output_tokens,
+        }
+    }
+}
+
+/// [`LlmClient`] for the Anthropic Messages API
+#[derive(Clone)]
+pub struct AnthropicClient {
+    pub client: reqwest::Client,
+    /// Full url of the messages endpoint, e.g. `https://api.anthropic.com/v1/messages`
+    pub llm_url: String,
+    api_key: String,
+    pub model_id: String,
+    /// Retries and timeouts of every upstream call
+    pub retry_policy: RetryPolicy,
+}
+
+impl AnthropicClient {
+    pub fn new(llm_url: String, model_id: String, api_key: String) -> Self {
+        Self {
+            client: http_client(&HttpClientConfig::default()),
+            llm_url,
+            api_key,
+            model_id,
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+
+    /// Use a shared HTTP client instead of the default one
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Retry and time out upstream calls according to `retry_policy`
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl LlmClient for AnthropicClient {
+    fn model_id(&self) -> &str {
+        &self.model_id
+    }
+
+    async fn complete(
+        &self,
+        history: &[ResponseItem],
+        params: &GenerationParams,
+        tools: &[Tool],
+    ) -> Result<LlmTurn, LlmError> {
+        let history = params.reasoning_options().upstream_history(history);
+        let request = AnthropicAdapter::to_messages_request(&history, &self.model_id, tools, params);
+
+        let res = send_with_retry(&self.retry_policy, "Anthropic API", || {
+            AnthropicAdapter::authorized_request(&self.client, &self.llm_url, &self.api_key).json(&request)
+        })
+        .await?;
+
+        let response: AnthropicMessagesResponse = res
+            .json()
+            .await
+            .map_err(|e| LlmError::from_reqwest("Failed to parse Anthropic response", &e))?;
+
+        Ok(LlmTurn {
+            items: params.reasoning_options().output(AnthropicAdapter::from_messages_response(&response)),
+            usage: Some((&response.usage).into()),
+            model: Some(response.model).filter(|m| !m.is_empty()).or_else(|| Some(self.model_id.clone())),
+        })
+    }
+
+    async fn stream(
+        &self,
+        history: &[ResponseItem],
+        params: &GenerationParams,
+        tools: &[Tool],
+        tx: tokio::sync::mpsc::Sender<StreamDelta>,
+    ) -> Result<Option<ResponseUsage>, LlmError> {
+        let history = params.reasoning_options().upstream_history(history);
+        let request = AnthropicAdapter::to_messages_request(&history, &self.model_id, tools, params);
+
+        let include_reasoning = params.reasoning_options().include;
+
+        let (event_tx, mut event_rx) = tokio::sync::mpsc::channel::<AnthropicStreamEvent>(64);
+        let translate = async move {
+            let mut accumulator = AnthropicStreamAccumulator::new();
+            // Function call index keyed by content block index
+            let mut calls = std::collections::HashMap::new();
+            let mut stream_error = None;
+            while let Some(event) = event_rx.recv().await {
+                accumulator.push(&event);
+                let delta = match event {
+                    AnthropicStreamEvent::ContentBlockStart {
+                        index,
+                        content_block: AnthropicContentBlock::ToolUse { id, name, .. },
+                    } => {
+                        let call_index = calls.len();
+                        calls.insert(index, call_index);
+                        Some(StreamDelta::FunctionCallStart { index: call_index, call_id: id, name })
+                    }
+                    AnthropicStreamEvent::ContentBlockStart {
+                        content_block: AnthropicContentBlock::Text { text },
+                        ..
+                    } if !text.is_empty() => Some(StreamDelta::Text(text)),
+                    AnthropicStreamEvent::ContentBlockStart {
+                        content_block: AnthropicContentBlock::Thinking { thinking, signature },
+                        ..
+                    } if include_reasoning && !(thinking.is_empty() && signature.is_empty()) => Some(StreamDelta::Reasoning {
+                        text: thinking,
+                        signature: Some(signature).filter(|s| !s.is_empty()),
+                    }),
+                    AnthropicStreamEvent::ContentBlockDelta { index, delta } => match delta {
+                        AnthropicBlockDelta::TextDelta { text } => Some(StreamDelta::Text(text)),
+                        AnthropicBlockDelta::InputJsonDelta { partial_json } => calls
+                            .get(&index)
+                            .map(|&call_index| StreamDelta::FunctionCallArguments { index: call_index, delta: partial_json }),
+                        AnthropicBlockDelta::ThinkingDelta { thinking } if include_reasoning => {
+                            Some(StreamDelta::Reasoning { text: thinking, signature: None })
+                        }
+                        AnthropicBlockDelta::SignatureDelta { signature } if include_reasoning => {
+                            Some(StreamDelta::Reasoning { text: String::new(), signature: Some(signature) })
+                        }
+                        _ => None,
+                    },
+                    AnthropicStreamEvent::Error { error } => {
+                        stream_error = Some(error.to_llm_error());
+                        None
+                    }
+                    _ => None,
+                };
+                if let Some(delta) = delta
+                    && tx.send(delta).await.is_err()
+                {
+                    break;
+                }
+            }
+            (accumulator.finish(), stream_error)
+        };
+        let (sent, (response, stream_error)) = tokio::join!(
+            AnthropicAdapter::stream_messages(
+                &self.client,
+                &self.llm_url,
+                &self.api_key,
+                &request,
+                &self.retry_policy,
+                event_tx,
+            ),
+            translate
+        );
+        sent?;
+        if let Some(error) = stream_error {
+            return Err(error);
+        }
+
+        Ok(Some((&response.usage).into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::{FunctionDefinition, FunctionParameters};
+
+    fn text(id: &str, role: Role, text: &str) -> ResponseItem {
+        ResponseItem::Message {
+            id: id.to_string(),
+            role,
+            content: vec![ContentPart::Text { text: text.to_string() }],
+        }
+    }
+
+    #[test]
+    fn test_to_messages_request_conversation_flow() {
+        let history = vec![
+            text("sys", Role::System, "You are terse."),
+            ResponseItem::Message {
+                id: "usr".to_string(),
+                role: Role::User,
+                content: vec![
+                    ContentPart::Text { text: "Weather where this photo was taken?".to_string() },
+                    ContentPart::Image {
+                        media_type: "image/jpeg".to_string(),
+                        data_base64: "/9j/4AAQ".to_string(),
+                    },
+                ],
+            },
+            ResponseItem::Reasoning {
+                id: "rs_1".to_string(),
+                thought_process: "Looks like Paris.".to_string(),
+                signature: Some("sig-abc".to_string()),
+            },
+            ResponseItem::Reasoning {
+                id: "rs_2".to_string(),
+                thought_process: "Unsigned trace from another provider".to_string(),
+                signature: None,
+            },
+            ResponseItem::FunctionCall {
+                id: "fc_1".to_string(),
+                call_id: "toolu_01".to_string(),
+                name: "get_weather".to_string(),
+                arguments: r#"{"city":"Paris"}"#.to_string(),
+            },
+            ResponseItem::FunctionCallOutput {
+                id: "fco_1".to_string(),
+                call_id: "toolu_01".to_string(),
+                output: "sunny".to_string(),
+                is_error: false,
+            },
+            text("usr2", Role::User, "Thanks, and tomorrow?"),
+        ];
+        let tools = vec![Tool {
+            r#type: "function".to_string(),
+            function: FunctionDefinition {
+                name: "get_weather".to_string(),
+                description: "Current weather for a city".to_string(),
+                parameters: FunctionParameters {
+                    r#type: "object".to_string(),
+                    properties: serde_json::json!({"city": {"type": "string"}}),
+                    required: Some(vec!["city".to_string()]),
+                },
+            },
+        }];
+        let params = GenerationParams {
+            temperature: Some(0.3),
+            tool_choice: Some(ToolChoice::String("required".to_string())),
+            ..Default::default()
+        };
+
+        let request = AnthropicAdapter::to_messages_request(&history, "claude-sonnet-4-5", &tools, &params);
+        assert_eq!(request.system.as_deref(), Some("You are terse."));
+        assert_eq!(request.max_tokens, DEFAULT_MAX_TOKENS);
+        assert_eq!(request.tool_choice, Some(AnthropicToolChoice::Any));
+
+        let roles: Vec<&str> = request.messages.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, vec!["user", "assistant", "user"]);
+        assert_eq!(request.messages[1].content.len(), 2);
+
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["messages"][0]["content"][1]["type"], "image");
+        assert_eq!(json["messages"][0]["content"][1]["source"]["media_type"], "image/jpeg");
+        assert_eq!(json["messages"][1]["content"][0]["type"], "thinking");
+        assert_eq!(json["messages"][1]["content"][0]["signature"], "sig-abc");
+        assert_eq!(json["messages"][1]["content"][1]["type"], "tool_use");
+        assert_eq!(json["messages"][1]["content"][1]["input"]["city"], "Paris");
+        assert_eq!(json["messages"][2]["content"][0]["type"], "tool_result");
+        assert_eq!(json["messages"][2]["content"][0]["tool_use_id"], "toolu_01");
+        assert!(json["messages"][2]["content"][0].get("is_error").is_none());
+        assert_eq!(json["messages"][2]["content"][1]["text"], "Thanks, and tomorrow?");
+        assert_eq!(json["tools"][0]["input_schema"]["required"][0], "city");
+        assert_eq!(json["tool_choice"]["type"], "any");
+    }
+
+    #[test]
+    fn test_to_messages_request_media_blocks() {
+        let history = vec![ResponseItem::Message {
+            id: "usr".to_string(),
+            role: Role::User,
+            content: vec![
+                ContentPart::ImageUrl { url: "https://example.com/cat.png".to_string(), detail: None },
+                ContentPart::ImageUrl { url: ContentPart::data_url("image/png", "iVBORw0KGgo="), detail: None },
+                ContentPart::File {
+                    filename: Some("report.pdf".to_string()),
+                    media_type: "application/pdf".to_string(),
+                    data_base64: Some("JVBERi0=".to_string()),
+                    url: None,
+                },
+                ContentPart::Audio { media_type: "audio/wav".to_string(), data_base64: "UklGRg==".to_string() },
+            ],
+        }];
+        let request = AnthropicAdapter::to_messages_request(&history, "claude-sonnet-4-5", &[], &GenerationParams::default());
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(
+            json["messages"][0]["content"],
+            serde_json::json!([
+                {"type": "image", "source": {"type": "url", "url": "https://example.com/cat.png"}},
+                {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "iVBORw0KGgo="}},
+                {"type": "document", "source": {"type": "base64", "media_type": "application/pdf", "data": "JVBERi0="}, "title": "report.pdf"}
+            ])
+        );
+    }
+
+    #[test]
+    fn test_from_messages_response_items() {
+        let response: AnthropicMessagesResponse = serde_json::from_value(serde_json::json!({
+            "id": "msg_01",
+            "type": "message",
+            "role": "assistant",
+            "model": "claude-sonnet-4-5",
+            "content": [
+                {"type": "thinking", "thinking": "Need the weather tool.", "signature": "sig-xyz"},
+                {"type": "text", "text": "Let me check."},
+                {"type": "tool_use", "id": "toolu_02", "name": "get_weather", "input": {"city": "Rome"}}
+            ],
+            "stop_reason": "tool_use",
+            "usage": {"input_tokens": 20, "output_tokens": 11}
+        }))
+        .unwrap();
+
+        let items = AnthropicAdapter::from_messages_response(&response);
+        assert_eq!(items.len(), 3);
+        assert!(matches!(
+            &items[0],
+            ResponseItem::Reasoning { signature: Some(signature), .. } if signature == "sig-xyz"
+        ));
+        assert!(matches!(&items[1], ResponseItem::Message { role: Role::Assistant, .. }));
+        match &items[2] {
+            ResponseItem::FunctionCall { call_id, arguments, .. } => {
+                assert_eq!(call_id, "toolu_02");
+                assert_eq!(arguments, r#"{"city":"Rome"}"#);
+            }
+            other => panic!("expected function call, got {:?}", other),
+        }
+        assert_eq!(response.stop_reason.as_deref(), Some("tool_use"));
+        assert_eq!(response.usage.output_tokens, 11);
+    }
+
+    #[tokio::test]
+    async fn test_stream_messages_against_mock_sse() {
+        let events = [
+            ("message_start", serde_json::json!({"type": "message_start", "message": {"id": "msg_02", "type": "message", "role": "assistant", "model": "claude-sonnet-4-5", "content": [], "stop_reason": null, "usage": {"input_tokens": 25, "output_tokens": 1}}})),
+            ("content_block_start", serde_json::json!({"type": "content_block_start", "index": 0, "content_block": {"type": "thinking", "thinking": ""}})),
+            ("content_block_delta", serde_json::json!({"type": "content_block_delta", "index": 0, "delta": {"type": "thinking_delta", "thinking": "Rome, "}})),
+            ("content_block_delta", serde_json::json!({"type": "content_block_delta", "index": 0, "delta": {"type": "signature_delta", "signature": "sig-stream"}})),
+            ("content_block_stop", serde_json::json!({"type": "content_block_stop", "index": 0})),
+            ("ping", serde_json::json!({"type": "ping"})),
+            ("content_block_start", serde_json::json!({"type": "content_block_start", "index": 1, "content_block": {"type": "text", "text": ""}})),
+            ("content_block_delta", serde_json::json!({"type": "content_block_delta", "index": 1, "delta": {"type": "text_delta", "text": "Checking"}})),
+            ("content_block_stop", serde_json::json!({"type": "content_block_stop", "index": 1})),
+            ("content_block_start", serde_json::json!({"type": "content_block_start", "index": 2, "content_block": {"type": "tool_use", "id": "toolu_03", "name": "get_weather", "input": {}}})),
+            ("content_block_delta", serde_json::json!({"type": "content_block_delta", "index": 2, "delta": {"type": "input_json_delta", "partial_json": "{\"city\": "}})),
+            ("content_block_delta", serde_json::json!({"type": "content_block_delta", "index": 2, "delta": {"type": "input_json_delta", "partial_json": "\"Rome\"}"}})),
+            ("content_block_stop", serde_json::json!({"type": "content_block_stop", "index": 2})),
+            ("message_delta", serde_json::json!({"type": "message_delta", "delta": {"stop_reason": "tool_use", "stop_sequence": null}, "usage": {"output_tokens": 42}})),
+            ("message_stop", serde_json::json!({"type": "message_stop"})),
+        ];
+        let body: String = events
+            .iter()
+            .map(|(name, data)| format!("event: {}\ndata: {}\n\n", name, data))
+            .collect();
+        let (addr, request_text) = crate::sse::spawn_mock_sse_server("HTTP/1.1 200 OK", body).await;
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(64);
+        let request = AnthropicAdapter::to_messages_request(
+            &[text("usr", Role::User, "Weather in Rome?")],
+            "claude-sonnet-4-5",
+            &[],
+            &GenerationParams::default(),
+        );
+        AnthropicAdapter::stream_messages(
+            &reqwest::Client::new(),
+            &format!("http://{}/v1/messages", addr),
+            "test-key",
+            &request,
+            &RetryPolicy::no_retry(),
+            tx,
+        )
+        .await
+        .unwrap();
+
+        let mut accumulator = AnthropicStreamAccumulator::new();
+        let mut count = 0;
+        while let Some(event) = rx.recv().await {
+            accumulator.push(&event);
+            count += 1;
+        }
+        assert_eq!(count, events.len());
+
+        let response = accumulator.finish();
+        assert_eq!(response.id, "msg_02");
+        assert_eq!(response.stop_reason.as_deref(), Some("tool_use"));
+        assert_eq!(response.usage.input_tokens, 25);
+        assert_eq!(response.usage.output_tokens, 42);
+
+        let items = AnthropicAdapter::from_messages_response(&response);
+        assert_eq!(items.len(), 3);
+        assert!(matches!(
+            &items[0],
+            ResponseItem::Reasoning { thought_process, signature: Some(signature), .. }
+                if thought_process == "Rome, " && signature == "sig-stream"
+        ));
+        assert!(matches!(&items[2], ResponseItem::FunctionCall { arguments, .. } if arguments == r#"{"city":"Rome"}"#));
+
+        let request_text = request_text.await.unwrap().to_ascii_lowercase();
+        assert!(request_text.contains("x-api-key: test-key"));
+        assert!(request_text.contains("anthropic-version: 2023-06-01"));
+        assert!(request_text.contains(r#""stream":true"#));
+    }
+}
@@ -1,13 +1,20 @@
+use std::collections::BTreeMap;
+
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::Value; // Import Value for flexible parameters
 
-use tracing::{ debug,warn};
+use tracing::{debug, warn};
 
+use crate::client::{LlmClient, LlmError, LlmTurn, StreamDelta};
+use crate::generation::{GenerationParams, ReasoningOptions};
+use crate::retry::{http_client, send_with_retry, RetryPolicy};
+use configuration::HttpClientConfig;
+use crate::sse::{split_sse_event, sse_event_data};
 use crate::tools::Tool;
-use anyhow::{Result,Context};
-
-use tokio::time::{sleep, Duration};
+use agent_models::response_item::{ContentPart, ResponseItem, ResponseToolChoice, ResponseUsage, Role};
+use uuid::Uuid;
+use anyhow::Result;
 
 use regex::Regex;
 
@@ -18,6 +25,8 @@ pub struct ChatLlmInteraction {
     pub llm_url:String,
     llm_api_key:String,
     pub model_id:String,
+    /// Retries and timeouts of every upstream call
+    pub retry_policy: RetryPolicy,
 
 }
 
@@ -33,10 +42,12 @@ pub struct ChatCompletionRequest {
     pub max_tokens: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub top_p: Option<f32>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none", deserialize_with = "deserialize_stop")]
     pub stop: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
 
     // --- Tool Calling Additions (Request) ---
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -45,13 +56,27 @@ pub struct ChatCompletionRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_choice: Option<ToolChoice>,
     // --- End Tool Calling Additions ---
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+
+    /// Gateway extension choosing what happens to the model's reasoning, never sent upstream
+    #[serde(default, skip_serializing)]
+    pub reasoning: Option<ReasoningOptions>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StreamOptions {
+    /// Ask for a final chunk carrying the token usage of the whole stream
+    #[serde(default)]
+    pub include_usage: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Message {
     pub role: String,    // "system", "user", "assistant", or "tool"
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub content: Option<String>, // Content for system/user/assistant, or result for tool
+    pub content: Option<MessageContent>, // Content for system/user/assistant, or result for tool
 
     // --- Tool Calling Additions (for Tool Result Message) ---
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -63,7 +88,184 @@ pub struct Message {
     pub tool_calls: Option<Vec<ToolCall>>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// Assistant message returned by [`ChatLlmInteraction::call_api`], with its reasoning split off
+#[derive(Debug, Clone)]
+pub struct ChatReply {
+    pub message: Message,
+    /// `reasoning_content` and `<think>` blocks of the answer, when the model reasoned
+    pub reasoning: Option<String>,
+}
+
+/// Message content: plain text or an array of typed parts (text, images, audio, files)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum MessageContent {
+    Text(String),
+    Parts(Vec<ChatContentPart>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChatContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ChatImageUrl },
+    InputAudio { input_audio: ChatInputAudio },
+    File { file: ChatFile },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChatImageUrl {
+    /// http(s) URL or `data:` URL
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChatInputAudio {
+    /// base64 audio
+    pub data: String,
+    /// "wav" or "mp3"
+    pub format: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChatFile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+    /// `data:` URL of the file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_data: Option<String>,
+    /// Id of a file uploaded to OpenAI
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_id: Option<String>,
+}
+
+impl From<String> for MessageContent {
+    fn from(text: String) -> Self {
+        MessageContent::Text(text)
+    }
+}
+
+impl From<&str> for MessageContent {
+    fn from(text: &str) -> Self {
+        MessageContent::Text(text.to_string())
+    }
+}
+
+impl MessageContent {
+    /// The text of the content, text parts joined by newlines
+    pub fn text(&self) -> String {
+        match self {
+            MessageContent::Text(text) => text.clone(),
+            MessageContent::Parts(parts) => parts
+                .iter()
+                .filter_map(|part| match part {
+                    ChatContentPart::Text { text } => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+
+    /// Content as sent to chat completions: a string when it is only text, parts otherwise.
+    /// Files referenced by URL have no chat completions form and are left out.
+    pub fn from_content_parts(parts: &[ContentPart]) -> Self {
+        if parts.iter().all(|p| matches!(p, ContentPart::Text { .. })) {
+            let text = parts
+                .iter()
+                .filter_map(|p| match p {
+                    ContentPart::Text { text } => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            return MessageContent::Text(text);
+        }
+        let parts = parts
+            .iter()
+            .filter_map(|part| match part {
+                ContentPart::Text { text } => Some(ChatContentPart::Text { text: text.clone() }),
+                ContentPart::Image { media_type, data_base64 } => Some(ChatContentPart::ImageUrl {
+                    image_url: ChatImageUrl { url: ContentPart::data_url(media_type, data_base64), detail: None },
+                }),
+                ContentPart::ImageUrl { url, detail } => Some(ChatContentPart::ImageUrl {
+                    image_url: ChatImageUrl { url: url.clone(), detail: detail.clone() },
+                }),
+                ContentPart::Audio { media_type, data_base64 } => Some(ChatContentPart::InputAudio {
+                    input_audio: ChatInputAudio { data: data_base64.clone(), format: audio_format(media_type).to_string() },
+                }),
+                ContentPart::File { filename, media_type, data_base64: Some(data), .. } => Some(ChatContentPart::File {
+                    file: ChatFile {
+                        filename: filename.clone(),
+                        file_data: Some(ContentPart::data_url(media_type, data)),
+                        file_id: None,
+                    },
+                }),
+                ContentPart::File { filename, .. } => {
+                    warn!("Dropping file part {:?}, chat completions only take inline files", filename);
+                    None
+                }
+            })
+            .collect();
+        MessageContent::Parts(parts)
+    }
+
+    /// Content parts of a chat message. `data:` image URLs become inline images;
+    /// parts without a `ContentPart` form (uploaded file ids) are rejected.
+    pub fn to_content_parts(&self) -> Result<Vec<ContentPart>, String> {
+        let parts = match self {
+            MessageContent::Text(text) => return Ok(vec![ContentPart::Text { text: text.clone() }]),
+            MessageContent::Parts(parts) => parts,
+        };
+        parts
+            .iter()
+            .map(|part| match part {
+                ChatContentPart::Text { text } => Ok(ContentPart::Text { text: text.clone() }),
+                ChatContentPart::ImageUrl { image_url } => Ok(match ContentPart::parse_data_url(&image_url.url) {
+                    Some((media_type, data)) => {
+                        ContentPart::Image { media_type: media_type.to_string(), data_base64: data.to_string() }
+                    }
+                    None => ContentPart::ImageUrl { url: image_url.url.clone(), detail: image_url.detail.clone() },
+                }),
+                ChatContentPart::InputAudio { input_audio } => Ok(ContentPart::Audio {
+                    media_type: audio_media_type(&input_audio.format),
+                    data_base64: input_audio.data.clone(),
+                }),
+                ChatContentPart::File { file } => {
+                    let Some((media_type, data)) = file.file_data.as_deref().and_then(ContentPart::parse_data_url) else {
+                        return Err("file parts need file_data as a base64 data: URL, file_id is not supported".to_string());
+                    };
+                    Ok(ContentPart::File {
+                        filename: file.filename.clone(),
+                        media_type: media_type.to_string(),
+                        data_base64: Some(data.to_string()),
+                        url: None,
+                    })
+                }
+            })
+            .collect()
+    }
+}
+
+/// `input_audio.format` of an audio media type
+fn audio_format(media_type: &str) -> &str {
+    match media_type {
+        "audio/mpeg" | "audio/mp3" => "mp3",
+        "audio/wav" | "audio/x-wav" | "audio/wave" => "wav",
+        other => other.strip_prefix("audio/").unwrap_or(other),
+    }
+}
+
+fn audio_media_type(format: &str) -> String {
+    match format {
+        "mp3" => "audio/mpeg".to_string(),
+        other => format!("audio/{}", other),
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(untagged)] // Handles string ("none", "auto") or object variants
 pub enum ToolChoice {
     String(String), // Represents "none" or "auto"
@@ -73,11 +275,163 @@ pub enum ToolChoice {
     },
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct FunctionName {
     pub name: String,
 }
 
+/// Constraint on the format of the assistant output (`response_format`)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponseFormat {
+    Text,
+    /// Any valid JSON object. OpenAI requires the word "JSON" to appear in the messages.
+    JsonObject,
+    /// JSON matching `json_schema.schema`
+    JsonSchema { json_schema: JsonSchemaFormat },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JsonSchemaFormat {
+    /// Schema name, letters, digits, `_` and `-` only
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub schema: Value,
+    /// Ask the upstream to enforce the schema exactly (OpenAI strict mode)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strict: Option<bool>,
+}
+
+impl ResponseFormat {
+    /// Whether the output is required to be JSON
+    pub fn is_json(&self) -> bool {
+        !matches!(self, ResponseFormat::Text)
+    }
+
+    pub fn schema(&self) -> Option<&Value> {
+        match self {
+            ResponseFormat::JsonSchema { json_schema } => Some(&json_schema.schema),
+            _ => None,
+        }
+    }
+}
+
+/// OpenAI accepts `stop` as either a single string or an array of strings
+fn deserialize_stop<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Stop {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match Option::<Stop>::deserialize(deserializer)? {
+        Some(Stop::One(stop)) => Some(vec![stop]),
+        Some(Stop::Many(stops)) => Some(stops),
+        None => None,
+    })
+}
+
+impl ChatCompletionRequest {
+    /// Copies generation controls onto the request; tool_choice is only sent alongside tools
+    pub fn apply_generation_params(&mut self, params: &GenerationParams) {
+        self.temperature = params.temperature;
+        self.top_p = params.top_p;
+        self.max_tokens = params.max_output_tokens;
+        self.stop = params.stop.clone();
+        self.response_format = params.response_format.clone();
+        self.tool_choice = if self.tools.is_some() {
+            params.tool_choice.clone()
+        } else {
+            None
+        };
+    }
+}
+
+/// Converts an Open Responses tool_choice into the chat completions form
+impl From<&ResponseToolChoice> for ToolChoice {
+    fn from(choice: &ResponseToolChoice) -> Self {
+        match choice {
+            ResponseToolChoice::Mode(mode) => ToolChoice::String(mode.clone()),
+            ResponseToolChoice::Function { name, .. } => ToolChoice::Function {
+                r#type: "function".to_string(),
+                function: FunctionName { name: name.clone() },
+            },
+        }
+    }
+}
+
+impl From<&ToolChoice> for ResponseToolChoice {
+    fn from(choice: &ToolChoice) -> Self {
+        match choice {
+            ToolChoice::String(mode) => ResponseToolChoice::Mode(mode.clone()),
+            ToolChoice::Function { function, .. } => ResponseToolChoice::Function {
+                r#type: "function".to_string(),
+                name: function.name.clone(),
+            },
+        }
+    }
+}
+
+/// Convert ResponseItem history into chat messages. Consecutive function calls become the
+/// `tool_calls` of a single assistant message, as chat completions expects.
+pub fn items_to_messages(history: &[ResponseItem]) -> Vec<Message> {
+    let mut messages: Vec<Message> = Vec::new();
+    for item in history {
+        match item {
+            ResponseItem::Message { role, content, .. } => {
+                let role = match role {
+                    Role::System => "system",
+                    Role::Assistant => "assistant",
+                    Role::Tool => "tool",
+                    Role::User => "user",
+                };
+                messages.push(Message {
+                    role: role.to_string(),
+                    content: Some(MessageContent::from_content_parts(content)),
+                    tool_call_id: None,
+                    tool_calls: None,
+                });
+            }
+            ResponseItem::FunctionCall { call_id, name, arguments, .. } => {
+                let tool_call = ToolCall {
+                    id: call_id.clone(),
+                    r#type: "function".to_string(),
+                    function: FunctionCall {
+                        name: name.clone(),
+                        arguments: arguments.clone(),
+                    },
+                };
+                match messages.last_mut() {
+                    Some(Message { role, content: None, tool_calls: Some(calls), .. }) if role == "assistant" => {
+                        calls.push(tool_call);
+                    }
+                    _ => messages.push(Message {
+                        role: "assistant".to_string(),
+                        content: None,
+                        tool_call_id: None,
+                        tool_calls: Some(vec![tool_call]),
+                    }),
+                }
+            }
+            ResponseItem::FunctionCallOutput { call_id, output, .. } => {
+                messages.push(Message {
+                    role: "tool".to_string(),
+                    content: Some(output.clone().into()),
+                    tool_call_id: Some(call_id.clone()),
+                    tool_calls: None,
+                });
+            }
+            ResponseItem::Reasoning { .. } => {}
+        }
+    }
+    messages
+}
+
 // --- Structs for Response ---
 
 #[allow(dead_code)]
@@ -111,16 +465,19 @@ pub struct ResponseMessage {
     pub content: Option<String>,
     // Tool calls requested by the model
     pub tool_calls: Option<Vec<ToolCall>>,
+    /// Reasoning of DeepSeek-style models (`reasoning` on Groq and some OpenRouter models)
+    #[serde(default, alias = "reasoning", skip_serializing_if = "Option::is_none")]
+    pub reasoning_content: Option<String>,
 }
 
-#[derive(Serialize,Deserialize, Debug, Clone)]
+#[derive(Serialize,Deserialize, Debug, Clone, PartialEq)]
 pub struct ToolCall {
     pub id: String,     // ID to be sent back in the tool result message
     pub r#type: String, // Typically "function"
     pub function: FunctionCall,
 }
 
-#[derive(Serialize,Deserialize, Debug, Clone)]
+#[derive(Serialize,Deserialize, Debug, Clone, PartialEq)]
 pub struct FunctionCall {
     pub name: String,
     // Arguments is a STRING containing JSON, needs parsing
@@ -128,13 +485,208 @@ pub struct FunctionCall {
 }
 // --- End Tool Call Structs ---
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
 pub struct Usage {
     pub prompt_tokens: u32,
     pub completion_tokens: u32,
     pub total_tokens: u32,
 }
 
+// --- Structs for Streaming Response ---
+
+/// One `chat.completion.chunk` of a streamed chat completion
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChatCompletionChunk {
+    // Some OpenAI-compatible servers omit these on every chunk
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub object: String,
+    #[serde(default)]
+    pub created: u64,
+    #[serde(default)]
+    pub model: String,
+    #[serde(default)]
+    pub choices: Vec<ChunkChoice>,
+    /// Only set on the final chunk when `stream_options.include_usage` was requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChunkChoice {
+    pub index: u32,
+    #[serde(default)]
+    pub delta: ChunkDelta,
+    #[serde(default)]
+    pub finish_reason: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ChunkDelta {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCallDelta>>,
+    #[serde(default, alias = "reasoning", skip_serializing_if = "Option::is_none")]
+    pub reasoning_content: Option<String>,
+}
+
+/// Fragment of a tool call; `id`, `type` and the function name only appear in the first fragment
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ToolCallDelta {
+    #[serde(default)]
+    pub index: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub r#type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub function: Option<FunctionCallDelta>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct FunctionCallDelta {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arguments: Option<String>,
+}
+
+/// Output of [`StreamAccumulator::push`] that a consumer can act on right away
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChatStreamEvent {
+    /// Text appended to the assistant message
+    Content(String),
+    /// Text appended to the `reasoning_content` of the message
+    Reasoning(String),
+    /// A tool call whose arguments are complete
+    ToolCall(ToolCall),
+}
+
+/// Rebuilds the complete `ChatCompletionResponse` from the chunks of a stream, stitching
+/// tool-call argument fragments by index. Only the first choice is accumulated.
+#[derive(Debug, Default)]
+pub struct StreamAccumulator {
+    id: String,
+    created: u64,
+    model: String,
+    content: String,
+    reasoning: String,
+    /// Tool calls keyed by their stream index, with the number already yielded as finished
+    tool_calls: BTreeMap<u32, ToolCall>,
+    finished_calls: usize,
+    finish_reason: Option<String>,
+    usage: Option<Usage>,
+}
+
+impl StreamAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a chunk, returning its content delta and the tool calls it completed.
+    /// A tool call is complete once a later call starts or the choice finishes.
+    pub fn push(&mut self, chunk: &ChatCompletionChunk) -> Vec<ChatStreamEvent> {
+        if self.id.is_empty() {
+            self.id = chunk.id.clone();
+            self.created = chunk.created;
+        }
+        if self.model.is_empty() {
+            self.model = chunk.model.clone();
+        }
+        if let Some(usage) = &chunk.usage {
+            self.usage = Some(usage.clone());
+        }
+
+        let mut events = Vec::new();
+        let Some(choice) = chunk.choices.iter().find(|c| c.index == 0) else {
+            return events;
+        };
+        if let Some(text) = &choice.delta.reasoning_content
+            && !text.is_empty()
+        {
+            self.reasoning.push_str(text);
+            events.push(ChatStreamEvent::Reasoning(text.clone()));
+        }
+        if let Some(text) = &choice.delta.content
+            && !text.is_empty()
+        {
+            self.content.push_str(text);
+            events.push(ChatStreamEvent::Content(text.clone()));
+        }
+        for fragment in choice.delta.tool_calls.iter().flatten() {
+            events.extend(self.finish_calls_before(fragment.index));
+            let call = self.tool_calls.entry(fragment.index).or_insert_with(|| ToolCall {
+                id: String::new(),
+                r#type: "function".to_string(),
+                function: FunctionCall { name: String::new(), arguments: String::new() },
+            });
+            if let Some(id) = &fragment.id {
+                call.id = id.clone();
+            }
+            if let Some(function) = &fragment.function {
+                if let Some(name) = &function.name {
+                    call.function.name.push_str(name);
+                }
+                if let Some(arguments) = &function.arguments {
+                    call.function.arguments.push_str(arguments);
+                }
+            }
+        }
+        if let Some(reason) = &choice.finish_reason {
+            self.finish_reason = Some(reason.clone());
+            events.extend(self.finish_calls_before(u32::MAX));
+        }
+        events
+    }
+
+    fn finish_calls_before(&mut self, index: u32) -> Vec<ChatStreamEvent> {
+        let finished: Vec<_> = self
+            .tool_calls
+            .range(..index)
+            .skip(self.finished_calls)
+            .map(|(_, call)| ChatStreamEvent::ToolCall(call.clone()))
+            .collect();
+        self.finished_calls += finished.len();
+        finished
+    }
+
+    /// Usage reported by the stream, only sent when `stream_options.include_usage` was requested
+    pub fn usage(&self) -> Option<&Usage> {
+        self.usage.as_ref()
+    }
+
+    /// The completion as a non-streamed response would have carried it
+    pub fn finish(self) -> ChatCompletionResponse {
+        let tool_calls: Vec<ToolCall> = self.tool_calls.into_values().collect();
+        let finish_reason = self
+            .finish_reason
+            .unwrap_or_else(|| if tool_calls.is_empty() { "stop" } else { "tool_calls" }.to_string());
+        ChatCompletionResponse {
+            id: self.id,
+            object: "chat.completion".to_string(),
+            created: self.created,
+            model: self.model,
+            choices: vec![Choice {
+                index: 0,
+                message: ResponseMessage {
+                    role: "assistant".to_string(),
+                    content: (!self.content.is_empty() || tool_calls.is_empty()).then_some(self.content),
+                    tool_calls: (!tool_calls.is_empty()).then_some(tool_calls),
+                    reasoning_content: (!self.reasoning.is_empty()).then_some(self.reasoning),
+                },
+                logprobs: None,
+                finish_reason,
+            }],
+            usage: self.usage.unwrap_or_default(),
+            system_fingerprint: None,
+        }
+    }
+}
+
 // --- API Call Function ---
 
 impl ChatLlmInteraction {
@@ -142,20 +694,69 @@ impl ChatLlmInteraction {
     pub fn new(llm_url:String,model_id:String,llm_api_key:String) -> Self {
         
         Self {
-            client: reqwest::Client::builder().timeout(std::time::Duration::from_secs(30)).build().unwrap_or_else(|_| reqwest::Client::new()),
-            llm_url:llm_url,
-            llm_api_key:llm_api_key,
-            model_id:model_id,
+            client: http_client(&HttpClientConfig::default()),
+            llm_url,
+            llm_api_key,
+            model_id,
+            retry_policy: RetryPolicy::default(),
         }
     }
 
+    /// Use a shared HTTP client instead of the default one
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Retry and time out upstream calls according to `retry_policy`
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    fn post(&self, payload: &ChatCompletionRequest) -> reqwest::RequestBuilder {
+        self.client
+            .post(self.llm_url.clone())
+            .bearer_auth(self.llm_api_key.clone())
+            .header("Content-Type", "application/json; charset=utf-8")
+            .json(payload)
+    }
+
+    /// Build a chat completions request for this interaction's model from ResponseItem history
+    pub fn build_request(
+        &self,
+        history: &[ResponseItem],
+        tools: &[Tool],
+        params: &GenerationParams,
+        stream: bool,
+    ) -> ChatCompletionRequest {
+        let mut request = ChatCompletionRequest {
+            model: self.model_id.clone(),
+            messages: items_to_messages(history),
+            temperature: None,
+            max_tokens: None,
+            top_p: None,
+            stop: None,
+            stream: stream.then_some(true),
+            // Upstream only reports usage on streams when asked to
+            stream_options: stream.then_some(StreamOptions { include_usage: true }),
+            tools: (!tools.is_empty()).then(|| tools.to_vec()),
+            tool_choice: None,
+            response_format: None,
+            reasoning: None,
+        };
+        request.apply_generation_params(params);
+        request
+    }
+
     /// Unified API call function for chat completions, handling both simple messages and tool calls.
+    /// The reasoning of the model is returned next to the message instead of inside its content.
     pub async fn call_api(
         &self,
         messages: Vec<Message>,
         tools: Option<Vec<Tool>>,
         tool_choice: Option<ToolChoice>,
-    ) -> anyhow::Result<Option<Message>> {
+    ) -> Result<Option<ChatReply>, LlmError> {
 
         let llm_request_payload = ChatCompletionRequest {
             model: self.model_id.clone(),
@@ -165,30 +766,36 @@ impl ChatLlmInteraction {
             top_p: None,
             stop: None,
             stream: None,
+            stream_options: None,
             tools,
             tool_choice,
+            response_format: None,
+            reasoning: None,
         };
 
-        let llm_response = self.call_chat_completions_v2(&llm_request_payload)
-            .await
-            .context("LLM API request failed")?;
+        let llm_response = self.call_chat_completions_v2(&llm_request_payload).await?;
 
         let response_message = llm_response
             .choices
-            .get(0)
-            .ok_or_else(|| anyhow::anyhow!("LLM response missing choices"))?
+            .first()
+            .ok_or_else(|| LlmError::Decode("LLM response missing choices".to_string()))?
             .message
             .clone();
 
 
+            let mut reasoning: Vec<String> = response_message.reasoning_content.clone().into_iter().collect();
             let final_content = if let Some(content_str) = response_message.content.clone() {
-                let cleaned_content_str = self.remove_think_tags(content_str).await?;
+                let (think, content_str) = split_think_tags(&content_str);
+                reasoning.push(think);
+                let cleaned_content_str = strip_think_tags_and_fences(&content_str);
                 
                 // Attempt to parse content as JSON. If successful, handle Value::String separately
                 if let Ok(json_value) = serde_json::from_str::<Value>(&cleaned_content_str) {
                     match json_value {
                         Value::String(s) => Some(s), // If it's a JSON string, take the inner string
-                        _ => Some(serde_json::to_string(&json_value).context("Failed to re-serialize JSON content")?), // Otherwise, re-serialize
+                        _ => Some(serde_json::to_string(&json_value).map_err(|e| {
+                            LlmError::Decode(format!("Failed to re-serialize JSON content: {}", e))
+                        })?), // Otherwise, re-serialize
                     }
                 } else {
                     Some(cleaned_content_str)
@@ -198,13 +805,16 @@ impl ChatLlmInteraction {
             };
 
         debug!("Final Content: {}", final_content.clone().unwrap_or_default());
-        
+        reasoning.retain(|r| !r.is_empty());
 
-        Ok(Some(Message {
-            role: response_message.role,
-            content: final_content,
-            tool_call_id: None, // This will be set on tool result messages, not assistant messages
-            tool_calls: response_message.tool_calls,
+        Ok(Some(ChatReply {
+            message: Message {
+                role: response_message.role,
+                content: final_content.map(Into::into),
+                tool_call_id: None, // This will be set on tool result messages, not assistant messages
+                tool_calls: response_message.tool_calls,
+            },
+            reasoning: (!reasoning.is_empty()).then(|| reasoning.join("\n")),
         }))
     }
 
@@ -214,144 +824,69 @@ impl ChatLlmInteraction {
     pub async fn call_chat_completions_v2(
         &self,
         request_payload: &ChatCompletionRequest,
-    ) -> Result<ChatCompletionResponse, reqwest::Error> {
+    ) -> Result<ChatCompletionResponse, LlmError> {
         
-        let mut retries = 0;
-        //let max_retries = 7; // You can adjust this
-        //let mut delay = Duration::from_secs(2); // Starting delay
-        let max_retries = 2;
-        let mut delay = Duration::from_secs(2);
-        let max_delay = Duration::from_secs(10); // Hard cap on any single wait
+        let response = send_with_retry(&self.retry_policy, "LLM API", || self.post(request_payload)).await?;
+        debug!("LLM API Response : {:?}", response);
 
-
-        loop {
-            let response = self.client
-                .post(self.llm_url.clone())
-                .bearer_auth(self.llm_api_key.clone())
-                .header("Content-Type", "application/json; charset=utf-8")
-                .json(request_payload)
-                .send()
-                .await?;
-
-            debug!("LLM API Response : {:?}", response);
-
-            if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
-                retries += 1;
-                if retries > max_retries {
-                    return Err(response.error_for_status().unwrap_err()); // Return the last 429 error
-                }
-                
-                // Check if the API tells us exactly how long to wait
-                let retry_after = response
-                    .headers()
-                    .get(reqwest::header::RETRY_AFTER)
-                    .and_then(|h| h.to_str().ok())
-                    .and_then(|s| s.parse::<f64>().ok())
-                    .map(Duration::from_secs_f64);
-                //let wait_time = retry_after.unwrap_or(delay);
-                
-                // Cap Retry-After: Groq often sends 500-900s which blocks the agent
-                let wait_time = retry_after
-                    .map(|ra| ra.min(max_delay))
-                    .unwrap_or(delay)
-                    .min(max_delay);
-                
-                warn!("Rate limit hit (429). Retrying in {:?}... (Retry {}/{})", wait_time, retries, max_retries);
-                sleep(wait_time).await;
-                
-                if retry_after.is_none() {
-                    // delay *= 2; // Exponential backoff only if no explicit Retry-After
-                    delay = (delay * 2).min(max_delay);
-                }
-            } else {
-                // Check for other HTTP errors and log the raw body before failing
-                if let Err(e) = response.error_for_status_ref() {
-                    let err_body = response.text().await.unwrap_or_else(|_| "Failed to read error body".to_string());
-                    tracing::error!("LLM API returned HTTP {}: {}", e.status().map(|s| s.as_u16()).unwrap_or(0), err_body);
-                    return Err(e);
-                }
-                
-                let response_body = response.json::<ChatCompletionResponse>().await?;
-                debug!("LLM API Response Body: {:?}", response_body);
-                return Ok(response_body);
-            }
-        }
+        let response_body = response
+            .json::<ChatCompletionResponse>()
+            .await
+            .map_err(|e| LlmError::from_reqwest("Failed to parse chat completion", &e))?;
+        debug!("LLM API Response Body: {:?}", response_body);
+        Ok(response_body)
     }
 
 
-    /// Stream chat completions from the upstream LLM, emitting parsed delta chunks
+    /// Stream chat completions from the upstream LLM, forwarding every parsed chunk to `tx` until `[DONE]`.
+    /// Returns the accumulated stream, from which the usage and the complete response can be read.
+    /// The request is retried until the upstream starts answering, never once data was forwarded.
     pub async fn call_chat_completions_stream(
         &self,
         request_payload: &ChatCompletionRequest,
-        tx: tokio::sync::mpsc::Sender<String>,
-    ) -> Result<Option<Usage>, reqwest::Error> {
+        tx: tokio::sync::mpsc::Sender<ChatCompletionChunk>,
+    ) -> Result<StreamAccumulator, LlmError> {
         let mut stream_payload = request_payload.clone();
         stream_payload.stream = Some(true);
 
-        let response = self.client
-            .post(self.llm_url.clone())
-            .bearer_auth(self.llm_api_key.clone())
-            .header("Content-Type", "application/json; charset=utf-8")
-            .json(&stream_payload)
-            .send()
-            .await?;
-
-        if let Err(e) = response.error_for_status_ref() {
-            let err_body = response.text().await.unwrap_or_else(|_| "Failed to read error body".to_string());
-            tracing::error!("LLM streaming API returned HTTP {}: {}", e.status().map(|s| s.as_u16()).unwrap_or(0), err_body);
-            return Err(e);
-        }
-
-        let mut response = response;
+        let mut response =
+            send_with_retry(&self.retry_policy, "LLM streaming API", || self.post(&stream_payload)).await?;
         let mut buffer = String::new();
-        let mut usage: Option<Usage> = None;
-
-        while let Some(chunk) = response.chunk().await? {
-            let chunk_str = String::from_utf8_lossy(&chunk);
-            buffer.push_str(&chunk_str);
+        let mut accumulator = StreamAccumulator::new();
 
-            while let Some(pos) = buffer.find('\n') {
-                let line = buffer[..pos].trim().to_string();
-                buffer.drain(..=pos);
+        'stream: while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| LlmError::from_reqwest("LLM stream interrupted", &e))?
+        {
+            buffer.push_str(&String::from_utf8_lossy(&chunk));
 
-                if line.is_empty() || line.starts_with(':') {
+            // An SSE event ends with a blank line; its payload may span several `data:` lines
+            while let Some((event, rest)) = split_sse_event(&buffer) {
+                let data = sse_event_data(&event);
+                buffer = rest;
+                if data.is_empty() {
                     continue;
                 }
-
-                if let Some(data) = line.strip_prefix("data:") {
-                    let data = data.trim();
-                    if data == "[DONE]" {
-                        let _ = tx.send("[DONE]".to_string()).await;
-                        break;
-                    }
-                    if let Ok(val) = serde_json::from_str::<serde_json::Value>(data) {
-                        if let Some(u) = val.get("usage").and_then(|u| if u.is_null() { None } else { Some(u) }) {
-                            if let Ok(parsed_usage) = serde_json::from_value::<Usage>(u.clone()) {
-                                usage = Some(parsed_usage);
-                            }
-                        }
-                    }
-                    let _ = tx.send(data.to_string()).await;
+                if data.trim() == "[DONE]" {
+                    break 'stream;
+                }
+                let chunk = parse_stream_chunk(&data)?;
+                if let Some(chunk) = chunk {
+                    accumulator.push(&chunk);
+                    // Keep accumulating when the receiver is gone, the caller still gets the response
+                    let _ = tx.send(chunk).await;
                 }
             }
         }
 
-        Ok(usage)
+        Ok(accumulator)
     }
 
-    // Helper function to extract text from a Message using precompiled regexes
+    // Helper function to extract text from a Message using precompiled regexes.
+    // Drops the reasoning; use `split_think_tags` to keep it.
     pub async fn remove_think_tags(&self, result: String) -> anyhow::Result<String> {
-        lazy_static::lazy_static! {
-            static ref RE_JSON: Regex = Regex::new(r"```json\s*([\s\S]*?)\s*```").unwrap();
-            static ref RE_CODE: Regex = Regex::new(r"```\s*([\s\S]*?)\s*```").unwrap();
-            static ref RE_THINK: Regex = Regex::new(r"<think>([\s\S]*?)</think>").unwrap();
-        }
-
-        let mut cleaned = RE_JSON.replace_all(&result, "$1").to_string();
-        cleaned = RE_CODE.replace_all(&cleaned, "$1").to_string();
-        cleaned = RE_THINK.replace_all(&cleaned, "").to_string();
-
-        Ok(cleaned.trim().to_string())
+        Ok(strip_think_tags_and_fences(&result))
     }
 
 
@@ -375,31 +910,604 @@ impl ChatLlmInteraction {
         &self,
         agent_role: String,
         user_query: String,
-    ) -> anyhow::Result<Option<String>> {
+    ) -> Result<Option<String>, LlmError> {
         let messages = vec![Message {
             role: agent_role,
-            content: Some(user_query),
+            content: Some(user_query.into()),
             tool_call_id: None,
             tool_calls: None,
         }];
 
-        let response_message = self.call_api(messages, None, None).await?;
-        Ok(response_message.and_then(|msg| msg.content))
+        let reply = self.call_api(messages, None, None).await?;
+        Ok(reply.and_then(|reply| reply.message.content).map(|content| content.text()))
     }
 
     pub async fn call_api_simple(
         &self,
         agent_role: String,
         user_query: String,
-    ) -> anyhow::Result<Option<Message>> {
+    ) -> Result<Option<Message>, LlmError> {
 
-        self.call_api_simple_v2(agent_role, user_query).await.and_then(|s| {
-            Ok(s.map(|content| Message {
+        self.call_api_simple_v2(agent_role, user_query).await.map(|s| {
+            s.map(|content| Message {
                 role: "assistant".to_string(),
-                content: Some(content),
+                content: Some(content.into()),
                 tool_call_id: None,
                 tool_calls: None,
-            }))
+            })
+        })
+    }
+}
+
+/// Output items of the first choice: reasoning first, then tool calls, then the assistant text.
+/// Reasoning comes from `reasoning_content` or from `<think>` blocks in the content.
+fn choice_items(response: ChatCompletionResponse) -> Vec<ResponseItem> {
+    let mut items = Vec::new();
+    if let Some(choice) = response.choices.into_iter().next() {
+        let (think, content) = match choice.message.content {
+            Some(content) => {
+                let (think, content) = split_think_tags(&content);
+                (think, Some(content))
+            }
+            None => (String::new(), None),
+        };
+        let reasoning: Vec<String> =
+            choice.message.reasoning_content.into_iter().chain(Some(think)).filter(|r| !r.is_empty()).collect();
+        if !reasoning.is_empty() {
+            items.push(ResponseItem::Reasoning {
+                id: format!("rs_{}", Uuid::new_v4()),
+                thought_process: reasoning.join("\n"),
+                signature: None,
+            });
+        }
+        for tc in choice.message.tool_calls.into_iter().flatten() {
+            items.push(ResponseItem::FunctionCall {
+                id: format!("fc_{}", Uuid::new_v4()),
+                call_id: tc.id,
+                name: tc.function.name,
+                arguments: tc.function.arguments,
+            });
+        }
+        if let Some(content) = content
+            && !content.is_empty()
+        {
+            items.push(ResponseItem::Message {
+                id: format!("resp_msg_{}", Uuid::new_v4()),
+                role: Role::Assistant,
+                content: vec![ContentPart::Text { text: content }],
+            });
+        }
+    }
+    items
+}
+
+/// Remove `<think>` blocks and unwrap markdown code fences around the model output
+pub(crate) fn strip_think_tags_and_fences(text: &str) -> String {
+    lazy_static::lazy_static! {
+        static ref RE_JSON: Regex = Regex::new(r"```json\s*([\s\S]*?)\s*```").unwrap();
+        static ref RE_CODE: Regex = Regex::new(r"```\s*([\s\S]*?)\s*```").unwrap();
+        static ref RE_THINK: Regex = Regex::new(r"<think>([\s\S]*?)</think>").unwrap();
+    }
+
+    let mut cleaned = RE_JSON.replace_all(text, "$1").to_string();
+    cleaned = RE_CODE.replace_all(&cleaned, "$1").to_string();
+    cleaned = RE_THINK.replace_all(&cleaned, "").to_string();
+    cleaned.trim().to_string()
+}
+
+/// Split `<think>` blocks off model output, returning the reasoning and the remaining text.
+/// A closing tag without an opening one, as left by chat templates that open the block in the
+/// prompt, makes everything before it reasoning.
+pub fn split_think_tags(text: &str) -> (String, String) {
+    lazy_static::lazy_static! {
+        static ref RE_THINK: Regex = Regex::new(r"<think>([\s\S]*?)</think>").unwrap();
+    }
+
+    let mut reasoning = Vec::new();
+    let mut rest = text;
+    if let Some(close) = text.find(THINK_CLOSE)
+        && !text[..close].contains(THINK_OPEN)
+    {
+        reasoning.push(text[..close].trim().to_string());
+        rest = &text[close + THINK_CLOSE.len()..];
+    }
+    reasoning.extend(RE_THINK.captures_iter(rest).map(|c| c[1].trim().to_string()));
+    let content = RE_THINK.replace_all(rest, "");
+    reasoning.retain(|r| !r.is_empty());
+    let content = if reasoning.is_empty() { content.to_string() } else { content.trim().to_string() };
+    (reasoning.join("\n"), content)
+}
+
+const THINK_OPEN: &str = "<think>";
+const THINK_CLOSE: &str = "</think>";
+
+/// Splits streamed content into text and reasoning deltas at `<think>` tags, including tags
+/// cut across chunks
+#[derive(Debug, Default)]
+pub struct ThinkTagSplitter {
+    buffer: String,
+    thinking: bool,
+    /// Drop the whitespace models put between `</think>` and the answer
+    trim_text: bool,
+}
+
+impl ThinkTagSplitter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Deltas for `text`, holding back a trailing fragment that may start a tag
+    pub fn push(&mut self, text: &str) -> Vec<StreamDelta> {
+        self.buffer.push_str(text);
+        let mut deltas = Vec::new();
+        loop {
+            let tag = if self.thinking { THINK_CLOSE } else { THINK_OPEN };
+            if let Some(pos) = self.buffer.find(tag) {
+                let before: String = self.buffer.drain(..pos + tag.len()).take(pos).collect();
+                self.emit(before, &mut deltas);
+                self.thinking = !self.thinking;
+                self.trim_text = !self.thinking;
+                continue;
+            }
+            // Tags are ASCII, so a suffix matching a tag prefix starts on a char boundary
+            let partial = (1..tag.len()).rev().find(|&n| self.buffer.ends_with(&tag[..n])).unwrap_or(0);
+            let ready: String = self.buffer.drain(..self.buffer.len() - partial).collect();
+            self.emit(ready, &mut deltas);
+            return deltas;
+        }
+    }
+
+    /// Whatever was held back when the stream ends
+    pub fn finish(&mut self) -> Vec<StreamDelta> {
+        let mut deltas = Vec::new();
+        let rest = std::mem::take(&mut self.buffer);
+        self.emit(rest, &mut deltas);
+        deltas
+    }
+
+    fn emit(&mut self, text: String, deltas: &mut Vec<StreamDelta>) {
+        if self.thinking {
+            if !text.is_empty() {
+                deltas.push(StreamDelta::Reasoning { text, signature: None });
+            }
+            return;
+        }
+        let text = if self.trim_text { text.trim_start().to_string() } else { text };
+        if !text.is_empty() {
+            self.trim_text = false;
+            deltas.push(StreamDelta::Text(text));
+        }
+    }
+}
+
+/// Parse the data of one SSE event. Error payloads sent mid-stream are classified;
+/// anything else that is not a chunk is skipped.
+fn parse_stream_chunk(data: &str) -> Result<Option<ChatCompletionChunk>, LlmError> {
+    let value = match serde_json::from_str::<Value>(data) {
+        Ok(value) => value,
+        Err(e) => {
+            warn!("Skipping unparseable chat.completion.chunk ({}): {}", e, data);
+            return Ok(None);
+        }
+    };
+    if let Some(error) = value.get("error") {
+        let code = error.get("code").and_then(Value::as_u64).and_then(|c| u16::try_from(c).ok());
+        return Err(LlmError::classify(code.unwrap_or(500), None, data));
+    }
+    match serde_json::from_value::<ChatCompletionChunk>(value) {
+        Ok(chunk) => Ok(Some(chunk)),
+        Err(e) => {
+            warn!("Skipping unparseable chat.completion.chunk ({}): {}", e, data);
+            Ok(None)
+        }
+    }
+}
+
+/// Deltas carried by the first choice of one chunk
+fn chunk_deltas(chunk: &ChatCompletionChunk) -> Vec<StreamDelta> {
+    let mut deltas = Vec::new();
+    let Some(choice) = chunk.choices.iter().find(|c| c.index == 0) else {
+        return deltas;
+    };
+
+    if let Some(text) = &choice.delta.reasoning_content
+        && !text.is_empty()
+    {
+        deltas.push(StreamDelta::Reasoning { text: text.clone(), signature: None });
+    }
+    if let Some(text) = &choice.delta.content
+        && !text.is_empty()
+    {
+        deltas.push(StreamDelta::Text(text.clone()));
+    }
+    for tool_call in choice.delta.tool_calls.iter().flatten() {
+        let index = tool_call.index as usize;
+        let function = tool_call.function.clone().unwrap_or_default();
+        if let Some(call_id) = &tool_call.id {
+            deltas.push(StreamDelta::FunctionCallStart {
+                index,
+                call_id: call_id.clone(),
+                name: function.name.unwrap_or_default(),
+            });
+        }
+        if let Some(arguments) = function.arguments
+            && !arguments.is_empty()
+        {
+            deltas.push(StreamDelta::FunctionCallArguments { index, delta: arguments });
+        }
+    }
+    deltas
+}
+
+/// Send `deltas` on, without reasoning unless it is included. False once the receiver is gone.
+async fn forward_deltas(tx: &tokio::sync::mpsc::Sender<StreamDelta>, deltas: Vec<StreamDelta>, include_reasoning: bool) -> bool {
+    for delta in deltas {
+        if !include_reasoning && matches!(delta, StreamDelta::Reasoning { .. }) {
+            continue;
+        }
+        if tx.send(delta).await.is_err() {
+            return false;
+        }
+    }
+    true
+}
+
+impl From<Usage> for ResponseUsage {
+    fn from(usage: Usage) -> Self {
+        ResponseUsage {
+            input_tokens: usage.prompt_tokens,
+            output_tokens: usage.completion_tokens,
+            total_tokens: usage.total_tokens,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl LlmClient for ChatLlmInteraction {
+    fn model_id(&self) -> &str {
+        &self.model_id
+    }
+
+    async fn complete(
+        &self,
+        history: &[ResponseItem],
+        params: &GenerationParams,
+        tools: &[Tool],
+    ) -> Result<LlmTurn, LlmError> {
+        let request = self.build_request(history, tools, params, false);
+        let response = self.call_chat_completions_v2(&request).await?;
+
+        let usage = Some(response.usage.clone().into());
+        let model = Some(response.model.clone()).filter(|m| !m.is_empty());
+        Ok(LlmTurn {
+            items: params.reasoning_options().output(choice_items(response)),
+            usage,
+            model: model.or_else(|| Some(self.model_id.clone())),
         })
     }
+
+    async fn stream(
+        &self,
+        history: &[ResponseItem],
+        params: &GenerationParams,
+        tools: &[Tool],
+        tx: tokio::sync::mpsc::Sender<StreamDelta>,
+    ) -> Result<Option<ResponseUsage>, LlmError> {
+        let request = self.build_request(history, tools, params, true);
+        let include_reasoning = params.reasoning_options().include;
+
+        // Translate chunks into deltas while the call is running
+        let (chunk_tx, mut chunk_rx) = tokio::sync::mpsc::channel::<ChatCompletionChunk>(64);
+        let translate = async move {
+            let mut think_tags = ThinkTagSplitter::new();
+            while let Some(chunk) = chunk_rx.recv().await {
+                let mut deltas = Vec::new();
+                for delta in chunk_deltas(&chunk) {
+                    match delta {
+                        StreamDelta::Text(text) => deltas.extend(think_tags.push(&text)),
+                        delta => deltas.push(delta),
+                    }
+                }
+                if !forward_deltas(&tx, deltas, include_reasoning).await {
+                    return;
+                }
+            }
+            forward_deltas(&tx, think_tags.finish(), include_reasoning).await;
+        };
+        let (accumulator, _) = tokio::join!(self.call_chat_completions_stream(&request, chunk_tx), translate);
+        Ok(accumulator?.usage().cloned().map(Into::into))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn function_call(call_id: &str) -> ResponseItem {
+        ResponseItem::FunctionCall {
+            id: format!("fc_{}", call_id),
+            call_id: call_id.to_string(),
+            name: "get_weather".to_string(),
+            arguments: "{}".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_items_to_messages_groups_parallel_tool_calls() {
+        let history = vec![
+            ResponseItem::Message {
+                id: "usr".to_string(),
+                role: Role::User,
+                content: vec![ContentPart::Text { text: "Weather in Paris and Rome?".to_string() }],
+            },
+            function_call("call_1"),
+            function_call("call_2"),
+            ResponseItem::FunctionCallOutput {
+                id: "out_1".to_string(),
+                call_id: "call_1".to_string(),
+                output: "sunny".to_string(),
+                is_error: false,
+            },
+        ];
+        let messages = items_to_messages(&history);
+
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0].role, "user");
+        assert_eq!(messages[1].role, "assistant");
+        assert_eq!(messages[1].tool_calls.as_ref().unwrap().len(), 2);
+        assert_eq!(messages[2].tool_call_id.as_deref(), Some("call_1"));
+    }
+
+    #[tokio::test]
+    async fn test_stream_retries_until_the_upstream_starts_answering() {
+        let body = concat!(
+            "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hi\"}}]}\n\n",
+            "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":3,\"completion_tokens\":1,\"total_tokens\":4}}\n\n",
+            "data: [DONE]\n\n",
+        );
+        let (addr, requests) = crate::sse::spawn_mock_http_sequence(vec![
+            ("HTTP/1.1 503 Service Unavailable", "text/plain", "overloaded".to_string()),
+            ("HTTP/1.1 200 OK", "text/event-stream", body.to_string()),
+        ])
+        .await;
+        let interaction = ChatLlmInteraction::new(format!("http://{}/v1/chat/completions", addr), "m".to_string(), "k".to_string())
+            .with_retry_policy(RetryPolicy {
+                base_delay: std::time::Duration::from_millis(1),
+                ..RetryPolicy::default()
+            });
+        let request = interaction.build_request(&[], &[], &GenerationParams::default(), true);
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+        let accumulator = interaction.call_chat_completions_stream(&request, tx).await.unwrap();
+        assert_eq!(accumulator.usage().map(|u| u.total_tokens), Some(4));
+        assert_eq!(requests.await.unwrap().len(), 2);
+
+        let mut chunks = Vec::new();
+        while let Some(chunk) = rx.recv().await {
+            chunks.push(chunk);
+        }
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(accumulator.finish().choices[0].message.content.as_deref(), Some("Hi"));
+    }
+
+    #[tokio::test]
+    async fn test_call_api_reports_typed_errors() {
+        let overflow = r#"{"error": {"message": "maximum context length exceeded", "code": "context_length_exceeded"}}"#;
+        let no_choices = r#"{"id": "c1", "object": "chat.completion", "created": 1, "model": "m", "choices": [], "usage": {"prompt_tokens": 1, "completion_tokens": 0, "total_tokens": 1}}"#;
+        let (addr, _) = crate::sse::spawn_mock_http_sequence(vec![
+            ("HTTP/1.1 400 Bad Request", "application/json", overflow.to_string()),
+            ("HTTP/1.1 200 OK", "application/json", no_choices.to_string()),
+        ])
+        .await;
+        let interaction = ChatLlmInteraction::new(format!("http://{}/", addr), "m".to_string(), "k".to_string())
+            .with_retry_policy(RetryPolicy::no_retry());
+
+        let err = interaction.call_api_simple_v2("user".to_string(), "Hi".to_string()).await.unwrap_err();
+        assert!(matches!(err, LlmError::ContextLengthExceeded(_)));
+        let err = interaction.call_api(Vec::new(), None, None).await.unwrap_err();
+        assert_eq!(err, LlmError::Decode("LLM response missing choices".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_call_api_returns_reasoning_next_to_the_message() {
+        let body = serde_json::json!({
+            "id": "c1", "object": "chat.completion", "created": 1, "model": "m",
+            "choices": [{"index": 0, "finish_reason": "stop", "message": {
+                "role": "assistant",
+                "content": "<think>Two plus two.</think>4",
+                "reasoning_content": "Simple sum."
+            }}],
+            "usage": {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2}
+        });
+        let (addr, _) = crate::sse::spawn_mock_http_server("HTTP/1.1 200 OK", "application/json", body.to_string()).await;
+        let interaction = ChatLlmInteraction::new(format!("http://{}/", addr), "m".to_string(), "k".to_string());
+
+        let reply = interaction.call_api(Vec::new(), None, None).await.unwrap().unwrap();
+        assert_eq!(reply.message.content.map(|content| content.text()).as_deref(), Some("4"));
+        assert_eq!(reply.reasoning.as_deref(), Some("Simple sum.\nTwo plus two."));
+    }
+
+    fn tool_chunk(index: u32, id: Option<&str>, name: Option<&str>, arguments: &str) -> ChatCompletionChunk {
+        serde_json::from_value(serde_json::json!({
+            "id": "chatcmpl-1",
+            "choices": [{"index": 0, "delta": {"tool_calls": [{
+                "index": index, "id": id, "type": id.map(|_| "function"),
+                "function": {"name": name, "arguments": arguments}
+            }]}}]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_stream_accumulator_stitches_tool_calls_by_index() {
+        let mut accumulator = StreamAccumulator::new();
+        let text: ChatCompletionChunk = serde_json::from_value(serde_json::json!({
+            "id": "chatcmpl-1", "object": "chat.completion.chunk", "created": 7, "model": "m",
+            "choices": [{"index": 0, "delta": {"role": "assistant", "content": "Checking"}}]
+        }))
+        .unwrap();
+        assert_eq!(accumulator.push(&text), vec![ChatStreamEvent::Content("Checking".to_string())]);
+
+        assert!(accumulator.push(&tool_chunk(0, Some("call_1"), Some("get_weather"), "")).is_empty());
+        assert!(accumulator.push(&tool_chunk(0, None, None, "{\"city\":")).is_empty());
+        assert!(accumulator.push(&tool_chunk(0, None, None, "\"Paris\"}")).is_empty());
+        // The second call starting completes the first one
+        let paris = ToolCall {
+            id: "call_1".to_string(),
+            r#type: "function".to_string(),
+            function: FunctionCall { name: "get_weather".to_string(), arguments: "{\"city\":\"Paris\"}".to_string() },
+        };
+        assert_eq!(
+            accumulator.push(&tool_chunk(1, Some("call_2"), Some("get_weather"), "{\"city\":\"Rome\"}")),
+            vec![ChatStreamEvent::ToolCall(paris.clone())]
+        );
+
+        let finish: ChatCompletionChunk = serde_json::from_value(serde_json::json!({
+            "id": "chatcmpl-1", "choices": [{"index": 0, "delta": {}, "finish_reason": "tool_calls"}],
+            "usage": {"prompt_tokens": 5, "completion_tokens": 9, "total_tokens": 14}
+        }))
+        .unwrap();
+        let events = accumulator.push(&finish);
+        assert!(matches!(&events[..], [ChatStreamEvent::ToolCall(call)] if call.id == "call_2"));
+
+        let response = accumulator.finish();
+        assert_eq!((response.id.as_str(), response.created, response.model.as_str()), ("chatcmpl-1", 7, "m"));
+        assert_eq!(response.usage.total_tokens, 14);
+        let choice = &response.choices[0];
+        assert_eq!(choice.finish_reason, "tool_calls");
+        assert_eq!(choice.message.content.as_deref(), Some("Checking"));
+        let calls = choice.message.tool_calls.as_ref().unwrap();
+        assert_eq!(calls[0], paris);
+        assert_eq!(calls[1].function.arguments, "{\"city\":\"Rome\"}");
+    }
+
+    #[tokio::test]
+    async fn test_stream_parses_multi_line_events_until_done() {
+        let body = concat!(
+            ": keep-alive\n\n",
+            "event: chunk\n",
+            "data: {\"id\":\"c1\",\"choices\":[{\"index\":0,\n",
+            "data: \"delta\":{\"content\":\"Hello\"}}]}\n\n",
+            "data: {\"id\":\"c1\",\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"length\"}]}\r\n\r\n",
+            "data: [DONE]\n\n",
+            "data: {\"id\":\"after-done\",\"choices\":[]}\n\n",
+        );
+        let (addr, _) = crate::sse::spawn_mock_sse_server("HTTP/1.1 200 OK", body.to_string()).await;
+        let interaction = ChatLlmInteraction::new(format!("http://{}/", addr), "m".to_string(), "k".to_string());
+        let request = interaction.build_request(&[], &[], &GenerationParams::default(), true);
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+        let response = interaction.call_chat_completions_stream(&request, tx).await.unwrap().finish();
+        assert_eq!(response.choices[0].message.content.as_deref(), Some("Hello"));
+        assert_eq!(response.choices[0].finish_reason, "length");
+
+        let mut ids = Vec::new();
+        while let Some(chunk) = rx.recv().await {
+            ids.push(chunk.id);
+        }
+        assert_eq!(ids, vec!["c1", "c1"]);
+
+        let error = r#"{"error": {"message": "Rate limit reached", "code": 429}}"#;
+        let (addr, _) = crate::sse::spawn_mock_sse_server("HTTP/1.1 200 OK", format!("data: {}\n\n", error)).await;
+        let interaction = ChatLlmInteraction::new(format!("http://{}/", addr), "m".to_string(), "k".to_string())
+            .with_retry_policy(RetryPolicy::no_retry());
+        let (tx, _rx) = tokio::sync::mpsc::channel(16);
+        let err = interaction.call_chat_completions_stream(&request, tx).await.unwrap_err();
+        assert!(matches!(err, LlmError::RateLimited { .. }));
+    }
+
+    fn completion(message: serde_json::Value) -> ChatCompletionResponse {
+        serde_json::from_value(serde_json::json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 1,
+            "model": "deepseek-reasoner",
+            "choices": [{"index": 0, "message": message, "finish_reason": "stop"}],
+            "usage": {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2}
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_choice_items_keep_reasoning() {
+        let items = choice_items(completion(serde_json::json!({
+            "role": "assistant",
+            "content": "<think>\nCount the letters.\n</think>\n\nThree.",
+            "reasoning_content": "The user wants a count."
+        })));
+        match &items[..] {
+            [ResponseItem::Reasoning { thought_process, signature: None, .. }, ResponseItem::Message { content, .. }] => {
+                assert_eq!(thought_process, "The user wants a count.\nCount the letters.");
+                assert_eq!(content, &vec![ContentPart::Text { text: "Three.".to_string() }]);
+            }
+            other => panic!("expected reasoning then message, got {:?}", other),
+        }
+
+        // Groq names the field `reasoning`; templates that open the block in the prompt leave only `</think>`
+        let items = choice_items(completion(serde_json::json!({"role": "assistant", "content": "hmm</think>Yes", "reasoning": "quick"})));
+        assert!(matches!(&items[0], ResponseItem::Reasoning { thought_process, .. } if thought_process == "quick\nhmm"));
+        assert!(matches!(&items[1], ResponseItem::Message { content, .. } if content == &vec![ContentPart::Text { text: "Yes".to_string() }]));
+
+        let params = GenerationParams {
+            reasoning: Some(ReasoningOptions { include: false, forward_signed: true }),
+            ..Default::default()
+        };
+        assert_eq!(params.reasoning_options().output(items).len(), 1);
+    }
+
+    #[test]
+    fn test_think_tag_splitter_handles_tags_across_chunks() {
+        let mut splitter = ThinkTagSplitter::new();
+        let mut deltas = Vec::new();
+        for chunk in ["<thi", "nk>plan", " it</th", "ink>\n\nDone <", "b>"] {
+            deltas.extend(splitter.push(chunk));
+        }
+        deltas.extend(splitter.finish());
+        assert_eq!(
+            deltas,
+            vec![
+                StreamDelta::Reasoning { text: "plan".to_string(), signature: None },
+                StreamDelta::Reasoning { text: " it".to_string(), signature: None },
+                StreamDelta::Text("Done ".to_string()),
+                StreamDelta::Text("<b>".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_message_content_parts_round_trip() {
+        let parts = vec![
+            ContentPart::Text { text: "What is in these?".to_string() },
+            ContentPart::Image { media_type: "image/png".to_string(), data_base64: "iVBORw0KGgo=".to_string() },
+            ContentPart::ImageUrl { url: "https://example.com/cat.jpg".to_string(), detail: Some("low".to_string()) },
+            ContentPart::Audio { media_type: "audio/mpeg".to_string(), data_base64: "SUQz".to_string() },
+            ContentPart::File {
+                filename: Some("report.pdf".to_string()),
+                media_type: "application/pdf".to_string(),
+                data_base64: Some("JVBERi0=".to_string()),
+                url: None,
+            },
+        ];
+        let content = MessageContent::from_content_parts(&parts);
+        assert_eq!(
+            serde_json::to_value(&content).unwrap(),
+            serde_json::json!([
+                {"type": "text", "text": "What is in these?"},
+                {"type": "image_url", "image_url": {"url": "data:image/png;base64,iVBORw0KGgo="}},
+                {"type": "image_url", "image_url": {"url": "https://example.com/cat.jpg", "detail": "low"}},
+                {"type": "input_audio", "input_audio": {"data": "SUQz", "format": "mp3"}},
+                {"type": "file", "file": {"filename": "report.pdf", "file_data": "data:application/pdf;base64,JVBERi0="}}
+            ])
+        );
+        assert_eq!(content.to_content_parts().unwrap(), parts);
+        assert_eq!(content.text(), "What is in these?");
+
+        // Text-only content stays a plain string for servers without content arrays
+        let text = MessageContent::from_content_parts(&[ContentPart::Text { text: "hi".to_string() }]);
+        assert_eq!(serde_json::to_value(&text).unwrap(), serde_json::json!("hi"));
+
+        let uploaded: MessageContent =
+            serde_json::from_value(serde_json::json!([{"type": "file", "file": {"file_id": "file-abc"}}])).unwrap();
+        assert!(uploaded.to_content_parts().is_err());
+    }
 }
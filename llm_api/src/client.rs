@@ -0,0 +1,295 @@
+//! Provider-agnostic LLM client.
+//!
+//! Every wire adapter ([`crate::chat`], [`crate::google_interactions`], [`crate::anthropic`],
+//! [`crate::responses`]) implements [`LlmClient`], taking the conversation as `ResponseItem`s and
+//! returning the turn as `ResponseItem`s, so callers never deal with upstream message formats.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::sync::mpsc::Sender;
+
+use agent_models::response_item::{ContentPart, ResponseItem, ResponseUsage};
+
+use crate::generation::GenerationParams;
+use crate::tools::Tool;
+
+/// Incremental output of a streaming turn
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamDelta {
+    /// Text appended to the assistant message
+    Text(String),
+    /// Start of a tool call; `index` identifies the call in the following argument fragments
+    FunctionCallStart {
+        index: usize,
+        call_id: String,
+        name: String,
+    },
+    /// Fragment of the JSON arguments of the tool call started with the same `index`
+    FunctionCallArguments { index: usize, delta: String },
+    /// Reasoning text appended to the current reasoning item. The signature, when the provider
+    /// signs its reasoning, usually comes last with an empty `text`.
+    Reasoning { text: String, signature: Option<String> },
+}
+
+/// Deltas replaying complete items, for backends without native streaming
+pub fn item_deltas(items: &[ResponseItem]) -> Vec<StreamDelta> {
+    let mut deltas = Vec::new();
+    let mut call_index = 0;
+    for item in items {
+        match item {
+            ResponseItem::Message { content, .. } => {
+                let text: String = content
+                    .iter()
+                    .filter_map(|part| match part {
+                        ContentPart::Text { text } => Some(text.as_str()),
+                        _ => None,
+                    })
+                    .collect();
+                if !text.is_empty() {
+                    deltas.push(StreamDelta::Text(text));
+                }
+            }
+            ResponseItem::FunctionCall { call_id, name, arguments, .. } => {
+                deltas.push(StreamDelta::FunctionCallStart {
+                    index: call_index,
+                    call_id: call_id.clone(),
+                    name: name.clone(),
+                });
+                if !arguments.is_empty() {
+                    deltas.push(StreamDelta::FunctionCallArguments {
+                        index: call_index,
+                        delta: arguments.clone(),
+                    });
+                }
+                call_index += 1;
+            }
+            ResponseItem::Reasoning { thought_process, signature, .. } => {
+                deltas.push(StreamDelta::Reasoning {
+                    text: thought_process.clone(),
+                    signature: signature.clone(),
+                });
+            }
+            ResponseItem::FunctionCallOutput { .. } => {}
+        }
+    }
+    deltas
+}
+
+/// Output of one completed turn
+#[derive(Debug, Clone, PartialEq)]
+pub struct LlmTurn {
+    pub items: Vec<ResponseItem>,
+    pub usage: Option<ResponseUsage>,
+    /// Model reported by the upstream, when it refines the requested one
+    pub model: Option<String>,
+}
+
+/// Failure of an upstream LLM call, classified from the HTTP status and the provider's error body
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum LlmError {
+    /// 429 or quota exhaustion; `retry_after` comes from the `Retry-After` header when present
+    #[error("rate limited by upstream: {message}")]
+    RateLimited { retry_after: Option<Duration>, message: String },
+    /// Missing, invalid or unauthorized API key
+    #[error("upstream authentication failed: {0}")]
+    Auth(String),
+    /// The prompt (history plus requested output) does not fit the model's context window
+    #[error("context length exceeded: {0}")]
+    ContextLengthExceeded(String),
+    /// The prompt or the output was blocked by the provider's safety or moderation filters
+    #[error("content filtered: {0}")]
+    ContentFiltered(String),
+    #[error("upstream request timed out: {0}")]
+    Timeout(String),
+    /// The upstream could not be reached or dropped the connection
+    #[error("upstream connection failed: {0}")]
+    Connection(String),
+    /// Any other non-success status
+    #[error("upstream returned HTTP {code}: {body}")]
+    UpstreamStatus { code: u16, body: String },
+    /// The upstream answered with a body that could not be parsed
+    #[error("failed to decode upstream response: {0}")]
+    Decode(String),
+    /// The request could not be built from the given history, tools or parameters
+    #[error("invalid request: {0}")]
+    InvalidRequest(String),
+}
+
+impl LlmError {
+    /// Whether the same call may succeed later or on another provider (429, 5xx, timeouts, connection errors)
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            LlmError::RateLimited { .. } | LlmError::Timeout(_) | LlmError::Connection(_) => true,
+            LlmError::UpstreamStatus { code, .. } => *code >= 500 || *code == 408,
+            _ => false,
+        }
+    }
+
+    /// Stable machine-readable code, used as the OpenAI-style `error.code`
+    pub fn code(&self) -> &'static str {
+        match self {
+            LlmError::RateLimited { .. } => "rate_limit_exceeded",
+            LlmError::Auth(_) => "invalid_api_key",
+            LlmError::ContextLengthExceeded(_) => "context_length_exceeded",
+            LlmError::ContentFiltered(_) => "content_filter",
+            LlmError::Timeout(_) => "timeout",
+            LlmError::Connection(_) => "upstream_unavailable",
+            LlmError::UpstreamStatus { .. } => "upstream_error",
+            LlmError::Decode(_) => "invalid_upstream_response",
+            LlmError::InvalidRequest(_) => "invalid_request",
+        }
+    }
+
+    /// Classify a non-success response from its status, `Retry-After` delay and body.
+    /// Understands the OpenAI, Gemini and Anthropic error body shapes.
+    pub fn classify(status: u16, retry_after: Option<Duration>, body: &str) -> Self {
+        let message = error_body_message(body).unwrap_or_else(|| body.trim().to_string());
+        let haystack = body.to_ascii_lowercase();
+        let mentions = |needles: &[&str]| needles.iter().any(|needle| haystack.contains(needle));
+
+        if mentions(&[
+            "context_length_exceeded",
+            "maximum context length",
+            "context window",
+            "prompt is too long",
+            "input token count",
+            "too many tokens",
+        ]) {
+            LlmError::ContextLengthExceeded(message)
+        } else if mentions(&["content_filter", "content_policy", "responsible ai", "prohibited_content"]) {
+            LlmError::ContentFiltered(message)
+        } else if status == 429 || mentions(&["rate_limit", "resource_exhausted"]) {
+            LlmError::RateLimited { retry_after, message }
+        } else if status == 401
+            || status == 403
+            || mentions(&["invalid_api_key", "authentication_error", "permission_error", "api_key_invalid"])
+        {
+            LlmError::Auth(message)
+        } else if status == 408 || status == 504 {
+            LlmError::Timeout(message)
+        } else {
+            LlmError::UpstreamStatus { code: status, body: message }
+        }
+    }
+
+    /// Consume a non-success response and classify it, logging the raw body
+    pub async fn from_response(context: &str, response: reqwest::Response) -> Self {
+        let status = response.status().as_u16();
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|h| h.to_str().ok())
+            .and_then(parse_retry_after);
+        let body = response.text().await.unwrap_or_else(|_| "Failed to read error body".to_string());
+        tracing::error!("{} returned HTTP {}: {}", context, status, body);
+        Self::classify(status, retry_after, &body)
+    }
+
+    /// Classify a transport or decoding error raised by reqwest
+    pub fn from_reqwest(context: &str, err: &reqwest::Error) -> Self {
+        let message = format!("{}: {}", context, err);
+        if err.is_timeout() {
+            LlmError::Timeout(message)
+        } else if err.is_decode() {
+            LlmError::Decode(message)
+        } else if let Some(status) = err.status() {
+            Self::classify(status.as_u16(), None, &message)
+        } else {
+            LlmError::Connection(message)
+        }
+    }
+}
+
+/// Delay of a `Retry-After` header given in seconds. Negative, infinite, NaN or
+/// out-of-range values come from the upstream and are ignored rather than trusted.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    Duration::try_from_secs_f64(value.trim().parse::<f64>().ok()?).ok()
+}
+
+/// The `message` of an OpenAI (`{"error": {"message"}}`), Gemini (`{"error": {"message", "status"}}`)
+/// or Anthropic (`{"type": "error", "error": {"type", "message"}}`) error body
+fn error_body_message(body: &str) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_str(body).ok()?;
+    let error = value.get("error")?;
+    match error {
+        serde_json::Value::String(message) => Some(message.clone()),
+        _ => error.get("message")?.as_str().map(String::from),
+    }
+}
+
+/// A model endpoint that runs conversation turns
+#[async_trait]
+pub trait LlmClient: Send + Sync {
+    /// Model id sent upstream
+    fn model_id(&self) -> &str;
+
+    /// Run one turn over `history` and wait for the complete output
+    async fn complete(
+        &self,
+        history: &[ResponseItem],
+        params: &GenerationParams,
+        tools: &[Tool],
+    ) -> Result<LlmTurn, LlmError>;
+
+    /// Stream one turn as deltas and return its usage. Default implementation calls complete and replays the items.
+    async fn stream(
+        &self,
+        history: &[ResponseItem],
+        params: &GenerationParams,
+        tools: &[Tool],
+        tx: Sender<StreamDelta>,
+    ) -> Result<Option<ResponseUsage>, LlmError> {
+        let turn = self.complete(history, params, tools).await?;
+        for delta in item_deltas(&turn.items) {
+            if tx.send(delta).await.is_err() {
+                break;
+            }
+        }
+        Ok(turn.usage)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_provider_error_bodies() {
+        let openai = r#"{"error": {"message": "This model's maximum context length is 8192 tokens", "type": "invalid_request_error", "code": "context_length_exceeded"}}"#;
+        assert_eq!(
+            LlmError::classify(400, None, openai),
+            LlmError::ContextLengthExceeded("This model's maximum context length is 8192 tokens".to_string())
+        );
+
+        let gemini = r#"{"error": {"code": 429, "message": "Quota exceeded", "status": "RESOURCE_EXHAUSTED"}}"#;
+        let err = LlmError::classify(429, Some(Duration::from_secs(7)), gemini);
+        assert_eq!(
+            err,
+            LlmError::RateLimited { retry_after: Some(Duration::from_secs(7)), message: "Quota exceeded".to_string() }
+        );
+        assert!(err.is_retryable());
+
+        let anthropic = r#"{"type": "error", "error": {"type": "authentication_error", "message": "invalid x-api-key"}}"#;
+        assert_eq!(LlmError::classify(401, None, anthropic), LlmError::Auth("invalid x-api-key".to_string()));
+
+        let filtered = r#"{"error": {"message": "The response was filtered", "code": "content_filter"}}"#;
+        assert!(matches!(LlmError::classify(400, None, filtered), LlmError::ContentFiltered(_)));
+
+        let overloaded = LlmError::classify(503, None, "upstream overloaded");
+        assert_eq!(overloaded, LlmError::UpstreamStatus { code: 503, body: "upstream overloaded".to_string() });
+        assert!(overloaded.is_retryable());
+        assert!(!LlmError::classify(404, None, "not found").is_retryable());
+    }
+
+    #[test]
+    fn test_parse_retry_after_ignores_invalid_values() {
+        assert_eq!(parse_retry_after("7"), Some(Duration::from_secs(7)));
+        assert_eq!(parse_retry_after(" 1.5 "), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_retry_after("-1"), None);
+        assert_eq!(parse_retry_after("inf"), None);
+        assert_eq!(parse_retry_after("NaN"), None);
+        assert_eq!(parse_retry_after("1e300"), None);
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), None);
+    }
+}
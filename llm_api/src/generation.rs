@@ -0,0 +1,153 @@
+use serde::{Deserialize, Serialize};
+
+use agent_models::response_item::CreateResponseRequest;
+pub use agent_models::response_item::ReasoningOptions;
+
+use crate::chat::{ChatCompletionRequest, ResponseFormat, ToolChoice};
+
+/// Provider-agnostic generation controls for a single turn.
+/// Unset fields are left to the upstream provider's defaults.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GenerationParams {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ToolChoice>,
+    /// Plain text (default), any JSON object, or JSON matching a schema
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<ReasoningOptions>,
+}
+
+impl GenerationParams {
+    /// Fill every unset field from `defaults`
+    pub fn with_defaults(&self, defaults: &GenerationParams) -> GenerationParams {
+        GenerationParams {
+            temperature: self.temperature.or(defaults.temperature),
+            top_p: self.top_p.or(defaults.top_p),
+            max_output_tokens: self.max_output_tokens.or(defaults.max_output_tokens),
+            stop: self.stop.clone().or_else(|| defaults.stop.clone()),
+            tool_choice: self.tool_choice.clone().or_else(|| defaults.tool_choice.clone()),
+            response_format: self.response_format.clone().or_else(|| defaults.response_format.clone()),
+            reasoning: self.reasoning.or(defaults.reasoning),
+        }
+    }
+
+    /// Reasoning handling of this turn, all reasoning kept and forwarded when unset
+    pub fn reasoning_options(&self) -> ReasoningOptions {
+        self.reasoning.unwrap_or_default()
+    }
+}
+
+impl From<&ChatCompletionRequest> for GenerationParams {
+    fn from(request: &ChatCompletionRequest) -> Self {
+        GenerationParams {
+            temperature: request.temperature,
+            top_p: request.top_p,
+            max_output_tokens: request.max_tokens,
+            stop: request.stop.clone(),
+            tool_choice: request.tool_choice.clone(),
+            response_format: request.response_format.clone(),
+            reasoning: request.reasoning,
+        }
+    }
+}
+
+impl From<&CreateResponseRequest> for GenerationParams {
+    fn from(request: &CreateResponseRequest) -> Self {
+        GenerationParams {
+            temperature: request.temperature,
+            top_p: request.top_p,
+            max_output_tokens: request.max_output_tokens,
+            stop: None,
+            tool_choice: request.tool_choice.as_ref().map(ToolChoice::from),
+            response_format: None,
+            reasoning: request.reasoning,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_defaults_keeps_request_values() {
+        let request = GenerationParams {
+            temperature: Some(0.2),
+            ..Default::default()
+        };
+        let defaults = GenerationParams {
+            temperature: Some(0.9),
+            max_output_tokens: Some(512),
+            stop: Some(vec!["END".to_string()]),
+            ..Default::default()
+        };
+
+        let merged = request.with_defaults(&defaults);
+        assert_eq!(merged.temperature, Some(0.2));
+        assert_eq!(merged.max_output_tokens, Some(512));
+        assert_eq!(merged.stop, Some(vec!["END".to_string()]));
+        assert_eq!(merged.top_p, None);
+    }
+
+    #[test]
+    fn test_from_chat_request_accepts_single_stop_string() {
+        let request: ChatCompletionRequest = serde_json::from_value(serde_json::json!({
+            "model": "gpt-4o",
+            "messages": [],
+            "max_tokens": 64,
+            "top_p": 0.5,
+            "stop": "\n\n"
+        }))
+        .unwrap();
+
+        let params = GenerationParams::from(&request);
+        assert_eq!(params.max_output_tokens, Some(64));
+        assert_eq!(params.top_p, Some(0.5));
+        assert_eq!(params.stop, Some(vec!["\n\n".to_string()]));
+    }
+
+    #[test]
+    fn test_from_response_request_takes_reasoning_options() {
+        let request: CreateResponseRequest = serde_json::from_value(serde_json::json!({
+            "model": "deepseek-reasoner",
+            "input": "Hi",
+            "reasoning": {"include": false}
+        }))
+        .unwrap();
+
+        let params = GenerationParams::from(&request);
+        assert_eq!(params.reasoning, Some(ReasoningOptions { include: false, forward_signed: true }));
+        assert!(serde_json::to_value(&request).unwrap().get("reasoning").is_none());
+    }
+
+    #[test]
+    fn test_response_format_round_trips_through_params() {
+        let format = serde_json::json!({
+            "type": "json_schema",
+            "json_schema": {"name": "plan", "schema": {"type": "object"}, "strict": true}
+        });
+        let request: ChatCompletionRequest = serde_json::from_value(serde_json::json!({
+            "model": "gpt-4o",
+            "messages": [],
+            "response_format": format
+        }))
+        .unwrap();
+
+        let params = GenerationParams::from(&request);
+        assert!(params.response_format.as_ref().is_some_and(ResponseFormat::is_json));
+
+        let mut upstream = request.clone();
+        upstream.response_format = None;
+        upstream.apply_generation_params(&params);
+        assert_eq!(serde_json::to_value(&upstream).unwrap()["response_format"], format);
+    }
+}
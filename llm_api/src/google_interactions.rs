@@ -1,15 +1,99 @@
 use serde::{Deserialize, Serialize};
-use agent_models::response_item::{ContentPart, ResponseItem, Role};
+use agent_models::response_item::{ContentPart, ResponseItem, ResponseUsage, Role};
 use std::collections::HashMap;
 
+use crate::chat::{ResponseFormat, ToolChoice};
+use crate::client::{LlmClient, LlmError, LlmTurn, StreamDelta};
+use crate::generation::GenerationParams;
+use crate::retry::{http_client, send_with_retry, RetryPolicy};
+use configuration::HttpClientConfig;
+use crate::sse::{split_sse_event, sse_event_data};
+use crate::tools::Tool;
+
+const MAX_GEMINI_STOP_SEQUENCES: usize = 5;
+
 // Data structures for Google's /v1/interactions API
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct GeminiInteractionRequest {
     pub contents: Vec<Content>,
+    /// System messages of the history, sent apart from the conversation turns
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_instruction: Option<SystemInstruction>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub previous_interaction_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<GeminiTool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_config: Option<ToolConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generation_config: Option<GenerationConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub safety_settings: Option<Vec<SafetySetting>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemInstruction {
+    pub parts: Vec<Part>,
+}
+
+/// Per-category blocking threshold, e.g. `HARM_CATEGORY_HARASSMENT` / `BLOCK_ONLY_HIGH`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SafetySetting {
+    pub category: String,
+    pub threshold: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerationConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_sequences: Option<Vec<String>>,
+    /// "application/json" for JSON output
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_mime_type: Option<String>,
+    /// OpenAPI-style schema the JSON output must match
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_schema: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiTool {
+    pub function_declarations: Vec<FunctionDeclaration>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FunctionDeclaration {
+    pub name: String,
+    pub description: String,
+    /// OpenAPI-style schema of the arguments
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolConfig {
+    pub function_calling_config: FunctionCallingConfig,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FunctionCallingConfig {
+    pub mode: String, // "AUTO", "ANY" or "NONE"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allowed_function_names: Option<Vec<String>>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
@@ -25,7 +109,14 @@ pub struct Content {
 pub enum Part {
     Text { text: String },
     InlineData { inline_data: InlineData },
-    FunctionCall { function_call: FunctionCall },
+    /// Content referenced by URI (Files API, Cloud Storage or public URL)
+    FileData { file_data: FileData },
+    FunctionCall {
+        function_call: FunctionCall,
+        /// Signature of the reasoning that led to the call, required back in multi-turn tool use
+        #[serde(rename = "thoughtSignature", default, skip_serializing_if = "Option::is_none")]
+        thought_signature: Option<String>,
+    },
     FunctionResponse { function_response: FunctionResponse },
 }
 
@@ -36,6 +127,13 @@ pub struct InlineData {
     pub data: String,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FileData {
+    pub mime_type: String,
+    pub file_uri: String,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct FunctionCall {
@@ -50,44 +148,155 @@ pub struct FunctionResponse {
     pub response: serde_json::Value,
 }
 
+// Data structures for Gemini `generateContent` responses (also the payload of each streamed chunk)
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiResponse {
+    #[serde(default)]
+    pub candidates: Vec<GeminiCandidate>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage_metadata: Option<GeminiUsageMetadata>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_feedback: Option<PromptFeedback>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_version: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiCandidate {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<GeminiResponseContent>,
+    /// "STOP", "MAX_TOKENS", "SAFETY", "RECITATION", ...
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finish_reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub safety_ratings: Vec<SafetyRating>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiResponseContent {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    #[serde(default)]
+    pub parts: Vec<GeminiPartResponse>,
+}
+
+/// A response part; exactly one of text, inline data or function call is normally set
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiPartResponse {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// Set on thought summaries when thinking is enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thought: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thought_signature: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inline_data: Option<InlineData>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub function_call: Option<GeminiFunctionCall>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiFunctionCall {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub name: String,
+    #[serde(default)]
+    pub args: serde_json::Value,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SafetyRating {
+    pub category: String,
+    pub probability: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blocked: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptFeedback {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub safety_ratings: Vec<SafetyRating>,
+}
+
+/// Finish reasons meaning the candidate was withheld by Gemini's content filters
+const BLOCKING_FINISH_REASONS: &[&str] = &["SAFETY", "RECITATION", "BLOCKLIST", "PROHIBITED_CONTENT", "SPII"];
+
+impl GeminiResponse {
+    /// Why the prompt or the first candidate was blocked, with the ratings that triggered it
+    pub fn block_reason(&self) -> Option<String> {
+        if let Some(feedback) = &self.prompt_feedback
+            && let Some(reason) = &feedback.block_reason
+        {
+            return Some(format!("prompt blocked ({}){}", reason, format_ratings(&feedback.safety_ratings)));
+        }
+        let candidate = self.candidates.first()?;
+        let reason = candidate.finish_reason.as_deref()?;
+        BLOCKING_FINISH_REASONS
+            .contains(&reason)
+            .then(|| format!("candidate blocked ({}){}", reason, format_ratings(&candidate.safety_ratings)))
+    }
+}
+
+fn format_ratings(ratings: &[SafetyRating]) -> String {
+    let flagged: Vec<String> = ratings
+        .iter()
+        .filter(|r| r.blocked.unwrap_or(false) || !matches!(r.probability.as_str(), "NEGLIGIBLE" | "LOW"))
+        .map(|r| format!("{}={}", r.category, r.probability))
+        .collect();
+    if flagged.is_empty() {
+        String::new()
+    } else {
+        format!(": {}", flagged.join(", "))
+    }
+}
+
 // --- Adapter Logic ---
 
 pub struct GoogleInteractionsAdapter;
 
 impl GoogleInteractionsAdapter {
     /// Converts a history of `ResponseItem`s into a `GeminiInteractionRequest`.
+    /// System messages are moved to `systemInstruction` and consecutive turns of the same role
+    /// are merged, as Gemini requires user and model turns to alternate. The signature of a
+    /// reasoning item goes on the function call that follows it; reasoning text is not sent.
     pub fn to_gemini_request(
         history: &[ResponseItem],
         previous_interaction_id: Option<String>,
     ) -> Result<GeminiInteractionRequest, serde_json::Error> {
-        let mut contents = Vec::new();
+        let mut contents: Vec<Content> = Vec::new();
+        let mut system_parts = Vec::new();
         let mut function_calls = HashMap::new();
+        let mut thought_signature = None;
 
         for item in history {
             match item {
                 ResponseItem::Message { role, content, .. } => {
                     let gemini_role = match role {
-                        Role::User => "user".to_string(),
-                        Role::Assistant => "model".to_string(),
-                        // System role is mapped to user role prompt in Google Interactions format
-                        Role::System => "user".to_string(), 
+                        Role::User | Role::System => "user",
+                        Role::Assistant => "model",
                         Role::Tool => continue, // Handled by FunctionCallOutput
                     };
 
-                    let parts = content
-                        .iter()
-                        .map(|part| match part {
-                            ContentPart::Text { text } => Part::Text { text: text.clone() },
-                            ContentPart::Image { media_type, data_base64 } => Part::InlineData {
-                                inline_data: InlineData {
-                                    mime_type: media_type.clone(),
-                                    data: data_base64.clone(),
-                                },
-                            },
-                        })
-                        .collect();
-                    
-                    contents.push(Content { role: gemini_role, parts });
+                    let parts: Vec<Part> = content.iter().filter_map(Self::to_gemini_part).collect();
+
+                    if *role == Role::System {
+                        system_parts.extend(parts);
+                    } else {
+                        Self::push_turn(&mut contents, gemini_role, parts);
+                    }
                 }
                 ResponseItem::FunctionCall { name, arguments, call_id, .. } => {
                     let args: serde_json::Value = serde_json::from_str(arguments)
@@ -97,52 +306,693 @@ impl GoogleInteractionsAdapter {
                             name: name.clone(),
                             args,
                         },
+                        thought_signature: thought_signature.take(),
                     };
                     function_calls.insert(call_id.clone(), name.clone());
-
-                    if let Some(last_content) = contents.last_mut() {
-                        if last_content.role == "model" {
-                            last_content.parts.push(call_part);
-                            continue;
-                        }
-                    }
-                    contents.push(Content {
-                        role: "model".to_string(),
-                        parts: vec![call_part],
-                    });
+                    Self::push_turn(&mut contents, "model", vec![call_part]);
                 }
                 ResponseItem::FunctionCallOutput { call_id, output, .. } => {
                     let name = function_calls.get(call_id).cloned().unwrap_or_else(|| "unknown_function".to_string());
                     let response: serde_json::Value = serde_json::from_str(output)
                         .unwrap_or_else(|_| serde_json::json!({ "output": output }));
-                    contents.push(Content {
-                        role: "user".to_string(),
-                        parts: vec![Part::FunctionResponse {
-                            function_response: FunctionResponse {
-                                name,
-                                response,
-                            },
-                        }],
-                    });
+                    let response_part = Part::FunctionResponse {
+                        function_response: FunctionResponse { name, response },
+                    };
+                    Self::push_turn(&mut contents, "user", vec![response_part]);
                 }
-                ResponseItem::Reasoning { .. } => {
-                    // Internal agent reasoning trace is not sent directly to Gemini endpoint
+                ResponseItem::Reasoning { signature, .. } => {
+                    if signature.is_some() {
+                        thought_signature = signature.clone();
+                    }
                 }
             }
         }
 
         Ok(GeminiInteractionRequest {
             contents,
+            system_instruction: (!system_parts.is_empty()).then_some(SystemInstruction { parts: system_parts }),
             previous_interaction_id,
+            tools: None,
+            tool_config: None,
+            generation_config: None,
+            safety_settings: None,
         })
     }
+
+    /// Base64 content goes inline, URLs as `fileData`. A `data:` URL is sent inline.
+    fn to_gemini_part(part: &ContentPart) -> Option<Part> {
+        let inline = |mime_type: &str, data: &str| Part::InlineData {
+            inline_data: InlineData { mime_type: mime_type.to_string(), data: data.to_string() },
+        };
+        let by_uri = |mime_type: &str, url: &str| match ContentPart::parse_data_url(url) {
+            Some((mime_type, data)) => inline(mime_type, data),
+            None => Part::FileData {
+                file_data: FileData { mime_type: mime_type.to_string(), file_uri: url.to_string() },
+            },
+        };
+        match part {
+            ContentPart::Text { text } => Some(Part::Text { text: text.clone() }),
+            ContentPart::Image { media_type, data_base64 } | ContentPart::Audio { media_type, data_base64 } => {
+                Some(inline(media_type, data_base64))
+            }
+            ContentPart::ImageUrl { url, .. } => Some(by_uri(image_type_from_url(url), url)),
+            ContentPart::File { media_type, data_base64: Some(data), .. } => Some(inline(media_type, data)),
+            ContentPart::File { media_type, url: Some(url), .. } => Some(by_uri(media_type, url)),
+            ContentPart::File { filename, .. } => {
+                tracing::warn!("Dropping file part {:?} without data or url", filename);
+                None
+            }
+        }
+    }
+
+    /// Append parts as a turn of `role`, extending the last turn when it has the same role
+    fn push_turn(contents: &mut Vec<Content>, role: &str, parts: Vec<Part>) {
+        if parts.is_empty() {
+            return;
+        }
+        match contents.last_mut() {
+            Some(last) if last.role == role => last.parts.extend(parts),
+            _ => contents.push(Content {
+                role: role.to_string(),
+                parts,
+            }),
+        }
+    }
+
+    /// Converts the first candidate of a Gemini response into `ResponseItem`s.
+    /// Thought parts become `Reasoning`, inline data becomes image content, adjacent text and
+    /// image parts share one message and function call ids are kept as `call_id`.
+    /// A thought signature on any other part is kept on the `Reasoning` before it.
+    pub fn from_gemini_response(response: &GeminiResponse) -> Vec<ResponseItem> {
+        let mut items = Vec::new();
+        let mut message_parts: Vec<ContentPart> = Vec::new();
+        let parts = response
+            .candidates
+            .first()
+            .and_then(|candidate| candidate.content.as_ref())
+            .map(|content| content.parts.as_slice())
+            .unwrap_or_default();
+
+        fn flush_message(items: &mut Vec<ResponseItem>, parts: &mut Vec<ContentPart>) {
+            if !parts.is_empty() {
+                items.push(ResponseItem::Message {
+                    id: format!("resp_msg_{}", uuid::Uuid::new_v4()),
+                    role: Role::Assistant,
+                    content: std::mem::take(parts),
+                });
+            }
+        }
+
+        for part in parts {
+            if let Some(signature) = &part.thought_signature
+                && !part.thought.unwrap_or(false)
+            {
+                flush_message(&mut items, &mut message_parts);
+                match items.last_mut() {
+                    Some(ResponseItem::Reasoning { signature: unsigned @ None, .. }) => {
+                        *unsigned = Some(signature.clone())
+                    }
+                    _ => items.push(ResponseItem::Reasoning {
+                        id: format!("rs_{}", uuid::Uuid::new_v4()),
+                        thought_process: String::new(),
+                        signature: Some(signature.clone()),
+                    }),
+                }
+            }
+            if let Some(call) = &part.function_call {
+                flush_message(&mut items, &mut message_parts);
+                items.push(ResponseItem::FunctionCall {
+                    id: format!("fc_{}", uuid::Uuid::new_v4()),
+                    call_id: call
+                        .id
+                        .clone()
+                        .unwrap_or_else(|| format!("call_{}", uuid::Uuid::new_v4())),
+                    name: call.name.clone(),
+                    arguments: call.args.to_string(),
+                });
+            } else if part.thought.unwrap_or(false) {
+                flush_message(&mut items, &mut message_parts);
+                items.push(ResponseItem::Reasoning {
+                    id: format!("rs_{}", uuid::Uuid::new_v4()),
+                    thought_process: part.text.clone().unwrap_or_default(),
+                    signature: part.thought_signature.clone(),
+                });
+            } else if let Some(text) = &part.text {
+                match message_parts.last_mut() {
+                    Some(ContentPart::Text { text: existing }) => existing.push_str(text),
+                    _ => message_parts.push(ContentPart::Text { text: text.clone() }),
+                }
+            } else if let Some(data) = &part.inline_data {
+                let media_type = data.mime_type.clone();
+                let data_base64 = data.data.clone();
+                message_parts.push(if media_type.starts_with("image/") {
+                    ContentPart::Image { media_type, data_base64 }
+                } else if media_type.starts_with("audio/") {
+                    ContentPart::Audio { media_type, data_base64 }
+                } else {
+                    ContentPart::File { filename: None, media_type, data_base64: Some(data_base64), url: None }
+                });
+            }
+        }
+        flush_message(&mut items, &mut message_parts);
+        items
+    }
+
+    /// Maps generation controls onto Gemini's `generationConfig`.
+    /// Returns None when nothing is set so the model defaults apply.
+    pub fn to_gemini_generation_config(params: &GenerationParams) -> Option<GenerationConfig> {
+        let config = GenerationConfig {
+            temperature: params.temperature,
+            top_p: params.top_p,
+            max_output_tokens: params.max_output_tokens,
+            // Gemini accepts at most five stop sequences
+            stop_sequences: params
+                .stop
+                .as_ref()
+                .map(|stop| stop.iter().take(MAX_GEMINI_STOP_SEQUENCES).cloned().collect()),
+            response_mime_type: params
+                .response_format
+                .as_ref()
+                .filter(|format| format.is_json())
+                .map(|_| "application/json".to_string()),
+            response_schema: params
+                .response_format
+                .as_ref()
+                .and_then(ResponseFormat::schema)
+                .map(Self::to_gemini_schema),
+        };
+        (config != GenerationConfig::default()).then_some(config)
+    }
+
+    /// Converts a JSON Schema (as generated by schemars) into the OpenAPI subset accepted by
+    /// `responseSchema`: `$ref`s are inlined, `null` alternatives become `nullable` and
+    /// unsupported keywords are dropped.
+    pub fn to_gemini_schema(schema: &serde_json::Value) -> serde_json::Value {
+        let definitions = schema
+            .get("$defs")
+            .or_else(|| schema.get("definitions"))
+            .cloned()
+            .unwrap_or_default();
+        gemini_schema_node(schema, &definitions, 0)
+    }
+
+    /// Converts chat-style tool definitions into a single Gemini `functionDeclarations` tool.
+    /// Returns None when no tools are given, as Gemini rejects an empty declaration list.
+    pub fn to_gemini_tools(tools: &[Tool]) -> Option<Vec<GeminiTool>> {
+        if tools.is_empty() {
+            return None;
+        }
+        let function_declarations = tools
+            .iter()
+            .map(|tool| {
+                let params = &tool.function.parameters;
+                let has_properties = params.properties.as_object().is_some_and(|p| !p.is_empty());
+                FunctionDeclaration {
+                    name: tool.function.name.clone(),
+                    description: tool.function.description.clone(),
+                    // Gemini rejects an object schema without properties, so parameterless functions omit it
+                    parameters: has_properties.then(|| serde_json::to_value(params).unwrap_or_default()),
+                }
+            })
+            .collect();
+        Some(vec![GeminiTool { function_declarations }])
+    }
+
+    /// Maps an OpenAI-style tool_choice onto Gemini's function calling mode.
+    pub fn to_gemini_tool_config(tool_choice: &ToolChoice) -> ToolConfig {
+        let (mode, allowed_function_names) = match tool_choice {
+            ToolChoice::String(mode) => match mode.as_str() {
+                "none" => ("NONE", None),
+                "required" | "any" => ("ANY", None),
+                _ => ("AUTO", None),
+            },
+            ToolChoice::Function { function, .. } => ("ANY", Some(vec![function.name.clone()])),
+        };
+        ToolConfig {
+            function_calling_config: FunctionCallingConfig {
+                mode: mode.to_string(),
+                allowed_function_names,
+            },
+        }
+    }
+}
+
+/// Depth at which `$ref`s stop being inlined, so recursive types terminate
+const MAX_GEMINI_SCHEMA_DEPTH: usize = 8;
+
+/// Keywords of Gemini's Schema object that are copied as they are
+const GEMINI_SCHEMA_KEYWORDS: &[&str] = &[
+    "description", "enum", "nullable", "minItems", "maxItems", "minLength", "maxLength", "pattern",
+    "minimum", "maximum", "minProperties", "maxProperties", "required", "title", "default",
+];
+
+fn gemini_schema_node(node: &serde_json::Value, definitions: &serde_json::Value, depth: usize) -> serde_json::Value {
+    use serde_json::{json, Map, Value};
+
+    let Value::Object(node) = node else {
+        // `true` accepts anything
+        return json!({});
+    };
+    if let Some(reference) = node.get("$ref").and_then(Value::as_str) {
+        let name = reference.rsplit('/').next().unwrap_or_default();
+        return match definitions.get(name) {
+            Some(target) if depth < MAX_GEMINI_SCHEMA_DEPTH => gemini_schema_node(target, definitions, depth + 1),
+            _ => json!({"type": "object"}),
+        };
+    }
+
+    let mut out = Map::new();
+    let mut nullable = false;
+    // `allOf` with a single member is how schemars wraps a described `$ref`
+    if let Some(Value::Array(all)) = node.get("allOf")
+        && let [single] = all.as_slice()
+        && let Value::Object(inlined) = gemini_schema_node(single, definitions, depth)
+    {
+        out.extend(inlined);
+    }
+    match node.get("type") {
+        Some(Value::Array(types)) => {
+            let mut types: Vec<&Value> = types.iter().filter(|t| t.as_str() != Some("null")).collect();
+            nullable = types.len() < node["type"].as_array().map_or(0, Vec::len);
+            if types.len() == 1 {
+                out.insert("type".to_string(), types.remove(0).clone());
+            } else {
+                let variants = types.into_iter().map(|t| json!({"type": t})).collect();
+                out.insert("anyOf".to_string(), Value::Array(variants));
+            }
+        }
+        Some(kind) => {
+            out.insert("type".to_string(), kind.clone());
+        }
+        None => {}
+    }
+    if let Some(Value::Array(variants)) = node.get("anyOf").or_else(|| node.get("oneOf")) {
+        let mut variants: Vec<Value> = variants
+            .iter()
+            .filter(|variant| {
+                let is_null = variant.get("type").and_then(Value::as_str) == Some("null");
+                nullable |= is_null;
+                !is_null
+            })
+            .map(|variant| gemini_schema_node(variant, definitions, depth))
+            .collect();
+        if variants.len() == 1
+            && let Value::Object(inlined) = variants.remove(0)
+        {
+            out.extend(inlined);
+        } else if !variants.is_empty() {
+            out.insert("anyOf".to_string(), Value::Array(variants));
+        }
+    }
+    if let Some(constant) = node.get("const") {
+        out.insert("enum".to_string(), json!([constant]));
+    }
+    if let Some(format) = node.get("format").and_then(Value::as_str)
+        && matches!(format, "int32" | "int64" | "float" | "double" | "date-time")
+    {
+        out.insert("format".to_string(), json!(format));
+    }
+    for keyword in GEMINI_SCHEMA_KEYWORDS {
+        if let Some(value) = node.get(*keyword) {
+            out.insert(keyword.to_string(), value.clone());
+        }
+    }
+    if let Some(Value::Object(properties)) = node.get("properties") {
+        let properties = properties
+            .iter()
+            .map(|(name, property)| (name.clone(), gemini_schema_node(property, definitions, depth)))
+            .collect();
+        out.insert("properties".to_string(), Value::Object(properties));
+    }
+    if let Some(items) = node.get("items") {
+        out.insert("items".to_string(), gemini_schema_node(items, definitions, depth));
+    }
+    if nullable {
+        out.insert("nullable".to_string(), json!(true));
+    }
+    Value::Object(out)
+}
+
+/// Gemini needs the MIME type of `fileData`; image URLs only hint at it with their extension
+fn image_type_from_url(url: &str) -> &'static str {
+    let path = url.split(['?', '#']).next().unwrap_or_default().to_ascii_lowercase();
+    match path.rsplit('.').next() {
+        Some("png") => "image/png",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("heic") => "image/heic",
+        _ => "image/jpeg",
+    }
+}
+
+// --- Streaming (`:streamGenerateContent?alt=sse`) ---
+
+/// Incremental output parsed from a Gemini SSE stream
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeminiStreamEvent {
+    /// Text of a partial candidate
+    Text(String),
+    /// Thought summary text, or just the signature sent on the part that follows the thoughts
+    Thought { text: String, signature: Option<String> },
+    /// Gemini sends each function call whole, never split across chunks
+    FunctionCall {
+        id: Option<String>,
+        name: String,
+        args: serde_json::Value,
+    },
+    /// The candidate finished, e.g. "STOP", "MAX_TOKENS" or "SAFETY"
+    Finish(String),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiUsageMetadata {
+    #[serde(default)]
+    pub prompt_token_count: Option<u32>,
+    #[serde(default)]
+    pub candidates_token_count: Option<u32>,
+    #[serde(default)]
+    pub total_token_count: Option<u32>,
+}
+
+impl GoogleInteractionsAdapter {
+    /// Calls `{base_url}/{model}:streamGenerateContent?alt=sse` and forwards incremental events to `tx`.
+    /// Returns the usage metadata of the last chunk that carried one.
+    /// The request is retried under `retry_policy` only until the stream starts.
+    pub async fn stream_generate_content(
+        client: &reqwest::Client,
+        base_url: &str,
+        model: &str,
+        api_key: &str,
+        request: &GeminiInteractionRequest,
+        retry_policy: &RetryPolicy,
+        tx: tokio::sync::mpsc::Sender<GeminiStreamEvent>,
+    ) -> Result<Option<GeminiUsageMetadata>, LlmError> {
+        let url = format!(
+            "{}/{}:streamGenerateContent?alt=sse&key={}",
+            base_url.trim_end_matches('/'),
+            model,
+            api_key
+        );
+        let mut response =
+            send_with_retry(retry_policy, "Gemini streaming API", || client.post(&url).json(request)).await?;
+        let mut buffer = String::new();
+        let mut usage = None;
+
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| LlmError::from_reqwest("Gemini stream interrupted", &e))?
+        {
+            buffer.push_str(&String::from_utf8_lossy(&chunk));
+
+            // An SSE event ends with a blank line; its payload may span several `data:` lines
+            while let Some((event, rest)) = split_sse_event(&buffer) {
+                let data = sse_event_data(&event);
+                buffer = rest;
+                if data.is_empty() {
+                    continue;
+                }
+                let (events, chunk_usage) = Self::parse_stream_chunk(&data);
+                if chunk_usage.is_some() {
+                    usage = chunk_usage;
+                }
+                for event in events {
+                    if tx.send(event).await.is_err() {
+                        return Ok(usage);
+                    }
+                }
+            }
+        }
+
+        // A final event without trailing blank line
+        let data = sse_event_data(&buffer);
+        if !data.is_empty() {
+            let (events, chunk_usage) = Self::parse_stream_chunk(&data);
+            if chunk_usage.is_some() {
+                usage = chunk_usage;
+            }
+            for event in events {
+                let _ = tx.send(event).await;
+            }
+        }
+
+        Ok(usage)
+    }
+
+    /// Events and usage carried by one streamed `GenerateContentResponse`
+    fn parse_stream_chunk(data: &str) -> (Vec<GeminiStreamEvent>, Option<GeminiUsageMetadata>) {
+        let response = match serde_json::from_str::<GeminiResponse>(data) {
+            Ok(response) => response,
+            Err(e) => {
+                tracing::warn!("Skipping undecodable Gemini stream chunk ({}): {}", e, data);
+                return (Vec::new(), None);
+            }
+        };
+
+        let mut events = Vec::new();
+        if let Some(candidate) = response.candidates.first() {
+            let parts = candidate.content.iter().flat_map(|content| content.parts.iter());
+            for part in parts {
+                if part.thought.unwrap_or(false) {
+                    events.push(GeminiStreamEvent::Thought {
+                        text: part.text.clone().unwrap_or_default(),
+                        signature: part.thought_signature.clone(),
+                    });
+                    continue;
+                }
+                if let Some(signature) = &part.thought_signature {
+                    events.push(GeminiStreamEvent::Thought { text: String::new(), signature: Some(signature.clone()) });
+                }
+                if let Some(text) = &part.text
+                    && !text.is_empty()
+                {
+                    events.push(GeminiStreamEvent::Text(text.clone()));
+                }
+                if let Some(call) = &part.function_call {
+                    events.push(GeminiStreamEvent::FunctionCall {
+                        id: call.id.clone(),
+                        name: call.name.clone(),
+                        args: if call.args.is_null() { serde_json::json!({}) } else { call.args.clone() },
+                    });
+                }
+            }
+            if let Some(reason) = &candidate.finish_reason {
+                events.push(GeminiStreamEvent::Finish(reason.clone()));
+            }
+        }
+        (events, response.usage_metadata)
+    }
+}
+
+impl From<GeminiUsageMetadata> for ResponseUsage {
+    fn from(usage: GeminiUsageMetadata) -> Self {
+        ResponseUsage {
+            input_tokens: usage.prompt_token_count.unwrap_or(0),
+            output_tokens: usage.candidates_token_count.unwrap_or(0),
+            total_tokens: usage.total_token_count.unwrap_or(0),
+        }
+    }
+}
+
+/// [`LlmClient`] for Gemini `generateContent`. The API is stateless, so the full history is sent on every turn.
+#[derive(Clone)]
+pub struct GeminiClient {
+    pub client: reqwest::Client,
+    /// Models endpoint, e.g. `https://generativelanguage.googleapis.com/v1beta/models`
+    pub base_url: String,
+    api_key: String,
+    pub model_id: String,
+    /// Content filter thresholds sent with every request
+    pub safety_settings: Vec<SafetySetting>,
+    /// Retries and timeouts of every upstream call
+    pub retry_policy: RetryPolicy,
+}
+
+impl GeminiClient {
+    pub fn new(base_url: String, model_id: String, api_key: String) -> Self {
+        Self {
+            client: http_client(&HttpClientConfig::default()),
+            base_url,
+            api_key,
+            model_id,
+            safety_settings: Vec::new(),
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+
+    /// Use a shared HTTP client instead of the default one
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    pub fn with_safety_settings(mut self, safety_settings: Vec<SafetySetting>) -> Self {
+        self.safety_settings = safety_settings;
+        self
+    }
+
+    /// Retry and time out upstream calls according to `retry_policy`
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Build a generateContent request from ResponseItem history
+    pub fn build_request(
+        &self,
+        history: &[ResponseItem],
+        tools: &[Tool],
+        params: &GenerationParams,
+    ) -> Result<GeminiInteractionRequest, LlmError> {
+        // generateContent rejects previousInteractionId, the full history is sent instead
+        let history = params.reasoning_options().upstream_history(history);
+        let mut request = GoogleInteractionsAdapter::to_gemini_request(&history, None)
+            .map_err(|e| LlmError::InvalidRequest(format!("Failed to build Gemini interaction request: {}", e)))?;
+        request.tools = GoogleInteractionsAdapter::to_gemini_tools(tools);
+        if request.tools.is_some() {
+            request.tool_config = params.tool_choice.as_ref().map(GoogleInteractionsAdapter::to_gemini_tool_config);
+        }
+        request.generation_config = GoogleInteractionsAdapter::to_gemini_generation_config(params);
+        if !self.safety_settings.is_empty() {
+            request.safety_settings = Some(self.safety_settings.clone());
+        }
+        Ok(request)
+    }
+}
+
+#[async_trait::async_trait]
+impl LlmClient for GeminiClient {
+    fn model_id(&self) -> &str {
+        &self.model_id
+    }
+
+    async fn complete(
+        &self,
+        history: &[ResponseItem],
+        params: &GenerationParams,
+        tools: &[Tool],
+    ) -> Result<LlmTurn, LlmError> {
+        let request = self.build_request(history, tools, params)?;
+        let url = format!(
+            "{}/{}:generateContent?key={}",
+            self.base_url.trim_end_matches('/'),
+            self.model_id,
+            self.api_key
+        );
+
+        let res = send_with_retry(&self.retry_policy, "Gemini API", || self.client.post(&url).json(&request)).await?;
+
+        let response: GeminiResponse = res
+            .json()
+            .await
+            .map_err(|e| LlmError::from_reqwest("Failed to parse Gemini response", &e))?;
+
+        let items = GoogleInteractionsAdapter::from_gemini_response(&response);
+        if items.is_empty()
+            && let Some(reason) = response.block_reason()
+        {
+            return Err(LlmError::ContentFiltered(format!("Gemini response blocked: {}", reason)));
+        }
+
+        Ok(LlmTurn {
+            items: params.reasoning_options().output(items),
+            usage: response.usage_metadata.map(Into::into),
+            model: Some(self.model_id.clone()),
+        })
+    }
+
+    async fn stream(
+        &self,
+        history: &[ResponseItem],
+        params: &GenerationParams,
+        tools: &[Tool],
+        tx: tokio::sync::mpsc::Sender<StreamDelta>,
+    ) -> Result<Option<ResponseUsage>, LlmError> {
+        let request = self.build_request(history, tools, params)?;
+        let include_reasoning = params.reasoning_options().include;
+
+        let (event_tx, mut event_rx) = tokio::sync::mpsc::channel::<GeminiStreamEvent>(64);
+        let translate = async move {
+            let mut call_index = 0;
+            while let Some(event) = event_rx.recv().await {
+                let deltas = match event {
+                    GeminiStreamEvent::Text(text) => vec![StreamDelta::Text(text)],
+                    GeminiStreamEvent::Thought { text, signature } if include_reasoning => {
+                        vec![StreamDelta::Reasoning { text, signature }]
+                    }
+                    GeminiStreamEvent::Thought { .. } => Vec::new(),
+                    GeminiStreamEvent::FunctionCall { id, name, args } => {
+                        let index = call_index;
+                        call_index += 1;
+                        vec![
+                            StreamDelta::FunctionCallStart {
+                                index,
+                                call_id: id.unwrap_or_else(|| format!("call_{}", uuid::Uuid::new_v4())),
+                                name,
+                            },
+                            StreamDelta::FunctionCallArguments { index, delta: args.to_string() },
+                        ]
+                    }
+                    GeminiStreamEvent::Finish(_) => Vec::new(),
+                };
+                for delta in deltas {
+                    if tx.send(delta).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        };
+        let (usage, _) = tokio::join!(
+            GoogleInteractionsAdapter::stream_generate_content(
+                &self.client,
+                &self.base_url,
+                &self.model_id,
+                &self.api_key,
+                &request,
+                &self.retry_policy,
+                event_tx,
+            ),
+            translate
+        );
+        Ok(usage?.map(Into::into))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::generation::ReasoningOptions;
     use agent_models::response_item::{ContentPart, ResponseItem, Role};
 
+    #[test]
+    fn test_gemini_client_request_uses_system_instruction_and_safety_settings() {
+        let client = GeminiClient::new(String::new(), "gemini-2.0-flash".to_string(), "g-key".to_string())
+            .with_safety_settings(vec![SafetySetting {
+                category: "HARM_CATEGORY_HARASSMENT".to_string(),
+                threshold: "BLOCK_ONLY_HIGH".to_string(),
+            }]);
+        let history = vec![
+            ResponseItem::Message {
+                id: "sys".to_string(),
+                role: Role::System,
+                content: vec![ContentPart::Text { text: "Answer in French.".to_string() }],
+            },
+            ResponseItem::Message {
+                id: "usr".to_string(),
+                role: Role::User,
+                content: vec![ContentPart::Text { text: "Hello".to_string() }],
+            },
+        ];
+        let request = client.build_request(&history, &[], &GenerationParams::default()).unwrap();
+        let json = serde_json::to_value(&request).unwrap();
+
+        assert_eq!(json["systemInstruction"]["parts"][0]["text"], "Answer in French.");
+        assert_eq!(json["contents"].as_array().unwrap().len(), 1);
+        assert_eq!(json["safetySettings"][0]["threshold"], "BLOCK_ONLY_HIGH");
+        assert!(json.get("previousInteractionId").is_none());
+    }
+
     #[test]
     fn test_to_gemini_request_conversation_flow() {
         let history = vec![
@@ -189,13 +1039,63 @@ mod tests {
         let req = GoogleInteractionsAdapter::to_gemini_request(&history, Some("inter_prev_999".to_string())).unwrap();
 
         assert_eq!(req.previous_interaction_id, Some("inter_prev_999".to_string()));
-        // Expected contents: System -> "user", User -> "user", FunctionCall -> "model", FunctionCallOutput -> "user", Assistant -> "model"
-        assert_eq!(req.contents.len(), 5);
+        // System -> systemInstruction, User -> "user", FunctionCall -> "model", FunctionCallOutput -> "user", Assistant -> "model"
+        assert_eq!(
+            req.system_instruction,
+            Some(SystemInstruction {
+                parts: vec![Part::Text { text: "You are a helpful coding assistant.".to_string() }],
+            })
+        );
+        assert_eq!(req.contents.len(), 4);
         assert_eq!(req.contents[0].role, "user");
-        assert_eq!(req.contents[1].role, "user");
-        assert_eq!(req.contents[2].role, "model");
-        assert_eq!(req.contents[3].role, "user");
-        assert_eq!(req.contents[4].role, "model");
+        assert_eq!(req.contents[1].role, "model");
+        assert_eq!(req.contents[2].role, "user");
+        assert_eq!(req.contents[3].role, "model");
+    }
+
+    #[test]
+    fn test_gemini_merges_consecutive_turns() {
+        let text = |id: &str, role: Role, text: &str| ResponseItem::Message {
+            id: id.to_string(),
+            role,
+            content: vec![ContentPart::Text { text: text.to_string() }],
+        };
+        let call = |call_id: &str, city: &str| ResponseItem::FunctionCall {
+            id: format!("fc_{}", call_id),
+            call_id: call_id.to_string(),
+            name: "get_weather".to_string(),
+            arguments: format!(r#"{{"city":"{}"}}"#, city),
+        };
+        let output = |call_id: &str| ResponseItem::FunctionCallOutput {
+            id: format!("fco_{}", call_id),
+            call_id: call_id.to_string(),
+            output: "sunny".to_string(),
+            is_error: false,
+        };
+        let history = vec![
+            text("m1", Role::System, "Be brief."),
+            text("m2", Role::User, "Weather in Paris"),
+            text("m3", Role::System, "Use metric units."),
+            text("m4", Role::User, "and in Rome?"),
+            text("m5", Role::Assistant, "Checking both."),
+            call("c1", "Paris"),
+            call("c2", "Rome"),
+            output("c1"),
+            output("c2"),
+        ];
+
+        let req = GoogleInteractionsAdapter::to_gemini_request(&history, None).unwrap();
+        assert_eq!(req.system_instruction.unwrap().parts.len(), 2);
+
+        let roles: Vec<&str> = req.contents.iter().map(|c| c.role.as_str()).collect();
+        assert_eq!(roles, vec!["user", "model", "user"]);
+        assert_eq!(req.contents[0].parts.len(), 2);
+        assert_eq!(req.contents[1].parts.len(), 3);
+        assert!(req.contents[2].parts.iter().all(|p| matches!(p, Part::FunctionResponse { .. })));
+
+        let json = serde_json::to_value(GoogleInteractionsAdapter::to_gemini_request(&history[..2], None).unwrap()).unwrap();
+        assert_eq!(json["systemInstruction"]["parts"][0]["text"], "Be brief.");
+        assert!(json.get("safetySettings").is_none());
     }
 
     #[test]
@@ -216,4 +1116,342 @@ mod tests {
         assert_eq!(req.contents.len(), 1);
         assert_eq!(req.contents[0].parts.len(), 2);
     }
+
+    #[test]
+    fn test_gemini_function_declarations_and_tool_config() {
+        use crate::chat::FunctionName;
+        use crate::tools::{FunctionDefinition, FunctionParameters};
+
+        let tools = vec![
+            Tool {
+                r#type: "function".to_string(),
+                function: FunctionDefinition {
+                    name: "get_weather".to_string(),
+                    description: "Current weather for a city".to_string(),
+                    parameters: FunctionParameters {
+                        r#type: "object".to_string(),
+                        properties: serde_json::json!({"city": {"type": "string"}}),
+                        required: Some(vec!["city".to_string()]),
+                    },
+                },
+            },
+            Tool {
+                r#type: "function".to_string(),
+                function: FunctionDefinition {
+                    name: "get_time".to_string(),
+                    description: "Current UTC time".to_string(),
+                    parameters: FunctionParameters {
+                        r#type: "object".to_string(),
+                        properties: serde_json::json!({}),
+                        required: None,
+                    },
+                },
+            },
+        ];
+
+        let gemini_tools = GoogleInteractionsAdapter::to_gemini_tools(&tools).unwrap();
+        let json = serde_json::to_value(&gemini_tools).unwrap();
+        let declarations = &json[0]["functionDeclarations"];
+        assert_eq!(declarations[0]["name"], "get_weather");
+        assert_eq!(declarations[0]["parameters"]["required"][0], "city");
+        assert!(declarations[1].get("parameters").is_none());
+        assert!(GoogleInteractionsAdapter::to_gemini_tools(&[]).is_none());
+
+        let forced = GoogleInteractionsAdapter::to_gemini_tool_config(&ToolChoice::Function {
+            r#type: "function".to_string(),
+            function: FunctionName { name: "get_weather".to_string() },
+        });
+        assert_eq!(forced.function_calling_config.mode, "ANY");
+        assert_eq!(forced.function_calling_config.allowed_function_names, Some(vec!["get_weather".to_string()]));
+        let none = GoogleInteractionsAdapter::to_gemini_tool_config(&ToolChoice::String("none".to_string()));
+        assert_eq!(none.function_calling_config.mode, "NONE");
+    }
+
+    #[test]
+    fn test_gemini_generation_config_mapping() {
+        assert!(GoogleInteractionsAdapter::to_gemini_generation_config(&GenerationParams::default()).is_none());
+
+        let params = GenerationParams {
+            temperature: Some(0.3),
+            max_output_tokens: Some(256),
+            stop: Some((0..7).map(|i| format!("stop{i}")).collect()),
+            ..Default::default()
+        };
+        let config = GoogleInteractionsAdapter::to_gemini_generation_config(&params).unwrap();
+        let json = serde_json::to_value(&config).unwrap();
+        assert_eq!(json["maxOutputTokens"], 256);
+        assert_eq!(json["stopSequences"].as_array().unwrap().len(), 5);
+        assert!(json.get("topP").is_none());
+    }
+
+    #[test]
+    fn test_gemini_response_schema_from_schemars() {
+        let params = GenerationParams {
+            response_format: Some(crate::structured::response_format_for::<agent_models::graph::graph_definition::WorkflowPlanInput>()),
+            ..Default::default()
+        };
+        let config = GoogleInteractionsAdapter::to_gemini_generation_config(&params).unwrap();
+        assert_eq!(config.response_mime_type.as_deref(), Some("application/json"));
+
+        let schema = config.response_schema.unwrap();
+        let text = schema.to_string();
+        assert!(!text.contains("$ref") && !text.contains("$schema") && !text.contains("\"null\""));
+        assert_eq!(schema["properties"]["plan_name"]["type"], "string");
+        // Referenced definitions are inlined, optional fields become nullable
+        let activity = &schema["properties"]["activities"]["items"];
+        assert_eq!(activity["type"], "object");
+        assert_eq!(activity["properties"]["activity_type"]["type"], "string");
+        assert_eq!(activity["properties"]["agent"]["type"], "object");
+        assert_eq!(activity["properties"]["agent"]["nullable"], true);
+        assert_eq!(activity["properties"]["tools"]["type"], "array");
+        assert_eq!(activity["properties"]["tools"]["nullable"], true);
+
+        let json_object = GenerationParams {
+            response_format: Some(crate::chat::ResponseFormat::JsonObject),
+            ..Default::default()
+        };
+        let config = GoogleInteractionsAdapter::to_gemini_generation_config(&json_object).unwrap();
+        assert_eq!(config.response_mime_type.as_deref(), Some("application/json"));
+        assert!(config.response_schema.is_none());
+    }
+
+    /// Serve one canned HTTP response on a local port and return its base url
+    async fn spawn_mock_sse_server(status_line: &'static str, body: String) -> (String, tokio::task::JoinHandle<String>) {
+        let (addr, request) = crate::sse::spawn_mock_sse_server(status_line, body).await;
+        (format!("http://{}/v1beta/models", addr), request)
+    }
+
+    #[test]
+    fn test_from_gemini_response_items() {
+        let response: GeminiResponse = serde_json::from_value(serde_json::json!({
+            "candidates": [{
+                "content": {
+                    "role": "model",
+                    "parts": [
+                        {"text": "Checking the forecast first.", "thought": true, "thoughtSignature": "sig-1"},
+                        {"text": "Here is the chart "},
+                        {"text": "you asked for:"},
+                        {"inlineData": {"mimeType": "image/png", "data": "iVBORw0KGgo="}},
+                        {"functionCall": {"id": "call_abc", "name": "get_weather", "args": {"city": "Paris"}}}
+                    ]
+                },
+                "finishReason": "STOP",
+                "safetyRatings": [{"category": "HARM_CATEGORY_HARASSMENT", "probability": "NEGLIGIBLE"}]
+            }],
+            "usageMetadata": {"promptTokenCount": 12, "candidatesTokenCount": 30, "totalTokenCount": 42}
+        }))
+        .unwrap();
+
+        assert_eq!(response.candidates[0].finish_reason.as_deref(), Some("STOP"));
+        assert_eq!(response.candidates[0].safety_ratings.len(), 1);
+        assert_eq!(response.block_reason(), None);
+
+        let items = GoogleInteractionsAdapter::from_gemini_response(&response);
+        assert_eq!(items.len(), 3);
+        match &items[0] {
+            ResponseItem::Reasoning { thought_process, signature, .. } => {
+                assert_eq!(thought_process, "Checking the forecast first.");
+                assert_eq!(signature.as_deref(), Some("sig-1"));
+            }
+            other => panic!("expected reasoning, got {:?}", other),
+        }
+        match &items[1] {
+            ResponseItem::Message { role, content, .. } => {
+                assert_eq!(*role, Role::Assistant);
+                assert_eq!(
+                    content,
+                    &vec![
+                        ContentPart::Text { text: "Here is the chart you asked for:".to_string() },
+                        ContentPart::Image {
+                            media_type: "image/png".to_string(),
+                            data_base64: "iVBORw0KGgo=".to_string(),
+                        },
+                    ]
+                );
+            }
+            other => panic!("expected message, got {:?}", other),
+        }
+        match &items[2] {
+            ResponseItem::FunctionCall { call_id, name, arguments, .. } => {
+                assert_eq!(call_id, "call_abc");
+                assert_eq!(name, "get_weather");
+                assert_eq!(arguments, r#"{"city":"Paris"}"#);
+            }
+            other => panic!("expected function call, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_gemini_block_reason() {
+        let blocked: GeminiResponse = serde_json::from_value(serde_json::json!({
+            "candidates": [{
+                "finishReason": "SAFETY",
+                "safetyRatings": [
+                    {"category": "HARM_CATEGORY_DANGEROUS_CONTENT", "probability": "HIGH", "blocked": true},
+                    {"category": "HARM_CATEGORY_HARASSMENT", "probability": "NEGLIGIBLE"}
+                ]
+            }]
+        }))
+        .unwrap();
+        assert!(GoogleInteractionsAdapter::from_gemini_response(&blocked).is_empty());
+        assert_eq!(
+            blocked.block_reason().as_deref(),
+            Some("candidate blocked (SAFETY): HARM_CATEGORY_DANGEROUS_CONTENT=HIGH")
+        );
+
+        let prompt_blocked: GeminiResponse =
+            serde_json::from_value(serde_json::json!({"promptFeedback": {"blockReason": "OTHER"}})).unwrap();
+        assert_eq!(prompt_blocked.block_reason().as_deref(), Some("prompt blocked (OTHER)"));
+    }
+
+    #[test]
+    fn test_gemini_media_parts() {
+        let history = vec![ResponseItem::Message {
+            id: "msg_1".to_string(),
+            role: Role::User,
+            content: vec![
+                ContentPart::ImageUrl { url: "gs://bucket/chart.webp".to_string(), detail: None },
+                ContentPart::ImageUrl { url: ContentPart::data_url("image/gif", "R0lGOD=="), detail: None },
+                ContentPart::File {
+                    filename: Some("report.pdf".to_string()),
+                    media_type: "application/pdf".to_string(),
+                    data_base64: None,
+                    url: Some("https://example.com/report.pdf".to_string()),
+                },
+                ContentPart::Audio { media_type: "audio/wav".to_string(), data_base64: "UklGRg==".to_string() },
+            ],
+        }];
+        let request = GoogleInteractionsAdapter::to_gemini_request(&history, None).unwrap();
+        assert_eq!(
+            request.contents[0].parts,
+            vec![
+                Part::FileData { file_data: FileData { mime_type: "image/webp".to_string(), file_uri: "gs://bucket/chart.webp".to_string() } },
+                Part::InlineData { inline_data: InlineData { mime_type: "image/gif".to_string(), data: "R0lGOD==".to_string() } },
+                Part::FileData {
+                    file_data: FileData { mime_type: "application/pdf".to_string(), file_uri: "https://example.com/report.pdf".to_string() }
+                },
+                Part::InlineData { inline_data: InlineData { mime_type: "audio/wav".to_string(), data: "UklGRg==".to_string() } },
+            ]
+        );
+
+        let response: GeminiResponse = serde_json::from_value(serde_json::json!({
+            "candidates": [{"content": {"role": "model", "parts": [{"inlineData": {"mimeType": "audio/mpeg", "data": "SUQz"}}]}}]
+        }))
+        .unwrap();
+        let items = GoogleInteractionsAdapter::from_gemini_response(&response);
+        assert!(matches!(&items[0], ResponseItem::Message { content, .. }
+            if content == &vec![ContentPart::Audio { media_type: "audio/mpeg".to_string(), data_base64: "SUQz".to_string() }]));
+    }
+
+    #[test]
+    fn test_gemini_thought_signature_round_trip() {
+        let response: GeminiResponse = serde_json::from_value(serde_json::json!({
+            "candidates": [{"content": {"role": "model", "parts": [
+                {"text": "Need the forecast.", "thought": true},
+                {"functionCall": {"name": "get_weather", "args": {"city": "Paris"}}, "thoughtSignature": "sig-call"}
+            ]}}]
+        }))
+        .unwrap();
+        let mut history = GoogleInteractionsAdapter::from_gemini_response(&response);
+        assert!(matches!(
+            &history[0],
+            ResponseItem::Reasoning { thought_process, signature: Some(signature), .. }
+                if thought_process == "Need the forecast." && signature == "sig-call"
+        ));
+        let ResponseItem::FunctionCall { call_id, .. } = &history[1] else {
+            panic!("expected the function call after the reasoning");
+        };
+        history.push(ResponseItem::FunctionCallOutput {
+            id: "fco_1".to_string(),
+            call_id: call_id.clone(),
+            output: r#"{"celsius": 21}"#.to_string(),
+            is_error: false,
+        });
+
+        let request = GoogleInteractionsAdapter::to_gemini_request(&history, None).unwrap();
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["contents"][0]["parts"][0]["thoughtSignature"], "sig-call");
+        assert_eq!(json["contents"][0]["parts"].as_array().unwrap().len(), 1);
+
+        let unsigned = ReasoningOptions { include: true, forward_signed: false }.upstream_history(&history);
+        let json = serde_json::to_value(GoogleInteractionsAdapter::to_gemini_request(&unsigned, None).unwrap()).unwrap();
+        assert!(json["contents"][0]["parts"][0].get("thoughtSignature").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_stream_generate_content_against_mock_sse() {
+        let chunks = [
+            serde_json::json!({"candidates": [{"content": {"role": "model", "parts": [
+                {"text": "planning", "thought": true},
+                {"text": "Hello"}
+            ]}}]}),
+            serde_json::json!({"candidates": [{"content": {"role": "model", "parts": [{"text": ", world"}]}}],
+                "usageMetadata": {"promptTokenCount": 4, "totalTokenCount": 4}}),
+            serde_json::json!({"candidates": [{"content": {"role": "model", "parts": [
+                {"functionCall": {"id": "fc-1", "name": "get_weather", "args": {"city": "Paris"}}, "thoughtSignature": "sig-1"}
+            ]}, "finishReason": "STOP"}],
+                "usageMetadata": {"promptTokenCount": 4, "candidatesTokenCount": 9, "totalTokenCount": 13}}),
+        ];
+        // Mix CRLF and LF event separators, as proxies in front of Gemini do
+        let body = format!(
+            "data: {}\r\n\r\ndata: {}\n\ndata: {}\n\n",
+            chunks[0], chunks[1], chunks[2]
+        );
+        let (base_url, request_text) = spawn_mock_sse_server("HTTP/1.1 200 OK", body).await;
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+        let request = GoogleInteractionsAdapter::to_gemini_request(&[], None).unwrap();
+        let usage = GoogleInteractionsAdapter::stream_generate_content(
+            &reqwest::Client::new(),
+            &base_url,
+            "gemini-2.0-flash",
+            "test-key",
+            &request,
+            &RetryPolicy::no_retry(),
+            tx,
+        )
+        .await
+        .unwrap();
+
+        let mut events = Vec::new();
+        while let Some(event) = rx.recv().await {
+            events.push(event);
+        }
+        assert_eq!(
+            events,
+            vec![
+                GeminiStreamEvent::Thought { text: "planning".to_string(), signature: None },
+                GeminiStreamEvent::Text("Hello".to_string()),
+                GeminiStreamEvent::Text(", world".to_string()),
+                GeminiStreamEvent::Thought { text: String::new(), signature: Some("sig-1".to_string()) },
+                GeminiStreamEvent::FunctionCall {
+                    id: Some("fc-1".to_string()),
+                    name: "get_weather".to_string(),
+                    args: serde_json::json!({"city": "Paris"}),
+                },
+                GeminiStreamEvent::Finish("STOP".to_string()),
+            ]
+        );
+        assert_eq!(usage.unwrap().total_token_count, Some(13));
+        assert!(request_text.await.unwrap().contains(":streamGenerateContent?alt=sse&key=test-key"));
+    }
+
+    #[tokio::test]
+    async fn test_stream_generate_content_http_error() {
+        let (base_url, _) = spawn_mock_sse_server("HTTP/1.1 429 Too Many Requests", "{}".to_string()).await;
+        let (tx, _rx) = tokio::sync::mpsc::channel(16);
+        let request = GoogleInteractionsAdapter::to_gemini_request(&[], None).unwrap();
+        let err = GoogleInteractionsAdapter::stream_generate_content(
+            &reqwest::Client::new(),
+            &base_url,
+            "gemini-2.0-flash",
+            "test-key",
+            &request,
+            &RetryPolicy::no_retry(),
+            tx,
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err, LlmError::RateLimited { .. }));
+    }
 }
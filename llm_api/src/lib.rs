@@ -1,3 +1,10 @@
 pub mod chat;
+pub mod client;
+pub mod generation;
 pub mod tools;
 pub mod google_interactions;
+pub mod anthropic;
+pub mod responses;
+pub mod retry;
+pub mod structured;
+mod sse;
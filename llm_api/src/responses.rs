@@ -0,0 +1,333 @@
+//! Client for upstreams implementing the Open Responses API (`POST /v1/responses`).
+//!
+//! Unlike [`crate::chat`], history is sent as `ResponseItem`s unchanged and the upstream may
+//! keep conversation state itself, addressed through `previous_response_id`.
+
+use reqwest::Client;
+use tracing::debug;
+
+use agent_models::response_item::{
+    CreateResponseRequest, ResponseError, ResponseItem, ResponseObject, ResponseStreamEvent, ResponsesInput,
+};
+
+use crate::client::{LlmClient, LlmError, LlmTurn};
+use crate::generation::GenerationParams;
+use crate::retry::{http_client, send_with_retry, RetryPolicy};
+use configuration::HttpClientConfig;
+use crate::sse::{split_sse_event, sse_event_data};
+use crate::tools::Tool;
+
+#[derive(Clone)]
+pub struct ResponsesLlmInteraction {
+    pub client: Client,
+    /// Full url of the upstream responses endpoint, e.g. `https://api.example.com/v1/responses`
+    pub llm_url: String,
+    llm_api_key: String,
+    pub model_id: String,
+    /// Retries and timeouts of every upstream call
+    pub retry_policy: RetryPolicy,
+}
+
+impl ResponsesLlmInteraction {
+    /// Create a new responses interaction entity
+    pub fn new(llm_url: String, model_id: String, llm_api_key: String) -> Self {
+        Self {
+            client: http_client(&HttpClientConfig::default()),
+            llm_url,
+            llm_api_key,
+            model_id,
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+
+    /// Use a shared HTTP client instead of the default one
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Retry and time out upstream calls according to `retry_policy`
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Build a request for this interaction's model. With a `previous_response_id`, `input`
+    /// only needs the items added since that response.
+    pub fn build_request(
+        &self,
+        input: Vec<ResponseItem>,
+        tools: &[Tool],
+        params: &GenerationParams,
+        previous_response_id: Option<String>,
+    ) -> CreateResponseRequest {
+        CreateResponseRequest {
+            model: Some(self.model_id.clone()),
+            input: Some(ResponsesInput::Items(input)),
+            previous_response_id,
+            stream: None,
+            temperature: params.temperature,
+            top_p: params.top_p,
+            max_output_tokens: params.max_output_tokens,
+            tools: (!tools.is_empty()).then(|| tools.iter().map(Into::into).collect()),
+            tool_choice: if tools.is_empty() {
+                None
+            } else {
+                params.tool_choice.as_ref().map(Into::into)
+            },
+            reasoning: None,
+        }
+    }
+
+    fn post(&self, request: &CreateResponseRequest) -> reqwest::RequestBuilder {
+        let mut builder = self
+            .client
+            .post(self.llm_url.clone())
+            .header("Content-Type", "application/json; charset=utf-8")
+            .json(request);
+        if !self.llm_api_key.is_empty() {
+            builder = builder.bearer_auth(self.llm_api_key.clone());
+        }
+        builder
+    }
+
+    /// Create a response and wait for it to complete
+    pub async fn create_response(&self, request: &CreateResponseRequest) -> Result<ResponseObject, LlmError> {
+        let mut payload = request.clone();
+        payload.stream = None;
+        if payload.model.is_none() {
+            payload.model = Some(self.model_id.clone());
+        }
+
+        let response = send_with_retry(&self.retry_policy, "Responses API", || self.post(&payload)).await?;
+
+        let response_body = response
+            .json::<ResponseObject>()
+            .await
+            .map_err(|e| LlmError::from_reqwest("Failed to parse Responses API response", &e))?;
+        debug!("Responses API Response Body: {:?}", response_body);
+        Ok(response_body)
+    }
+
+    /// Create a response with `stream: true`, forwarding every typed event to `tx`.
+    /// Returns the response carried by the terminal `response.completed` or `response.failed` event.
+    /// Event types this crate does not model are skipped.
+    pub async fn create_response_stream(
+        &self,
+        request: &CreateResponseRequest,
+        tx: tokio::sync::mpsc::Sender<ResponseStreamEvent>,
+    ) -> Result<Option<ResponseObject>, LlmError> {
+        let mut payload = request.clone();
+        payload.stream = Some(true);
+        if payload.model.is_none() {
+            payload.model = Some(self.model_id.clone());
+        }
+
+        let mut response =
+            send_with_retry(&self.retry_policy, "Responses streaming API", || self.post(&payload)).await?;
+        let mut buffer = String::new();
+        let mut last_response = None;
+        let mut receiver_open = true;
+
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| LlmError::from_reqwest("Responses stream interrupted", &e))?
+        {
+            buffer.push_str(&String::from_utf8_lossy(&chunk));
+
+            while let Some((event, rest)) = split_sse_event(&buffer) {
+                buffer = rest;
+                if let Some(event) = Self::parse_stream_event(&sse_event_data(&event)) {
+                    Self::forward(event, &tx, &mut last_response, &mut receiver_open).await;
+                }
+            }
+        }
+
+        // A final event without trailing blank line
+        if let Some(event) = Self::parse_stream_event(&sse_event_data(&buffer)) {
+            Self::forward(event, &tx, &mut last_response, &mut receiver_open).await;
+        }
+        Ok(last_response)
+    }
+
+    /// Remember terminal responses and pass the event on while anyone is listening
+    async fn forward(
+        event: ResponseStreamEvent,
+        tx: &tokio::sync::mpsc::Sender<ResponseStreamEvent>,
+        last_response: &mut Option<ResponseObject>,
+        receiver_open: &mut bool,
+    ) {
+        if let ResponseStreamEvent::Completed { response, .. } | ResponseStreamEvent::Failed { response, .. } = &event {
+            *last_response = Some(response.clone());
+        }
+        // Keep reading after the receiver is gone so the final response is still returned
+        if *receiver_open && tx.send(event).await.is_err() {
+            *receiver_open = false;
+        }
+    }
+
+    fn parse_stream_event(data: &str) -> Option<ResponseStreamEvent> {
+        if data.is_empty() || data == "[DONE]" {
+            return None;
+        }
+        match serde_json::from_str(data) {
+            Ok(event) => Some(event),
+            Err(e) => {
+                debug!("Skipping unsupported Responses stream event ({}): {}", e, data);
+                None
+            }
+        }
+    }
+}
+
+/// Classify the error of a failed response; `server_error` is the only code the upstream may recover from
+pub fn response_error(error: &ResponseError) -> LlmError {
+    let status = if error.code == "server_error" { 500 } else { 400 };
+    let body = serde_json::json!({ "error": { "code": error.code, "message": error.message } }).to_string();
+    LlmError::classify(status, None, &body)
+}
+
+/// Stateless turns: the full history is sent every time and no upstream chain is kept
+#[async_trait::async_trait]
+impl LlmClient for ResponsesLlmInteraction {
+    fn model_id(&self) -> &str {
+        &self.model_id
+    }
+
+    async fn complete(
+        &self,
+        history: &[ResponseItem],
+        params: &GenerationParams,
+        tools: &[Tool],
+    ) -> Result<LlmTurn, LlmError> {
+        let request = self.build_request(history.to_vec(), tools, params, None);
+        let response = self.create_response(&request).await?;
+        if let Some(error) = &response.error {
+            return Err(response_error(error));
+        }
+
+        Ok(LlmTurn {
+            usage: response.usage.clone(),
+            model: Some(response.model).filter(|m| !m.is_empty()).or_else(|| Some(self.model_id.clone())),
+            items: response.output,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use agent_models::response_item::{ContentPart, ResponseUsage, Role};
+
+    fn user_message(text: &str) -> ResponseItem {
+        ResponseItem::Message {
+            id: "msg_user".to_string(),
+            role: Role::User,
+            content: vec![ContentPart::Text { text: text.to_string() }],
+        }
+    }
+
+    fn response_object(id: &str, output: Vec<ResponseItem>) -> ResponseObject {
+        ResponseObject {
+            id: id.to_string(),
+            object: "response".to_string(),
+            created: 0,
+            model: "upstream-model".to_string(),
+            output,
+            usage: Some(ResponseUsage { input_tokens: 3, output_tokens: 2, total_tokens: 5 }),
+            status: Some("completed".to_string()),
+            error: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_response_passes_previous_response_id() {
+        let reply = response_object(
+            "resp_2",
+            vec![ResponseItem::Message {
+                id: "msg_1".to_string(),
+                role: Role::Assistant,
+                content: vec![ContentPart::Text { text: "Paris".to_string() }],
+            }],
+        );
+        let (addr, request_text) = crate::sse::spawn_mock_http_server(
+            "HTTP/1.1 200 OK",
+            "application/json",
+            serde_json::to_string(&reply).unwrap(),
+        )
+        .await;
+
+        let llm = ResponsesLlmInteraction::new(
+            format!("http://{}/v1/responses", addr),
+            "upstream-model".to_string(),
+            "sk-test".to_string(),
+        );
+        let request = llm.build_request(
+            vec![user_message("Capital of France?")],
+            &[],
+            &GenerationParams { temperature: Some(0.1), ..Default::default() },
+            Some("resp_1".to_string()),
+        );
+        let response = llm.create_response(&request).await.unwrap();
+        assert_eq!(response, reply);
+
+        let request_text = request_text.await.unwrap();
+        assert!(request_text.to_ascii_lowercase().contains("authorization: bearer sk-test"));
+        let body: serde_json::Value =
+            serde_json::from_str(request_text.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert_eq!(body["previous_response_id"], "resp_1");
+        assert_eq!(body["model"], "upstream-model");
+        assert_eq!(body["input"][0]["type"], "message");
+        assert!(body.get("stream").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_create_response_stream_skips_unknown_events() {
+        let completed = response_object("resp_3", Vec::new());
+        let events = [
+            serde_json::to_value(ResponseStreamEvent::Created {
+                sequence_number: 0,
+                response: response_object("resp_3", Vec::new()),
+            })
+            .unwrap(),
+            serde_json::json!({"type": "response.in_progress", "sequence_number": 1}),
+            serde_json::to_value(ResponseStreamEvent::OutputTextDelta {
+                sequence_number: 2,
+                item_id: "msg_1".to_string(),
+                output_index: 0,
+                content_index: 0,
+                delta: "Hi".to_string(),
+            })
+            .unwrap(),
+            serde_json::to_value(ResponseStreamEvent::Completed { sequence_number: 3, response: completed.clone() })
+                .unwrap(),
+        ];
+        let mut body: String = events
+            .iter()
+            .map(|e| format!("event: {}\ndata: {}\n\n", e["type"].as_str().unwrap(), e))
+            .collect();
+        body.push_str("data: [DONE]\n\n");
+        let (addr, request_text) = crate::sse::spawn_mock_sse_server("HTTP/1.1 200 OK", body).await;
+
+        let llm = ResponsesLlmInteraction::new(
+            format!("http://{}/v1/responses", addr),
+            "upstream-model".to_string(),
+            String::new(),
+        );
+        let request = llm.build_request(vec![user_message("Hello")], &[], &GenerationParams::default(), None);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+        let last = llm.create_response_stream(&request, tx).await.unwrap();
+        assert_eq!(last, Some(completed));
+
+        let mut types = Vec::new();
+        while let Some(event) = rx.recv().await {
+            types.push(event.event_type());
+        }
+        assert_eq!(types, vec!["response.created", "response.output_text.delta", "response.completed"]);
+
+        let request_text = request_text.await.unwrap();
+        assert!(request_text.contains(r#""stream":true"#));
+        assert!(!request_text.to_ascii_lowercase().contains("authorization:"));
+    }
+}
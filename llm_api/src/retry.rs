@@ -0,0 +1,322 @@
+//! Retry and timeout policy shared by the HTTP clients of this crate.
+//!
+//! Only the request itself is retried: once a success status and headers have been received the
+//! body is read once, so a stream never replays output it already delivered. For the same reason
+//! the attempt timeout stops at the headers; the body is bounded by the read timeout of the client.
+
+use std::time::{Duration, Instant};
+
+use configuration::{HttpClientConfig, RetryConfig};
+use tracing::warn;
+
+use crate::client::LlmError;
+
+/// Connect timeout of clients whose configuration sets none
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest wait for the next bytes of a response, for clients whose configuration sets none
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// HTTP client with the connect and read timeouts of `config`. No timeout covers a whole
+/// response, so streams run as long as the upstream keeps sending.
+pub fn http_client(config: &HttpClientConfig) -> reqwest::Client {
+    let millis = |ms: Option<u64>, default: Duration| ms.map_or(default, Duration::from_millis);
+    reqwest::Client::builder()
+        .connect_timeout(millis(config.connect_timeout_ms, DEFAULT_CONNECT_TIMEOUT))
+        .read_timeout(millis(config.read_timeout_ms, DEFAULT_READ_TIMEOUT))
+        .build()
+        .unwrap_or_else(|_| reqwest::Client::new())
+}
+
+/// When and how often a failed LLM call is tried again
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Attempts in total, the first one included; 1 disables retries
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled on every following one
+    pub base_delay: Duration,
+    /// Upper bound of any single delay, `Retry-After` included
+    pub max_delay: Duration,
+    /// Fraction of each delay removed at random (0.0 to 1.0) so clients do not retry in lockstep
+    pub jitter: f64,
+    /// HTTP statuses worth retrying. Timeouts and connection errors are always retried.
+    pub retryable_statuses: Vec<u16>,
+    /// Time budget for all attempts and delays together
+    pub deadline: Option<Duration>,
+    /// Time each attempt may wait for the response headers
+    pub request_timeout: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(10),
+            jitter: 0.2,
+            retryable_statuses: vec![408, 429, 500, 502, 503, 504, 529],
+            deadline: None,
+            request_timeout: None,
+        }
+    }
+}
+
+impl From<&RetryConfig> for RetryPolicy {
+    /// The default policy overlaid with the fields set in `config`
+    fn from(config: &RetryConfig) -> Self {
+        let defaults = Self::default();
+        Self {
+            max_attempts: config.max_attempts.unwrap_or(defaults.max_attempts),
+            base_delay: config.base_delay_ms.map_or(defaults.base_delay, Duration::from_millis),
+            max_delay: config.max_delay_ms.map_or(defaults.max_delay, Duration::from_millis),
+            jitter: config.jitter.unwrap_or(defaults.jitter),
+            retryable_statuses: config.retryable_statuses.clone().unwrap_or(defaults.retryable_statuses),
+            deadline: config.deadline_ms.map(Duration::from_millis).or(defaults.deadline),
+            request_timeout: config.request_timeout_ms.map(Duration::from_millis).or(defaults.request_timeout),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A single attempt
+    pub fn no_retry() -> Self {
+        Self { max_attempts: 1, ..Self::default() }
+    }
+
+    /// Whether `err` is worth another attempt under this policy
+    pub fn should_retry(&self, err: &LlmError) -> bool {
+        match err {
+            LlmError::Timeout(_) | LlmError::Connection(_) => true,
+            LlmError::RateLimited { .. } => self.retryable_statuses.contains(&429),
+            LlmError::UpstreamStatus { code, .. } => self.retryable_statuses.contains(code),
+            _ => false,
+        }
+    }
+
+    /// Delay before retry number `retry` (1 for the first), honouring `Retry-After` up to `max_delay`
+    pub fn delay(&self, retry: u32, err: &LlmError) -> Duration {
+        if let LlmError::RateLimited { retry_after: Some(retry_after), .. } = err {
+            return (*retry_after).min(self.max_delay);
+        }
+        let exponential = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+            .min(self.max_delay);
+        // The field is public, so a NaN set in code must not reach mul_f64
+        let jitter = if self.jitter.is_nan() { 0.0 } else { self.jitter.clamp(0.0, 1.0) };
+        exponential.mul_f64(1.0 - jitter * rand::random::<f64>())
+    }
+
+    /// Run `attempt` until it succeeds, fails with a non-retryable error, runs out of attempts
+    /// or would exceed the deadline. Each attempt receives the timeout it should apply.
+    pub async fn run<T, F, Fut>(&self, context: &str, mut attempt: F) -> Result<T, LlmError>
+    where
+        F: FnMut(Option<Duration>) -> Fut,
+        Fut: std::future::Future<Output = Result<T, LlmError>>,
+    {
+        let started = Instant::now();
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let remaining = self.deadline.map(|deadline| deadline.saturating_sub(started.elapsed()));
+            let timeout = match (self.request_timeout, remaining) {
+                (Some(timeout), Some(remaining)) => Some(timeout.min(remaining)),
+                (timeout, remaining) => timeout.or(remaining),
+            };
+
+            let err = match attempt(timeout).await {
+                Ok(value) => return Ok(value),
+                Err(err) => err,
+            };
+            if attempts >= self.max_attempts.max(1) || !self.should_retry(&err) {
+                return Err(err);
+            }
+
+            let delay = self.delay(attempts, &err);
+            if let Some(deadline) = self.deadline
+                && started.elapsed() + delay >= deadline
+            {
+                return Err(err);
+            }
+            warn!("{} failed ({}). Retrying in {:?}... (attempt {}/{})", context, err, delay, attempts + 1, self.max_attempts);
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+/// Send the request built by `request` under `policy`, returning the first success response.
+/// Error statuses are classified (and their body consumed) before deciding to retry.
+pub(crate) async fn send_with_retry(
+    policy: &RetryPolicy,
+    context: &str,
+    request: impl Fn() -> reqwest::RequestBuilder,
+) -> Result<reqwest::Response, LlmError> {
+    policy
+        .run(context, |timeout| {
+            let send = request().send();
+            async move {
+                // Only until the headers: a `RequestBuilder::timeout` would also cut the body off
+                let sent = match timeout {
+                    Some(timeout) => tokio::time::timeout(timeout, send).await.map_err(|_| {
+                        LlmError::Timeout(format!("{} sent no response within {:?}", context, timeout))
+                    })?,
+                    None => send.await,
+                };
+                let response = sent.map_err(|e| LlmError::from_reqwest(&format!("{} request failed", context), &e))?;
+                if !response.status().is_success() {
+                    return Err(LlmError::from_response(context, response).await);
+                }
+                Ok(response)
+            }
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sse::spawn_mock_http_sequence;
+
+    fn fast_policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+            ..RetryPolicy::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retries_server_errors_until_success() {
+        let (addr, requests) = spawn_mock_http_sequence(vec![
+            ("HTTP/1.1 503 Service Unavailable", "text/plain", "overloaded".to_string()),
+            ("HTTP/1.1 502 Bad Gateway", "text/plain", "bad gateway".to_string()),
+            ("HTTP/1.1 200 OK", "application/json", "{}".to_string()),
+        ])
+        .await;
+        let client = reqwest::Client::new();
+        let url = format!("http://{}/", addr);
+
+        let response = send_with_retry(&fast_policy(3), "Mock API", || client.post(&url)).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(requests.await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_does_not_retry_client_errors_or_beyond_max_attempts() {
+        let (addr, requests) = spawn_mock_http_sequence(vec![
+            ("HTTP/1.1 400 Bad Request", "application/json", r#"{"error": {"message": "bad tool schema"}}"#.to_string()),
+        ])
+        .await;
+        let client = reqwest::Client::new();
+        let url = format!("http://{}/", addr);
+        let err = send_with_retry(&fast_policy(3), "Mock API", || client.post(&url)).await.unwrap_err();
+        assert_eq!(err, LlmError::UpstreamStatus { code: 400, body: "bad tool schema".to_string() });
+        assert_eq!(requests.await.unwrap().len(), 1);
+
+        let (addr, requests) = spawn_mock_http_sequence(vec![
+            ("HTTP/1.1 429 Too Many Requests", "text/plain", "slow down".to_string()),
+            ("HTTP/1.1 429 Too Many Requests", "text/plain", "slow down".to_string()),
+        ])
+        .await;
+        let url = format!("http://{}/", addr);
+        let err = send_with_retry(&fast_policy(2), "Mock API", || client.post(&url)).await.unwrap_err();
+        assert!(matches!(err, LlmError::RateLimited { .. }));
+        assert_eq!(requests.await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_deadline_and_request_timeout() {
+        // Accepts connections but never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut sockets = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                sockets.push(socket);
+            }
+        });
+
+        let policy = RetryPolicy {
+            request_timeout: Some(Duration::from_millis(50)),
+            deadline: Some(Duration::from_millis(120)),
+            base_delay: Duration::from_millis(40),
+            jitter: 0.0,
+            max_attempts: 10,
+            ..RetryPolicy::default()
+        };
+        let client = reqwest::Client::new();
+        let started = Instant::now();
+        let err = send_with_retry(&policy, "Mock API", || client.post(&url)).await.unwrap_err();
+        assert!(matches!(err, LlmError::Timeout(_)));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_request_timeout_does_not_cut_the_body_off() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // Answers the headers at once and the body only after the request timeout
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 4096];
+            let _ = socket.read(&mut buf).await.unwrap();
+            socket.write_all(b"HTTP/1.1 200 OK\r\ncontent-type: text/plain\r\nconnection: close\r\n\r\nfirst ").await.unwrap();
+            tokio::time::sleep(Duration::from_millis(150)).await;
+            socket.write_all(b"second").await.unwrap();
+            socket.shutdown().await.unwrap();
+        });
+
+        let policy = RetryPolicy { request_timeout: Some(Duration::from_millis(50)), ..RetryPolicy::no_retry() };
+        let client = http_client(&HttpClientConfig::default());
+        let response = send_with_retry(&policy, "Mock API", || client.get(&url)).await.unwrap();
+        assert_eq!(response.text().await.unwrap(), "first second");
+
+        // A read timeout shorter than the gap does cut it off
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 4096];
+            let _ = socket.read(&mut buf).await.unwrap();
+            socket.write_all(b"HTTP/1.1 200 OK\r\ncontent-type: text/plain\r\nconnection: close\r\n\r\nfirst ").await.unwrap();
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            let _ = socket.write_all(b"second").await;
+        });
+        let client = http_client(&HttpClientConfig { read_timeout_ms: Some(50), ..HttpClientConfig::default() });
+        let response = send_with_retry(&policy, "Mock API", || client.get(&url)).await.unwrap();
+        assert!(response.text().await.unwrap_err().is_timeout());
+    }
+
+    #[test]
+    fn test_delay_is_exponential_capped_and_honours_retry_after() {
+        let policy = RetryPolicy {
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(300),
+            jitter: 0.0,
+            ..RetryPolicy::default()
+        };
+        let err = LlmError::UpstreamStatus { code: 503, body: String::new() };
+        assert_eq!(policy.delay(1, &err), Duration::from_millis(100));
+        assert_eq!(policy.delay(2, &err), Duration::from_millis(200));
+        assert_eq!(policy.delay(5, &err), Duration::from_millis(300));
+
+        let limited = LlmError::RateLimited { retry_after: Some(Duration::from_secs(60)), message: String::new() };
+        assert_eq!(policy.delay(1, &limited), Duration::from_millis(300));
+
+        let config: RetryConfig = toml::from_str("max_attempts = 5\nbase_delay_ms = 250\ndeadline_ms = 2000").unwrap();
+        let config = RetryPolicy::from(&config);
+        assert_eq!(config.max_attempts, 5);
+        assert_eq!(config.base_delay, Duration::from_millis(250));
+        assert_eq!(config.deadline, Some(Duration::from_secs(2)));
+        assert_eq!(config.max_delay, RetryPolicy::default().max_delay);
+        assert_eq!(config.retryable_statuses, RetryPolicy::default().retryable_statuses);
+
+        for jitter in ["nan", "inf", "-0.5", "1.5"] {
+            assert!(toml::from_str::<RetryConfig>(&format!("jitter = {jitter}")).is_err(), "jitter = {jitter}");
+        }
+        let nan = RetryPolicy { jitter: f64::NAN, ..policy };
+        assert_eq!(nan.delay(1, &err), Duration::from_millis(100));
+    }
+}
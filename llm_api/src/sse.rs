@@ -0,0 +1,87 @@
+//! Server-sent event framing shared by the streaming adapters.
+
+/// Split the first complete SSE event off the buffer
+pub(crate) fn split_sse_event(buffer: &str) -> Option<(String, String)> {
+    let normalized = buffer.replace("\r\n", "\n");
+    let pos = normalized.find("\n\n")?;
+    Some((normalized[..pos].to_string(), normalized[pos + 2..].to_string()))
+}
+
+/// Joined `data:` lines of one SSE event
+pub(crate) fn sse_event_data(event: &str) -> String {
+    event
+        .lines()
+        .filter_map(|line| line.strip_prefix("data:"))
+        .map(|data| data.strip_prefix(' ').unwrap_or(data))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// One-shot HTTP server answering the first request with `status_line` and an SSE `body`.
+/// Returns the server address and a handle resolving to the raw request it received.
+#[cfg(test)]
+pub(crate) async fn spawn_mock_sse_server(
+    status_line: &'static str,
+    body: String,
+) -> (std::net::SocketAddr, tokio::task::JoinHandle<String>) {
+    spawn_mock_http_server(status_line, "text/event-stream", body).await
+}
+
+/// One-shot HTTP server answering the first request with `status_line` and `body`
+#[cfg(test)]
+pub(crate) async fn spawn_mock_http_server(
+    status_line: &'static str,
+    content_type: &'static str,
+    body: String,
+) -> (std::net::SocketAddr, tokio::task::JoinHandle<String>) {
+    let (addr, requests) = spawn_mock_http_sequence(vec![(status_line, content_type, body)]).await;
+    let handle = tokio::spawn(async move { requests.await.unwrap().remove(0) });
+    (addr, handle)
+}
+
+/// HTTP server answering successive requests with the given `(status_line, content_type, body)`
+/// responses, one connection each. The handle resolves to the raw requests once all were served.
+#[cfg(test)]
+pub(crate) async fn spawn_mock_http_sequence(
+    responses: Vec<(&'static str, &'static str, String)>,
+) -> (std::net::SocketAddr, tokio::task::JoinHandle<Vec<String>>) {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = tokio::spawn(async move {
+        let mut requests = Vec::new();
+        for (status_line, content_type, body) in responses {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            // Read the whole request (headers and JSON body) before answering
+            let mut request = Vec::new();
+            let mut buf = [0u8; 4096];
+            loop {
+                let n = socket.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request);
+                if let Some(header_end) = text.find("\r\n\r\n") {
+                    let content_length = text[..header_end]
+                        .lines()
+                        .find_map(|l| l.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse::<usize>().unwrap()))
+                        .unwrap_or(0);
+                    if request.len() >= header_end + 4 + content_length {
+                        break;
+                    }
+                }
+                if n == 0 {
+                    break;
+                }
+            }
+            let response = format!(
+                "{}\r\ncontent-type: {}\r\nconnection: close\r\n\r\n{}",
+                status_line, content_type, body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+            socket.shutdown().await.unwrap();
+            requests.push(String::from_utf8_lossy(&request).into_owned());
+        }
+        requests
+    });
+    (addr, handle)
+}
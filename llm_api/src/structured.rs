@@ -0,0 +1,192 @@
+//! Typed structured output on top of any [`LlmClient`].
+//!
+//! The JSON schema of the target type is generated with `schemars` and sent as `response_format`
+//! (Gemini `responseSchema`) and in the prompt, for providers without native schema support.
+//! Output that does not deserialize into the type is sent back with the error until it does.
+
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use tracing::warn;
+use uuid::Uuid;
+
+use agent_models::response_item::{ContentPart, ResponseItem, Role};
+
+use crate::chat::{strip_think_tags_and_fences, JsonSchemaFormat, ResponseFormat};
+use crate::client::{LlmClient, LlmError};
+use crate::generation::GenerationParams;
+
+/// `response_format` constraining the output to the JSON schema of `T`
+pub fn response_format_for<T: JsonSchema>() -> ResponseFormat {
+    let schema = serde_json::to_value(schemars::schema_for!(T)).unwrap_or_default();
+    // OpenAI only accepts letters, digits, `_` and `-` in schema names
+    let name: String = T::schema_name()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .collect();
+    ResponseFormat::JsonSchema {
+        json_schema: JsonSchemaFormat {
+            name,
+            description: schema.get("description").and_then(|d| d.as_str()).map(String::from),
+            schema,
+            strict: None,
+        },
+    }
+}
+
+/// Parse model output into `T`, ignoring `<think>` blocks and markdown code fences.
+/// The error describes the mismatch so it can be sent back to the model.
+pub fn parse_structured<T: DeserializeOwned>(output: &str) -> Result<T, String> {
+    let cleaned = strip_think_tags_and_fences(output);
+    let value: serde_json::Value =
+        serde_json::from_str(&cleaned).map_err(|e| format!("the answer is not valid JSON ({})", e))?;
+    serde_json::from_value(value).map_err(|e| format!("the JSON does not match the schema ({})", e))
+}
+
+/// Run `history` on `client` until the answer deserializes into `T`, for at most `max_attempts`
+/// turns. Each failed answer is sent back with its validation error. Upstream failures are
+/// returned as they are; running out of attempts gives [`LlmError::Decode`].
+pub async fn call_structured<T: JsonSchema + DeserializeOwned>(
+    client: &dyn LlmClient,
+    history: &[ResponseItem],
+    params: &GenerationParams,
+    max_attempts: u32,
+) -> Result<T, LlmError> {
+    let format = response_format_for::<T>();
+    let params = GenerationParams { response_format: Some(format.clone()), ..params.clone() };
+    let schema = format.schema().map(|s| s.to_string()).unwrap_or_default();
+
+    let mut history = history.to_vec();
+    history.push(text_message(
+        Role::System,
+        format!("Answer with a single JSON value matching this JSON schema, without any other text:\n{}", schema),
+    ));
+
+    let mut last_error = String::new();
+    for attempt in 1..=max_attempts.max(1) {
+        let turn = client.complete(&history, &params, &[]).await?;
+        let output = output_text(&turn.items);
+        match parse_structured::<T>(&output) {
+            Ok(value) => return Ok(value),
+            Err(error) => {
+                warn!("Structured output rejected (attempt {}/{}): {}", attempt, max_attempts, error);
+                history.push(text_message(Role::Assistant, output));
+                history.push(text_message(
+                    Role::User,
+                    format!("Your answer was rejected: {}. Reply again with only the corrected JSON.", error),
+                ));
+                last_error = error;
+            }
+        }
+    }
+    Err(LlmError::Decode(format!(
+        "no valid {} after {} attempts: {}",
+        T::schema_name(),
+        max_attempts.max(1),
+        last_error
+    )))
+}
+
+fn output_text(items: &[ResponseItem]) -> String {
+    items
+        .iter()
+        .filter_map(|item| match item {
+            ResponseItem::Message { content, .. } => Some(content),
+            _ => None,
+        })
+        .flatten()
+        .filter_map(|part| match part {
+            ContentPart::Text { text } => Some(text.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn text_message(role: Role, text: String) -> ResponseItem {
+    ResponseItem::Message {
+        id: format!("msg_{}", Uuid::new_v4()),
+        role,
+        content: vec![ContentPart::Text { text }],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::LlmTurn;
+    use crate::tools::Tool;
+    use std::sync::Mutex;
+
+    #[derive(Debug, PartialEq, serde::Deserialize, schemars::JsonSchema)]
+    struct Forecast {
+        city: String,
+        celsius: i32,
+    }
+
+    /// Answers with the scripted replies in order and records the histories it received
+    struct ScriptedClient {
+        replies: Mutex<Vec<&'static str>>,
+        histories: Mutex<Vec<(Vec<ResponseItem>, GenerationParams)>>,
+    }
+
+    #[async_trait::async_trait]
+    impl LlmClient for ScriptedClient {
+        fn model_id(&self) -> &str {
+            "scripted"
+        }
+
+        async fn complete(
+            &self,
+            history: &[ResponseItem],
+            params: &GenerationParams,
+            _tools: &[Tool],
+        ) -> Result<LlmTurn, LlmError> {
+            self.histories.lock().unwrap().push((history.to_vec(), params.clone()));
+            let reply = self.replies.lock().unwrap().remove(0);
+            Ok(LlmTurn { items: vec![text_message(Role::Assistant, reply.to_string())], usage: None, model: None })
+        }
+    }
+
+    #[test]
+    fn test_response_format_for_uses_schemars_schema() {
+        let ResponseFormat::JsonSchema { json_schema } = response_format_for::<Forecast>() else {
+            panic!("expected a json_schema format");
+        };
+        assert_eq!(json_schema.name, "Forecast");
+        assert_eq!(json_schema.schema["type"], "object");
+        assert_eq!(json_schema.schema["required"], serde_json::json!(["city", "celsius"]));
+    }
+
+    #[tokio::test]
+    async fn test_call_structured_reprompts_with_validation_error() {
+        let client = ScriptedClient {
+            replies: Mutex::new(vec![
+                "<think>easy</think>```json\n{\"city\": \"Paris\"}\n```",
+                "{\"city\": \"Paris\", \"celsius\": 21}",
+            ]),
+            histories: Mutex::new(Vec::new()),
+        };
+        let history = vec![text_message(Role::User, "Weather in Paris?".to_string())];
+
+        let forecast: Forecast = call_structured(&client, &history, &GenerationParams::default(), 3).await.unwrap();
+        assert_eq!(forecast, Forecast { city: "Paris".to_string(), celsius: 21 });
+
+        let histories = client.histories.lock().unwrap();
+        assert_eq!(histories.len(), 2);
+        let (retry_history, params) = &histories[1];
+        assert!(params.response_format.as_ref().is_some_and(|f| f.schema().is_some()));
+        let ResponseItem::Message { role: Role::User, content, .. } = retry_history.last().unwrap() else {
+            panic!("expected the validation error as a user message");
+        };
+        assert!(matches!(&content[0], ContentPart::Text { text } if text.contains("missing field `celsius`")));
+    }
+
+    #[tokio::test]
+    async fn test_call_structured_gives_up_after_max_attempts() {
+        let client = ScriptedClient {
+            replies: Mutex::new(vec!["not json", "still not json"]),
+            histories: Mutex::new(Vec::new()),
+        };
+        let err = call_structured::<Forecast>(&client, &[], &GenerationParams::default(), 2).await.unwrap_err();
+        assert!(matches!(err, LlmError::Decode(message) if message.contains("after 2 attempts")));
+    }
+}
@@ -1,5 +1,6 @@
 // tools.rs
 
+use agent_models::response_item::ResponseTool;
 use anyhow::{Context, Result};
 use rmcp::model::Tool as RmcpTool; // Alias for clarity
 use serde::{Deserialize, Serialize};
@@ -40,6 +41,48 @@ pub struct FunctionParameters {
     pub required: Option<Vec<String>>,
 }
 
+/// Converts an Open Responses function tool (flat format) into the chat completions tool format.
+impl From<&ResponseTool> for Tool {
+    fn from(tool: &ResponseTool) -> Self {
+        let schema = &tool.parameters;
+        Tool {
+            r#type: "function".to_string(),
+            function: FunctionDefinition {
+                name: tool.name.clone(),
+                description: tool.description.clone().unwrap_or_default(),
+                parameters: FunctionParameters {
+                    r#type: schema
+                        .get("type")
+                        .and_then(Value::as_str)
+                        .unwrap_or("object")
+                        .to_string(),
+                    properties: schema
+                        .get("properties")
+                        .cloned()
+                        .unwrap_or_else(|| Value::Object(Map::new())),
+                    required: schema
+                        .get("required")
+                        .and_then(Value::as_array)
+                        .map(|arr| arr.iter().filter_map(Value::as_str).map(String::from).collect()),
+                },
+            },
+        }
+    }
+}
+
+/// Converts a chat completions tool into the flat Open Responses function tool format.
+impl From<&Tool> for ResponseTool {
+    fn from(tool: &Tool) -> Self {
+        ResponseTool {
+            r#type: "function".to_string(),
+            name: tool.function.name.clone(),
+            description: Some(tool.function.description.clone()).filter(|d| !d.is_empty()),
+            parameters: serde_json::to_value(&tool.function.parameters).unwrap_or(Value::Null),
+            strict: None,
+        }
+    }
+}
+
 /// Represents the result of a tool call to be sent back to the LLM.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ToolResult {